@@ -0,0 +1,534 @@
+//! `jstz`'s implementation of JavaScript's `AbortController` and `AbortSignal` Web API
+//! classes.
+//!
+//! An `AbortSignal` passed to `SmartFunction.call` (via the request's `signal`) cancels
+//! the call: if the signal is aborted before the callee completes, the returned promise
+//! is rejected with the signal's reason and the callee's state changes are rolled back.
+//!
+//! More information:
+//!  - [MDN documentation][mdn]
+//!  - [WHATWG `DOM` specification][spec]
+//!
+//! [mdn]: https://developer.mozilla.org/en-US/docs/Web/API/AbortController
+//! [spec]: https://dom.spec.whatwg.org/#aborting-ongoing-activities
+
+use boa_engine::{
+    js_string,
+    object::{
+        builtins::{JsArray, JsFunction},
+        ErasedObject, PROTOTYPE,
+    },
+    property::Attribute,
+    Context, JsArgs, JsData, JsError, JsNativeError, JsObject, JsResult, JsValue,
+    NativeFunction,
+};
+use boa_gc::{Finalize, GcRefMut, Trace};
+use jstz_core::{
+    accessor,
+    native::{
+        register_global_class, Accessor, ClassBuilder, JsNativeObject, NativeClass,
+    },
+    runtime::Timers,
+    value::IntoJs,
+};
+
+use crate::{
+    event::{fire_event, EventTarget, EventTargetClass},
+    idl::dom_exception,
+};
+
+#[derive(Default, Trace, Finalize, JsData)]
+pub struct AbortSignal {
+    pub(crate) event_target: EventTarget,
+    // The signal is aborted iff the reason is not `undefined`
+    reason: JsValue,
+    abort_algorithms: Vec<JsFunction>,
+    dependent: bool,
+    source_signals: Vec<JsObject>,
+    dependent_signals: Vec<JsObject>,
+}
+
+impl AbortSignal {
+    pub fn aborted(&self) -> bool {
+        !self.reason.is_undefined()
+    }
+
+    pub fn reason(&self) -> &JsValue {
+        &self.reason
+    }
+
+    /// Creates a new (non-aborted) `AbortSignal` object
+    pub fn new_object(context: &mut Context) -> JsResult<JsNativeObject<Self>> {
+        JsNativeObject::new::<AbortSignalClass>(Self::default(), context)
+    }
+
+    /// Adds an algorithm that is called with the abort reason when `signal` is
+    /// aborted. Does nothing if `signal` is already aborted.
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://dom.spec.whatwg.org/#abortsignal-add
+    pub fn add_algorithm(signal: &JsObject, algorithm: JsFunction) -> JsResult<()> {
+        let mut signal = Self::downcast_mut(signal)?;
+        if !signal.aborted() {
+            signal.abort_algorithms.push(algorithm);
+        }
+
+        Ok(())
+    }
+
+    /// Aborts `signal` (and its dependent signals) with the given `reason`, or an
+    /// `AbortError` if `reason` is `undefined`.
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://dom.spec.whatwg.org/#abortsignal-signal-abort
+    pub fn signal_abort(
+        signal: &JsObject,
+        reason: JsValue,
+        context: &mut Context,
+    ) -> JsResult<()> {
+        // 1. If signal is aborted, then return.
+        if Self::downcast_mut(signal)?.aborted() {
+            return Ok(());
+        }
+
+        // 2. Set signal's abort reason to reason if it is given; otherwise to a new
+        //    "AbortError" DOMException.
+        let reason = if reason.is_undefined() {
+            dom_exception("AbortError", "signal is aborted without reason", context)
+        } else {
+            reason
+        };
+
+        // 3-4. Let dependentSignalsToAbort be a new list. For each dependentSignal of
+        //      signal's dependent signals that is not aborted, set its abort reason to
+        //      signal's abort reason and append it to dependentSignalsToAbort.
+        let dependent_signals = {
+            let mut signal = Self::downcast_mut(signal)?;
+            signal.reason = reason.clone();
+            signal.dependent_signals.clone()
+        };
+
+        let mut dependent_signals_to_abort = Vec::new();
+        for dependent_signal in dependent_signals {
+            let mut dependent = Self::downcast_mut(&dependent_signal)?;
+            if !dependent.aborted() {
+                dependent.reason = reason.clone();
+                drop(dependent);
+                dependent_signals_to_abort.push(dependent_signal);
+            }
+        }
+
+        // 5. Run the abort steps for signal.
+        Self::run_abort_steps(signal, context)?;
+
+        // 6. For each dependentSignal of dependentSignalsToAbort, run the abort steps
+        //    for dependentSignal.
+        for dependent_signal in dependent_signals_to_abort {
+            Self::run_abort_steps(&dependent_signal, context)?;
+        }
+
+        Ok(())
+    }
+
+    /// https://dom.spec.whatwg.org/#run-the-abort-steps
+    fn run_abort_steps(signal: &JsObject, context: &mut Context) -> JsResult<()> {
+        // 1-2. For each algorithm of signal's abort algorithms: run algorithm, then
+        //      empty signal's abort algorithms.
+        let (algorithms, reason) = {
+            let mut signal = Self::downcast_mut(signal)?;
+            (
+                std::mem::take(&mut signal.abort_algorithms),
+                signal.reason.clone(),
+            )
+        };
+
+        for algorithm in algorithms {
+            algorithm.call(&JsValue::undefined(), &[reason.clone()], context)?;
+        }
+
+        // 3. Fire an event named abort at signal.
+        fire_event(signal, "abort", context)?;
+
+        Ok(())
+    }
+
+    /// Creates a signal that is aborted as soon as any of `signals` is aborted.
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://dom.spec.whatwg.org/#create-a-dependent-abort-signal
+    pub fn create_dependent(
+        signals: &[JsObject],
+        context: &mut Context,
+    ) -> JsResult<JsNativeObject<Self>> {
+        // 1. Let resultSignal be a new object implementing signalInterface.
+        let result_signal = Self::new_object(context)?;
+
+        // 2. For each signal of signals: if signal is aborted, then set resultSignal's
+        //    abort reason to signal's abort reason and return resultSignal.
+        for signal in signals {
+            let signal = Self::downcast_mut(signal)?;
+            if signal.aborted() {
+                result_signal.deref_mut().reason = signal.reason.clone();
+                return Ok(result_signal);
+            }
+        }
+
+        // 3. Set resultSignal's dependent to true.
+        result_signal.deref_mut().dependent = true;
+
+        // 4. For each signal of signals:
+        for signal in signals {
+            // 4.1. If signal's dependent is false, then append signal to
+            //      resultSignal's source signals and append resultSignal to signal's
+            //      dependent signals.
+            // 4.2. Otherwise, do the same for each of signal's source signals.
+            let sources = {
+                let signal_ref = Self::downcast_mut(signal)?;
+                if signal_ref.dependent {
+                    signal_ref.source_signals.clone()
+                } else {
+                    vec![signal.clone()]
+                }
+            };
+
+            for source in sources {
+                let already_linked = result_signal
+                    .deref()
+                    .source_signals
+                    .iter()
+                    .any(|s| JsObject::equals(s, &source));
+                if already_linked {
+                    continue;
+                }
+
+                result_signal
+                    .deref_mut()
+                    .source_signals
+                    .push(source.clone());
+                Self::downcast_mut(&source)?
+                    .dependent_signals
+                    .push(result_signal.to_object());
+            }
+        }
+
+        // 5. Return resultSignal.
+        Ok(result_signal)
+    }
+
+    fn downcast_mut(signal: &JsObject) -> JsResult<GcRefMut<'_, ErasedObject, Self>> {
+        signal.downcast_mut::<Self>().ok_or_else(|| {
+            JsNativeError::typ()
+                .with_message("Failed to convert js value into rust type `AbortSignal`")
+                .into()
+        })
+    }
+
+    fn try_from_js(value: &JsValue) -> JsResult<GcRefMut<'_, ErasedObject, Self>> {
+        value
+            .as_object()
+            .and_then(|obj| obj.downcast_mut::<Self>())
+            .ok_or_else(|| {
+                JsNativeError::typ()
+                    .with_message(
+                        "Failed to convert js value into rust type `AbortSignal`",
+                    )
+                    .into()
+            })
+    }
+}
+
+pub struct AbortSignalClass;
+
+impl AbortSignalClass {
+    fn aborted(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            AbortSignal,
+            "aborted",
+            get:((signal, _context) => Ok(signal.aborted().into()))
+        )
+    }
+
+    fn reason(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            AbortSignal,
+            "reason",
+            get:((signal, _context) => Ok(signal.reason().clone()))
+        )
+    }
+
+    fn onabort(context: &mut Context) -> Accessor {
+        Accessor::new("onabort")
+            .get(
+                NativeFunction::from_fn_ptr(|this, _args, context| {
+                    let handler = AbortSignal::try_from_js(this)?
+                        .event_target
+                        .event_handler("abort");
+
+                    Ok(handler.into_js(context))
+                }),
+                context,
+            )
+            .set(
+                NativeFunction::from_fn_ptr(|this, args, _context| {
+                    // Non-callable values are treated as `null`
+                    let handler = args.get_or_undefined(0).as_callable().cloned();
+                    AbortSignal::try_from_js(this)?
+                        .event_target
+                        .set_event_handler("abort", handler);
+
+                    Ok(JsValue::undefined())
+                }),
+                context,
+            )
+    }
+
+    fn throw_if_aborted(
+        this: &JsValue,
+        _args: &[JsValue],
+        _context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let signal = AbortSignal::try_from_js(this)?;
+        if signal.aborted() {
+            return Err(JsError::from_opaque(signal.reason.clone()));
+        }
+
+        Ok(JsValue::undefined())
+    }
+
+    fn abort(
+        _this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let signal = AbortSignal::new_object(context)?;
+        AbortSignal::signal_abort(
+            signal.object(),
+            args.get_or_undefined(0).clone(),
+            context,
+        )?;
+
+        Ok(signal.into())
+    }
+
+    fn timeout(
+        _this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let milliseconds = args.get_or_undefined(0).to_index(context)?;
+        let signal = AbortSignal::new_object(context)?;
+
+        let timers = Timers::from_context(context).ok_or_else(|| {
+            JsNativeError::error().with_message("Timers are not available")
+        })?;
+
+        let callback = NativeFunction::from_copy_closure_with_captures(
+            |_, _, signal, context| {
+                let reason = dom_exception("TimeoutError", "signal timed out", context);
+                AbortSignal::signal_abort(signal, reason, context)?;
+
+                Ok(JsValue::undefined())
+            },
+            signal.to_object(),
+        )
+        .to_js_function(context.realm());
+
+        timers.schedule(callback, vec![], milliseconds, false);
+
+        Ok(signal.into())
+    }
+
+    fn any(
+        _this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let Some(signals) = args.get_or_undefined(0).as_object() else {
+            return Err(JsNativeError::typ()
+                .with_message("Expected a sequence of `AbortSignal`")
+                .into());
+        };
+
+        let signals = JsArray::from_object(signals.clone())?;
+        let mut sources = Vec::new();
+        for i in 0..signals.length(context)? {
+            let JsValue::Object(signal) = signals.get(i, context)? else {
+                return Err(JsNativeError::typ()
+                    .with_message("Expected `AbortSignal`")
+                    .into());
+            };
+            drop(AbortSignal::downcast_mut(&signal)?);
+            sources.push(signal);
+        }
+
+        Ok(AbortSignal::create_dependent(&sources, context)?.into())
+    }
+}
+
+impl NativeClass for AbortSignalClass {
+    type Instance = AbortSignal;
+
+    const NAME: &'static str = "AbortSignal";
+
+    fn data_constructor(
+        _target: &JsValue,
+        _args: &[JsValue],
+        _context: &mut Context,
+    ) -> JsResult<Self::Instance> {
+        Err(JsNativeError::typ()
+            .with_message("Illegal constructor")
+            .into())
+    }
+
+    fn init(class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        // `AbortSignal` extends `EventTarget`
+        let event_target_prototype = class
+            .context()
+            .global_object()
+            .get(js_string!(EventTargetClass::NAME), class.context())?
+            .as_object()
+            .map(|constructor| constructor.get(PROTOTYPE, class.context()))
+            .transpose()?
+            .and_then(|prototype| prototype.as_object().cloned());
+
+        let aborted = Self::aborted(class.context());
+        let reason = Self::reason(class.context());
+        let onabort = Self::onabort(class.context());
+
+        class
+            .inherit(event_target_prototype)
+            .accessor(js_string!("aborted"), aborted, Attribute::all())
+            .accessor(js_string!("reason"), reason, Attribute::all())
+            .accessor(js_string!("onabort"), onabort, Attribute::all())
+            .method(
+                js_string!("throwIfAborted"),
+                0,
+                NativeFunction::from_fn_ptr(Self::throw_if_aborted),
+            )
+            .static_method(
+                js_string!("abort"),
+                0,
+                NativeFunction::from_fn_ptr(Self::abort),
+            )
+            .static_method(
+                js_string!("timeout"),
+                1,
+                NativeFunction::from_fn_ptr(Self::timeout),
+            )
+            .static_method(js_string!("any"), 1, NativeFunction::from_fn_ptr(Self::any));
+
+        Ok(())
+    }
+}
+
+#[derive(Trace, Finalize, JsData)]
+pub struct AbortController {
+    signal: JsNativeObject<AbortSignal>,
+}
+
+impl AbortController {
+    pub fn signal(&self) -> &JsNativeObject<AbortSignal> {
+        &self.signal
+    }
+
+    /// Aborts the controller's signal
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://dom.spec.whatwg.org/#abortcontroller-signal-abort
+    pub fn abort(&self, reason: JsValue, context: &mut Context) -> JsResult<()> {
+        AbortSignal::signal_abort(self.signal.object(), reason, context)
+    }
+
+    fn try_from_js(value: &JsValue) -> JsResult<GcRefMut<'_, ErasedObject, Self>> {
+        value
+            .as_object()
+            .and_then(|obj| obj.downcast_mut::<Self>())
+            .ok_or_else(|| {
+                JsNativeError::typ()
+                    .with_message(
+                        "Failed to convert js value into rust type `AbortController`",
+                    )
+                    .into()
+            })
+    }
+}
+
+pub struct AbortControllerClass;
+
+impl AbortControllerClass {
+    fn signal(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            AbortController,
+            "signal",
+            get:((controller, context) => Ok(controller.signal().clone().into_js(context)))
+        )
+    }
+
+    fn abort(
+        this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        // NB: The controller must not be borrowed while the abort algorithms run
+        let signal = AbortController::try_from_js(this)?.signal().clone();
+        AbortSignal::signal_abort(
+            signal.object(),
+            args.get_or_undefined(0).clone(),
+            context,
+        )?;
+
+        Ok(JsValue::undefined())
+    }
+}
+
+impl NativeClass for AbortControllerClass {
+    type Instance = AbortController;
+
+    const NAME: &'static str = "AbortController";
+
+    fn data_constructor(
+        _target: &JsValue,
+        _args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<Self::Instance> {
+        Ok(AbortController {
+            signal: AbortSignal::new_object(context)?,
+        })
+    }
+
+    fn init(class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        let signal = Self::signal(class.context());
+
+        class
+            .accessor(js_string!("signal"), signal, Attribute::all())
+            .method(
+                js_string!("abort"),
+                0,
+                NativeFunction::from_fn_ptr(Self::abort),
+            );
+
+        Ok(())
+    }
+}
+
+pub struct AbortApi;
+
+impl jstz_core::Api for AbortApi {
+    fn init(self, context: &mut Context) {
+        register_global_class::<AbortSignalClass>(context)
+            .expect("The `AbortSignal` class shouldn't exist yet");
+        register_global_class::<AbortControllerClass>(context)
+            .expect("The `AbortController` class shouldn't exist yet");
+    }
+}
@@ -0,0 +1,579 @@
+//! `jstz`'s implementation of JavaScript's `Event`, `CustomEvent` and `EventTarget`
+//! Web API classes.
+//!
+//! Since there is no DOM in `jstz`, events are only ever dispatched to a single
+//! target: there are no capturing or bubbling phases.
+//!
+//! More information:
+//!  - [MDN documentation][mdn]
+//!  - [WHATWG `DOM` specification][spec]
+//!
+//! [mdn]: https://developer.mozilla.org/en-US/docs/Web/API/Event
+//! [spec]: https://dom.spec.whatwg.org/#events
+
+mod target;
+
+use boa_engine::{
+    js_string,
+    object::{ErasedObject, PROTOTYPE},
+    property::Attribute,
+    value::TryFromJs,
+    Context, JsArgs, JsData, JsError, JsNativeError, JsObject, JsResult, JsValue,
+    NativeFunction,
+};
+use boa_gc::{Finalize, GcRefMut, Trace};
+use jstz_core::{
+    accessor,
+    native::{
+        register_global_class, Accessor, ClassBuilder, JsNativeObject, NativeClass,
+    },
+    runtime::Timers,
+    value::IntoJs,
+};
+
+pub use target::{dispatch_event, EventListenerOptions, EventTarget, EventTargetClass};
+
+use self::target::EventTargetApi;
+
+/// The phase of an event's dispatch.
+///
+/// [spec] https://dom.spec.whatwg.org/#dom-event-eventphase
+pub mod phase {
+    pub const NONE: u16 = 0;
+    pub const CAPTURING_PHASE: u16 = 1;
+    pub const AT_TARGET: u16 = 2;
+    pub const BUBBLING_PHASE: u16 = 3;
+}
+
+#[derive(Trace, Finalize, JsData, Clone)]
+pub struct Event {
+    type_: String,
+    bubbles: bool,
+    cancelable: bool,
+    composed: bool,
+    is_trusted: bool,
+    time_stamp: f64,
+    pub(crate) target: Option<JsObject>,
+    pub(crate) current_target: Option<JsObject>,
+    pub(crate) event_phase: u16,
+    // Flags, see https://dom.spec.whatwg.org/#stop-propagation-flag
+    pub(crate) stop_propagation: bool,
+    pub(crate) stop_immediate_propagation: bool,
+    pub(crate) canceled: bool,
+    pub(crate) in_passive_listener: bool,
+    pub(crate) dispatch: bool,
+}
+
+/// The `EventInit` dictionary
+///
+/// More information:
+///  - [WHATWG specification][spec]
+///
+/// [spec] https://dom.spec.whatwg.org/#dictdef-eventinit
+#[derive(Default, Clone)]
+pub struct EventInit {
+    pub bubbles: bool,
+    pub cancelable: bool,
+    pub composed: bool,
+}
+
+fn get_optional_member<T: TryFromJs>(
+    obj: &JsObject,
+    name: &str,
+    context: &mut Context,
+) -> JsResult<Option<T>> {
+    let value = obj.get(js_string!(name), context)?;
+    if value.is_undefined() {
+        return Ok(None);
+    }
+
+    Ok(Some(value.try_js_into(context)?))
+}
+
+impl TryFromJs for EventInit {
+    fn try_from_js(value: &JsValue, context: &mut Context) -> JsResult<Self> {
+        if value.is_null_or_undefined() {
+            return Ok(Self::default());
+        }
+
+        let obj = value.as_object().ok_or_else(|| {
+            JsError::from_native(JsNativeError::typ().with_message("Expected object"))
+        })?;
+
+        Ok(Self {
+            bubbles: get_optional_member(obj, "bubbles", context)?.unwrap_or_default(),
+            cancelable: get_optional_member(obj, "cancelable", context)?
+                .unwrap_or_default(),
+            composed: get_optional_member(obj, "composed", context)?.unwrap_or_default(),
+        })
+    }
+}
+
+impl Event {
+    /// Creates a new event.
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://dom.spec.whatwg.org/#concept-event-constructor
+    pub fn new(type_: String, init: EventInit, context: &mut Context) -> Self {
+        // The time stamp is relative to the runtime's virtual clock
+        let time_stamp = Timers::from_context(context)
+            .map(|timers| timers.now())
+            .unwrap_or_default() as f64;
+
+        Self {
+            type_,
+            bubbles: init.bubbles,
+            cancelable: init.cancelable,
+            composed: init.composed,
+            is_trusted: false,
+            time_stamp,
+            target: None,
+            current_target: None,
+            event_phase: phase::NONE,
+            stop_propagation: false,
+            stop_immediate_propagation: false,
+            canceled: false,
+            in_passive_listener: false,
+            dispatch: false,
+        }
+    }
+
+    /// Creates a new event that is dispatched by the `jstz` runtime itself.
+    pub fn new_trusted(type_: &str, context: &mut Context) -> Self {
+        Self {
+            is_trusted: true,
+            ..Self::new(type_.to_string(), EventInit::default(), context)
+        }
+    }
+
+    pub fn type_(&self) -> &str {
+        &self.type_
+    }
+
+    pub fn default_prevented(&self) -> bool {
+        self.canceled
+    }
+
+    /// Signals that the event's default action should not be taken.
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://dom.spec.whatwg.org/#set-the-canceled-flag
+    pub fn prevent_default(&mut self) {
+        if self.cancelable && !self.in_passive_listener {
+            self.canceled = true;
+        }
+    }
+
+    pub fn stop_propagation(&mut self) {
+        self.stop_propagation = true;
+    }
+
+    pub fn stop_immediate_propagation(&mut self) {
+        self.stop_propagation = true;
+        self.stop_immediate_propagation = true;
+    }
+
+    /// Downcasts a js value into an `Event`, including instances of subclasses of `Event`
+    pub fn try_from_js(value: &JsValue) -> JsResult<GcRefMut<'_, ErasedObject, Self>> {
+        let obj = value.as_object().ok_or_else(|| {
+            JsError::from_native(
+                JsNativeError::typ()
+                    .with_message("Failed to convert js value into rust type `Event`"),
+            )
+        })?;
+
+        if let Some(event) = obj.downcast_mut::<Self>() {
+            return Ok(event);
+        }
+
+        obj.downcast_mut::<CustomEvent>()
+            .map(|custom_event| GcRefMut::map(custom_event, |e| &mut e.event))
+            .ok_or_else(|| {
+                JsNativeError::typ()
+                    .with_message("Failed to convert js value into rust type `Event`")
+                    .into()
+            })
+    }
+}
+
+#[derive(Trace, Finalize, JsData, Clone)]
+pub struct CustomEvent {
+    event: Event,
+    detail: JsValue,
+}
+
+impl CustomEvent {
+    pub fn detail(&self) -> &JsValue {
+        &self.detail
+    }
+
+    fn try_from_js(value: &JsValue) -> JsResult<GcRefMut<'_, ErasedObject, Self>> {
+        value
+            .as_object()
+            .and_then(|obj| obj.downcast_mut::<Self>())
+            .ok_or_else(|| {
+                JsNativeError::typ()
+                    .with_message(
+                        "Failed to convert js value into rust type `CustomEvent`",
+                    )
+                    .into()
+            })
+    }
+}
+
+/// The `CustomEventInit` dictionary
+///
+/// More information:
+///  - [WHATWG specification][spec]
+///
+/// [spec] https://dom.spec.whatwg.org/#dictdef-customeventinit
+#[derive(Default)]
+pub struct CustomEventInit {
+    event_init: EventInit,
+    detail: JsValue,
+}
+
+impl TryFromJs for CustomEventInit {
+    fn try_from_js(value: &JsValue, context: &mut Context) -> JsResult<Self> {
+        let event_init: EventInit = value.try_js_into(context)?;
+        let detail = match value.as_object() {
+            Some(obj) => obj.get(js_string!("detail"), context)?,
+            None => JsValue::undefined(),
+        };
+
+        Ok(Self { event_init, detail })
+    }
+}
+
+fn require_type(
+    args: &[JsValue],
+    class_name: &str,
+    context: &mut Context,
+) -> JsResult<String> {
+    if args.is_empty() {
+        return Err(JsNativeError::typ()
+            .with_message(format!(
+                "Failed to construct '{}': 1 argument required, but only 0 present.",
+                class_name
+            ))
+            .into());
+    }
+
+    Ok(args[0].to_string(context)?.to_std_string_escaped())
+}
+
+pub struct EventClass;
+
+impl EventClass {
+    fn type_(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Event,
+            "type",
+            get:((event, context) => Ok(event.type_.clone().into_js(context)))
+        )
+    }
+
+    fn target(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Event,
+            "target",
+            get:((event, context) => Ok(event.target.clone().into_js(context)))
+        )
+    }
+
+    fn current_target(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Event,
+            "currentTarget",
+            get:((event, context) => Ok(event.current_target.clone().into_js(context)))
+        )
+    }
+
+    fn event_phase(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Event,
+            "eventPhase",
+            get:((event, _context) => Ok(event.event_phase.into()))
+        )
+    }
+
+    fn bubbles(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Event,
+            "bubbles",
+            get:((event, _context) => Ok(event.bubbles.into()))
+        )
+    }
+
+    fn cancelable(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Event,
+            "cancelable",
+            get:((event, _context) => Ok(event.cancelable.into()))
+        )
+    }
+
+    fn composed(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Event,
+            "composed",
+            get:((event, _context) => Ok(event.composed.into()))
+        )
+    }
+
+    fn default_prevented(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Event,
+            "defaultPrevented",
+            get:((event, _context) => Ok(event.default_prevented().into()))
+        )
+    }
+
+    fn is_trusted(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Event,
+            "isTrusted",
+            get:((event, _context) => Ok(event.is_trusted.into()))
+        )
+    }
+
+    fn time_stamp(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Event,
+            "timeStamp",
+            get:((event, _context) => Ok(event.time_stamp.into()))
+        )
+    }
+
+    fn prevent_default(
+        this: &JsValue,
+        _args: &[JsValue],
+        _context: &mut Context,
+    ) -> JsResult<JsValue> {
+        Event::try_from_js(this)?.prevent_default();
+
+        Ok(JsValue::undefined())
+    }
+
+    fn stop_propagation(
+        this: &JsValue,
+        _args: &[JsValue],
+        _context: &mut Context,
+    ) -> JsResult<JsValue> {
+        Event::try_from_js(this)?.stop_propagation();
+
+        Ok(JsValue::undefined())
+    }
+
+    fn stop_immediate_propagation(
+        this: &JsValue,
+        _args: &[JsValue],
+        _context: &mut Context,
+    ) -> JsResult<JsValue> {
+        Event::try_from_js(this)?.stop_immediate_propagation();
+
+        Ok(JsValue::undefined())
+    }
+
+    fn composed_path(
+        this: &JsValue,
+        _args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let event = Event::try_from_js(this)?;
+
+        // Without a tree, the path only ever consists of the current target
+        let path: Vec<JsObject> = event.current_target.clone().into_iter().collect();
+
+        Ok(path.into_js(context))
+    }
+
+    fn define_phase_constants(class: &mut ClassBuilder<'_>) {
+        for (name, value) in [
+            ("NONE", phase::NONE),
+            ("CAPTURING_PHASE", phase::CAPTURING_PHASE),
+            ("AT_TARGET", phase::AT_TARGET),
+            ("BUBBLING_PHASE", phase::BUBBLING_PHASE),
+        ] {
+            class
+                .property(js_string!(name), value, Attribute::READONLY)
+                .static_property(js_string!(name), value, Attribute::READONLY);
+        }
+    }
+}
+
+impl NativeClass for EventClass {
+    type Instance = Event;
+
+    const NAME: &'static str = "Event";
+
+    const LENGTH: usize = 1;
+
+    fn data_constructor(
+        _target: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<Self::Instance> {
+        let type_ = require_type(args, Self::NAME, context)?;
+        let init: EventInit = args.get_or_undefined(1).try_js_into(context)?;
+
+        Ok(Event::new(type_, init, context))
+    }
+
+    fn init(class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        let type_ = Self::type_(class.context());
+        let target = Self::target(class.context());
+        let current_target = Self::current_target(class.context());
+        let event_phase = Self::event_phase(class.context());
+        let bubbles = Self::bubbles(class.context());
+        let cancelable = Self::cancelable(class.context());
+        let composed = Self::composed(class.context());
+        let default_prevented = Self::default_prevented(class.context());
+        let is_trusted = Self::is_trusted(class.context());
+        let time_stamp = Self::time_stamp(class.context());
+
+        class
+            .accessor(js_string!("type"), type_, Attribute::all())
+            .accessor(js_string!("target"), target, Attribute::all())
+            .accessor(
+                js_string!("currentTarget"),
+                current_target,
+                Attribute::all(),
+            )
+            .accessor(js_string!("eventPhase"), event_phase, Attribute::all())
+            .accessor(js_string!("bubbles"), bubbles, Attribute::all())
+            .accessor(js_string!("cancelable"), cancelable, Attribute::all())
+            .accessor(js_string!("composed"), composed, Attribute::all())
+            .accessor(
+                js_string!("defaultPrevented"),
+                default_prevented,
+                Attribute::all(),
+            )
+            .accessor(js_string!("isTrusted"), is_trusted, Attribute::all())
+            .accessor(js_string!("timeStamp"), time_stamp, Attribute::all())
+            .method(
+                js_string!("preventDefault"),
+                0,
+                NativeFunction::from_fn_ptr(Self::prevent_default),
+            )
+            .method(
+                js_string!("stopPropagation"),
+                0,
+                NativeFunction::from_fn_ptr(Self::stop_propagation),
+            )
+            .method(
+                js_string!("stopImmediatePropagation"),
+                0,
+                NativeFunction::from_fn_ptr(Self::stop_immediate_propagation),
+            )
+            .method(
+                js_string!("composedPath"),
+                0,
+                NativeFunction::from_fn_ptr(Self::composed_path),
+            );
+
+        Self::define_phase_constants(class);
+
+        Ok(())
+    }
+}
+
+pub struct CustomEventClass;
+
+impl CustomEventClass {
+    fn detail(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            CustomEvent,
+            "detail",
+            get:((custom_event, _context) => Ok(custom_event.detail().clone()))
+        )
+    }
+}
+
+impl NativeClass for CustomEventClass {
+    type Instance = CustomEvent;
+
+    const NAME: &'static str = "CustomEvent";
+
+    const LENGTH: usize = 1;
+
+    fn data_constructor(
+        _target: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<Self::Instance> {
+        let type_ = require_type(args, Self::NAME, context)?;
+        let init: CustomEventInit = args.get_or_undefined(1).try_js_into(context)?;
+
+        Ok(CustomEvent {
+            event: Event::new(type_, init.event_init, context),
+            detail: init.detail,
+        })
+    }
+
+    fn init(class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        // `CustomEvent` extends `Event`
+        let event_prototype = class
+            .context()
+            .global_object()
+            .get(js_string!(EventClass::NAME), class.context())?
+            .as_object()
+            .map(|constructor| constructor.get(PROTOTYPE, class.context()))
+            .transpose()?
+            .and_then(|prototype| prototype.as_object().cloned());
+
+        let detail = Self::detail(class.context());
+
+        class.inherit(event_prototype).accessor(
+            js_string!("detail"),
+            detail,
+            Attribute::all(),
+        );
+
+        Ok(())
+    }
+}
+
+/// Creates a new trusted event of the given `type_`, and dispatches it to `target`.
+///
+/// More information:
+///  - [WHATWG specification][spec]
+///
+/// [spec] https://dom.spec.whatwg.org/#concept-event-fire
+pub fn fire_event(
+    target: &JsObject,
+    type_: &str,
+    context: &mut Context,
+) -> JsResult<bool> {
+    let event =
+        JsNativeObject::new::<EventClass>(Event::new_trusted(type_, context), context)?;
+
+    dispatch_event(target, event.object(), context)
+}
+
+pub struct EventApi;
+
+impl jstz_core::Api for EventApi {
+    fn init(self, context: &mut Context) {
+        register_global_class::<EventClass>(context)
+            .expect("The `Event` class shouldn't exist yet");
+        register_global_class::<CustomEventClass>(context)
+            .expect("The `CustomEvent` class shouldn't exist yet");
+        EventTargetApi.init(context);
+    }
+}
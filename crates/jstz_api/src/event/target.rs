@@ -0,0 +1,488 @@
+use boa_engine::{
+    js_string, object::ErasedObject, value::TryFromJs, Context, JsArgs, JsData, JsError,
+    JsNativeError, JsObject, JsResult, JsValue, NativeFunction,
+};
+use boa_gc::{Finalize, GcRefMut, Trace};
+use jstz_core::native::{register_global_class, ClassBuilder, NativeClass};
+
+use crate::{
+    abort::AbortSignal,
+    idl::dom_exception,
+    js_log::{log, LogData, LogLevel},
+};
+
+use super::{phase, Event};
+
+#[derive(Trace, Finalize, Clone)]
+struct EventListener {
+    id: u64,
+    type_: String,
+    callback: Option<JsObject>,
+    capture: bool,
+    once: bool,
+    passive: bool,
+    // Whether the listener was registered by an event handler attribute
+    // (e.g. `signal.onabort = ...`)
+    attribute: bool,
+}
+
+#[derive(Default, Trace, Finalize, JsData)]
+pub struct EventTarget {
+    listeners: Vec<EventListener>,
+    next_id: u64,
+}
+
+/// The `AddEventListenerOptions` dictionary (or a boolean `capture` flag)
+///
+/// More information:
+///  - [WHATWG specification][spec]
+///
+/// [spec] https://dom.spec.whatwg.org/#dictdef-addeventlisteneroptions
+#[derive(Default)]
+pub struct EventListenerOptions {
+    pub capture: bool,
+    pub once: bool,
+    pub passive: bool,
+    pub signal: Option<JsObject>,
+}
+
+impl TryFromJs for EventListenerOptions {
+    fn try_from_js(value: &JsValue, context: &mut Context) -> JsResult<Self> {
+        let Some(obj) = value.as_object() else {
+            return Ok(Self {
+                capture: value.to_boolean(),
+                ..Self::default()
+            });
+        };
+
+        let capture = obj.get(js_string!("capture"), context)?.to_boolean();
+        let once = obj.get(js_string!("once"), context)?.to_boolean();
+        let passive = obj.get(js_string!("passive"), context)?.to_boolean();
+        let signal =
+            match obj.get(js_string!("signal"), context)? {
+                JsValue::Undefined => None,
+                JsValue::Object(signal) if signal.is::<AbortSignal>() => Some(signal),
+                _ => return Err(JsNativeError::typ()
+                    .with_message(
+                        "Failed to read the 'signal' property: Expected `AbortSignal`",
+                    )
+                    .into()),
+            };
+
+        Ok(Self {
+            capture,
+            once,
+            passive,
+            signal,
+        })
+    }
+}
+
+impl EventTarget {
+    /// Adds an event listener, returning `false` if an equivalent listener is
+    /// already registered.
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://dom.spec.whatwg.org/#add-an-event-listener
+    pub fn add_event_listener(
+        &mut self,
+        type_: String,
+        callback: JsObject,
+        options: &EventListenerOptions,
+    ) -> bool {
+        let exists = self.listeners.iter().any(|listener| {
+            !listener.attribute
+                && listener.type_ == type_
+                && listener.capture == options.capture
+                && listener
+                    .callback
+                    .as_ref()
+                    .is_some_and(|cb| JsObject::equals(cb, &callback))
+        });
+
+        if exists {
+            return false;
+        }
+
+        self.push(EventListener {
+            id: 0,
+            type_,
+            callback: Some(callback),
+            capture: options.capture,
+            once: options.once,
+            passive: options.passive,
+            attribute: false,
+        });
+
+        true
+    }
+
+    /// Removes an event listener.
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://dom.spec.whatwg.org/#remove-an-event-listener
+    pub fn remove_event_listener(
+        &mut self,
+        type_: &str,
+        callback: &JsObject,
+        capture: bool,
+    ) {
+        self.listeners.retain(|listener| {
+            listener.attribute
+                || listener.type_ != type_
+                || listener.capture != capture
+                || !listener
+                    .callback
+                    .as_ref()
+                    .is_some_and(|cb| JsObject::equals(cb, callback))
+        })
+    }
+
+    /// Returns the value of the event handler attribute for events of the given `type_`
+    /// (e.g. `onabort` for `"abort"`).
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://html.spec.whatwg.org/multipage/webappapis.html#event-handler-attributes
+    pub fn event_handler(&self, type_: &str) -> Option<JsObject> {
+        self.listeners
+            .iter()
+            .find(|listener| listener.attribute && listener.type_ == type_)
+            .and_then(|listener| listener.callback.clone())
+    }
+
+    /// Sets the value of an event handler attribute. The listener keeps the position
+    /// of the first time it was set.
+    pub fn set_event_handler(&mut self, type_: &str, callback: Option<JsObject>) {
+        match self
+            .listeners
+            .iter_mut()
+            .find(|listener| listener.attribute && listener.type_ == type_)
+        {
+            Some(listener) => listener.callback = callback,
+            None if callback.is_some() => self.push(EventListener {
+                id: 0,
+                type_: type_.to_string(),
+                callback,
+                capture: false,
+                once: false,
+                passive: false,
+                attribute: true,
+            }),
+            None => (),
+        }
+    }
+
+    fn push(&mut self, mut listener: EventListener) {
+        listener.id = self.next_id;
+        self.next_id += 1;
+        self.listeners.push(listener);
+    }
+
+    fn contains(&self, id: u64) -> bool {
+        self.listeners.iter().any(|listener| listener.id == id)
+    }
+
+    fn remove(&mut self, id: u64) {
+        self.listeners.retain(|listener| listener.id != id)
+    }
+
+    /// Downcasts a js value into an `EventTarget`, including instances of subclasses of
+    /// `EventTarget`
+    pub fn try_from_js(value: &JsValue) -> JsResult<GcRefMut<'_, ErasedObject, Self>> {
+        let obj =
+            value.as_object().ok_or_else(|| {
+                JsError::from_native(JsNativeError::typ().with_message(
+                    "Failed to convert js value into rust type `EventTarget`",
+                ))
+            })?;
+
+        if let Some(target) = obj.downcast_mut::<Self>() {
+            return Ok(target);
+        }
+
+        obj.downcast_mut::<AbortSignal>()
+            .map(|signal| GcRefMut::map(signal, |s| &mut s.event_target))
+            .ok_or_else(|| {
+                JsNativeError::typ()
+                    .with_message(
+                        "Failed to convert js value into rust type `EventTarget`",
+                    )
+                    .into()
+            })
+    }
+}
+
+/// Reports an exception thrown by a listener without interrupting the dispatch.
+/// Errors that cannot be caught (e.g. running out of gas) are propagated.
+///
+/// More information:
+///  - [WHATWG specification][spec]
+///
+/// [spec] https://html.spec.whatwg.org/multipage/webappapis.html#report-the-exception
+fn report_exception(err: JsError, context: &mut Context) -> JsResult<()> {
+    if let Some(native) = err.as_native() {
+        if native.is_no_instructions_remain() || native.is_runtime_limit() {
+            return Err(err);
+        }
+    }
+
+    log(
+        LogData {
+            level: LogLevel::ERROR,
+            text: format!("Uncaught {}", err),
+            groups_len: 0,
+        },
+        context,
+    )
+}
+
+fn call_listener(
+    callback: &JsObject,
+    target: &JsObject,
+    event: &JsObject,
+    context: &mut Context,
+) -> JsResult<JsValue> {
+    let args = [event.clone().into()];
+
+    if callback.is_callable() {
+        return callback.call(&target.clone().into(), &args, context);
+    }
+
+    let handle_event = callback.get(js_string!("handleEvent"), context)?;
+    match handle_event.as_callable() {
+        Some(handle_event) => handle_event.call(&callback.clone().into(), &args, context),
+        None => Err(JsNativeError::typ()
+            .with_message("'handleEvent' is not a function")
+            .into()),
+    }
+}
+
+/// Dispatches `event` to `target`, returning `false` if the event was canceled.
+///
+/// Listeners are invoked in registration order. Listeners that are added during the
+/// dispatch are not invoked, listeners that are removed are skipped.
+///
+/// More information:
+///  - [WHATWG specification][spec]
+///
+/// [spec] https://dom.spec.whatwg.org/#concept-event-dispatch
+pub fn dispatch_event(
+    target: &JsObject,
+    event: &JsObject,
+    context: &mut Context,
+) -> JsResult<bool> {
+    let target_value: JsValue = target.clone().into();
+    let event_value: JsValue = event.clone().into();
+
+    let type_ = {
+        let mut event = Event::try_from_js(&event_value)?;
+        if event.dispatch {
+            return Err(JsError::from_opaque(dom_exception(
+                "InvalidStateError",
+                "The event is already being dispatched.",
+                context,
+            )));
+        }
+
+        event.dispatch = true;
+        event.target = Some(target.clone());
+        event.current_target = Some(target.clone());
+        event.event_phase = phase::AT_TARGET;
+        event.type_.clone()
+    };
+
+    let listeners: Vec<EventListener> = EventTarget::try_from_js(&target_value)?
+        .listeners
+        .iter()
+        .filter(|listener| listener.type_ == type_)
+        .cloned()
+        .collect();
+
+    let mut result = Ok(());
+    for listener in listeners {
+        if Event::try_from_js(&event_value)?.stop_immediate_propagation {
+            break;
+        }
+
+        {
+            let mut event_target = EventTarget::try_from_js(&target_value)?;
+            if !event_target.contains(listener.id) {
+                continue;
+            }
+            if listener.once {
+                event_target.remove(listener.id);
+            }
+        }
+
+        let Some(callback) = listener.callback else {
+            continue;
+        };
+
+        Event::try_from_js(&event_value)?.in_passive_listener = listener.passive;
+        let call_result = call_listener(&callback, target, event, context);
+        Event::try_from_js(&event_value)?.in_passive_listener = false;
+
+        if let Err(err) = call_result {
+            if let Err(err) = report_exception(err, context) {
+                result = Err(err);
+                break;
+            }
+        }
+    }
+
+    let mut event = Event::try_from_js(&event_value)?;
+    event.event_phase = phase::NONE;
+    event.current_target = None;
+    event.dispatch = false;
+    event.stop_propagation = false;
+    event.stop_immediate_propagation = false;
+
+    result.map(|()| !event.canceled)
+}
+
+pub struct EventTargetClass;
+
+impl EventTargetClass {
+    fn add_event_listener(
+        this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let type_ = args
+            .get_or_undefined(0)
+            .to_string(context)?
+            .to_std_string_escaped();
+        let Some(callback) = args.get_or_undefined(1).as_object().cloned() else {
+            return Ok(JsValue::undefined());
+        };
+        let options: EventListenerOptions =
+            args.get_or_undefined(2).try_js_into(context)?;
+
+        // Listeners are never added with an already aborted signal
+        if let Some(signal) = &options.signal {
+            if signal
+                .downcast_ref::<AbortSignal>()
+                .is_some_and(|signal| signal.aborted())
+            {
+                return Ok(JsValue::undefined());
+            }
+        }
+
+        let added = EventTarget::try_from_js(this)?.add_event_listener(
+            type_.clone(),
+            callback.clone(),
+            &options,
+        );
+
+        // Remove the listener once the signal is aborted
+        if let (true, Some(signal)) = (added, options.signal) {
+            let target = this.clone();
+            let capture = options.capture;
+            let remove = NativeFunction::from_copy_closure_with_captures(
+                |_, _, (target, type_, callback, capture), _| {
+                    EventTarget::try_from_js(target)?
+                        .remove_event_listener(type_, callback, *capture);
+                    Ok(JsValue::undefined())
+                },
+                (target, type_, callback, capture),
+            );
+
+            AbortSignal::add_algorithm(&signal, remove.to_js_function(context.realm()))?;
+        }
+
+        Ok(JsValue::undefined())
+    }
+
+    fn remove_event_listener(
+        this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let type_ = args
+            .get_or_undefined(0)
+            .to_string(context)?
+            .to_std_string_escaped();
+        let Some(callback) = args.get_or_undefined(1).as_object() else {
+            return Ok(JsValue::undefined());
+        };
+        let options: EventListenerOptions =
+            args.get_or_undefined(2).try_js_into(context)?;
+
+        EventTarget::try_from_js(this)?.remove_event_listener(
+            &type_,
+            callback,
+            options.capture,
+        );
+
+        Ok(JsValue::undefined())
+    }
+
+    fn dispatch_event(
+        this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let target = this.as_object().ok_or_else(|| {
+            JsNativeError::typ()
+                .with_message("Failed to convert js value into rust type `EventTarget`")
+        })?;
+        // Ensure `this` is an event target before dispatching
+        drop(EventTarget::try_from_js(this)?);
+
+        let event = args.get_or_undefined(0);
+        drop(Event::try_from_js(event)?);
+        let event = event.as_object().expect("Event should be an object");
+
+        Ok(dispatch_event(target, event, context)?.into())
+    }
+}
+
+impl NativeClass for EventTargetClass {
+    type Instance = EventTarget;
+
+    const NAME: &'static str = "EventTarget";
+
+    fn data_constructor(
+        _target: &JsValue,
+        _args: &[JsValue],
+        _context: &mut Context,
+    ) -> JsResult<Self::Instance> {
+        Ok(EventTarget::default())
+    }
+
+    fn init(class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        class
+            .method(
+                js_string!("addEventListener"),
+                2,
+                NativeFunction::from_fn_ptr(Self::add_event_listener),
+            )
+            .method(
+                js_string!("removeEventListener"),
+                2,
+                NativeFunction::from_fn_ptr(Self::remove_event_listener),
+            )
+            .method(
+                js_string!("dispatchEvent"),
+                1,
+                NativeFunction::from_fn_ptr(Self::dispatch_event),
+            );
+
+        Ok(())
+    }
+}
+
+pub(super) struct EventTargetApi;
+
+impl jstz_core::Api for EventTargetApi {
+    fn init(self, context: &mut Context) {
+        register_global_class::<EventTargetClass>(context)
+            .expect("The `EventTarget` class shouldn't exist yet");
+    }
+}
@@ -69,13 +69,16 @@ impl Inner {
 #[derive(Trace, Finalize, Clone)]
 pub struct Body {
     inner: Option<Inner>,
+    // Whether the body has been consumed. Distinguishes a used body
+    // from a null one.
+    used: bool,
 }
 
 impl Body {
     pub fn from_http_body(body: HttpBody, _context: &mut Context) -> JsResult<Self> {
         let inner = body.map(Inner::Bytes);
 
-        Ok(Self { inner })
+        Ok(Self { inner, used: false })
     }
 
     pub fn to_http_body(&self) -> HttpBody {
@@ -88,13 +91,19 @@ impl Body {
 
 impl Body {
     fn new(inner: Inner) -> Self {
-        Self { inner: Some(inner) }
+        Self {
+            inner: Some(inner),
+            used: false,
+        }
     }
 
     fn inner(&mut self) -> JsResult<Inner> {
         // Consumes the body
         match self.inner.take() {
-            Some(inner) => Ok(inner),
+            Some(inner) => {
+                self.used = true;
+                Ok(inner)
+            }
             None => Err(JsError::from_native(
                 JsNativeError::typ().with_message("Body is null or has been used"),
             )),
@@ -103,7 +112,10 @@ impl Body {
 
     /// Returns a `null` body
     pub fn null() -> Self {
-        Self { inner: None }
+        Self {
+            inner: None,
+            used: false,
+        }
     }
 
     /// Returns whether the body has been read from.
@@ -116,11 +128,23 @@ impl Body {
         // 1. Return true if this’s `body` is non-null and this’s
         //    body’s stream is disturbed; otherwise false.
         // FIXME: Support streams
-        self.inner.is_none()
+        self.used
     }
 
     pub fn is_null(&self) -> bool {
-        self.is_used()
+        self.inner.is_none() && !self.used
+    }
+
+    /// Returns a copy of the body that can be consumed independently.
+    ///
+    /// Bodies are fully buffered, so teeing a body amounts to copying it.
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://fetch.spec.whatwg.org/#concept-body-clone
+    pub fn tee(&self) -> Self {
+        self.clone()
     }
 
     /// Returns a promise fulfilled with body's content as an ArrayBuffer
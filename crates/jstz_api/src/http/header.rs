@@ -3,7 +3,8 @@
 //! Represents response/request headers, allowing you to query them and take different
 //! actions depending on the results in `jstz` smart functions
 //!
-//! FIXME: This is not spec compliant
+//! Since `jstz` is a server runtime, forbidden request and response header names
+//! are not filtered. Only the `"immutable"` and `"none"` guards are supported.
 //!
 //! More information:
 //!  - [MDN documentation][mdn]
@@ -12,7 +13,7 @@
 //! [mdn]: https://developer.mozilla.org/en-US/docs/Web/API/Headers
 //! [spec]: https://fetch.spec.whatwg.org/#headers-class
 
-use std::{cell::RefCell, collections::BTreeMap, ops::DerefMut};
+use std::cell::{Ref, RefCell};
 
 use boa_engine::{
     builtins, js_string,
@@ -23,48 +24,109 @@ use boa_engine::{
 };
 use boa_gc::{empty_trace, Finalize, GcRefMut, Trace};
 use derive_more::Deref;
-use http::{header::Entry, HeaderMap, HeaderName, HeaderValue};
+use http::{HeaderMap, HeaderName, HeaderValue};
 use jstz_core::{
     iterators::{PairIterable, PairIterableMethods, PairIteratorClass, PairValue},
     native::{register_global_class, ClassBuilder, NativeClass},
     value::IntoJs,
 };
-#[derive(Default, Clone, Deref, JsData)]
+
+/// A headers guard
+///
+/// More information:
+///  - [WHATWG specification][spec]
+///
+/// [spec] https://fetch.spec.whatwg.org/#headers-guard
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadersGuard {
+    #[default]
+    None,
+    Immutable,
+}
+
+#[derive(Default, Clone, JsData)]
 pub struct Headers {
-    // TODO probably don't need Deref? It exposes HeaderMap impl and
-    // probably shouldn't
-    // NOT implementing DerefMut because mutators would need to also
-    // clear the cache
-    #[deref]
-    headers: HeaderMap,
+    // The header list. Names are lowercased and values are byte sequences.
+    //
+    // See: https://fetch.spec.whatwg.org/#concept-header-list
+    list: Vec<(String, Vec<u8>)>,
+    guard: HeadersGuard,
     // Cached sorted and combined list of header entries for iteration
     cached_iteration: RefCell<Option<Vec<(String, String)>>>,
 }
 
-// Sort and combine header entries, see:
-// https://fetch.spec.whatwg.org/#concept-header-list-sort-and-combine
-fn sort_and_combine_headers(headers: &HeaderMap) -> JsResult<Vec<(String, String)>> {
-    // collect header entries into a BTreeMap to sort by header name
-    let mut map: BTreeMap<String, Vec<String>> = BTreeMap::new();
+/// Isomorphic decodes a byte sequence
+///
+/// See: https://infra.spec.whatwg.org/#isomorphic-decode
+fn isomorphic_decode(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
 
-    for key in headers.keys() {
-        let vals = Header::try_from_iter(headers.get_all(key))?;
-        map.insert(key.to_string(), vals.headers);
-    }
+/// Isomorphic encodes a string, which must only contain code points less than
+/// or equal to U+00FF
+///
+/// See: https://infra.spec.whatwg.org/#isomorphic-encode
+fn isomorphic_encode(string: &str) -> Vec<u8> {
+    string.chars().map(|c| c as u8).collect()
+}
 
-    // combine headers except for set-cookie
-    let mut entries: Vec<(String, String)> = Vec::default();
-    for (key, vals) in map.into_iter() {
-        if key == "set-cookie" {
-            for val in vals {
-                entries.push((key.clone(), val));
+// Sort and combine header entries, see:
+// https://fetch.spec.whatwg.org/#concept-header-list-sort-and-combine
+fn sort_and_combine_headers(list: &[(String, Vec<u8>)]) -> Vec<(String, String)> {
+    // 1. Let headers be an empty list of headers with the key being the name and value the value.
+    let mut headers: Vec<(String, String)> = Vec::new();
+
+    // 2. Let names be the result of convert header names to a sorted-lowercase set
+    //    with all the names of the headers in list.
+    let mut names: Vec<&str> = list.iter().map(|(name, _)| name.as_str()).collect();
+    names.sort_unstable();
+    names.dedup();
+
+    // 3. For each name of names:
+    for name in names {
+        // 1. If name is `set-cookie`, then:
+        if name == "set-cookie" {
+            // 1. Let values be a list of all values of headers in list whose name
+            //    is a byte-case-insensitive match for name, in order.
+            // 2. For each value of values:
+            //     1. Append (name, value) to headers.
+            for (_, value) in list.iter().filter(|(n, _)| n == name) {
+                headers.push((name.to_string(), isomorphic_decode(value)));
+            }
+        }
+        // 2. Otherwise:
+        else {
+            // 1. Let value be the result of getting name from list.
+            // 2. Assert: value is non-null.
+            // 3. Append (name, value) to headers.
+            if let Some(value) = get_combined(list, name) {
+                headers.push((name.to_string(), value));
             }
-        } else {
-            entries.push((key.clone(), vals.join(", ")))
         }
     }
 
-    Ok(entries)
+    // 4. Return headers.
+    headers
+}
+
+// Gets a (lowercased) name from a header list, see:
+// https://fetch.spec.whatwg.org/#concept-header-list-get
+fn get_combined(list: &[(String, Vec<u8>)], name: &str) -> Option<String> {
+    // 1. If list does not contain name, then return null.
+    // 2. Return the values of all headers in list whose name is a
+    //    byte-case-insensitive match for name, separated from each other by
+    //    0x2C 0x20, in order.
+    let values: Vec<String> = list
+        .iter()
+        .filter(|(n, _)| n == name)
+        .map(|(_, value)| isomorphic_decode(value))
+        .collect();
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.join(", "))
+    }
 }
 
 impl Headers {
@@ -72,14 +134,39 @@ impl Headers {
         headers: http::HeaderMap,
         _context: &mut Context,
     ) -> JsResult<Self> {
-        Ok(Self {
-            headers,
-            cached_iteration: RefCell::default(),
-        })
+        Ok(Self::from(headers))
+    }
+
+    /// Converts the header list into a `HeaderMap`. Fails if a header value
+    /// contains bytes that cannot be represented in an HTTP/1.1 message
+    /// (e.g. control characters).
+    pub fn to_http_headers(&self) -> JsResult<http::HeaderMap> {
+        let mut headers = HeaderMap::with_capacity(self.list.len());
+        for (name, value) in &self.list {
+            let value = HeaderValue::from_bytes(value).map_err(|_| {
+                JsError::from_native(JsNativeError::typ().with_message(format!(
+                    "Header value for '{name}' cannot be represented as an HTTP header"
+                )))
+            })?;
+            headers.append(str_to_header_name(name)?, value);
+        }
+        Ok(headers)
     }
 
-    pub fn to_http_headers(&self) -> http::HeaderMap {
-        self.headers.clone()
+    /// Returns the header list as (lowercased) name and value pairs, in insertion
+    /// order
+    pub fn entries(&self) -> impl Iterator<Item = (&str, String)> {
+        self.list
+            .iter()
+            .map(|(name, value)| (name.as_str(), isomorphic_decode(value)))
+    }
+
+    pub fn guard(&self) -> HeadersGuard {
+        self.guard
+    }
+
+    pub fn set_guard(&mut self, guard: HeadersGuard) {
+        self.guard = guard;
     }
 
     // clear cached iteration vector, should be called whenever we
@@ -89,17 +176,44 @@ impl Headers {
         *cached_iteration = None;
     }
 
-    // get (or rebuild) cached iteration vector
-    fn get_cached_iteration(&self) -> JsResult<Vec<(String, String)>> {
-        let mut cached_iteration = self.cached_iteration.borrow_mut();
-        match cached_iteration.deref_mut() {
-            Some(iterable) => Ok(iterable.clone()),
-            None => {
-                let iterable = sort_and_combine_headers(&self.headers)?;
-                *cached_iteration = Some(iterable.clone());
-                Ok(iterable)
-            }
+    // get (or rebuild) cached iteration vector, which is borrowed rather than
+    // cloned so that iterating over the headers is linear
+    fn get_cached_iteration(&self) -> Ref<'_, Vec<(String, String)>> {
+        if self.cached_iteration.borrow().is_none() {
+            *self.cached_iteration.borrow_mut() =
+                Some(sort_and_combine_headers(&self.list));
         }
+        Ref::map(self.cached_iteration.borrow(), |cached_iteration| {
+            cached_iteration
+                .as_ref()
+                .expect("Cached iteration should have been built")
+        })
+    }
+
+    // https://fetch.spec.whatwg.org/#headers-validate
+    fn validate(&self, name: &str, value: &str) -> JsResult<()> {
+        // 1. If name is not a header name or value is not a header value, then
+        //    throw a TypeError.
+        if !is_header_name(name) {
+            return Err(JsNativeError::typ()
+                .with_message(format!("Invalid header name: '{name}'"))
+                .into());
+        }
+        if !is_header_value(value) {
+            return Err(JsNativeError::typ()
+                .with_message(format!("Invalid header value for '{name}'"))
+                .into());
+        }
+
+        // 2. If headers’s guard is "immutable", then throw a TypeError.
+        if self.guard == HeadersGuard::Immutable {
+            return Err(JsNativeError::typ()
+                .with_message("Headers are immutable")
+                .into());
+        }
+
+        // 3-5. Forbidden request and response header names are not filtered.
+        Ok(())
     }
 }
 
@@ -111,45 +225,19 @@ unsafe impl Trace for Headers {
 
 impl From<HeaderMap> for Headers {
     fn from(headers: HeaderMap) -> Self {
+        let list = headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.as_bytes().to_vec()))
+            .collect();
+
         Self {
-            headers,
+            list,
+            guard: HeadersGuard::None,
             cached_iteration: RefCell::default(),
         }
     }
 }
 
-// A collection of header values
-pub struct Header {
-    pub headers: Vec<String>,
-}
-
-impl Header {
-    pub fn try_from_iter<'a, T>(iter: T) -> JsResult<Self>
-    where
-        T: IntoIterator<Item = &'a HeaderValue>,
-    {
-        let headers = iter
-            .into_iter()
-            .map(|header_value| header_value.to_str().map(|x| x.into()))
-            .collect::<Result<Vec<String>, http::header::ToStrError>>()
-            .map_err(|_| {
-                JsError::from_native(JsNativeError::typ().with_message(
-                    "Failed to convert header value to printable ascii string",
-                ))
-            })?;
-        Ok(Header { headers })
-    }
-}
-
-impl IntoJs for Header {
-    fn into_js(self, context: &mut Context) -> JsValue {
-        if self.headers.is_empty() {
-            return JsValue::null();
-        }
-        self.headers.join(", ").into_js(context)
-    }
-}
-
 impl Headers {
     /// Creates a new Headers object
     pub fn new() -> Self {
@@ -162,11 +250,22 @@ impl Headers {
     /// More information:
     ///  - [WHATWG specification][spec]
     ///
-    /// [spec] https://fetch.spec.whatwg.org/#dom-headers-append
+    /// [spec] https://fetch.spec.whatwg.org/#concept-headers-append
     pub fn append(&mut self, name: &str, value: &str) -> JsResult<()> {
+        // 1. Normalize value.
+        let value = normalize_header_value(value);
+
+        // 2. If validating (name, value) for headers returns false, then return.
+        self.validate(name, value)?;
+
+        // 3-4. (Guard is never "request-no-cors")
+
+        // 5. Append (name, value) to headers’s header list.
         self.clear_cached_iteration();
-        self.headers
-            .append(str_to_header_name(name)?, str_to_header_value(value)?);
+        self.list
+            .push((name.to_ascii_lowercase(), isomorphic_encode(value)));
+
+        // 6. (Guard is never "request-no-cors")
         Ok(())
     }
 
@@ -177,15 +276,22 @@ impl Headers {
     ///
     /// [spec] https://fetch.spec.whatwg.org/#dom-headers-delete
     pub fn remove(&mut self, name: &str) -> JsResult<()> {
-        self.clear_cached_iteration();
-        let name = str_to_header_name(name)?;
-        match self.headers.entry(name) {
-            Entry::Occupied(entry) => {
-                entry.remove_entry_mult();
-                Ok(())
-            }
-            Entry::Vacant(_) => Ok(()),
+        // 1. If validating (name, ``) for this returns false, then return.
+        self.validate(name, "")?;
+
+        // 2-3. (Guard is never "request-no-cors")
+
+        // 4. If this’s header list does not contain name, then return.
+        // 5. Delete name from this’s header list.
+        let name = name.to_ascii_lowercase();
+        if !self.list.iter().any(|(n, _)| *n == name) {
+            return Ok(());
         }
+        self.clear_cached_iteration();
+        self.list.retain(|(n, _)| *n != name);
+
+        // 6. (Guard is never "request-no-cors")
+        Ok(())
     }
 
     /// Returns a String of all the values of a header within a Headers object with a given name.
@@ -194,15 +300,30 @@ impl Headers {
     ///  - [WHATWG specification][spec]
     ///
     /// [spec] https://fetch.spec.whatwg.org/#dom-headers-get
-    pub fn get(&self, name: &str) -> JsResult<Header> {
-        let name = str_to_header_name(name)?;
-        let headers = Header::try_from_iter(self.headers.get_all(name))?;
-        Ok(headers)
+    pub fn get(&self, name: &str) -> JsResult<Option<String>> {
+        // 1. If name is not a header name, then throw a TypeError.
+        check_header_name(name)?;
+
+        // 2. Return the result of getting name from this’s header list.
+        Ok(get_combined(&self.list, &name.to_ascii_lowercase()))
     }
 
-    pub fn get_set_cookie(&self) -> JsResult<Vec<String>> {
-        let headers = Header::try_from_iter(self.headers.get_all("set-cookie"))?;
-        Ok(headers.headers)
+    /// Returns an array containing the values of all `Set-Cookie` headers
+    /// associated with a response.
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://fetch.spec.whatwg.org/#dom-headers-getsetcookie
+    pub fn get_set_cookie(&self) -> Vec<String> {
+        // 1. If this’s header list does not contain `Set-Cookie`, then return « ».
+        // 2. Return the values of all headers in this’s header list whose name is a
+        //    byte-case-insensitive match for `Set-Cookie`, in order.
+        self.list
+            .iter()
+            .filter(|(name, _)| name == "set-cookie")
+            .map(|(_, value)| isomorphic_decode(value))
+            .collect()
     }
 
     /// Returns a boolean stating whether a Headers object contains a certain header.
@@ -212,8 +333,12 @@ impl Headers {
     ///
     /// [spec] https://fetch.spec.whatwg.org/#dom-headers-has
     pub fn contains(&self, name: &str) -> JsResult<bool> {
-        let name = str_to_header_name(name)?;
-        Ok(self.headers.contains_key(&name))
+        // 1. If name is not a header name, then throw a TypeError.
+        check_header_name(name)?;
+
+        // 2. Return true if this’s header list contains name; otherwise false.
+        let name = name.to_ascii_lowercase();
+        Ok(self.list.iter().any(|(n, _)| *n == name))
     }
 
     /// Sets a new value for an existing header inside a Headers object, or adds the header if it does not already exist.
@@ -223,10 +348,34 @@ impl Headers {
     ///
     /// [spec] https://fetch.spec.whatwg.org/#dom-headers-set
     pub fn set(&mut self, name: &str, value: &str) -> JsResult<()> {
+        // 1. Normalize value.
+        let value = normalize_header_value(value);
+
+        // 2. If validating (name, value) for this returns false, then return.
+        self.validate(name, value)?;
+
+        // 3. (Guard is never "request-no-cors")
+
+        // 4. Set (name, value) in this’s header list:
+        //    If list contains name, then set the value of the first such header to
+        //    value and remove the others. Otherwise, append (name, value) to list.
+        let name = name.to_ascii_lowercase();
+        let value = isomorphic_encode(value);
         self.clear_cached_iteration();
-        let name = str_to_header_name(name)?;
-        let value = str_to_header_value(value)?;
-        self.headers.insert(name, value);
+        match self.list.iter().position(|(n, _)| *n == name) {
+            Some(index) => {
+                self.list[index].1 = value;
+                let mut i = 0;
+                self.list.retain(|(n, _)| {
+                    let keep = *n != name || i == index;
+                    i += 1;
+                    keep
+                });
+            }
+            None => self.list.push((name, value)),
+        }
+
+        // 5. (Guard is never "request-no-cors")
         Ok(())
     }
 }
@@ -267,29 +416,25 @@ fn require_args(
     Ok(())
 }
 
+/// A WebIDL `ByteString`, i.e. a string whose code points are all less than or
+/// equal to U+00FF
+///
+/// See: https://webidl.spec.whatwg.org/#es-ByteString
 #[derive(Deref)]
-struct ByteString(String);
+pub(crate) struct ByteString(pub String);
 
 impl TryFromJs for ByteString {
     fn try_from_js(value: &JsValue, context: &mut Context) -> JsResult<Self> {
         let s: JsString = value.to_string(context)?;
-        for c in s.iter() {
-            if c >= 256 {
-                return Err(JsNativeError::typ().with_message("invalid ByteString"))?;
-            }
+        if s.iter().any(|c| c > 0xFF) {
+            return Err(JsNativeError::typ().with_message("invalid ByteString"))?;
         }
-        let string = s.to_std_string().ok().ok_or::<JsError>(
-            JsNativeError::typ()
-                .with_message("error converting value to String")
-                .into(),
-        )?;
+        // Code units <= 0xFF are never surrogates, so this is lossless
+        let string = s.iter().map(|c| char::from(c as u8)).collect();
         Ok(ByteString(string))
     }
 }
 
-#[derive(Deref)]
-struct HeaderVal(String);
-
 const HTTP_WHITESPACE: &[char] = &[
     '\u{09}', // HT
     '\u{20}', // SP
@@ -297,17 +442,55 @@ const HTTP_WHITESPACE: &[char] = &[
     '\u{0D}', // CR
 ];
 
+// https://fetch.spec.whatwg.org/#concept-header-value-normalize
 fn normalize_header_value(string: &str) -> &str {
     string
         .trim_start_matches(HTTP_WHITESPACE)
         .trim_end_matches(HTTP_WHITESPACE)
 }
 
-impl TryFromJs for HeaderVal {
-    fn try_from_js(value: &JsValue, context: &mut Context) -> JsResult<Self> {
-        let ByteString(string) = value.try_js_into(context)?;
-        let string = normalize_header_value(string.as_str()).to_string();
-        Ok(HeaderVal(string))
+// https://fetch.spec.whatwg.org/#header-name
+fn is_header_name(name: &str) -> bool {
+    // A header name is a byte sequence that matches the field-name token production.
+    !name.is_empty()
+        && name.chars().all(|c| {
+            c.is_ascii_alphanumeric()
+                || matches!(
+                    c,
+                    '!' | '#'
+                        | '$'
+                        | '%'
+                        | '&'
+                        | '\''
+                        | '*'
+                        | '+'
+                        | '-'
+                        | '.'
+                        | '^'
+                        | '_'
+                        | '`'
+                        | '|'
+                        | '~'
+                )
+        })
+}
+
+// https://fetch.spec.whatwg.org/#header-value
+fn is_header_value(value: &str) -> bool {
+    // A header value is a byte sequence that matches the following conditions:
+    //  - Has no leading or trailing HTTP tab or space bytes.
+    //  - Contains no 0x00 (NUL) or HTTP newline bytes.
+    let trimmed = value.trim_matches(&['\u{09}', '\u{20}'][..]);
+    trimmed.len() == value.len() && !value.contains(['\u{00}', '\u{0A}', '\u{0D}'])
+}
+
+fn check_header_name(name: &str) -> JsResult<()> {
+    if is_header_name(name) {
+        Ok(())
+    } else {
+        Err(JsNativeError::typ()
+            .with_message(format!("Invalid header name: '{name}'"))
+            .into())
     }
 }
 
@@ -318,9 +501,9 @@ impl HeadersClass {
         context: &mut Context,
     ) -> JsResult<JsValue> {
         require_args(args, 2, "append", "Headers")?;
-        let mut headers = Headers::try_from_js(this)?;
         let name: ByteString = args.get_or_undefined(0).try_js_into(context)?;
-        let value: HeaderVal = args.get_or_undefined(1).try_js_into(context)?;
+        let value: ByteString = args.get_or_undefined(1).try_js_into(context)?;
+        let mut headers = Headers::try_from_js(this)?;
 
         headers.append(&name, &value)?;
 
@@ -333,8 +516,8 @@ impl HeadersClass {
         context: &mut Context,
     ) -> JsResult<JsValue> {
         require_args(args, 1, "delete", "Headers")?;
-        let mut headers = Headers::try_from_js(this)?;
         let name: ByteString = args.get_or_undefined(0).try_js_into(context)?;
+        let mut headers = Headers::try_from_js(this)?;
 
         headers.remove(&name)?;
 
@@ -343,8 +526,8 @@ impl HeadersClass {
 
     fn get(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
         require_args(args, 1, "get", "Headers")?;
-        let headers = Headers::try_from_js(this)?;
         let name: ByteString = args.get_or_undefined(0).try_js_into(context)?;
+        let headers = Headers::try_from_js(this)?;
 
         Ok(headers.get(&name)?.into_js(context))
     }
@@ -355,22 +538,22 @@ impl HeadersClass {
         context: &mut Context,
     ) -> JsResult<JsValue> {
         let headers = Headers::try_from_js(this)?;
-        Ok(headers.get_set_cookie()?.into_js(context))
+        Ok(headers.get_set_cookie().into_js(context))
     }
 
     fn has(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
         require_args(args, 1, "has", "Headers")?;
-        let headers = Headers::try_from_js(this)?;
         let name: ByteString = args.get_or_undefined(0).try_js_into(context)?;
+        let headers = Headers::try_from_js(this)?;
 
         Ok(headers.contains(&name)?.into())
     }
 
     fn set(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
         require_args(args, 2, "set", "Headers")?;
-        let mut headers = Headers::try_from_js(this)?;
         let name: ByteString = args.get_or_undefined(0).try_js_into(context)?;
-        let value: HeaderVal = args.get_or_undefined(1).try_js_into(context)?;
+        let value: ByteString = args.get_or_undefined(1).try_js_into(context)?;
+        let mut headers = Headers::try_from_js(this)?;
 
         headers.set(&name, &value)?;
 
@@ -384,96 +567,133 @@ pub struct HeaderEntry {
     value: String,
 }
 
+/// Creates a sequence from an iterable, returning `None` if `value` is not
+/// iterable
+///
+/// See: https://webidl.spec.whatwg.org/#create-sequence-from-iterable
+fn iterable_to_sequence(
+    obj: &JsObject,
+    context: &mut Context,
+) -> JsResult<Option<Vec<JsValue>>> {
+    // 1. Let method be ? GetMethod(V, @@iterator).
+    let method = obj.get(JsSymbol::iterator(), context)?;
+    if method.is_null_or_undefined() {
+        return Ok(None);
+    }
+    let method = method.as_callable().ok_or_else(|| {
+        JsError::from_native(
+            JsNativeError::typ().with_message("Symbol.iterator is not a function"),
+        )
+    })?;
+
+    // 2. Let iteratorRecord be ? GetIteratorFromMethod(V, method).
+    let iterator = method.call(&obj.clone().into(), &[], context)?;
+    let iterator = iterator.as_object().ok_or_else(|| {
+        JsError::from_native(
+            JsNativeError::typ().with_message("Returned iterator is not an object"),
+        )
+    })?;
+    let next = iterator.get(js_string!("next"), context)?;
+
+    // 3. Initialize i to be 0.
+    // 4. Repeat
+    let mut sequence = Vec::new();
+    loop {
+        // 1. Let next be ? IteratorStepValue(iteratorRecord).
+        let result = next.call(&iterator.clone().into(), &[], context)?;
+        let result = result.as_object().ok_or_else(|| {
+            JsError::from_native(
+                JsNativeError::typ().with_message("Iterator result is not an object"),
+            )
+        })?;
+
+        // 2. If next is done, then return an IDL sequence value of type
+        //    sequence<T> of length i.
+        if result.get(js_string!("done"), context)?.to_boolean() {
+            return Ok(Some(sequence));
+        }
+
+        // 3. Initialize Si to the result of converting next to an IDL value of type T.
+        // 4. Set i to i + 1.
+        sequence.push(result.get(js_string!("value"), context)?);
+    }
+}
+
 impl TryFromJs for HeaderEntry {
     fn try_from_js(value: &JsValue, context: &mut Context) -> JsResult<Self> {
-        let obj = value.as_object()
-	    .ok_or(JsNativeError::typ()
-		   .with_message("Failed to construct 'Headers': The provided value cannot be converted to a sequence."))?;
-        let arr: JsArray = iterable_to_sequence(obj, context)?.try_js_into(context)?;
+        let not_a_sequence = || -> JsError {
+            JsNativeError::typ()
+                .with_message("Failed to construct 'Headers': The provided value cannot be converted to a sequence.")
+                .into()
+        };
+
+        let obj = value.as_object().ok_or_else(not_a_sequence)?;
+        let sequence = iterable_to_sequence(obj, context)?.ok_or_else(not_a_sequence)?;
 
-        if arr.length(context)? != 2 {
+        let [name, value] = sequence.as_slice() else {
             return Err(JsNativeError::typ().with_message(
                 "Failed to construct 'Headers': sequence element with length not 2",
             ))?;
-        }
+        };
 
-        let name: ByteString = arr.get(0, context)?.try_js_into(context)?;
-        let value: HeaderVal = arr.get(1, context)?.try_js_into(context)?;
-        let name: String = name.to_string();
-        let value: String = value.to_string();
+        let ByteString(name) = name.try_js_into(context)?;
+        let ByteString(value) = value.try_js_into(context)?;
 
         Ok(Self { name, value })
     }
 }
 
-fn get_iterator(obj: &JsObject, context: &mut Context) -> JsResult<JsValue> {
-    // TODO workaround until JsSymbol::iterator() is pub
-    let symbol_iterator: JsSymbol = context
-        .intrinsics()
-        .constructors()
-        .symbol()
-        .constructor()
-        .get(js_string!("iterator"), context)?
-        .as_symbol()
-        .ok_or(JsNativeError::typ().with_message("Symbol.iterator was not a Symbol?"))?;
-    obj.get(symbol_iterator, context)
-}
+/// Converts an object to a `record<ByteString, ByteString>`
+///
+/// See: https://webidl.spec.whatwg.org/#es-record
+fn object_to_record(obj: &JsObject, context: &mut Context) -> JsResult<Vec<HeaderEntry>> {
+    // 1-3. Let result be a new empty instance of record<K, V>.
+    let mut result = Vec::new();
 
-// TODO this is not spec compliant, because it doesn't observe the
-// object in the precisely the right way.
-//
-// Should do: https://webidl.spec.whatwg.org/#es-sequence
-// for HeadersInit in particular we have sequence<sequence<ByteString>>
-//
-// TODO: using Array.from as a hack, expose something in boa
-fn iterable_to_sequence(obj: &JsObject, context: &mut Context) -> JsResult<JsValue> {
-    let array_from_obj = context
+    // 4. Let keys be ? O.[[OwnPropertyKeys]]().
+    let own_keys = context
         .intrinsics()
-        .constructors()
-        .array()
-        .constructor()
-        .get(js_string!("from"), context)?;
-    let array_from: &JsObject = array_from_obj.as_callable().ok_or::<JsError>(
-        JsNativeError::typ()
-            .with_message("Array.from was not callable")
-            .into(),
-    )?;
-
-    if get_iterator(obj, context)?.is_callable() {
-        array_from.call(&JsValue::null(), &[obj.clone().into()], context)
-    } else {
-        Err(JsNativeError::typ()
-            .with_message("expected iterable to convert to sequence")
-            .into())
-    }
-}
-
-fn js_array_to_header_entries(
-    obj: &JsObject,
-    context: &mut Context,
-) -> JsResult<Vec<HeaderEntry>> {
-    let arr = JsArray::from_object(obj.clone())?;
+        .objects()
+        .reflect()
+        .get(js_string!("ownKeys"), context)?;
+    let keys = own_keys.call(&JsValue::undefined(), &[obj.clone().into()], context)?;
+    let keys = JsArray::from_object(keys.to_object(context)?)?;
+
+    // 5. For each key of keys:
+    for i in 0..keys.length(context)? {
+        let key = keys.get(i, context)?;
+
+        // 1. Let desc be ? O.[[GetOwnProperty]](key).
+        let desc = builtins::object::OrdinaryObject::get_own_property_descriptor(
+            &JsValue::undefined(),
+            &[obj.clone().into(), key.clone()],
+            context,
+        )?;
 
-    let mut vec = vec![];
+        // 2. If desc is not undefined and desc.[[Enumerable]] is true:
+        let enumerable = match desc.as_object() {
+            Some(desc) => desc.get(js_string!("enumerable"), context)?.to_boolean(),
+            None => false,
+        };
+        if !enumerable {
+            continue;
+        }
 
-    let length = arr.length(context)?;
-    for i in 0..length {
-        vec.push(HeaderEntry::try_from_js(&arr.get(i, context)?, context)?)
-    }
+        // 1. Let typedKey be key converted to an IDL value of type K.
+        let ByteString(name) = key.try_js_into(context)?;
 
-    Ok(vec)
-}
+        // 2. Let value be ? Get(O, key).
+        // 3. Let typedValue be value converted to an IDL value of type V.
+        let ByteString(value) = obj
+            .get(key.to_property_key(context)?, context)?
+            .try_js_into(context)?;
 
-fn str_to_header_name(str: &str) -> JsResult<HeaderName> {
-    HeaderName::try_from(&str.to_ascii_lowercase()).map_err(|_| {
-        JsError::from_native(JsNativeError::typ().with_message("Invalid header name"))
-    })
-}
+        // 4. Set result[typedKey] to typedValue.
+        result.push(HeaderEntry { name, value });
+    }
 
-fn str_to_header_value(str: &str) -> JsResult<HeaderValue> {
-    HeaderValue::try_from(str).map_err(|_| {
-        JsError::from_native(JsNativeError::typ().with_message("Invalid header value"))
-    })
+    // 6. Return result.
+    Ok(result)
 }
 
 /// The `HeadersInit` enum
@@ -488,6 +708,12 @@ pub struct HeadersInit {
 }
 
 impl Headers {
+    /// Fills a new Headers object with `init`
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://fetch.spec.whatwg.org/#concept-headers-fill
     pub fn from_init(init: HeadersInit) -> JsResult<Headers> {
         let mut headers = Headers::default();
         for entry in init.entries {
@@ -510,31 +736,14 @@ impl TryFromJs for HeadersInit {
             )
         })?;
 
-        // if it looks iterable, convert to sequence<sequence<ByteString>>
-        let entries = if get_iterator(obj, context)?.is_callable() {
-            let entries_obj = iterable_to_sequence(obj, context)?;
-            let entries_array = entries_obj
-                .as_object()
-                .ok_or(JsNativeError::typ().with_message(
-                "Internal error in Headers constructor: expected array from `Array.from`",
-            ))?;
-            js_array_to_header_entries(entries_array, context)?
-        } else {
-            // TODO: Expose `enumerable_own_property_names` in Boa
-            // TODO: also should throw if there are any own property
-            // symbols, because we should try to ToString them
-            let arr = builtins::object::OrdinaryObject::entries(
-                &JsValue::undefined(),
-                &[value.clone()],
-                context,
-            )?
-            .to_object(context)
-            .ok()
-            .ok_or(
-                JsNativeError::typ().with_message("Expected array from `Object.entries`"),
-            )?;
-
-            js_array_to_header_entries(&arr, context)?
+        // If it is iterable, convert to sequence<sequence<ByteString>>, otherwise
+        // convert to record<ByteString, ByteString>
+        let entries = match iterable_to_sequence(obj, context)? {
+            Some(sequence) => sequence
+                .iter()
+                .map(|entry| HeaderEntry::try_from_js(entry, context))
+                .collect::<JsResult<_>>()?,
+            None => object_to_record(obj, context)?,
         };
 
         Ok(HeadersInit { entries })
@@ -599,7 +808,7 @@ impl NativeClass for HeadersClass {
 
 impl PairIterable for Headers {
     fn pair_iterable_len(&self) -> JsResult<usize> {
-        Ok(self.get_cached_iteration()?.len())
+        Ok(self.get_cached_iteration().len())
     }
 
     fn pair_iterable_get(
@@ -607,12 +816,11 @@ impl PairIterable for Headers {
         index: usize,
         context: &mut Context,
     ) -> JsResult<jstz_core::iterators::PairValue> {
-        let cached_iteration = self.get_cached_iteration()?;
-        match cached_iteration.get(index) {
+        let elem = self.get_cached_iteration().get(index).cloned();
+        match elem {
             None => Err(JsNativeError::typ()
                 .with_message("Index out of bounds error in iterator"))?,
             Some(elem) => {
-                let elem = elem.clone();
                 let key: JsValue = elem.0.into_js(context);
                 let value: JsValue = elem.1.into_js(context);
                 Ok(PairValue { key, value })
@@ -621,6 +829,12 @@ impl PairIterable for Headers {
     }
 }
 
+fn str_to_header_name(str: &str) -> JsResult<HeaderName> {
+    HeaderName::try_from(&str.to_ascii_lowercase()).map_err(|_| {
+        JsError::from_native(JsNativeError::typ().with_message("Invalid header name"))
+    })
+}
+
 struct HeadersIteratorClass;
 impl PairIteratorClass for HeadersIteratorClass {
     type Iterable = Headers;
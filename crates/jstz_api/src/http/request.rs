@@ -13,7 +13,8 @@ use boa_engine::{
     object::{builtins::JsPromise, ErasedObject},
     property::Attribute,
     value::TryFromJs,
-    Context, JsArgs, JsData, JsError, JsNativeError, JsResult, JsValue, NativeFunction,
+    Context, JsArgs, JsData, JsError, JsNativeError, JsObject, JsResult, JsValue,
+    NativeFunction,
 };
 use boa_gc::{custom_trace, Finalize, GcRefMut, Trace};
 use http::{Method, Request as InnerRequest, Uri};
@@ -27,6 +28,8 @@ use jstz_core::{
 };
 use url::Url;
 
+use crate::{abort::AbortSignal, structured_clone};
+
 use super::{
     body::{Body, BodyWithType, HttpBody},
     header::{Headers, HeadersClass},
@@ -42,6 +45,8 @@ pub struct RequestOptions {
     method: Method,
     headers: Option<Headers>,
     body: BodyWithType,
+    // `Some(None)` if `init["signal"]` is explicitly `null`
+    signal: Option<Option<JsNativeObject<AbortSignal>>>,
 }
 
 #[derive(JsData)]
@@ -49,6 +54,7 @@ pub struct Request {
     request: InnerRequest<Body>,
     headers: JsNativeObject<Headers>,
     url: Url,
+    signal: JsNativeObject<AbortSignal>,
 }
 
 impl Request {
@@ -77,6 +83,7 @@ impl Request {
             request,
             headers,
             url,
+            signal: AbortSignal::new_object(context)?,
         })
     }
 }
@@ -97,19 +104,10 @@ fn clone_inner_request<T: Clone>(request: &InnerRequest<T>) -> InnerRequest<T> {
         .expect("Cannot construct a malformed request from a valid one")
 }
 
-impl Clone for Request {
-    fn clone(&self) -> Self {
-        Self {
-            request: clone_inner_request(&self.request),
-            headers: self.headers.clone(),
-            url: self.url.clone(),
-        }
-    }
-}
-
 impl Finalize for Request {
     fn finalize(&self) {
         self.headers.finalize();
+        self.signal.finalize();
         self.request.body().finalize()
     }
 }
@@ -117,6 +115,7 @@ impl Finalize for Request {
 unsafe impl Trace for Request {
     custom_trace!(this, mark, {
         mark(&this.headers);
+        mark(&this.signal);
         mark(this.request.body());
     });
 }
@@ -162,7 +161,7 @@ impl Request {
         // 1. Let `request` be null
         // 3. Let `base_url` be `this's` relevant settings object's API base URL
         //    (This is managed by the `Url` library)
-        let mut request = match info {
+        let (mut request, input_signal) = match info {
             // 5. If `info` is a string, then:
             RequestInfo::String(url) => {
                 // 1. Let `parsed_url` be the result of parsing `url` with `base_url`
@@ -190,20 +189,26 @@ impl Request {
                     .body(Body::null())
                     .unwrap();
 
-                Request {
+                let request = Request {
                     request,
                     url: parsed_url,
                     headers: JsNativeObject::new::<HeadersClass>(
                         Headers::default(),
                         context,
                     )?,
-                }
+                    signal: AbortSignal::new_object(context)?,
+                };
+
+                (request, None)
             }
             // 6. Otheriwse:
             RequestInfo::Request(request) => {
                 // 1. Assert: input is a `Request` object
                 // 2. Set reqiest to input's request
-                request.clone()
+                // 13. (Hoisted) If `input` is a `Request` object, set `signal` to
+                //     `input`'s signal
+                let signal = request.signal.clone();
+                (request, Some(signal))
             }
         };
 
@@ -225,7 +230,12 @@ impl Request {
         // 4. Set `request`'s method to `method`
         *request.request.method_mut() = method;
 
-        // 26-32: (FIXME:) SKIPPED
+        // 26-28: (FIXME:) SKIPPED
+
+        // 29. If `init["signal"]` exists, then set `signal` to it.
+        let signal = options.signal.unwrap_or(input_signal);
+
+        // 30-32: (FIXME:) SKIPPED
 
         // 33. If init is not empty, then
         // Note: init (aka options) has default values
@@ -265,7 +275,7 @@ impl Request {
         // 4. If `context_type` is non-null and `request`'s header's does not contain `Content-Type`,
         //    then append `("Content-Type", content_type)` to `request`'s headers
         if let Some(content_type) = content_type {
-            if !request.headers.deref().contains_key("Content-Type") {
+            if !request.headers.deref().contains("Content-Type")? {
                 request
                     .headers
                     .deref_mut()
@@ -273,7 +283,12 @@ impl Request {
             }
         }
 
-        // 38-42: (FIXME:) SKIPPED
+        // 38. Set `this`'s signal to the result of creating a dependent abort signal
+        //     from « `signal` » if `signal` is non-null; otherwise « ».
+        let signals: Vec<JsObject> = signal.iter().map(|s| s.to_object()).collect();
+        request.signal = AbortSignal::create_dependent(&signals, context)?;
+
+        // 39-42: (FIXME:) SKIPPED
 
         Ok(request)
     }
@@ -290,6 +305,10 @@ impl Request {
         &self.headers
     }
 
+    pub fn signal(&self) -> &JsNativeObject<AbortSignal> {
+        &self.signal
+    }
+
     pub fn array_buffer(&mut self, context: &mut Context) -> JsResult<JsPromise> {
         self.request.body_mut().array_buffer(context)
     }
@@ -309,6 +328,35 @@ impl Request {
     pub fn body(&mut self) -> &mut Body {
         self.request.body_mut()
     }
+
+    /// Returns a copy of the request whose signal follows the signal of the
+    /// original request, rather than being shared with it.
+    pub fn try_clone(&self, context: &mut Context) -> JsResult<Self> {
+        let signal = self.signal.to_object();
+        Ok(Self {
+            request: clone_inner_request(&self.request),
+            headers: self.headers.clone(),
+            url: self.url.clone(),
+            signal: AbortSignal::create_dependent(&[signal], context)?,
+        })
+    }
+
+    /// Returns a structured clone of the request (see [`structured_clone`]) whose
+    /// signal follows the signal of the original request.
+    ///
+    /// [`structured_clone`]: crate::structured_clone::structured_clone
+    pub fn structured_clone(
+        this: &JsNativeObject<Self>,
+        context: &mut Context,
+    ) -> JsResult<JsNativeObject<Self>> {
+        let clone: JsNativeObject<Self> =
+            structured_clone::structured_clone(this.inner(), context)?.try_into()?;
+
+        let signal = this.deref().signal.to_object();
+        clone.deref_mut().signal = AbortSignal::create_dependent(&[signal], context)?;
+
+        Ok(clone)
+    }
 }
 
 pub struct RequestClass;
@@ -354,6 +402,15 @@ impl RequestClass {
         )
     }
 
+    fn signal(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Request,
+            "signal",
+            get:((request, _context) => Ok(request.signal().inner().clone()))
+        )
+    }
+
     fn body_used(context: &mut Context) -> Accessor {
         accessor!(
             context,
@@ -395,12 +452,12 @@ impl RequestClass {
 }
 
 impl TryFromJs for RequestInfo {
-    fn try_from_js(value: &JsValue, _context: &mut Context) -> JsResult<Self> {
+    fn try_from_js(value: &JsValue, context: &mut Context) -> JsResult<Self> {
         if let Some(string) = value.as_string() {
             Ok(Self::String(string.to_std_string_escaped()))
         } else {
-            let request = Request::try_from_js(value)?;
-            Ok(Self::Request(request.clone()))
+            let request = Request::try_from_js(value)?.try_clone(context)?;
+            Ok(Self::Request(request))
         }
     }
 }
@@ -441,10 +498,17 @@ impl TryFromJs for RequestOptions {
             Default::default()
         };
 
+        let signal = match obj.get(js_string!("signal"), context)? {
+            JsValue::Undefined => None,
+            JsValue::Null => Some(None),
+            value => Some(Some(value.try_into()?)),
+        };
+
         Ok(Self {
             method,
             headers,
             body,
+            signal,
         })
     }
 }
@@ -474,12 +538,14 @@ impl NativeClass for RequestClass {
         let headers = Self::headers(class.context());
         let method = Self::method(class.context());
         let url = Self::url(class.context());
+        let signal = Self::signal(class.context());
 
         class
             .accessor(js_string!("bodyUsed"), body_used, Attribute::all())
             .accessor(js_string!("headers"), headers, Attribute::all())
             .accessor(js_string!("method"), method, Attribute::all())
             .accessor(js_string!("url"), url, Attribute::all())
+            .accessor(js_string!("signal"), signal, Attribute::all())
             .method(
                 js_string!("arrayBuffer"),
                 0,
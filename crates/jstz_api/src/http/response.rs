@@ -13,10 +13,11 @@ use boa_engine::{
     object::{builtins::JsPromise, ErasedObject},
     property::Attribute,
     value::TryFromJs,
-    Context, JsArgs, JsData, JsError, JsNativeError, JsResult, JsValue, NativeFunction,
+    Context, JsArgs, JsData, JsError, JsNativeError, JsResult, JsString, JsValue,
+    NativeFunction,
 };
 use boa_gc::{custom_trace, Finalize, GcRefMut, Trace};
-use http::{header, Response as InnerResponse, StatusCode};
+use http::{header, StatusCode};
 use jstz_core::{
     accessor,
     native::{
@@ -28,39 +29,79 @@ use url::Url;
 
 use super::{
     body::{Body, BodyWithType, HttpBody},
-    header::{Headers, HeadersClass},
+    header::{ByteString, Headers, HeadersClass, HeadersGuard},
 };
 
+/// The type of a response.
+///
+/// More information:
+///  - [WHATWG specification][spec]
+///
+/// [spec]: https://fetch.spec.whatwg.org/#concept-response-type
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseType {
+    Basic,
+    Cors,
+    #[default]
+    Default,
+    Error,
+    Opaque,
+    OpaqueRedirect,
+}
+
+impl ResponseType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResponseType::Basic => "basic",
+            ResponseType::Cors => "cors",
+            ResponseType::Default => "default",
+            ResponseType::Error => "error",
+            ResponseType::Opaque => "opaque",
+            ResponseType::OpaqueRedirect => "opaqueredirect",
+        }
+    }
+}
+
 #[derive(JsData)]
 pub struct Response {
-    response: InnerResponse<Body>,
+    response_type: ResponseType,
+    // 0 for network errors, otherwise a valid status code
+    status: u16,
+    status_text: String,
+    url_list: Vec<Url>,
     headers: JsNativeObject<Headers>,
-    url: Option<Url>,
+    body: Body,
 }
 
 impl Finalize for Response {
     fn finalize(&self) {
         self.headers.finalize();
-        self.response.body().finalize()
+        self.body.finalize()
     }
 }
 
 unsafe impl Trace for Response {
     custom_trace!(this, mark, {
         mark(&this.headers);
-        mark(this.response.body());
+        mark(&this.body);
     });
 }
 
 pub struct ResponseOptions {
     status: u16,
+    status_text: String,
     headers: Headers,
 }
 
 impl ResponseOptions {
-    pub fn from(status_code: http::StatusCode, headers: header::HeaderMap) -> Self {
+    pub fn from(
+        status_code: http::StatusCode,
+        status_text: String,
+        headers: header::HeaderMap,
+    ) -> Self {
         Self {
             status: status_code.as_u16(),
+            status_text,
             headers: Headers::from(headers),
         }
     }
@@ -70,27 +111,52 @@ impl Default for ResponseOptions {
     fn default() -> Self {
         Self {
             status: 200,
+            status_text: String::new(),
             headers: Default::default(),
         }
     }
 }
 
+/// A null body status is a status that is 101, 103, 204, 205, or 304.
+///
+/// [spec]: https://fetch.spec.whatwg.org/#null-body-status
+fn is_null_body_status(status: u16) -> bool {
+    matches!(status, 101 | 103 | 204 | 205 | 304)
+}
+
+/// Returns whether `status_text` matches the `reason-phrase` token production,
+/// i.e. `*( HTAB / SP / VCHAR / obs-text )`.
+///
+/// [spec]: https://httpwg.org/specs/rfc9112.html#status.line
+fn is_reason_phrase(status_text: &str) -> bool {
+    status_text
+        .chars()
+        .all(|c| matches!(c, '\t' | ' '..='~' | '\u{80}'..='\u{ff}'))
+}
+
 impl Response {
-    pub fn to_http_response(&self) -> http::Response<HttpBody> {
+    /// Converts the response into an `http::Response`.
+    ///
+    /// A network error (status 0) cannot be represented by `http`, so it is
+    /// converted into a `500 Internal Server Error`. The status message is
+    /// available via [`Response::status_text`].
+    pub fn to_http_response(&self) -> JsResult<http::Response<HttpBody>> {
+        let status = StatusCode::from_u16(self.status)
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
         let mut builder = http::Response::builder()
-            .status(self.response.status())
-            .version(self.response.version())
-            .extension(self.url.clone());
+            .status(status)
+            .extension(self.url().cloned());
 
-        let headers = self.headers.deref().to_http_headers();
+        let headers = self.headers.deref().to_http_headers()?;
 
         *builder.headers_mut().unwrap() = headers;
 
-        let body = self.response.body().to_http_body();
+        let body = self.body.to_http_body();
 
-        builder
+        Ok(builder
             .body(body)
-            .expect("Expected valid http response from a valid response")
+            .expect("Expected valid http response from a valid response"))
     }
 
     /// Creates a new Response object.
@@ -106,17 +172,27 @@ impl Response {
         options: ResponseOptions,
         context: &mut Context,
     ) -> JsResult<Self> {
-        // 1. If `init["status"]` is not in the range 200 to 500 inclusive,
-        // 2. (FIXME:) SKIPPED
-        // 3. Set response's response's status to `init["status"]`
-        let status = StatusCode::from_u16(options.status).map_err(|_| {
-            // 1. (cont.) then throw a RangeError
-            JsError::from_native(
+        // 1. If `init["status"]` is not in the range 200 to 599 inclusive,
+        //    then throw a RangeError
+        if !(200..=599).contains(&options.status) {
+            return Err(JsError::from_native(
                 JsNativeError::range().with_message("Invalid status code"),
-            )
-        })?;
+            ));
+        }
+
+        // 2. If `init["statusText"]` does not match the `reason-phrase` token
+        //    production, then throw a TypeError
+        if !is_reason_phrase(&options.status_text) {
+            return Err(JsError::from_native(
+                JsNativeError::typ().with_message("Invalid status text"),
+            ));
+        }
+
+        // 3. Set response's response's status to `init["status"]`
+        let status = options.status;
 
-        // 3. (FIXME:) SKIPPED
+        // 4. Set response's response's status message to `init["statusText"]`
+        let status_text = options.status_text;
 
         // 5. If  `init["headers"]` exists, then fill response's headers with `init["headers"]`
         let mut headers = options.headers;
@@ -124,7 +200,13 @@ impl Response {
         // 6. If `body` was given, then:
         //    Default is `Body::null()`.
         let body = {
-            // 1. (FIXME:) SKIPPED
+            // 1. If response's status is a null body status, then throw a TypeError
+            if !body_with_type.body.is_null() && is_null_body_status(status) {
+                return Err(JsError::from_native(
+                    JsNativeError::typ()
+                        .with_message("Response with null body status cannot have body"),
+                ));
+            }
 
             // 3. If `body_with_type`'s type is non-null and response's header list does
             //    not contain `Content-Type`
@@ -140,23 +222,26 @@ impl Response {
             body_with_type.body
         };
 
-        let response =
-            InnerResponse::builder()
-                .status(status)
-                .body(body)
-                .map_err(|_| {
-                    JsError::from_native(
-                        JsNativeError::typ().with_message("Malformed response"),
-                    )
-                })?;
-
         Ok(Self {
-            response,
+            response_type: ResponseType::Default,
+            status,
+            status_text,
+            url_list: Vec::new(),
             headers: JsNativeObject::new::<HeadersClass>(headers, context)?,
-            url: None,
+            body,
         })
     }
 
+    /// Returns the type of the response.
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://fetch.spec.whatwg.org/#dom-response-type
+    pub fn response_type(&self) -> ResponseType {
+        self.response_type
+    }
+
     /// Returns the Headers object associated with the response.
     ///
     /// More information:
@@ -175,7 +260,7 @@ impl Response {
     ///
     /// [spec] https://fetch.spec.whatwg.org/#dom-response-ok
     pub fn ok(&self) -> bool {
-        self.response.status().is_success()
+        (200..=299).contains(&self.status)
     }
 
     /// Returns the status code of the response. (This will be 200 for a success).
@@ -185,17 +270,17 @@ impl Response {
     ///
     /// [spec] https://fetch.spec.whatwg.org/#dom-response-status
     pub fn status(&self) -> u16 {
-        self.response.status().as_u16()
+        self.status
     }
 
-    /// Returns the status message corresponding to the status code. (e.g., OK for 200).
+    /// Returns the status message of the response (e.g., OK for 200).
     ///
     /// More information:
     ///  - [WHATWG specification][spec]
     ///
     /// [spec] https://fetch.spec.whatwg.org/#dom-response-statustext
-    pub fn status_text(&self) -> Option<String> {
-        self.response.status().canonical_reason().map(String::from)
+    pub fn status_text(&self) -> &str {
+        &self.status_text
     }
 
     /// Returns whether or not the response is the result of a redirect
@@ -205,7 +290,9 @@ impl Response {
     ///
     /// [spec] https://fetch.spec.whatwg.org/#dom-response-redirected
     pub fn redirected(&self) -> bool {
-        self.url.is_some()
+        // Return true if this’s response’s URL list’s size is greater than 1;
+        // otherwise false.
+        self.url_list.len() > 1
     }
 
     /// Returns the URL of the response.
@@ -215,14 +302,39 @@ impl Response {
     ///
     /// [spec] https://fetch.spec.whatwg.org/#dom-response-url
     pub fn url(&self) -> Option<&Url> {
-        // self.url.as_deref() but Url doesn't implement deref :(
-        match &self.url {
-            Some(url) => Some(url),
-            None => None,
-        }
+        // A response's URL is the last URL in its URL list
+        self.url_list.last()
     }
 
-    // FIXME: Missing `clone` and `type`
+    /// Returns a clone of the response.
+    ///
+    /// More information:
+    ///  - [WHATWG specification][spec]
+    ///
+    /// [spec] https://fetch.spec.whatwg.org/#dom-response-clone
+    pub fn clone(&self, context: &mut Context) -> JsResult<Self> {
+        // 1. If this is unusable, then throw a TypeError
+        if self.body_used() {
+            return Err(JsError::from_native(
+                JsNativeError::typ().with_message("Response body has already been used"),
+            ));
+        }
+
+        // 2. Let `cloned_response` be the result of cloning this's response.
+        //    The body of the response is teed.
+        // 3. Return the result of creating a Response object, given `cloned_response`,
+        //    this's headers's guard, and this's relevant realm.
+        let headers = self.headers.deref().clone();
+
+        Ok(Self {
+            response_type: self.response_type,
+            status: self.status,
+            status_text: self.status_text.clone(),
+            url_list: self.url_list.clone(),
+            headers: JsNativeObject::new::<HeadersClass>(headers, context)?,
+            body: self.body.tee(),
+        })
+    }
 }
 
 // Body mixin
@@ -230,22 +342,22 @@ impl Response {
     /// Return a boolean value that declares whether the body has been
     /// used in a response yet.
     pub fn body_used(&self) -> bool {
-        self.response.body().is_used()
+        self.body.is_used()
     }
 
     /// Returns a promise that resolves with an ArrayBuffer representation of the response body.
     pub fn array_buffer(&mut self, context: &mut Context) -> JsResult<JsPromise> {
-        self.response.body_mut().array_buffer(context)
+        self.body.array_buffer(context)
     }
 
     /// Returns a promise that resolves with the result of parsing the response body text as JSON.
     pub fn json(&mut self, context: &mut Context) -> JsResult<JsPromise> {
-        self.response.body_mut().json(context)
+        self.body.json(context)
     }
 
     /// Returns a promise that resolves with a text representation of the response body.
     pub fn text(&mut self, context: &mut Context) -> JsResult<JsPromise> {
-        self.response.body_mut().text(context)
+        self.body.text(context)
     }
 }
 
@@ -265,20 +377,18 @@ impl ResponseBuilder {
         //       status is 0, status message is the empty byte sequence,
         //       header list is « », body is null, and body info is a
         //       new response body info
-        //
-        // FIXME: jstz only supports status, body and headers here.
-        // FIXME: http::Response won't support 0 as a status code
-        let status = 500;
-        let body = Body::null();
-        let headers = Headers::new();
+        let mut headers = Headers::new();
+
+        // 2. Set response's headers's guard to "immutable".
+        headers.set_guard(HeadersGuard::Immutable);
 
         Ok(Response {
-            response: InnerResponse::builder()
-                .status(status)
-                .body(body)
-                .expect("fixed inputs should never fail"),
+            response_type: ResponseType::Error,
+            status: 0,
+            status_text: String::new(),
+            url_list: Vec::new(),
             headers: JsNativeObject::new::<HeadersClass>(headers, context)?,
-            url: None,
+            body: Body::null(),
         })
     }
 
@@ -299,13 +409,9 @@ impl ResponseBuilder {
             JsError::from_native(JsNativeError::typ().with_message("Invalid URL"))
         })?;
 
-        // 2. If status is not a redirect status, then row a RangeError
-        let status = StatusCode::from_u16(status.unwrap_or(302)).map_err(|_| {
-            JsError::from_native(
-                JsNativeError::range().with_message("Invalid status code"),
-            )
-        })?;
-        if !status.is_redirection() {
+        // 3. If status is not a redirect status, then throw a RangeError
+        let status = status.unwrap_or(302);
+        if !matches!(status, 301 | 302 | 303 | 307 | 308) {
             return Err(JsError::from_native(
                 JsNativeError::range().with_message("Expected a redirect status"),
             ));
@@ -318,18 +424,19 @@ impl ResponseBuilder {
 
         // 7. Append `("Location", value)` to response's header list
         headers.append("Location", &location)?;
+        headers.set_guard(HeadersGuard::Immutable);
 
         // 4. Let `response` be the result of creating a Response object,
         //    given a new response, "immutable", and the current realm
         // 5. Set `response`'s response’s status to status
         // 8. Return `response`
         Ok(Response {
-            response: InnerResponse::builder()
-                .status(status)
-                .body(Body::null())
-                .expect("fixed inputs should never fail"),
+            response_type: ResponseType::Default,
+            status,
+            status_text: String::new(),
+            url_list: Vec::new(),
             headers: JsNativeObject::new::<HeadersClass>(headers, context)?,
-            url: Some(parsed_url),
+            body: Body::null(),
         })
     }
 
@@ -339,7 +446,11 @@ impl ResponseBuilder {
     ///  - [WHATWG specification][spec]
     ///
     /// [spec] https://fetch.spec.whatwg.org/#dom-response-json
-    pub fn json(value: &JsValue, context: &mut Context) -> JsResult<Response> {
+    pub fn json(
+        value: &JsValue,
+        options: ResponseOptions,
+        context: &mut Context,
+    ) -> JsResult<Response> {
         // 1, 2, 4. See `BodyWithType::json`
         let body = BodyWithType::json(value, context)?;
 
@@ -347,7 +458,7 @@ impl ResponseBuilder {
         //    new response, "response", and the current realm.
         // 4. Perform initialize a response given `response`, `init`, and
         //    `(body, "application/json")`.
-        Response::new(body, Default::default(), context)
+        Response::new(body, options, context)
     }
 }
 
@@ -385,12 +496,17 @@ impl ResponseClass {
         context: &mut Context,
     ) -> JsResult<JsValue> {
         let value = args.get_or_undefined(0);
+        let options: ResponseOptions = match args.get(1) {
+            None | Some(JsValue::Undefined) => Default::default(),
+            Some(options) => options.try_js_into(context)?,
+        };
 
-        Ok(
-            JsNativeObject::new::<Self>(ResponseBuilder::json(value, context)?, context)?
-                .inner()
-                .clone(),
-        )
+        Ok(JsNativeObject::new::<Self>(
+            ResponseBuilder::json(value, options, context)?,
+            context,
+        )?
+        .inner()
+        .clone())
     }
 
     fn static_redirect(
@@ -440,7 +556,7 @@ impl ResponseClass {
         accessor!(
             context,
             Response,
-            "redirected",
+            "status",
             get:((response, _context) => Ok(response.status().into()))
         )
     }
@@ -450,7 +566,7 @@ impl ResponseClass {
             context,
             Response,
             "statusText",
-            get:((response, context) => Ok(response.status_text().into_js(context)))
+            get:((response, _context) => Ok(JsString::from(response.status_text()).into()))
         )
     }
 
@@ -460,14 +576,24 @@ impl ResponseClass {
             Response,
             "url",
             get:((response, context) => {
+                // Return the empty string if this’s response’s URL is null
                 match response.url() {
-                    None => Ok(JsValue::null()),
+                    None => Ok(String::new().into_js(context)),
                     Some(url) => Ok(url.to_string().into_js(context)),
                 }
             })
         )
     }
 
+    fn response_type(context: &mut Context) -> Accessor {
+        accessor!(
+            context,
+            Response,
+            "type",
+            get:((response, _context) => Ok(JsString::from(response.response_type().as_str()).into()))
+        )
+    }
+
     fn body_used(context: &mut Context) -> Accessor {
         accessor!(
             context,
@@ -506,6 +632,18 @@ impl ResponseClass {
 
         Ok(request.json(context)?.into())
     }
+
+    fn clone(
+        this: &JsValue,
+        _args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let response = Response::clone(&Response::try_from_js(this)?, context)?;
+
+        Ok(JsNativeObject::new::<Self>(response, context)?
+            .inner()
+            .clone())
+    }
 }
 
 impl TryFromJs for ResponseOptions {
@@ -521,6 +659,16 @@ impl TryFromJs for ResponseOptions {
             200
         };
 
+        let status_text: String =
+            if obj.has_property(js_string!("statusText"), context)? {
+                let status_text: ByteString = obj
+                    .get(js_string!("statusText"), context)?
+                    .try_js_into(context)?;
+                status_text.0
+            } else {
+                String::new()
+            };
+
         let headers: Headers = if obj.has_property(js_string!("headers"), context)? {
             Headers::from_init(
                 obj.get(js_string!("headers"), context)?
@@ -530,7 +678,11 @@ impl TryFromJs for ResponseOptions {
             Default::default()
         };
 
-        Ok(Self { status, headers })
+        Ok(Self {
+            status,
+            status_text,
+            headers,
+        })
     }
 }
 
@@ -565,6 +717,7 @@ impl NativeClass for ResponseClass {
         let status_text = Self::status_text(class.context());
         let headers = Self::headers(class.context());
         let body_used = Self::body_used(class.context());
+        let response_type = Self::response_type(class.context());
 
         class
             .static_method(
@@ -582,6 +735,7 @@ impl NativeClass for ResponseClass {
                 1,
                 NativeFunction::from_fn_ptr(Self::static_json),
             )
+            .accessor(js_string!("type"), response_type, Attribute::all())
             .accessor(js_string!("url"), url, Attribute::all())
             .accessor(js_string!("redirected"), redirected, Attribute::all())
             .accessor(js_string!("status"), status, Attribute::all())
@@ -603,6 +757,11 @@ impl NativeClass for ResponseClass {
                 js_string!("json"),
                 0,
                 NativeFunction::from_fn_ptr(Self::json),
+            )
+            .method(
+                js_string!("clone"),
+                0,
+                NativeFunction::from_fn_ptr(Self::clone),
             );
 
         Ok(())
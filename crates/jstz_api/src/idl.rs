@@ -1,5 +1,6 @@
 use boa_engine::{
     builtins::{array_buffer::ArrayBuffer, dataview::DataView, typed_array::TypedArray},
+    js_string,
     object::builtins::{JsArrayBuffer, JsDataView, JsTypedArray},
     value::TryFromJs,
     Context, JsData, JsNativeError, JsResult, JsValue,
@@ -132,6 +133,21 @@ impl BufferSource for JsBufferSource {
     }
 }
 
+/// Creates an error object emulating a `DOMException` with the given `name`
+/// (e.g. `"AbortError"`).
+///
+/// https://webidl.spec.whatwg.org/#idl-DOMException
+pub fn dom_exception(name: &str, message: &str, context: &mut Context) -> JsValue {
+    let error = JsNativeError::error()
+        .with_message(message.to_string())
+        .to_opaque(context);
+    error
+        .set(js_string!("name"), js_string!(name), false, context)
+        .expect("Setting the name of an error object should not fail");
+
+    error.into()
+}
+
 // https://webidl.spec.whatwg.org/#idl-types
 
 pub type Any = JsValue;
@@ -11,6 +11,8 @@ use serde::{Deserialize, Serialize};
 use tezos_smart_rollup::storage::path::{self, OwnedPath, RefPath};
 use utoipa::ToSchema;
 
+use crate::structured_clone::{deserialize, serialize, SerializedValue};
+
 #[derive(Debug, Trace, Finalize, JsData)]
 pub struct Kv {
     prefix: String,
@@ -18,8 +20,11 @@ pub struct Kv {
 
 const KV_PATH: RefPath = RefPath::assert_from(b"/jstz_kv");
 
-// TODO: Figure out a more effective way of serializing values using json
 /// A value stored in the Key-Value store. Always valid JSON.
+///
+/// Values are serialized using the structured serialization algorithm (see
+/// [`SerializedValue::to_json`]), so values that JSON cannot represent (e.g.
+/// `undefined`, `Map`s or `Date`s) are preserved.
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(try_from = "String", into = "String")]
 #[schema(value_type = String, format = "json")]
@@ -110,7 +115,7 @@ impl KvApi {
     fn set(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
         preamble!(this, args, key);
 
-        let value = KvValue(args.get_or_undefined(1).to_json(context)?);
+        let value = KvValue(serialize(args.get_or_undefined(1), context)?.to_json());
 
         runtime::with_js_tx(|tx| this.set(tx, &key, value))?;
 
@@ -122,7 +127,9 @@ impl KvApi {
 
         runtime::with_js_hrt_and_tx(|hrt, tx| -> JsResult<JsValue> {
             match this.get(hrt.deref(), tx, &key)? {
-                Some(value) => JsValue::from_json(&value.0, context),
+                Some(value) => {
+                    deserialize(&SerializedValue::from_json(&value.0)?, context)
+                }
                 None => Ok(JsValue::null()),
             }
         })
@@ -1,11 +1,14 @@
+pub mod abort;
 mod console;
 pub mod encoding;
+pub mod event;
 pub mod file;
 pub mod http;
 pub mod idl;
 pub mod js_log;
 mod kv;
 pub mod random;
+pub mod structured_clone;
 pub mod stream;
 pub mod todo;
 pub mod url;
@@ -16,3 +19,4 @@ pub use kv::Kv;
 pub use kv::KvApi;
 pub use kv::KvValue;
 pub use random::RandomApi;
+pub use structured_clone::StructuredCloneApi;
@@ -0,0 +1,512 @@
+//! JSON encoding of [`SerializedValue`]s.
+//!
+//! Values that JSON can represent (`null`, booleans, finite numbers, strings,
+//! arrays without holes and plain objects) are encoded as plain JSON, so values
+//! written by `Kv.set` remain readable by other JSON consumers (and values written
+//! before structured serialization was introduced can still be read).
+//!
+//! All other values are encoded as objects tagged with a `"$jstz"` key, e.g.
+//! `{ "$jstz": "Date", "value": 0 }`. A plain object that has a `"$jstz"` key is
+//! itself wrapped in a `{ "$jstz": "Object", "properties": { ... } }` object.
+
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use boa_engine::{JsError, JsNativeError, JsResult};
+use serde_json::{json, Map, Value};
+
+use super::{SerializedValue, MAX_ARRAY_LENGTH};
+
+const TAG: &str = "$jstz";
+
+fn tagged(tag: &str, fields: Value) -> Value {
+    let mut object = Map::new();
+    object.insert(TAG.to_string(), tag.into());
+    if let Value::Object(fields) = fields {
+        object.extend(fields);
+    }
+    Value::Object(object)
+}
+
+fn number_to_json(n: f64) -> Value {
+    if n.is_nan() {
+        return tagged("Number", json!({ "value": "NaN" }));
+    }
+    if n.is_infinite() {
+        let value = if n > 0.0 { "Infinity" } else { "-Infinity" };
+        return tagged("Number", json!({ "value": value }));
+    }
+    if n == 0.0 && n.is_sign_negative() {
+        return tagged("Number", json!({ "value": "-0" }));
+    }
+
+    if n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64 {
+        (n as i64).into()
+    } else {
+        n.into()
+    }
+}
+
+fn properties_to_json(
+    properties: &[(String, SerializedValue)],
+    references: &[usize],
+) -> Value {
+    Value::Object(
+        properties
+            .iter()
+            .map(|(key, value)| (key.clone(), value.encode(references)))
+            .collect(),
+    )
+}
+
+/// Collects the objects of `value` in the order in which they are numbered when
+/// deserializing. If `sorted`, object properties are visited in the (sorted) order
+/// of their JSON encoding, otherwise in their original order.
+fn collect_objects<'a>(
+    value: &'a SerializedValue,
+    sorted: bool,
+    objects: &mut Vec<&'a SerializedValue>,
+) {
+    let visit_properties =
+        |properties: &'a [(String, SerializedValue)],
+         objects: &mut Vec<&'a SerializedValue>| {
+            let mut properties: Vec<_> = properties.iter().collect();
+            if sorted {
+                properties.sort_by(|(a, _), (b, _)| a.cmp(b));
+            }
+            for (_, value) in properties {
+                collect_objects(value, sorted, objects);
+            }
+        };
+
+    match value {
+        SerializedValue::Undefined
+        | SerializedValue::Null
+        | SerializedValue::Boolean(_)
+        | SerializedValue::Number(_)
+        | SerializedValue::BigInt(_)
+        | SerializedValue::String(_)
+        | SerializedValue::Reference(_) => {}
+        SerializedValue::ArrayBufferView { buffer, .. } => {
+            objects.push(value);
+            collect_objects(buffer, sorted, objects);
+        }
+        SerializedValue::Map(entries) => {
+            objects.push(value);
+            for (key, value) in entries {
+                collect_objects(key, sorted, objects);
+                collect_objects(value, sorted, objects);
+            }
+        }
+        SerializedValue::Set(values) => {
+            objects.push(value);
+            for value in values {
+                collect_objects(value, sorted, objects);
+            }
+        }
+        SerializedValue::Array {
+            elements,
+            properties,
+            ..
+        } => {
+            objects.push(value);
+            for (_, element) in elements {
+                collect_objects(element, sorted, objects);
+            }
+            visit_properties(properties, objects);
+        }
+        SerializedValue::Object(properties) => {
+            objects.push(value);
+            visit_properties(properties, objects);
+        }
+        _ => objects.push(value),
+    }
+}
+
+fn invalid(message: &str) -> JsError {
+    JsNativeError::typ()
+        .with_message(format!("Invalid serialized value: {message}"))
+        .into()
+}
+
+fn field<'a>(object: &'a Map<String, Value>, name: &str) -> JsResult<&'a Value> {
+    object
+        .get(name)
+        .ok_or_else(|| invalid(&format!("missing field `{name}`")))
+}
+
+fn str_field<'a>(object: &'a Map<String, Value>, name: &str) -> JsResult<&'a str> {
+    field(object, name)?
+        .as_str()
+        .ok_or_else(|| invalid(&format!("expected field `{name}` to be a string")))
+}
+
+fn usize_field(object: &Map<String, Value>, name: &str) -> JsResult<usize> {
+    field(object, name)?
+        .as_u64()
+        .map(|n| n as usize)
+        .ok_or_else(|| invalid(&format!("expected field `{name}` to be an integer")))
+}
+
+fn number_field(object: &Map<String, Value>, name: &str) -> JsResult<f64> {
+    number_from_json(field(object, name)?)
+}
+
+fn array_field<'a>(
+    object: &'a Map<String, Value>,
+    name: &str,
+) -> JsResult<&'a Vec<Value>> {
+    field(object, name)?
+        .as_array()
+        .ok_or_else(|| invalid(&format!("expected field `{name}` to be an array")))
+}
+
+fn number_from_json(value: &Value) -> JsResult<f64> {
+    match value {
+        Value::Number(n) => n.as_f64().ok_or_else(|| invalid("invalid number")),
+        Value::String(s) => match s.as_str() {
+            "NaN" => Ok(f64::NAN),
+            "Infinity" => Ok(f64::INFINITY),
+            "-Infinity" => Ok(f64::NEG_INFINITY),
+            "-0" => Ok(-0.0),
+            _ => Err(invalid("invalid number")),
+        },
+        _ => Err(invalid("invalid number")),
+    }
+}
+
+fn properties_from_json(value: &Value) -> JsResult<Vec<(String, SerializedValue)>> {
+    value
+        .as_object()
+        .ok_or_else(|| invalid("expected properties to be an object"))?
+        .iter()
+        .map(|(key, value)| Ok((key.clone(), SerializedValue::from_json(value)?)))
+        .collect()
+}
+
+fn bytes_from_json(value: &Value) -> JsResult<Vec<u8>> {
+    let encoded = value
+        .as_str()
+        .ok_or_else(|| invalid("expected bytes to be a base64 string"))?;
+    BASE64
+        .decode(encoded)
+        .map_err(|_| invalid("expected bytes to be a base64 string"))
+}
+
+impl SerializedValue {
+    /// Encodes the serialized value as JSON
+    pub fn to_json(&self) -> Value {
+        // JSON objects don't preserve the order of their keys, so references are
+        // renumbered to match the order in which objects are decoded
+        let mut original = Vec::new();
+        collect_objects(self, false, &mut original);
+        let mut sorted = Vec::new();
+        collect_objects(self, true, &mut sorted);
+
+        let positions: HashMap<*const SerializedValue, usize> = sorted
+            .into_iter()
+            .enumerate()
+            .map(|(index, object)| (object as *const _, index))
+            .collect();
+        let references: Vec<usize> = original
+            .into_iter()
+            .map(|object| positions[&(object as *const _)])
+            .collect();
+
+        self.encode(&references)
+    }
+
+    fn encode(&self, references: &[usize]) -> Value {
+        match self {
+            SerializedValue::Undefined => tagged("Undefined", json!({})),
+            SerializedValue::Null => Value::Null,
+            SerializedValue::Boolean(b) => (*b).into(),
+            SerializedValue::Number(n) => number_to_json(*n),
+            SerializedValue::BigInt(n) => tagged("BigInt", json!({ "value": n })),
+            SerializedValue::String(s) => s.clone().into(),
+            SerializedValue::Reference(index) => {
+                let index = references.get(*index).copied().unwrap_or(*index);
+                tagged("Reference", json!({ "index": index }))
+            }
+            SerializedValue::BooleanObject(b) => {
+                tagged("BooleanObject", json!({ "value": b }))
+            }
+            SerializedValue::NumberObject(n) => {
+                tagged("NumberObject", json!({ "value": number_to_json(*n) }))
+            }
+            SerializedValue::BigIntObject(n) => {
+                tagged("BigIntObject", json!({ "value": n }))
+            }
+            SerializedValue::StringObject(s) => {
+                tagged("StringObject", json!({ "value": s }))
+            }
+            SerializedValue::Date(time) => {
+                tagged("Date", json!({ "value": number_to_json(*time) }))
+            }
+            SerializedValue::RegExp { source, flags } => {
+                tagged("RegExp", json!({ "source": source, "flags": flags }))
+            }
+            SerializedValue::ArrayBuffer(bytes) => {
+                tagged("ArrayBuffer", json!({ "bytes": BASE64.encode(bytes) }))
+            }
+            SerializedValue::ArrayBufferView {
+                constructor,
+                buffer,
+                byte_offset,
+                length,
+            } => tagged(
+                "ArrayBufferView",
+                json!({
+                    "constructor": constructor,
+                    "buffer": buffer.encode(references),
+                    "byteOffset": byte_offset,
+                    "length": length,
+                }),
+            ),
+            SerializedValue::Map(entries) => {
+                let entries: Vec<Value> = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        json!([key.encode(references), value.encode(references)])
+                    })
+                    .collect();
+                tagged("Map", json!({ "entries": entries }))
+            }
+            SerializedValue::Set(values) => {
+                let values: Vec<Value> = values
+                    .iter()
+                    .map(|value| value.encode(references))
+                    .collect();
+                tagged("Set", json!({ "values": values }))
+            }
+            SerializedValue::Error { name, message } => {
+                tagged("Error", json!({ "name": name, "message": message }))
+            }
+            SerializedValue::Array {
+                length,
+                elements,
+                properties,
+            } => {
+                let is_dense = elements.len() == *length as usize;
+                if is_dense && properties.is_empty() {
+                    return Value::Array(
+                        elements
+                            .iter()
+                            .map(|(_, element)| element.encode(references))
+                            .collect(),
+                    );
+                }
+
+                // Sparse arrays are encoded as a list of `[index, element]`
+                let elements: Vec<Value> = elements
+                    .iter()
+                    .map(|(index, element)| json!([index, element.encode(references)]))
+                    .collect();
+                tagged(
+                    "Array",
+                    json!({
+                        "length": length,
+                        "elements": elements,
+                        "properties": properties_to_json(properties, references),
+                    }),
+                )
+            }
+            SerializedValue::Object(properties) => {
+                if properties.iter().any(|(key, _)| key == TAG) {
+                    tagged(
+                        "Object",
+                        json!({ "properties": properties_to_json(properties, references) }),
+                    )
+                } else {
+                    properties_to_json(properties, references)
+                }
+            }
+            SerializedValue::Request {
+                method,
+                url,
+                headers,
+                body,
+            } => tagged(
+                "Request",
+                json!({
+                    "method": method,
+                    "url": url,
+                    "headers": headers,
+                    "body": body.as_ref().map(|body| BASE64.encode(body)),
+                }),
+            ),
+        }
+    }
+
+    /// Decodes a serialized value from JSON produced by [`SerializedValue::to_json`]
+    /// (or any plain JSON value)
+    pub fn from_json(value: &Value) -> JsResult<Self> {
+        let object = match value {
+            Value::Null => return Ok(SerializedValue::Null),
+            Value::Bool(b) => return Ok(SerializedValue::Boolean(*b)),
+            Value::Number(_) => {
+                return Ok(SerializedValue::Number(number_from_json(value)?))
+            }
+            Value::String(s) => return Ok(SerializedValue::String(s.clone())),
+            Value::Array(elements) => {
+                let (length, elements) = elements_from_json(elements)?;
+                return Ok(SerializedValue::Array {
+                    length,
+                    elements,
+                    properties: Vec::new(),
+                });
+            }
+            Value::Object(object) => object,
+        };
+
+        let Some(tag) = object.get(TAG) else {
+            return Ok(SerializedValue::Object(properties_from_json(value)?));
+        };
+
+        let tag = tag
+            .as_str()
+            .ok_or_else(|| invalid("expected a string tag"))?;
+        let serialized = match tag {
+            "Undefined" => SerializedValue::Undefined,
+            "Number" => SerializedValue::Number(number_field(object, "value")?),
+            "BigInt" => SerializedValue::BigInt(str_field(object, "value")?.to_string()),
+            "Reference" => SerializedValue::Reference(usize_field(object, "index")?),
+            "BooleanObject" => SerializedValue::BooleanObject(
+                field(object, "value")?
+                    .as_bool()
+                    .ok_or_else(|| invalid("expected a boolean"))?,
+            ),
+            "NumberObject" => {
+                SerializedValue::NumberObject(number_field(object, "value")?)
+            }
+            "BigIntObject" => {
+                SerializedValue::BigIntObject(str_field(object, "value")?.to_string())
+            }
+            "StringObject" => {
+                SerializedValue::StringObject(str_field(object, "value")?.to_string())
+            }
+            "Date" => SerializedValue::Date(number_field(object, "value")?),
+            "RegExp" => SerializedValue::RegExp {
+                source: str_field(object, "source")?.to_string(),
+                flags: str_field(object, "flags")?.to_string(),
+            },
+            "ArrayBuffer" => {
+                SerializedValue::ArrayBuffer(bytes_from_json(field(object, "bytes")?)?)
+            }
+            "ArrayBufferView" => SerializedValue::ArrayBufferView {
+                constructor: str_field(object, "constructor")?.to_string(),
+                buffer: Box::new(Self::from_json(field(object, "buffer")?)?),
+                byte_offset: usize_field(object, "byteOffset")?,
+                length: usize_field(object, "length")?,
+            },
+            "Map" => SerializedValue::Map(
+                array_field(object, "entries")?
+                    .iter()
+                    .map(|entry| match entry.as_array().map(Vec::as_slice) {
+                        Some([key, value]) => {
+                            Ok((Self::from_json(key)?, Self::from_json(value)?))
+                        }
+                        _ => Err(invalid("expected a map entry")),
+                    })
+                    .collect::<JsResult<_>>()?,
+            ),
+            "Set" => SerializedValue::Set(
+                array_field(object, "values")?
+                    .iter()
+                    .map(Self::from_json)
+                    .collect::<JsResult<_>>()?,
+            ),
+            "Error" => SerializedValue::Error {
+                name: str_field(object, "name")?.to_string(),
+                message: field(object, "message")?.as_str().map(str::to_string),
+            },
+            "Array" => {
+                let elements = array_field(object, "elements")?;
+                let (length, elements) = match object.get("length") {
+                    Some(_) => sparse_elements_from_json(
+                        usize_field(object, "length")?,
+                        elements,
+                    )?,
+                    // Arrays with holes used to be encoded densely
+                    None => elements_from_json(elements)?,
+                };
+                SerializedValue::Array {
+                    length,
+                    elements,
+                    properties: properties_from_json(field(object, "properties")?)?,
+                }
+            }
+            "Object" => SerializedValue::Object(properties_from_json(field(
+                object,
+                "properties",
+            )?)?),
+            "Request" => SerializedValue::Request {
+                method: str_field(object, "method")?.to_string(),
+                url: str_field(object, "url")?.to_string(),
+                headers: array_field(object, "headers")?
+                    .iter()
+                    .map(|header| match header.as_array().map(Vec::as_slice) {
+                        Some([Value::String(name), Value::String(value)]) => {
+                            Ok((name.clone(), value.clone()))
+                        }
+                        _ => Err(invalid("expected a header")),
+                    })
+                    .collect::<JsResult<_>>()?,
+                body: match field(object, "body")? {
+                    Value::Null => None,
+                    body => Some(bytes_from_json(body)?),
+                },
+            },
+            _ => return Err(invalid(&format!("unknown tag `{tag}`"))),
+        };
+
+        Ok(serialized)
+    }
+}
+
+type Elements = (u32, Vec<(u32, SerializedValue)>);
+
+fn array_length(length: usize) -> JsResult<u32> {
+    u32::try_from(length)
+        .ok()
+        .filter(|length| *length <= MAX_ARRAY_LENGTH)
+        .ok_or_else(|| invalid("array too long"))
+}
+
+/// Decodes the elements of a dense array, where holes are tagged `"Hole"`
+fn elements_from_json(elements: &[Value]) -> JsResult<Elements> {
+    let length = array_length(elements.len())?;
+    let elements = elements
+        .iter()
+        .enumerate()
+        .filter(|(_, element)| match element {
+            Value::Object(object) => object.get(TAG) != Some(&json!("Hole")),
+            _ => true,
+        })
+        .map(|(index, element)| Ok((index as u32, SerializedValue::from_json(element)?)))
+        .collect::<JsResult<_>>()?;
+    Ok((length, elements))
+}
+
+/// Decodes the `[index, element]` list of a sparse array
+fn sparse_elements_from_json(length: usize, elements: &[Value]) -> JsResult<Elements> {
+    let length = array_length(length)?;
+    let mut previous = None;
+    let elements = elements
+        .iter()
+        .map(|entry| match entry.as_array().map(Vec::as_slice) {
+            Some([index, element]) => {
+                let index = index
+                    .as_u64()
+                    .filter(|index| *index < length as u64)
+                    .map(|index| index as u32)
+                    .filter(|index| previous.map_or(true, |previous| previous < *index))
+                    .ok_or_else(|| invalid("invalid array index"))?;
+                previous = Some(index);
+                Ok((index, SerializedValue::from_json(element)?))
+            }
+            _ => Err(invalid("expected an array element")),
+        })
+        .collect::<JsResult<_>>()?;
+    Ok((length, elements))
+}
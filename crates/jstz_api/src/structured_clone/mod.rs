@@ -0,0 +1,692 @@
+//! `jstz`'s implementation of the structured clone algorithm and the
+//! `structuredClone` global.
+//!
+//! Values are serialized into a [`SerializedValue`] which can be deserialized
+//! (possibly in another realm) into a deep copy of the original value. The same
+//! serialization is used for values stored in the `Kv` store (see
+//! [`SerializedValue::to_json`]) and for requests passed between smart functions.
+//!
+//! Supported values are primitives (except symbols), primitive wrapper objects,
+//! `Array`, plain objects, `Date`, `RegExp`, `Map`, `Set`, `ArrayBuffer`, typed
+//! arrays, `DataView`, errors and `Request` objects. Cycles and shared references
+//! are preserved. Other values (e.g. functions) throw a `DataCloneError`.
+//!
+//! More information:
+//!  - [MDN documentation][mdn]
+//!  - [WHATWG `HTML` specification][spec]
+//!
+//! [mdn]: https://developer.mozilla.org/en-US/docs/Web/API/structuredClone
+//! [spec]: https://html.spec.whatwg.org/multipage/structured-data.html#safe-passing-of-structured-data
+
+mod json;
+
+use std::collections::HashMap;
+
+use boa_engine::{
+    builtins::{
+        error::ErrorObject, map::ordered_map::OrderedMap, object::OrdinaryObject,
+        set::ordered_set::OrderedSet,
+    },
+    js_string,
+    object::builtins::{
+        JsArray, JsArrayBuffer, JsDataView, JsDate, JsMap, JsRegExp, JsSet, JsTypedArray,
+    },
+    Context, JsArgs, JsBigInt, JsError, JsNativeError, JsObject, JsResult, JsString,
+    JsValue, NativeFunction,
+};
+use jstz_core::native::JsNativeObject;
+
+use crate::{
+    http::{
+        body::HttpBody,
+        request::{Request, RequestClass},
+    },
+    idl::{dom_exception, BufferSource},
+};
+
+/// A JavaScript value serialized by the structured serialization algorithm.
+///
+/// Objects are numbered in the order in which they are serialized. An object
+/// that was already serialized is represented by a [`SerializedValue::Reference`]
+/// to its number.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SerializedValue {
+    Undefined,
+    Null,
+    Boolean(bool),
+    Number(f64),
+    BigInt(String),
+    String(String),
+    /// A reference to the `n`-th serialized object
+    Reference(usize),
+    BooleanObject(bool),
+    NumberObject(f64),
+    BigIntObject(String),
+    StringObject(String),
+    Date(f64),
+    RegExp {
+        source: String,
+        flags: String,
+    },
+    ArrayBuffer(Vec<u8>),
+    ArrayBufferView {
+        /// The name of the view's constructor, e.g. `"Uint8Array"` or `"DataView"`
+        constructor: String,
+        buffer: Box<SerializedValue>,
+        byte_offset: usize,
+        /// The length of the view (in elements for typed arrays, in bytes for
+        /// `DataView`s)
+        length: usize,
+    },
+    Map(Vec<(SerializedValue, SerializedValue)>),
+    Set(Vec<SerializedValue>),
+    Error {
+        name: String,
+        message: Option<String>,
+    },
+    Array {
+        /// The array's length
+        length: u32,
+        /// The array's own indexed elements, in increasing order of index.
+        /// Holes are not serialized, so sparse arrays stay small.
+        elements: Vec<(u32, SerializedValue)>,
+        /// Any non-index properties of the array
+        properties: Vec<(String, SerializedValue)>,
+    },
+    Object(Vec<(String, SerializedValue)>),
+    Request {
+        method: String,
+        url: String,
+        headers: Vec<(String, String)>,
+        body: HttpBody,
+    },
+}
+
+const TYPED_ARRAYS: [&str; 11] = [
+    "Int8Array",
+    "Uint8Array",
+    "Uint8ClampedArray",
+    "Int16Array",
+    "Uint16Array",
+    "Int32Array",
+    "Uint32Array",
+    "BigInt64Array",
+    "BigUint64Array",
+    "Float32Array",
+    "Float64Array",
+];
+
+const ERROR_NAMES: [&str; 7] = [
+    "Error",
+    "EvalError",
+    "RangeError",
+    "ReferenceError",
+    "SyntaxError",
+    "TypeError",
+    "URIError",
+];
+
+fn data_clone_error(message: &str, context: &mut Context) -> JsError {
+    JsError::from_opaque(dom_exception("DataCloneError", message, context))
+}
+
+/// Returns the own enumerable string-keyed properties of `obj`
+fn own_keys(obj: &JsObject, context: &mut Context) -> JsResult<Vec<JsString>> {
+    let keys =
+        OrdinaryObject::keys(&JsValue::undefined(), &[obj.clone().into()], context)?;
+    let keys = JsArray::from_object(
+        keys.as_object()
+            .expect("`Object.keys` should return an array")
+            .clone(),
+    )?;
+
+    let mut result = Vec::new();
+    for i in 0..keys.length(context)? {
+        let key = keys.get(i, context)?;
+        result.push(key.to_string(context)?);
+    }
+
+    Ok(result)
+}
+
+/// Maximum length of an array that can be cloned
+pub const MAX_ARRAY_LENGTH: u32 = 1 << 24;
+
+fn array_index(key: &JsString) -> Option<u32> {
+    let key = key.to_std_string().ok()?;
+    let index = key.parse::<u32>().ok()?;
+
+    // Canonical array indices only (e.g. not "01")
+    (index.to_string() == key && index != u32::MAX).then_some(index)
+}
+
+struct Serializer {
+    memory: HashMap<JsObject, usize>,
+}
+
+impl Serializer {
+    /// https://html.spec.whatwg.org/multipage/structured-data.html#structuredserializeinternal
+    fn serialize(
+        &mut self,
+        value: &JsValue,
+        context: &mut Context,
+    ) -> JsResult<SerializedValue> {
+        let obj = match value {
+            JsValue::Undefined => return Ok(SerializedValue::Undefined),
+            JsValue::Null => return Ok(SerializedValue::Null),
+            JsValue::Boolean(b) => return Ok(SerializedValue::Boolean(*b)),
+            JsValue::Integer(n) => return Ok(SerializedValue::Number(*n as f64)),
+            JsValue::Rational(n) => return Ok(SerializedValue::Number(*n)),
+            JsValue::BigInt(n) => return Ok(SerializedValue::BigInt(n.to_string())),
+            JsValue::String(s) => {
+                return Ok(SerializedValue::String(s.to_std_string_escaped()))
+            }
+            JsValue::Symbol(_) => {
+                return Err(data_clone_error("Symbols cannot be cloned", context))
+            }
+            JsValue::Object(obj) => obj,
+        };
+
+        // If memory[value] exists, return it
+        if let Some(index) = self.memory.get(obj) {
+            return Ok(SerializedValue::Reference(*index));
+        }
+        self.memory.insert(obj.clone(), self.memory.len());
+
+        if obj.is_callable() {
+            return Err(data_clone_error("Functions cannot be cloned", context));
+        }
+
+        if let Some(b) = obj.downcast_ref::<bool>() {
+            return Ok(SerializedValue::BooleanObject(*b));
+        }
+        if let Some(n) = obj.downcast_ref::<f64>() {
+            return Ok(SerializedValue::NumberObject(*n));
+        }
+        if let Some(n) = obj.downcast_ref::<JsBigInt>() {
+            return Ok(SerializedValue::BigIntObject(n.to_string()));
+        }
+        if let Some(s) = obj.downcast_ref::<JsString>() {
+            return Ok(SerializedValue::StringObject(s.to_std_string_escaped()));
+        }
+
+        if let Ok(date) = JsDate::from_object(obj.clone()) {
+            let time = date.get_time(context)?.to_number(context)?;
+            return Ok(SerializedValue::Date(time));
+        }
+
+        if let Ok(regexp) = JsRegExp::from_object(obj.clone()) {
+            return Ok(SerializedValue::RegExp {
+                source: regexp.source(context)?,
+                flags: regexp.flags(context)?,
+            });
+        }
+
+        if let Ok(buffer) = JsArrayBuffer::from_object(obj.clone()) {
+            return Ok(SerializedValue::ArrayBuffer(buffer.clone_data(context)?));
+        }
+
+        if let Ok(typed_array) = JsTypedArray::from_object(obj.clone()) {
+            let constructor = typed_array
+                .to_string_tag(context)?
+                .to_string(context)?
+                .to_std_string_escaped();
+            if !TYPED_ARRAYS.contains(&constructor.as_str()) {
+                return Err(data_clone_error("Unknown typed array", context));
+            }
+
+            let buffer = typed_array.buffer(context)?;
+            return Ok(SerializedValue::ArrayBufferView {
+                constructor,
+                buffer: Box::new(self.serialize(&buffer, context)?),
+                byte_offset: typed_array.byte_offset(context)?,
+                length: typed_array.length(context)?,
+            });
+        }
+
+        if let Ok(data_view) = JsDataView::from_object(obj.clone()) {
+            let buffer = data_view.buffer(context)?;
+            return Ok(SerializedValue::ArrayBufferView {
+                constructor: "DataView".to_string(),
+                buffer: Box::new(self.serialize(&buffer, context)?),
+                byte_offset: data_view.byte_offset(context)? as usize,
+                length: data_view.byte_length(context)? as usize,
+            });
+        }
+
+        // NB: The borrow of the map (or set) must be released before serializing
+        //     its entries since they may be accessed by getters.
+        let map_entries: Option<Vec<(JsValue, JsValue)>> =
+            obj.downcast_ref::<OrderedMap<JsValue>>().map(|map| {
+                map.iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            });
+        if let Some(entries) = map_entries {
+            let mut serialized = Vec::with_capacity(entries.len());
+            for (key, value) in entries {
+                serialized.push((
+                    self.serialize(&key, context)?,
+                    self.serialize(&value, context)?,
+                ));
+            }
+            return Ok(SerializedValue::Map(serialized));
+        }
+
+        let set_values: Option<Vec<JsValue>> = obj
+            .downcast_ref::<OrderedSet>()
+            .map(|set| set.iter().cloned().collect());
+        if let Some(values) = set_values {
+            let mut serialized = Vec::with_capacity(values.len());
+            for value in values {
+                serialized.push(self.serialize(&value, context)?);
+            }
+            return Ok(SerializedValue::Set(serialized));
+        }
+
+        if obj.is::<ErrorObject>() {
+            let name = obj
+                .get(js_string!("name"), context)?
+                .to_string(context)?
+                .to_std_string_escaped();
+            let name = if ERROR_NAMES.contains(&name.as_str()) {
+                name
+            } else {
+                "Error".to_string()
+            };
+
+            let message = if obj.has_own_property(js_string!("message"), context)? {
+                Some(
+                    obj.get(js_string!("message"), context)?
+                        .to_string(context)?
+                        .to_std_string_escaped(),
+                )
+            } else {
+                None
+            };
+
+            return Ok(SerializedValue::Error { name, message });
+        }
+
+        if let Some(mut request) = obj.downcast_mut::<Request>() {
+            let headers = request
+                .headers()
+                .deref()
+                .entries()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect();
+
+            return Ok(SerializedValue::Request {
+                method: request.method().to_string(),
+                url: request.url().to_string(),
+                headers,
+                body: request.body().to_http_body(),
+            });
+        }
+
+        if let Ok(array) = JsArray::from_object(obj.clone()) {
+            let length = array.length(context)?;
+            if length > MAX_ARRAY_LENGTH as u64 {
+                return Err(data_clone_error(
+                    "The array is too long to be cloned",
+                    context,
+                ));
+            }
+            let mut elements = Vec::new();
+            let mut properties = Vec::new();
+
+            // Own keys list array indices first, in increasing order
+            for key in own_keys(obj, context)? {
+                let value = obj.get(key.clone(), context)?;
+                let value = self.serialize(&value, context)?;
+                match array_index(&key) {
+                    Some(index) if (index as u64) < length => {
+                        elements.push((index, value))
+                    }
+                    _ => properties.push((key.to_std_string_escaped(), value)),
+                }
+            }
+
+            return Ok(SerializedValue::Array {
+                length: length as u32,
+                elements,
+                properties,
+            });
+        }
+
+        // Other platform objects and exotic objects (e.g. proxies, promises or
+        // weak maps) cannot be cloned
+        if !obj.is::<OrdinaryObject>() {
+            return Err(data_clone_error("The object cannot be cloned", context));
+        }
+
+        let mut properties = Vec::new();
+        for key in own_keys(obj, context)? {
+            let value = obj.get(key.clone(), context)?;
+            properties.push((
+                key.to_std_string_escaped(),
+                self.serialize(&value, context)?,
+            ));
+        }
+
+        Ok(SerializedValue::Object(properties))
+    }
+}
+
+struct Deserializer {
+    memory: Vec<JsValue>,
+}
+
+impl Deserializer {
+    /// Reserves the number of the next deserialized object
+    fn reserve(&mut self) -> usize {
+        self.memory.push(JsValue::undefined());
+        self.memory.len() - 1
+    }
+
+    fn remember(&mut self, index: usize, value: JsValue) -> JsValue {
+        self.memory[index] = value.clone();
+        value
+    }
+
+    /// https://html.spec.whatwg.org/multipage/structured-data.html#structureddeserialize
+    fn deserialize(
+        &mut self,
+        serialized: &SerializedValue,
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let value = match serialized {
+            SerializedValue::Undefined => JsValue::undefined(),
+            SerializedValue::Null => JsValue::null(),
+            SerializedValue::Boolean(b) => (*b).into(),
+            SerializedValue::Number(n) => (*n).into(),
+            SerializedValue::BigInt(n) => parse_bigint(n)?.into(),
+            SerializedValue::String(s) => js_string!(s.as_str()).into(),
+            SerializedValue::Reference(index) => {
+                return self.memory.get(*index).cloned().ok_or_else(|| {
+                    data_clone_error("Invalid reference in serialized value", context)
+                });
+            }
+            SerializedValue::BooleanObject(b) => {
+                let index = self.reserve();
+                let obj = JsValue::from(*b).to_object(context)?;
+                self.remember(index, obj.into())
+            }
+            SerializedValue::NumberObject(n) => {
+                let index = self.reserve();
+                let obj = JsValue::from(*n).to_object(context)?;
+                self.remember(index, obj.into())
+            }
+            SerializedValue::BigIntObject(n) => {
+                let index = self.reserve();
+                let obj = JsValue::from(parse_bigint(n)?).to_object(context)?;
+                self.remember(index, obj.into())
+            }
+            SerializedValue::StringObject(s) => {
+                let index = self.reserve();
+                let obj = JsValue::from(js_string!(s.as_str())).to_object(context)?;
+                self.remember(index, obj.into())
+            }
+            SerializedValue::Date(time) => {
+                let index = self.reserve();
+                let date = JsDate::new(context);
+                date.set_time(*time, context)?;
+                self.remember(index, date.into())
+            }
+            SerializedValue::RegExp { source, flags } => {
+                let index = self.reserve();
+                let regexp = JsRegExp::new(source.as_str(), flags.as_str(), context)?;
+                self.remember(index, regexp.into())
+            }
+            SerializedValue::ArrayBuffer(bytes) => {
+                let index = self.reserve();
+                let buffer = JsArrayBuffer::from_byte_block(bytes.clone(), context)?;
+                self.remember(index, buffer.into())
+            }
+            SerializedValue::ArrayBufferView {
+                constructor,
+                buffer,
+                byte_offset,
+                length,
+            } => {
+                let index = self.reserve();
+                let buffer = self.deserialize(buffer, context)?;
+                let constructor = view_constructor(constructor, context)?;
+                let view = constructor.construct(
+                    &[buffer, (*byte_offset).into(), (*length).into()],
+                    None,
+                    context,
+                )?;
+                self.remember(index, view.into())
+            }
+            SerializedValue::Map(entries) => {
+                let index = self.reserve();
+                let map = JsMap::new(context);
+                self.remember(index, map.clone().into());
+                for (key, value) in entries {
+                    let key = self.deserialize(key, context)?;
+                    let value = self.deserialize(value, context)?;
+                    map.set(key, value, context)?;
+                }
+                map.into()
+            }
+            SerializedValue::Set(values) => {
+                let index = self.reserve();
+                let set = JsSet::new(context);
+                self.remember(index, set.clone().into());
+                for value in values {
+                    let value = self.deserialize(value, context)?;
+                    set.add(value, context)?;
+                }
+                set.into()
+            }
+            SerializedValue::Error { name, message } => {
+                let index = self.reserve();
+                let error = match name.as_str() {
+                    "EvalError" => JsNativeError::eval(),
+                    "RangeError" => JsNativeError::range(),
+                    "ReferenceError" => JsNativeError::reference(),
+                    "SyntaxError" => JsNativeError::syntax(),
+                    "TypeError" => JsNativeError::typ(),
+                    "URIError" => JsNativeError::uri(),
+                    _ => JsNativeError::error(),
+                };
+                let error = error
+                    .with_message(message.clone().unwrap_or_default())
+                    .to_opaque(context);
+                self.remember(index, error.into())
+            }
+            SerializedValue::Array {
+                length,
+                elements,
+                properties,
+            } => {
+                if *length > MAX_ARRAY_LENGTH {
+                    return Err(data_clone_error(
+                        "The array is too long to be cloned",
+                        context,
+                    ));
+                }
+                let index = self.reserve();
+                let array = JsArray::new(context);
+                self.remember(index, array.clone().into());
+                array.set(js_string!("length"), *length, true, context)?;
+                for (i, element) in elements {
+                    if *i >= *length {
+                        return Err(data_clone_error(
+                            "Invalid array index in serialized value",
+                            context,
+                        ));
+                    }
+                    let element = self.deserialize(element, context)?;
+                    array.create_data_property_or_throw(*i, element, context)?;
+                }
+                for (key, value) in properties {
+                    let value = self.deserialize(value, context)?;
+                    array.create_data_property_or_throw(
+                        js_string!(key.as_str()),
+                        value,
+                        context,
+                    )?;
+                }
+                array.into()
+            }
+            SerializedValue::Object(properties) => {
+                let index = self.reserve();
+                let obj = JsObject::with_object_proto(context.intrinsics());
+                self.remember(index, obj.clone().into());
+                for (key, value) in properties {
+                    let value = self.deserialize(value, context)?;
+                    obj.create_data_property_or_throw(
+                        js_string!(key.as_str()),
+                        value,
+                        context,
+                    )?;
+                }
+                obj.into()
+            }
+            SerializedValue::Request {
+                method,
+                url,
+                headers,
+                body,
+            } => {
+                let index = self.reserve();
+                let request = deserialize_request(method, url, headers, body, context)?;
+                self.remember(index, request.into())
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+fn parse_bigint(n: &str) -> JsResult<JsBigInt> {
+    JsBigInt::from_string(n).ok_or_else(|| {
+        JsNativeError::syntax()
+            .with_message("Invalid BigInt in serialized value")
+            .into()
+    })
+}
+
+fn view_constructor(name: &str, context: &mut Context) -> JsResult<JsObject> {
+    let constructors = context.intrinsics().constructors();
+    let constructor = match name {
+        "Int8Array" => Some(constructors.typed_int8_array()),
+        "Uint8Array" => Some(constructors.typed_uint8_array()),
+        "Uint8ClampedArray" => Some(constructors.typed_uint8clamped_array()),
+        "Int16Array" => Some(constructors.typed_int16_array()),
+        "Uint16Array" => Some(constructors.typed_uint16_array()),
+        "Int32Array" => Some(constructors.typed_int32_array()),
+        "Uint32Array" => Some(constructors.typed_uint32_array()),
+        "BigInt64Array" => Some(constructors.typed_bigint64_array()),
+        "BigUint64Array" => Some(constructors.typed_biguint64_array()),
+        "Float32Array" => Some(constructors.typed_float32_array()),
+        "Float64Array" => Some(constructors.typed_float64_array()),
+        "DataView" => Some(constructors.data_view()),
+        _ => None,
+    }
+    .map(|constructor| constructor.constructor());
+
+    constructor
+        .ok_or_else(|| data_clone_error("Unknown view type in serialized value", context))
+}
+
+fn deserialize_request(
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+    body: &HttpBody,
+    context: &mut Context,
+) -> JsResult<JsNativeObject<Request>> {
+    let invalid_request = || {
+        JsError::from_native(
+            JsNativeError::typ().with_message("Invalid request in serialized value"),
+        )
+    };
+
+    let mut builder = http::Request::builder().method(method).uri(url);
+    for (name, value) in headers {
+        builder = builder.header(name, value);
+    }
+    let request = builder.body(body.clone()).map_err(|_| invalid_request())?;
+
+    JsNativeObject::new::<RequestClass>(
+        Request::from_http_request(request, context)?,
+        context,
+    )
+}
+
+/// Serializes `value`, throwing a `DataCloneError` if `value` (or any value it
+/// references) cannot be cloned.
+pub fn serialize(value: &JsValue, context: &mut Context) -> JsResult<SerializedValue> {
+    Serializer {
+        memory: HashMap::new(),
+    }
+    .serialize(value, context)
+}
+
+/// Deserializes a value in the current realm of `context`
+pub fn deserialize(
+    serialized: &SerializedValue,
+    context: &mut Context,
+) -> JsResult<JsValue> {
+    Deserializer { memory: Vec::new() }.deserialize(serialized, context)
+}
+
+/// Returns a deep copy of `value`.
+///
+/// More information:
+///  - [WHATWG specification][spec]
+///
+/// [spec] https://html.spec.whatwg.org/multipage/structured-data.html#structured-cloning
+pub fn structured_clone(value: &JsValue, context: &mut Context) -> JsResult<JsValue> {
+    let serialized = serialize(value, context)?;
+    deserialize(&serialized, context)
+}
+
+pub struct StructuredCloneApi;
+
+impl StructuredCloneApi {
+    fn structured_clone(
+        _this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        if args.is_empty() {
+            return Err(JsNativeError::typ()
+                .with_message(
+                    "Failed to execute 'structuredClone': 1 argument required, but only 0 present.",
+                )
+                .into());
+        }
+
+        // Transferring (e.g. of `ArrayBuffer`s) is not supported
+        if let Some(options) = args.get_or_undefined(1).as_object() {
+            let transfer = options.get(js_string!("transfer"), context)?;
+            if let Some(transfer) = transfer.as_object() {
+                let transfer = JsArray::from_object(transfer.clone())?;
+                if transfer.length(context)? > 0 {
+                    return Err(data_clone_error(
+                        "Transferring objects is not supported",
+                        context,
+                    ));
+                }
+            }
+        }
+
+        structured_clone(args.get_or_undefined(0), context)
+    }
+}
+
+impl jstz_core::Api for StructuredCloneApi {
+    fn init(self, context: &mut Context) {
+        context
+            .register_global_builtin_callable(
+                js_string!("structuredClone"),
+                1,
+                NativeFunction::from_fn_ptr(Self::structured_clone),
+            )
+            .expect("structuredClone should only be registered once");
+    }
+}
@@ -282,6 +282,39 @@ async fn get_balance(
     Ok(())
 }
 
+async fn get_tickets(
+    account: Option<AddressOrAlias>,
+    network: Option<NetworkName>,
+) -> Result<()> {
+    let cfg = Config::load()?;
+
+    let address = AddressOrAlias::resolve_or_use_current_user(account, &cfg)?;
+    debug!("resolved `account` -> {:?}", address);
+
+    let tickets = cfg.jstz_client(&network)?.get_tickets(&address).await?;
+    if tickets.is_empty() {
+        info!("{} holds no tickets", address);
+        return Ok(());
+    }
+
+    info!("Tickets of {}:", address);
+    for ticket in tickets {
+        match ticket.metadata {
+            Some(metadata) => info!(
+                "  {}: {} (ticketer: {}, token id: {}, content: {})",
+                ticket.ticket_hash,
+                ticket.amount,
+                metadata.ticketer,
+                metadata.token_id,
+                metadata.content.unwrap_or_else(|| "none".to_string())
+            ),
+            None => info!("  {}: {}", ticket.ticket_hash, ticket.amount),
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Command {
     /// 🌐 Creates a user account.
@@ -326,6 +359,16 @@ pub enum Command {
         #[arg(short, long, default_value = None)]
         network: Option<NetworkName>,
     },
+    /// 🎟️ Outputs the tickets held by an account.
+    Tickets {
+        /// Address or alias of the account (user or smart function).
+        #[arg(short, long, value_name = "ALIAS|ADDRESS")]
+        account: Option<AddressOrAlias>,
+        /// Specifies the network from the config file, defaulting to the configured default network.
+        /// Use `dev` for the local sandbox.
+        #[arg(short, long, default_value = None)]
+        network: Option<NetworkName>,
+    },
     /// 🔄 Creates alias for a deployed smart function.
     Alias {
         /// Alias of the smart function.
@@ -345,5 +388,6 @@ pub async fn exec(command: Command) -> Result<()> {
         Command::List { long } => list_accounts(long),
         Command::Code { account, network } => get_code(account, network).await,
         Command::Balance { account, network } => get_balance(account, network).await,
+        Command::Tickets { account, network } => get_tickets(account, network).await,
     }
 }
@@ -37,6 +37,11 @@ pub enum Command {
         /// The amount in XTZ to transfer.
         #[arg(short, long)]
         amount: f64,
+        /// Waits until the withdrawal can be executed on the L1 and prints the commitment and
+        /// proof to execute it, or until it is executed if the jstz node executes cemented
+        /// withdrawals, as the sandbox does.
+        #[arg(long, default_value_t = false)]
+        wait: bool,
         /// Specifies the network from the config file, defaulting to the configured default network.
         /// Use `dev` for the local sandbox.
         #[arg(short, long, default_value = None)]
@@ -55,8 +60,9 @@ pub async fn exec(command: Command) -> Result<()> {
         Command::Withdraw {
             to,
             amount,
+            wait,
             network,
-        } => withdraw::exec(to, amount, network).await,
+        } => withdraw::exec(to, amount, wait, network).await,
     }
 }
 
@@ -1,16 +1,20 @@
 use crate::{
     bridge::convert_tez_to_mutez,
     config::{Config, NetworkName},
-    error::{bail_user_error, Result},
+    error::{anyhow, bail, bail_user_error, Result},
     run,
     term::styles,
     utils::AddressOrAlias,
 };
-use log::debug;
+use jstz_node::withdrawals::WithdrawalStatus;
+use log::{debug, info};
+use serde::Deserialize;
+use spinners::{Spinner, Spinners};
 
 pub async fn exec(
     to: AddressOrAlias,
     amount: f64,
+    wait: bool,
     network: Option<NetworkName>,
 ) -> Result<()> {
     let cfg = Config::load()?;
@@ -35,5 +39,91 @@ pub async fn exec(
         receiver: to_pkh,
     };
     let json_data = serde_json::to_string(&withdraw)?;
-    run::exec(url, http_method, gas_limit, Some(json_data), network, false).await
+    let receipt = run::run_function(
+        url,
+        http_method,
+        gas_limit,
+        Some(json_data),
+        network.clone(),
+        false,
+    )
+    .await?;
+    if !wait {
+        return Ok(());
+    }
+
+    if !receipt.status_code.is_success() {
+        bail_user_error!("The withdrawal failed.");
+    }
+    let outbox_message_id = receipt
+        .body
+        .as_deref()
+        .and_then(|body| serde_json::from_slice::<WithdrawalResponse>(body).ok())
+        .ok_or(anyhow!("Invalid withdrawal response"))?
+        .outbox_message_id;
+    debug!("Outbox message id: {}", outbox_message_id);
+
+    let mut spinner = Spinner::new(
+        Spinners::BoxBounce2,
+        "Waiting for the withdrawal to be executable on L1...".into(),
+    );
+    match cfg
+        .jstz_client(&network)?
+        .wait_for_withdrawal(&outbox_message_id)
+        .await?
+    {
+        WithdrawalStatus::Executed { operation_hash, .. } => {
+            spinner.stop_with_symbol(&format!(
+                "Withdrawal executed on L1 in operation {}",
+                operation_hash
+            ));
+        }
+        WithdrawalStatus::Executable {
+            level,
+            index,
+            commitment,
+            proof,
+        } => {
+            spinner.stop_with_symbol(&format!(
+                "Withdrawal ready to be executed on L1 (outbox message {} of level {})",
+                index, level
+            ));
+            print_execution_instructions(&commitment, &proof);
+        }
+        WithdrawalStatus::Failed {
+            level,
+            index,
+            commitment,
+            proof,
+            error,
+        } => {
+            spinner.stop_with_symbol(&format!(
+                "The node failed to execute the withdrawal on L1 \
+                 (outbox message {} of level {}): {}",
+                index, level, error
+            ));
+            print_execution_instructions(&commitment, &proof);
+        }
+        status => bail!("Unexpected withdrawal status: {:?}", status),
+    }
+
+    Ok(())
+}
+
+fn print_execution_instructions(commitment: &str, proof: &str) {
+    info!("Commitment: {}", commitment);
+    info!("Proof: {}", proof);
+    info!(
+        "Execute it with `{}`",
+        styles::command(format!(
+            "octez-client execute outbox message of smart rollup <ROLLUP_ADDRESS> \
+             from <SOURCE> for commitment hash {} and output proof {}",
+            commitment, proof
+        ))
+    );
+}
+
+#[derive(Deserialize)]
+struct WithdrawalResponse {
+    outbox_message_id: String,
 }
@@ -1,8 +1,18 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
 use boa_engine::JsError;
+use jstz_crypto::secret_key::SecretKey;
 use jstz_proto::{
     context::account::ParsedCode,
-    operation::{Content, DeployFunction, Operation, SignedOperation},
+    operation::{
+        chunk::{OperationChunk, MAX_CHUNKS},
+        Content, DeployFunction, Operation, RevealDeployFunction, SignedOperation,
+    },
     receipt::{ReceiptContent, ReceiptResult},
+    reveal_data::{self, PreimageHash},
 };
 use log::{debug, info};
 
@@ -10,19 +20,62 @@ use crate::{
     account,
     config::{Config, NetworkName, SmartFunction},
     error::{anyhow, bail, bail_user_error, user_error, Result},
+    jstz::JstzClient,
     term::styles,
     utils::read_file_or_input_or_piped,
 };
 
+/// Writes the preimages of `code` to `preimages_dir`, returning the root hash.
+fn write_preimages(code: &str, preimages_dir: &Path) -> Result<PreimageHash> {
+    fs::create_dir_all(preimages_dir)?;
+
+    let mut result = Ok(());
+    let root_hash = reveal_data::prepare_preimages(code.as_bytes(), |hash, page| {
+        if result.is_ok() {
+            result = fs::write(preimages_dir.join(hash.to_string()), page);
+        }
+    });
+    result?;
+
+    Ok(root_hash)
+}
+
+/// Posts `signed_op` to jstz-node, splitting it into chunks signed with
+/// `secret_key` if it is too large to fit in a single external message.
+async fn post_operation(
+    jstz_client: &JstzClient,
+    signed_op: &SignedOperation,
+    secret_key: &SecretKey,
+) -> Result<()> {
+    let encoded_op = bincode::serialize(signed_op)?;
+
+    if !OperationChunk::is_required(&encoded_op) {
+        return jstz_client.post_operation(signed_op).await;
+    }
+
+    let chunks = OperationChunk::split(&encoded_op, &signed_op.public_key, secret_key)?;
+    if chunks.len() > MAX_CHUNKS as usize {
+        bail_user_error!(
+            "The smart function is too large to be deployed in chunks. Please use {}.",
+            styles::command("--reveal")
+        );
+    }
+
+    debug!("Posting operation in {} chunks", chunks.len());
+    for chunk in &chunks {
+        jstz_client.post_operation_chunk(chunk).await?;
+    }
+
+    Ok(())
+}
+
 pub async fn exec(
     code_op: Option<String>,
     balance: u64,
     name: Option<String>,
+    reveal: Option<Option<PathBuf>>,
     network: Option<NetworkName>,
 ) -> Result<()> {
-    // maximum size of code until the DAL is implemented
-    const MAX_CODE_LENGTH: usize = 3915;
-
     let mut cfg = Config::load()?;
     // Load sandbox if the selected network is Dev and sandbox is not already loaded
     if cfg.network_name(&network)? == NetworkName::Dev && cfg.sandbox.is_none() {
@@ -60,22 +113,46 @@ pub async fn exec(
     let code = read_file_or_input_or_piped(code_op)?
         .ok_or(user_error!("No function code supplied. Please provide a filename or pipe the file contents into stdin."))?;
 
-    if code.bytes().len() > MAX_CODE_LENGTH {
-        bail_user_error!("The data availability layer is not yet available. Smart functions are currently restricted to {MAX_CODE_LENGTH} bytes");
-    }
-
     debug!("Code: {}", code);
 
     let code: ParsedCode = code
         .try_into()
         .map_err(|err: JsError| user_error!("{err}"))?;
-    let op = Operation {
-        source: user.address.clone(),
-        nonce,
-        content: Content::DeployFunction(DeployFunction {
+
+    let content = match reveal {
+        None => Content::DeployFunction(DeployFunction {
             function_code: code,
             account_credit: balance,
         }),
+        Some(preimages_dir) => {
+            let preimages_dir = match preimages_dir {
+                Some(preimages_dir) => preimages_dir,
+                None if cfg.network_name(&network)? == NetworkName::Dev => {
+                    cfg.sandbox()?.octez_rollup_node_dir.join("wasm_2_0_0")
+                }
+                None => bail_user_error!(
+                    "Please provide the preimages directory of the rollup node with {}.",
+                    styles::command("--reveal <PREIMAGES_DIR>")
+                ),
+            };
+
+            let root_hash = write_preimages(&String::from(code), &preimages_dir)?;
+            debug!(
+                "Preimages written to {:?} (root hash: {})",
+                preimages_dir, root_hash
+            );
+
+            Content::RevealDeployFunction(RevealDeployFunction {
+                root_hash,
+                account_credit: balance,
+            })
+        }
+    };
+
+    let op = Operation {
+        source: user.address.clone(),
+        nonce,
+        content,
     };
 
     debug!("Operation: {:?}", op);
@@ -90,7 +167,7 @@ pub async fn exec(
     debug!("Signed operation: {:?}", signed_op);
 
     // 3. Send operation to jstz-node
-    jstz_client.post_operation(&signed_op).await?;
+    post_operation(&jstz_client, &signed_op, &user.secret_key).await?;
     let receipt = jstz_client.wait_for_operation_receipt(&hash).await?;
 
     debug!("Receipt: {:?}", receipt);
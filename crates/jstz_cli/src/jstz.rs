@@ -2,9 +2,13 @@ use std::time::Duration;
 
 use anyhow::{bail, Result};
 use jstz_api::KvValue;
+use jstz_node::{mempool::OperationStatus, withdrawals::WithdrawalStatus};
 use jstz_proto::{
-    context::account::{Address, Nonce},
-    operation::{OperationHash, SignedOperation},
+    context::{
+        account::{Address, Nonce},
+        ticket_table::TicketHolding,
+    },
+    operation::{chunk::OperationChunk, OperationHash, SignedOperation},
     receipt::Receipt,
 };
 use log::debug;
@@ -49,6 +53,23 @@ impl JstzClient {
         }
     }
 
+    pub async fn get_operation_status(
+        &self,
+        hash: &OperationHash,
+    ) -> Result<OperationStatus> {
+        let response = self
+            .get(&format!("{}/operations/{}/status", self.endpoint, hash))
+            .await?;
+
+        match response.status() {
+            StatusCode::OK => {
+                let status = response.json::<OperationStatus>().await?;
+                Ok(status)
+            }
+            _ => bail!("Failed to get the operation status"),
+        }
+    }
+
     pub async fn get_nonce(&self, address: &Address) -> Result<Nonce> {
         let response = self
             .get(&format!("{}/accounts/{}/nonce", self.endpoint, address))
@@ -85,6 +106,40 @@ impl JstzClient {
         }
     }
 
+    pub async fn get_tickets(&self, address: &Address) -> Result<Vec<TicketHolding>> {
+        let response = self
+            .get(&format!("{}/accounts/{}/tickets", self.endpoint, address))
+            .await?;
+
+        match response.status() {
+            StatusCode::OK => {
+                let tickets = response.json::<Vec<TicketHolding>>().await?;
+                Ok(tickets)
+            }
+            _ => bail!("Failed to get the tickets"),
+        }
+    }
+
+    pub async fn get_withdrawal_status(
+        &self,
+        outbox_message_id: &str,
+    ) -> Result<WithdrawalStatus> {
+        let response = self
+            .get(&format!(
+                "{}/outbox/withdrawals/{}/status",
+                self.endpoint, outbox_message_id
+            ))
+            .await?;
+
+        match response.status() {
+            StatusCode::OK => {
+                let status = response.json::<WithdrawalStatus>().await?;
+                Ok(status)
+            }
+            _ => bail!("Failed to get the withdrawal status"),
+        }
+    }
+
     pub async fn get_balance(&self, address: &Address) -> Result<u64> {
         let response = self
             .get(&format!("{}/accounts/{}/balance", self.endpoint, address))
@@ -167,12 +222,43 @@ impl JstzClient {
                 return Ok(receipt);
             }
 
+            match self.get_operation_status(hash).await? {
+                OperationStatus::Failed { reason, .. } => {
+                    bail_user_error!("Operation failed: {}", reason)
+                }
+                status => debug!("Operation status: {:?}", status),
+            }
+
             // tokio sleep
             sleep(Duration::from_millis(200)).await;
             retries += 1;
         }
     }
 
+    /// Waits until the outbox message of the withdrawal `outbox_message_id`
+    /// can be executed on the L1, or was executed or given up on by the node,
+    /// returning the status of the withdrawal. The commitment of the level of the message is
+    /// cemented after the refutation period of the rollup, hence no timeout.
+    pub async fn wait_for_withdrawal(
+        &self,
+        outbox_message_id: &str,
+    ) -> Result<WithdrawalStatus> {
+        loop {
+            let status = self.get_withdrawal_status(outbox_message_id).await?;
+            debug!("Withdrawal status: {:?}", status);
+            if matches!(
+                status,
+                WithdrawalStatus::Executable { .. }
+                    | WithdrawalStatus::Executed { .. }
+                    | WithdrawalStatus::Failed { .. }
+            ) {
+                return Ok(status);
+            }
+
+            sleep(Duration::from_secs(2)).await;
+        }
+    }
+
     pub async fn post_operation(&self, operation: &SignedOperation) -> Result<()> {
         let response = self
             .client
@@ -183,11 +269,33 @@ impl JstzClient {
 
         match response.status() {
             StatusCode::OK => Ok(()),
+            StatusCode::BAD_REQUEST => {
+                let error = response.json::<serde_json::Value>().await?;
+                bail_user_error!(
+                    "Operation rejected: {}",
+                    error["error"].as_str().unwrap_or("unknown error")
+                )
+            }
             // For any other status, return a generic error
             _ => bail!("Failed to post operation"),
         }
     }
 
+    pub async fn post_operation_chunk(&self, chunk: &OperationChunk) -> Result<()> {
+        let response = self
+            .client
+            .post(&format!("{}/operations/chunks", self.endpoint))
+            .json(chunk)
+            .send()
+            .await?;
+
+        match response.status() {
+            StatusCode::OK => Ok(()),
+            // For any other status, return a generic error
+            _ => bail!("Failed to post operation chunk"),
+        }
+    }
+
     async fn get(&self, url: &str) -> Result<reqwest::Response> {
         Ok(self.client.get(url).send().await?)
     }
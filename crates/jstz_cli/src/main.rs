@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use clap::Parser;
 use clap_complete::Shell;
 
@@ -37,6 +39,11 @@ enum Command {
         /// Name (or alias) of the function.
         #[arg(long, default_value = None)]
         name: Option<String>,
+        /// Deploys the function code through the reveal data channel by writing its
+        /// preimages to the given preimages directory of the rollup node.
+        /// Defaults to the rollup node of the local sandbox.
+        #[arg(long, value_name = "PREIMAGES_DIR", num_args = 0..=1)]
+        reveal: Option<Option<PathBuf>>,
         /// Specifies the network from the config file, defaulting to the configured default network.
         /// Use `dev` for the local sandbox.
         #[arg(short, long, default_value = None)]
@@ -121,8 +128,9 @@ async fn exec(command: Command) -> Result<()> {
             code,
             balance,
             name,
+            reveal,
             network,
-        } => deploy::exec(code, balance, name, network).await,
+        } => deploy::exec(code, balance, name, reveal, network).await,
         Command::Run {
             url,
             http_method,
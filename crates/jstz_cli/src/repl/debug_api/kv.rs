@@ -4,7 +4,10 @@ use boa_engine::{
     js_string, object::ObjectInitializer, Context, JsArgs, JsObject, JsResult, JsValue,
     NativeFunction,
 };
-use jstz_api::{Kv, KvValue};
+use jstz_api::{
+    structured_clone::{deserialize, serialize, SerializedValue},
+    Kv, KvValue,
+};
 use jstz_core::runtime;
 
 pub struct KvApi;
@@ -22,7 +25,9 @@ impl KvApi {
 
         runtime::with_js_hrt_and_tx(|hrt, tx| -> JsResult<JsValue> {
             match kv.get(hrt.deref(), tx, &key)? {
-                Some(value) => JsValue::from_json(&value.0, context),
+                Some(value) => {
+                    deserialize(&SerializedValue::from_json(&value.0)?, context)
+                }
                 None => Ok(JsValue::null()),
             }
         })
@@ -36,7 +41,7 @@ impl KvApi {
         let account: String = args.get_or_undefined(0).try_js_into(context)?;
         let key: String = args.get_or_undefined(1).try_js_into(context)?;
 
-        let value = KvValue(args.get_or_undefined(2).to_json(context)?);
+        let value = KvValue(serialize(args.get_or_undefined(2), context)?.to_json());
 
         let kv = Kv::new(account);
 
@@ -6,7 +6,7 @@ use jstz_proto::context::account::Address;
 use jstz_proto::executor::JSTZ_HOST;
 use jstz_proto::{
     operation::{Content as OperationContent, Operation, RunFunction, SignedOperation},
-    receipt::{ReceiptContent, ReceiptResult},
+    receipt::{ReceiptContent, ReceiptResult, RunFunctionReceipt},
 };
 use log::{debug, info};
 use spinners::{Spinner, Spinners};
@@ -65,6 +65,19 @@ pub async fn exec(
     network: Option<NetworkName>,
     trace: bool,
 ) -> Result<()> {
+    run_function(url, http_method, gas_limit, json_data, network, trace).await?;
+    Ok(())
+}
+
+/// Runs the function at `url` and returns the receipt of its response
+pub async fn run_function(
+    url: String,
+    http_method: String,
+    gas_limit: u32,
+    json_data: Option<String>,
+    network: Option<NetworkName>,
+    trace: bool,
+) -> Result<RunFunctionReceipt> {
     // 1. Get the current user (checking if we are logged in)
     let mut cfg = Config::load()?;
     account::login_quick(&mut cfg)?;
@@ -164,12 +177,8 @@ pub async fn exec(
     let receipt = jstz_client.wait_for_operation_receipt(&hash).await?;
 
     debug!("Receipt: {:?}", receipt);
-    let (status_code, headers, body) = match receipt.result {
-        ReceiptResult::Success(ReceiptContent::RunFunction(run_function)) => (
-            run_function.status_code,
-            run_function.headers,
-            run_function.body,
-        ),
+    let run_function = match receipt.result {
+        ReceiptResult::Success(ReceiptContent::RunFunction(run_function)) => run_function,
         ReceiptResult::Success(_) => {
             bail!("Expected a `RunFunction` receipt, but got something else.")
         }
@@ -177,20 +186,30 @@ pub async fn exec(
         ReceiptResult::Failed(err) => bail_user_error!("{err}"),
     };
 
+    let status = if run_function.status_text.is_empty() {
+        format!("Status code: {}", run_function.status_code)
+    } else {
+        format!(
+            "Status code: {} {}",
+            run_function.status_code.as_u16(),
+            run_function.status_text
+        )
+    };
+
     if let Some(spinner) = spinner.as_mut() {
-        spinner.stop_with_symbol(&format!("Status code: {}", status_code));
+        spinner.stop_with_symbol(&status);
     } else {
-        info!("Status code: {}", status_code);
+        info!("{}", status);
     }
 
-    info!("Headers: {:?}", headers);
-    if let Some(body) = body {
-        info!("Body: {}", String::from_utf8_lossy(&body));
+    info!("Headers: {:?}", run_function.headers);
+    if let Some(body) = &run_function.body {
+        info!("Body: {}", String::from_utf8_lossy(body));
     }
 
     cfg.save()?;
 
-    Ok(())
+    Ok(run_function)
 }
 
 async fn spawn_trace(address: &Address, jstz_client: &JstzClient) -> Result<()> {
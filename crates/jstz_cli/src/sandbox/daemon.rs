@@ -15,13 +15,17 @@ use console::style;
 use futures::Future;
 use in_container::in_container;
 use indicatif::{ProgressBar, ProgressStyle};
+use jstz_node::config::{NodeSettings, OutboxExecutorConfig};
 use jstz_rollup::{rollup::make_installer, Exchanger, JstzRollup, NativeBridge};
 use log::info;
 use nix::{
     sys::signal::{kill, Signal},
     unistd::Pid,
 };
-use octez::OctezThread;
+use octez::{
+    r#async::{client::OctezClientConfigBuilder, endpoint::Endpoint},
+    OctezThread,
+};
 use prettytable::{format::consts::FORMAT_DEFAULT, Cell, Row, Table};
 use regex::Regex;
 use signal_hook::{
@@ -474,6 +478,19 @@ fn spawn_jstz_node(cfg: &Config) -> Result<impl Future<Output = Result<()>> + 's
         .open(log_path.clone())?;
     debug!(log_file, "Jstz node started 🎉");
 
+    // The operator executes the withdrawals of the sandbox on the L1
+    let mut octez_client =
+        OctezClientConfigBuilder::new(Endpoint::localhost(SANDBOX_OCTEZ_NODE_RPC_PORT))
+            .set_base_dir(cfg.sandbox()?.octez_client_dir.clone())
+            .set_disable_unsafe_disclaimer(true);
+    if let Some(octez_path) = &cfg.octez_path {
+        octez_client = octez_client.set_binary_path(octez_path.join("octez-client"));
+    }
+    let outbox_executor = OutboxExecutorConfig {
+        executor: OPERATOR_ADDRESS.to_string(),
+        octez_client: octez_client.build()?,
+    };
+
     Ok(jstz_node::run(
         SANDBOX_LOCAL_HOST_ADDR,
         SANDBOX_JSTZ_NODE_PORT,
@@ -482,6 +499,9 @@ fn spawn_jstz_node(cfg: &Config) -> Result<impl Future<Output = Result<()>> + 's
             SANDBOX_LOCAL_HOST_ADDR, SANDBOX_OCTEZ_SMART_ROLLUP_PORT
         ),
         kernel_log_path,
+        None,
+        Some(outbox_executor),
+        NodeSettings::default(),
     ))
 }
 
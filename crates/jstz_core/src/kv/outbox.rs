@@ -1,6 +1,12 @@
 use crate::error::Result;
 use derive_more::{Display, Error, From};
+use nom::{
+    bytes::complete::take,
+    error::{make_error, ErrorKind},
+    number::complete::{be_u32, u8 as nom_u8},
+};
 use serde::{Deserialize, Serialize};
+use tezos_crypto_rs::hash::ContractKt1Hash;
 use tezos_smart_rollup::{
     core_unsafe::MAX_OUTPUT_SIZE,
     michelson::{ticket::FA2_1Ticket, MichelsonContract, MichelsonPair},
@@ -12,19 +18,255 @@ use tezos_smart_rollup::{
     types::{Contract, Entrypoint},
 };
 
-use tezos_data_encoding::{enc::BinWriter, encoding::HasEncoding, nom::NomReader};
-use tezos_smart_rollup_host::{path::RefPath, runtime::Runtime};
+use tezos_data_encoding::{
+    enc::BinWriter,
+    encoding::{Encoding, HasEncoding},
+    nom::{NomReader, NomResult},
+};
+use tezos_smart_rollup_host::{
+    path::{self, OwnedPath, RefPath},
+    runtime::Runtime,
+};
 
-use super::Storage;
+use super::{transaction::Transaction, value, Storage};
 
 const PERSISTENT_OUTBOX_QUEUE_ROOT: RefPath<'static> =
     RefPath::assert_from(b"/outbox/persistent");
 
 const JSTZ_OUTBOX_QUEUE_META: RefPath<'static> = RefPath::assert_from(b"/outbox/meta");
 
+const LEVEL_OUTPUTS_PATH: RefPath<'static> =
+    RefPath::assert_from(b"/outbox/level_outputs");
+
+/// Path of the withdrawals waiting to be batched, each stored under its index
+/// in the queue
+const PENDING_WITHDRAWALS_PATH: RefPath<'static> =
+    RefPath::assert_from(b"/outbox/withdrawals/pending");
+
+/// Path of the number of withdrawals waiting to be batched at the end of the
+/// level
+pub const PENDING_WITHDRAWALS_LEN_PATH: RefPath<'static> =
+    RefPath::assert_from(b"/outbox/withdrawals/len");
+
+/// Path of the typed calls waiting for space in the outbox
+const PENDING_CALLS_PATH: RefPath<'static> = RefPath::assert_from(b"/outbox/calls");
+
+const PENDING_CALLS_META_PATH: RefPath<'static> =
+    RefPath::assert_from(b"/outbox/calls/meta");
+
+const NEXT_WITHDRAWAL_ID_PATH: RefPath<'static> =
+    RefPath::assert_from(b"/outbox/withdrawals/next_id");
+
+/// Path under which the outbox location of each withdrawal is recorded, once
+/// its batch has been written to the outbox
+pub const WITHDRAWAL_LOCATIONS_PATH: RefPath<'static> =
+    RefPath::assert_from(b"/outbox/withdrawals/locations");
+
+// Size of the tag of an outbox message and of the length prefix of a batch
+const OUTBOX_MESSAGE_TAG_SIZE: usize = 1;
+const BATCH_LENGTH_PREFIX_SIZE: usize = 4;
+// Tag of typed transaction batches in the outbox
+const TYPED_TRANSACTION_BATCH_TAG: u8 = 1;
+
 type WithdrawalParameters = MichelsonPair<MichelsonContract, FA2_1Ticket>;
 type Withdrawal = OutboxMessageTransactionBatch<WithdrawalParameters>;
 
+// Tags of the binary encoding of Micheline nodes
+const INT_TAG: u8 = 0;
+const STRING_TAG: u8 = 1;
+const SEQ_TAG: u8 = 2;
+const PRIM_NO_ARGS_TAG: u8 = 3;
+const PRIM_NO_ARGS_ANNOTS_TAG: u8 = 4;
+const PRIM_1_ARG_TAG: u8 = 5;
+const PRIM_1_ARG_ANNOTS_TAG: u8 = 6;
+const PRIM_2_ARGS_TAG: u8 = 7;
+const PRIM_2_ARGS_ANNOTS_TAG: u8 = 8;
+const PRIM_GENERIC_TAG: u8 = 9;
+const BYTES_TAG: u8 = 10;
+// Code of the `Pair` data constructor
+const PAIR_PRIM: u8 = 7;
+// Codes of the Michelson types
+const LIST_TYPE_PRIM: u8 = 95;
+const NAT_TYPE_PRIM: u8 = 98;
+const OPTION_TYPE_PRIM: u8 = 99;
+const PAIR_TYPE_PRIM: u8 = 101;
+const BYTES_TYPE_PRIM: u8 = 105;
+const TICKET_TYPE_PRIM: u8 = 135;
+// Maximum nesting of a Micheline expression
+const MAX_MICHELINE_DEPTH: usize = 256;
+
+/// A Michelson value or type in the binary encoding of Micheline expressions,
+/// used as the parameters of arbitrary L1 contract calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MichelineExpr(Vec<u8>);
+
+impl MichelineExpr {
+    /// Fails if `bytes` is not the encoding of a single Micheline expression.
+    pub fn try_from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        match skip_node(&bytes, 0, &mut |_| ()) {
+            Ok(([], ())) => Ok(Self(bytes)),
+            _ => Err(OutboxError::InvalidMichelineExpr)?,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Whether the expression, read as a type, contains a `ticket` type
+    pub fn contains_ticket_type(&self) -> bool {
+        let mut contains_ticket = false;
+        let _ = skip_node(&self.0, 0, &mut |prim| {
+            contains_ticket |= prim == TICKET_TYPE_PRIM
+        });
+        contains_ticket
+    }
+
+    /// Returns the type `pair self (list (ticket (pair nat (option bytes))))`
+    /// of the values returned by [Self::pair_with_tickets]
+    pub fn pair_with_tickets_type(&self) -> Self {
+        let mut bytes = vec![PRIM_2_ARGS_TAG, PAIR_TYPE_PRIM];
+        bytes.extend_from_slice(&self.0);
+        bytes.extend_from_slice(&[
+            PRIM_1_ARG_TAG,
+            LIST_TYPE_PRIM,
+            PRIM_1_ARG_TAG,
+            TICKET_TYPE_PRIM,
+            PRIM_2_ARGS_TAG,
+            PAIR_TYPE_PRIM,
+            PRIM_NO_ARGS_TAG,
+            NAT_TYPE_PRIM,
+            PRIM_1_ARG_TAG,
+            OPTION_TYPE_PRIM,
+            PRIM_NO_ARGS_TAG,
+            BYTES_TYPE_PRIM,
+        ]);
+        Self(bytes)
+    }
+
+    /// Returns the expression `Pair self { ticket_1; ...; ticket_n }`
+    pub fn pair_with_tickets(&self, tickets: &[FA2_1Ticket]) -> Result<Self> {
+        let mut encoded_tickets = Vec::new();
+        for ticket in tickets {
+            ticket
+                .bin_write(&mut encoded_tickets)
+                .map_err(|_| OutboxError::OutboxMessageSerializationError)?;
+        }
+        let mut bytes = vec![PRIM_2_ARGS_TAG, PAIR_PRIM];
+        bytes.extend_from_slice(&self.0);
+        bytes.push(SEQ_TAG);
+        bytes.extend_from_slice(&(encoded_tickets.len() as u32).to_be_bytes());
+        bytes.extend(encoded_tickets);
+        Ok(Self(bytes))
+    }
+}
+
+fn skip_zarith(mut input: &[u8]) -> NomResult<()> {
+    loop {
+        let (rest, byte) = nom_u8(input)?;
+        input = rest;
+        if byte & 0x80 == 0 {
+            return Ok((input, ()));
+        }
+    }
+}
+
+fn take_dynamic(input: &[u8]) -> NomResult<&[u8]> {
+    let (input, size) = be_u32(input)?;
+    take(size)(input)
+}
+
+fn skip_nodes<'a>(
+    mut input: &'a [u8],
+    depth: usize,
+    on_prim: &mut dyn FnMut(u8),
+) -> NomResult<'a, ()> {
+    while !input.is_empty() {
+        (input, ()) = skip_node(input, depth, on_prim)?;
+    }
+    Ok((input, ()))
+}
+
+// Reads the code of a primitive application
+fn read_prim<'a>(input: &'a [u8], on_prim: &mut dyn FnMut(u8)) -> NomResult<'a, ()> {
+    let (input, prim) = nom_u8(input)?;
+    on_prim(prim);
+    Ok((input, ()))
+}
+
+/// Parses a Micheline node, returning the input that follows it. `on_prim` is
+/// called with the code of each primitive application of the node.
+fn skip_node<'a>(
+    input: &'a [u8],
+    depth: usize,
+    on_prim: &mut dyn FnMut(u8),
+) -> NomResult<'a, ()> {
+    if depth > MAX_MICHELINE_DEPTH {
+        return Err(nom::Err::Error(make_error(input, ErrorKind::TooLarge)));
+    }
+    let depth = depth + 1;
+    let (input, tag) = nom_u8(input)?;
+    match tag {
+        INT_TAG => skip_zarith(input),
+        STRING_TAG | BYTES_TAG => take_dynamic(input).map(|(input, _)| (input, ())),
+        SEQ_TAG => {
+            let (input, nodes) = take_dynamic(input)?;
+            skip_nodes(nodes, depth, on_prim)?;
+            Ok((input, ()))
+        }
+        PRIM_NO_ARGS_TAG => read_prim(input, on_prim),
+        PRIM_NO_ARGS_ANNOTS_TAG => {
+            let (input, ()) = read_prim(input, on_prim)?;
+            take_dynamic(input).map(|(input, _)| (input, ()))
+        }
+        PRIM_1_ARG_TAG | PRIM_1_ARG_ANNOTS_TAG => {
+            let (input, ()) = read_prim(input, on_prim)?;
+            let (input, ()) = skip_node(input, depth, on_prim)?;
+            if tag == PRIM_1_ARG_ANNOTS_TAG {
+                return take_dynamic(input).map(|(input, _)| (input, ()));
+            }
+            Ok((input, ()))
+        }
+        PRIM_2_ARGS_TAG | PRIM_2_ARGS_ANNOTS_TAG => {
+            let (input, ()) = read_prim(input, on_prim)?;
+            let (input, ()) = skip_node(input, depth, on_prim)?;
+            let (input, ()) = skip_node(input, depth, on_prim)?;
+            if tag == PRIM_2_ARGS_ANNOTS_TAG {
+                return take_dynamic(input).map(|(input, _)| (input, ()));
+            }
+            Ok((input, ()))
+        }
+        PRIM_GENERIC_TAG => {
+            let (input, ()) = read_prim(input, on_prim)?;
+            let (input, args) = take_dynamic(input)?;
+            skip_nodes(args, depth, on_prim)?;
+            take_dynamic(input).map(|(input, _)| (input, ()))
+        }
+        _ => Err(nom::Err::Error(make_error(input, ErrorKind::Tag))),
+    }
+}
+
+impl HasEncoding for MichelineExpr {
+    fn encoding() -> Encoding {
+        Encoding::Custom
+    }
+}
+
+impl BinWriter for MichelineExpr {
+    fn bin_write(&self, output: &mut Vec<u8>) -> tezos_data_encoding::enc::BinResult {
+        output.extend_from_slice(&self.0);
+        Ok(())
+    }
+}
+
+impl<'a> NomReader<'a> for MichelineExpr {
+    fn nom_read(input: &'a [u8]) -> NomResult<'a, Self> {
+        let (rest, ()) = skip_node(input, 0, &mut |_| ())?;
+        let expr = input[..input.len() - rest.len()].to_vec();
+        Ok((rest, MichelineExpr(expr)))
+    }
+}
+
 #[derive(Debug, HasEncoding, PartialEq)]
 pub enum OutboxMessage {
     Withdrawal(Withdrawal),
@@ -64,9 +306,9 @@ impl BinWriter for OutboxMessage {
 
 impl<'a> NomReader<'a> for OutboxMessage {
     fn nom_read(input: &'a [u8]) -> tezos_data_encoding::nom::NomResult<'a, Self> {
-        nom::branch::alt((nom::combinator::map(Withdrawal::nom_read, |withdrawal| {
+        nom::combinator::map(Withdrawal::nom_read, |withdrawal| {
             OutboxMessage::Withdrawal(withdrawal)
-        }),))(input)
+        })(input)
     }
 }
 
@@ -76,6 +318,441 @@ impl From<OutboxMessage> for OutboxMessageFull<OutboxMessage> {
     }
 }
 
+/// A call to an L1 contract, written to the outbox as a typed transaction
+/// batch. On L1, `parameters` are read with the type `parameters_ty`, which
+/// must be the type of the destination entrypoint. Ticket values are only
+/// accepted where `parameters_ty` has a ticket type, so the rollup cannot be
+/// made to send tickets that are not accounted for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypedCall {
+    parameters: MichelineExpr,
+    parameters_ty: MichelineExpr,
+    destination: Contract,
+    entrypoint: Entrypoint,
+}
+
+impl TypedCall {
+    /// Fails if the entrypoint is invalid or if the call does not fit in an
+    /// outbox message.
+    pub fn new(
+        destination: &ContractKt1Hash,
+        entrypoint: &str,
+        parameters: MichelineExpr,
+        parameters_ty: MichelineExpr,
+    ) -> Result<Self> {
+        let entrypoint = Entrypoint::try_from(entrypoint.to_string())
+            .map_err(|_| OutboxError::InvalidEntrypoint)?;
+        let call = Self {
+            parameters,
+            parameters_ty,
+            destination: Contract::Originated(destination.clone()),
+            entrypoint,
+        };
+        if call.encode()?.len() > MAX_OUTPUT_SIZE {
+            Err(OutboxError::OutboxMessageTooLarge)?
+        }
+        Ok(call)
+    }
+
+    /// Returns the binary encoding of the outbox message of the call
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut transaction = Vec::new();
+        self.parameters
+            .bin_write(&mut transaction)
+            .and_then(|()| self.parameters_ty.bin_write(&mut transaction))
+            .and_then(|()| self.destination.bin_write(&mut transaction))
+            .and_then(|()| self.entrypoint.bin_write(&mut transaction))
+            .map_err(|_| OutboxError::OutboxMessageSerializationError)?;
+
+        let mut bytes = Vec::with_capacity(
+            OUTBOX_MESSAGE_TAG_SIZE + BATCH_LENGTH_PREFIX_SIZE + transaction.len(),
+        );
+        bytes.push(TYPED_TRANSACTION_BATCH_TAG);
+        bytes.extend_from_slice(&(transaction.len() as u32).to_be_bytes());
+        bytes.extend(transaction);
+        Ok(bytes)
+    }
+}
+
+/// Identifier of a withdrawal, assigned when the withdrawal is queued
+pub type WithdrawalId = u64;
+
+/// Position of an outbox message in the outbox, required to execute the
+/// message on L1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxLocation {
+    /// Level at which the message was written to the outbox
+    pub level: u32,
+    /// Index of the message in the outbox of `level`
+    pub index: u32,
+}
+
+/// Number of messages written to the outbox during `level`, used to
+/// compute the [OutboxLocation] of withdrawal batches.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct LevelOutputs {
+    level: u32,
+    count: u32,
+}
+
+impl LevelOutputs {
+    fn load(rt: &impl Runtime) -> Result<Self> {
+        Ok(Storage::get(rt, &LEVEL_OUTPUTS_PATH)?.unwrap_or_default())
+    }
+
+    fn save(&self, rt: &mut impl Runtime) -> Result<()> {
+        Storage::insert(rt, &LEVEL_OUTPUTS_PATH, self)
+    }
+
+    fn record(rt: &mut impl Runtime, count: u32) -> Result<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        let mut outputs = Self::load(rt)?;
+        outputs.count += count;
+        outputs.save(rt)
+    }
+}
+
+/// Resets the count of messages written to the outbox at the start of
+/// `level`. Must be called on each start of level for the recorded
+/// [OutboxLocation]s to be accurate.
+pub fn start_level(rt: &mut impl Runtime, level: u32) -> Result<()> {
+    LevelOutputs { level, count: 0 }.save(rt)
+}
+
+/// A withdrawal waiting to be aggregated with the other withdrawals to the
+/// same ticketer at the end of the level.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct QueuedWithdrawal {
+    id: WithdrawalId,
+    /// Base58 encoded destination (ticketer) of the withdrawal
+    ticketer: String,
+    /// Binary encoding of the transactions of the withdrawal, without the
+    /// length prefix of the batch
+    transactions: Vec<u8>,
+}
+
+impl QueuedWithdrawal {
+    pub fn new(id: WithdrawalId, message: &OutboxMessage) -> Result<Self> {
+        let OutboxMessage::Withdrawal(withdrawal) = message else {
+            return Err(OutboxError::InvalidWithdrawal.into());
+        };
+        if withdrawal.is_empty() {
+            Err(OutboxError::InvalidWithdrawal)?
+        }
+        let ticketer = withdrawal[0].destination.to_b58check();
+        if (1..withdrawal.len())
+            .any(|i| withdrawal[i].destination.to_b58check() != ticketer)
+        {
+            Err(OutboxError::InvalidWithdrawal)?
+        }
+
+        let mut bytes = Vec::new();
+        withdrawal
+            .bin_write(&mut bytes)
+            .map_err(|_| OutboxError::OutboxMessageSerializationError)?;
+        let transactions = bytes.split_off(BATCH_LENGTH_PREFIX_SIZE);
+        if OUTBOX_MESSAGE_TAG_SIZE + BATCH_LENGTH_PREFIX_SIZE + transactions.len()
+            > MAX_OUTPUT_SIZE
+        {
+            Err(OutboxError::OutboxMessageTooLarge)?
+        }
+        Ok(Self {
+            id,
+            ticketer,
+            transactions,
+        })
+    }
+}
+
+/// Withdrawals to the same ticketer, aggregated into a single outbox message
+struct WithdrawalBatch {
+    withdrawals: Vec<QueuedWithdrawal>,
+    size: usize,
+}
+
+impl WithdrawalBatch {
+    fn new(withdrawal: QueuedWithdrawal) -> Self {
+        Self {
+            size: withdrawal.transactions.len(),
+            withdrawals: vec![withdrawal],
+        }
+    }
+
+    fn try_push(&mut self, withdrawal: QueuedWithdrawal) -> Option<QueuedWithdrawal> {
+        let size = self.size + withdrawal.transactions.len();
+        if OUTBOX_MESSAGE_TAG_SIZE + BATCH_LENGTH_PREFIX_SIZE + size > MAX_OUTPUT_SIZE {
+            return Some(withdrawal);
+        }
+        self.size = size;
+        self.withdrawals.push(withdrawal);
+        None
+    }
+
+    fn to_message(&self) -> Result<OutboxMessage> {
+        let mut bytes = Vec::with_capacity(BATCH_LENGTH_PREFIX_SIZE + self.size);
+        bytes.extend_from_slice(&(self.size as u32).to_be_bytes());
+        for withdrawal in &self.withdrawals {
+            bytes.extend_from_slice(&withdrawal.transactions);
+        }
+        let (_, withdrawal) = Withdrawal::nom_read(&bytes)
+            .map_err(|_| OutboxError::OutboxMessageSerializationError)?;
+        Ok(OutboxMessage::Withdrawal(withdrawal))
+    }
+}
+
+/// Groups `withdrawals` by ticketer, in order of first appearance, and packs
+/// each group into as few batches as fit in an outbox message.
+fn batch_withdrawals(withdrawals: Vec<QueuedWithdrawal>) -> Vec<WithdrawalBatch> {
+    let mut groups: Vec<(String, Vec<WithdrawalBatch>)> = Vec::new();
+    for withdrawal in withdrawals {
+        let position = groups
+            .iter()
+            .position(|(ticketer, _)| *ticketer == withdrawal.ticketer);
+        let batches = match position {
+            Some(position) => &mut groups[position].1,
+            None => {
+                groups.push((withdrawal.ticketer.clone(), Vec::new()));
+                &mut groups.last_mut().expect("Group was just pushed").1
+            }
+        };
+        let overflow = match batches.last_mut() {
+            Some(batch) => batch.try_push(withdrawal),
+            None => Some(withdrawal),
+        };
+        if let Some(withdrawal) = overflow {
+            batches.push(WithdrawalBatch::new(withdrawal));
+        }
+    }
+    groups
+        .into_iter()
+        .flat_map(|(_, batches)| batches)
+        .collect()
+}
+
+fn withdrawal_location_path(id: WithdrawalId) -> Result<OwnedPath> {
+    let id_path = OwnedPath::try_from(format!("/{}", id))?;
+    Ok(path::concat(&WITHDRAWAL_LOCATIONS_PATH, &id_path)?)
+}
+
+/// Returns the outbox location of the withdrawal `id`, or `None` if the
+/// withdrawal has not been written to the outbox yet.
+pub fn withdrawal_location(
+    rt: &impl Runtime,
+    id: WithdrawalId,
+) -> Result<Option<OutboxLocation>> {
+    Storage::get(rt, &withdrawal_location_path(id)?)
+}
+
+/// Returns the number of withdrawals waiting to be batched
+pub(crate) fn pending_withdrawals_len(rt: &impl Runtime) -> Result<u32> {
+    Ok(Storage::get(rt, &PENDING_WITHDRAWALS_LEN_PATH)?.unwrap_or(0))
+}
+
+/// Allocates the identifier of the next queued withdrawal. The counter is
+/// updated in durable storage rather than in the transaction, such that the
+/// identifiers of rolled back withdrawals are never reused.
+pub(crate) fn next_withdrawal_id(rt: &mut impl Runtime) -> Result<WithdrawalId> {
+    let id = Storage::get::<WithdrawalId>(rt, &NEXT_WITHDRAWAL_ID_PATH)?.unwrap_or(0);
+    Storage::insert(rt, &NEXT_WITHDRAWAL_ID_PATH, &(id + 1))?;
+    Ok(id)
+}
+
+fn pending_withdrawal_path(index: u32) -> Result<OwnedPath> {
+    let index_path = OwnedPath::try_from(format!("/{}", index))?;
+    Ok(path::concat(&PENDING_WITHDRAWALS_PATH, &index_path)?)
+}
+
+// Appends `withdrawals` to the pending withdrawals, of which there are `len`
+fn push_pending_withdrawals(
+    rt: &mut impl Runtime,
+    len: u32,
+    withdrawals: Vec<QueuedWithdrawal>,
+) -> Result<()> {
+    let mut len = len;
+    for withdrawal in withdrawals {
+        // A withdrawal may exceed the maximum size of a single write
+        rt.store_write_all(
+            &pending_withdrawal_path(len)?,
+            &value::serialize(&withdrawal)?,
+        )?;
+        len += 1;
+    }
+    Storage::insert(rt, &PENDING_WITHDRAWALS_LEN_PATH, &len)
+}
+
+fn read_pending_withdrawals(rt: &impl Runtime) -> Result<Vec<QueuedWithdrawal>> {
+    (0..pending_withdrawals_len(rt)?)
+        .map(|index| {
+            let bytes = rt.store_read_all(&pending_withdrawal_path(index)?)?;
+            value::deserialize(&bytes)
+        })
+        .collect()
+}
+
+/// Appends `withdrawals` to the withdrawals waiting to be batched
+pub(crate) fn queue_withdrawals(
+    rt: &mut impl Runtime,
+    withdrawals: Vec<QueuedWithdrawal>,
+) -> Result<()> {
+    if withdrawals.is_empty() {
+        return Ok(());
+    }
+    let len = pending_withdrawals_len(rt)?;
+    push_pending_withdrawals(rt, len, withdrawals)
+}
+
+/// Aggregates the pending withdrawals to the same ticketer into batches and
+/// writes them to the outbox, recording the [OutboxLocation] of each
+/// withdrawal. Batches that do not fit in the outbox of the current level
+/// remain pending until the next flush.
+pub(crate) fn flush_withdrawals(rt: &mut impl Runtime) -> Result<()> {
+    let pending = read_pending_withdrawals(rt)?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let mut outputs = LevelOutputs::load(rt)?;
+    let mut remaining = Vec::new();
+    let mut batch_count = 0;
+    for batch in batch_withdrawals(pending) {
+        if remaining.is_empty() {
+            let message = batch.to_message()?.into();
+            match write_outbox_message(rt, &message) {
+                Ok(()) => {
+                    let location = OutboxLocation {
+                        level: outputs.level,
+                        index: outputs.count,
+                    };
+                    for withdrawal in &batch.withdrawals {
+                        Storage::insert(
+                            rt,
+                            &withdrawal_location_path(withdrawal.id)?,
+                            &location,
+                        )?;
+                    }
+                    outputs.count += 1;
+                    batch_count += 1;
+                    continue;
+                }
+                Err(crate::Error::HostError {
+                    source:
+                        tezos_smart_rollup::host::RuntimeError::HostErr(
+                            tezos_smart_rollup_host::Error::FullOutbox,
+                        ),
+                }) => (),
+                Err(e) => {
+                    debug_msg!(rt, "Error while writing withdrawals to outbox: {:?}", e);
+                }
+            }
+        }
+        remaining.extend(batch.withdrawals);
+    }
+
+    outputs.save(rt)?;
+    // The withdrawals that did not fit in the outbox are queued again, from the
+    // start of the queue
+    if rt.store_has(&PENDING_WITHDRAWALS_PATH)?.is_some() {
+        rt.store_delete(&PENDING_WITHDRAWALS_PATH)?;
+    }
+    let remaining_len = remaining.len();
+    push_pending_withdrawals(rt, 0, remaining)?;
+    debug_msg!(
+        rt,
+        "Flush withdrawals (batches: {}, pending withdrawals: {})\n",
+        batch_count,
+        remaining_len
+    );
+    Ok(())
+}
+
+/// Indexes of the typed calls waiting for space in the outbox. The encoded
+/// calls are stored under [PENDING_CALLS_PATH], from `head` (included) to
+/// `tail` (excluded).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct PendingCalls {
+    head: u64,
+    tail: u64,
+}
+
+impl PendingCalls {
+    fn load(rt: &impl Runtime) -> Result<Self> {
+        Ok(Storage::get(rt, &PENDING_CALLS_META_PATH)?.unwrap_or_default())
+    }
+
+    fn save(&self, rt: &mut impl Runtime) -> Result<()> {
+        Storage::insert(rt, &PENDING_CALLS_META_PATH, self)
+    }
+
+    fn len(&self) -> u32 {
+        (self.tail - self.head) as u32
+    }
+
+    fn path(index: u64) -> Result<OwnedPath> {
+        let index_path = OwnedPath::try_from(format!("/{}", index))?;
+        Ok(path::concat(&PENDING_CALLS_PATH, &index_path)?)
+    }
+}
+
+/// Returns the number of typed calls waiting for space in the outbox
+pub(crate) fn pending_calls_len(rt: &impl Runtime) -> Result<u32> {
+    Ok(PendingCalls::load(rt)?.len())
+}
+
+// Writes `output` to the outbox, returning `false` if it did not fit in the
+// outbox of the current level
+fn try_write_output(rt: &mut impl Runtime, output: &[u8]) -> bool {
+    match rt.write_output(output) {
+        Ok(()) => true,
+        Err(tezos_smart_rollup::host::RuntimeError::HostErr(
+            tezos_smart_rollup_host::Error::FullOutbox,
+        )) => false,
+        Err(e) => {
+            debug_msg!(rt, "Error while writing call to outbox: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Writes the pending typed calls, then `calls`, to the outbox. Calls that
+/// do not fit in the outbox of the current level remain pending until the
+/// next flush.
+pub(crate) fn flush_calls(rt: &mut impl Runtime, calls: Vec<TypedCall>) -> Result<()> {
+    let mut pending = PendingCalls::load(rt)?;
+    if pending.len() == 0 && calls.is_empty() {
+        return Ok(());
+    }
+
+    let mut flushed_count = 0;
+    while pending.head < pending.tail {
+        let path = PendingCalls::path(pending.head)?;
+        let output = rt.store_read_all(&path)?;
+        if !try_write_output(rt, &output) {
+            break;
+        }
+        rt.store_delete(&path)?;
+        pending.head += 1;
+        flushed_count += 1;
+    }
+    for call in calls {
+        let output = call.encode()?;
+        if pending.len() == 0 && try_write_output(rt, &output) {
+            flushed_count += 1;
+            continue;
+        }
+        rt.store_write_all(&PendingCalls::path(pending.tail)?, &output)?;
+        pending.tail += 1;
+    }
+
+    pending.save(rt)?;
+    LevelOutputs::record(rt, flushed_count)?;
+    if pending.len() > 0 {
+        debug_msg!(rt, "Flush calls (pending calls: {})\n", pending.len());
+    }
+    Ok(())
+}
+
 /// Represents a pending outbox queue stored as part of the
 /// trasaction's snapshot.
 #[derive(Debug, Default)]
@@ -307,6 +984,7 @@ pub(crate) fn flush(
 
     //  3. Enqueue the remaining messages into the outbox queue
     persistent_queue.batch_queue_message(rt, outbox_messages)?;
+    LevelOutputs::record(rt, flushed_count)?;
     if flushed_count > 0 {
         debug_msg!(
             rt,
@@ -329,12 +1007,17 @@ pub enum OutboxError {
     OutboxQueueMetaAlreadyExists,
     InvalidTicketType,
     InvalidEntrypoint,
+    InvalidMichelineExpr,
+    /// Outbox message exceeds the maximum size of an outbox message
+    OutboxMessageTooLarge,
+    /// Withdrawals must be single ticketer withdrawal messages
+    InvalidWithdrawal,
 }
 
 #[cfg(test)]
 mod test {
     use jstz_crypto::public_key_hash::PublicKeyHash;
-    use tezos_data_encoding::nom::NomReader;
+    use tezos_data_encoding::{enc::BinWriter, nom::NomReader};
     use tezos_smart_rollup::{
         michelson::{
             ticket::FA2_1Ticket, MichelsonContract, MichelsonNat, MichelsonOption,
@@ -348,7 +1031,15 @@ mod test {
 
     use crate::kv::outbox::{flush, write_outbox_message, PersistentOutboxQueue};
 
-    use super::{OutboxMessage, SnapshotOutboxQueue};
+    use super::{
+        flush_calls, pending_calls_len, MichelineExpr, OutboxMessage,
+        SnapshotOutboxQueue, TypedCall,
+    };
+
+    // Micheline encoding of `Pair 1 "a"`
+    const PAIR_EXPR: [u8; 10] = [7, 7, 0, 1, 1, 0, 0, 0, 1, b'a'];
+    // Micheline encoding of `pair int string`
+    const PAIR_TYPE: [u8; 6] = [7, 101, 3, 91, 3, 104];
 
     fn make_withdrawal(account: &PublicKeyHash) -> OutboxMessage {
         let creator =
@@ -524,6 +1215,98 @@ mod test {
         ));
     }
 
+    #[test]
+    fn micheline_expr_rejects_invalid_encodings() {
+        assert!(MichelineExpr::try_from_bytes(PAIR_EXPR.to_vec()).is_ok());
+        assert!(MichelineExpr::try_from_bytes(PAIR_EXPR[..9].to_vec()).is_err());
+        assert!(MichelineExpr::try_from_bytes([&PAIR_EXPR[..], &[0]].concat()).is_err());
+        assert!(MichelineExpr::try_from_bytes(vec![42]).is_err());
+        assert!(MichelineExpr::try_from_bytes(vec![]).is_err());
+    }
+
+    #[test]
+    fn typed_call_encoding() {
+        let creator =
+            Contract::from_b58check("KT1NgXQ6Mwu3XKFDcKdYFS6dkkY3iNKdBKEc").unwrap();
+        let ticket = FA2_1Ticket::new(
+            creator.clone(),
+            MichelsonPair(MichelsonNat::from(0), MichelsonOption(None)),
+            10,
+        )
+        .unwrap();
+        let parameters = MichelineExpr::try_from_bytes(PAIR_EXPR.to_vec())
+            .unwrap()
+            .pair_with_tickets(&[ticket.clone(), ticket])
+            .unwrap();
+        assert!(MichelineExpr::try_from_bytes(parameters.as_bytes().to_vec()).is_ok());
+        let parameters_ty = MichelineExpr::try_from_bytes(PAIR_TYPE.to_vec())
+            .unwrap()
+            .pair_with_tickets_type();
+        assert!(MichelineExpr::try_from_bytes(parameters_ty.as_bytes().to_vec()).is_ok());
+
+        let Contract::Originated(destination) = creator.clone() else {
+            unreachable!()
+        };
+        let call = TypedCall::new(
+            &destination,
+            "settle",
+            parameters.clone(),
+            parameters_ty.clone(),
+        )
+        .unwrap();
+        let bytes = call.encode().unwrap();
+
+        let mut transaction = parameters.as_bytes().to_vec();
+        transaction.extend_from_slice(parameters_ty.as_bytes());
+        creator.bin_write(&mut transaction).unwrap();
+        Entrypoint::try_from("settle".to_string())
+            .unwrap()
+            .bin_write(&mut transaction)
+            .unwrap();
+        assert_eq!(1, bytes[0]);
+        assert_eq!((transaction.len() as u32).to_be_bytes(), bytes[1..5]);
+        assert_eq!(transaction, bytes[5..]);
+    }
+
+    #[test]
+    fn micheline_expr_detects_ticket_types() {
+        let pair_ty = MichelineExpr::try_from_bytes(PAIR_TYPE.to_vec()).unwrap();
+        assert!(!pair_ty.contains_ticket_type());
+        assert!(pair_ty.pair_with_tickets_type().contains_ticket_type());
+        // `ticket unit`
+        let ticket_ty = MichelineExpr::try_from_bytes(vec![5, 135, 3, 108]).unwrap();
+        assert!(ticket_ty.contains_ticket_type());
+    }
+
+    #[test]
+    fn flush_calls_keeps_calls_that_do_not_fit_in_the_outbox() {
+        let mut host = MockHost::default();
+        let Contract::Originated(destination) =
+            Contract::from_b58check("KT1NgXQ6Mwu3XKFDcKdYFS6dkkY3iNKdBKEc").unwrap()
+        else {
+            unreachable!()
+        };
+        let call = TypedCall::new(
+            &destination,
+            "default",
+            MichelineExpr::try_from_bytes(PAIR_EXPR.to_vec()).unwrap(),
+            MichelineExpr::try_from_bytes(PAIR_TYPE.to_vec()).unwrap(),
+        )
+        .unwrap();
+
+        flush_calls(&mut host, vec![call.clone(); 120]).unwrap();
+        assert_eq!(20, pending_calls_len(&host).unwrap());
+        let level = host.run_level(|_| {});
+        assert_eq!(100, host.outbox_at(level).len());
+
+        flush_calls(&mut host, vec![]).unwrap();
+        assert_eq!(0, pending_calls_len(&host).unwrap());
+        let level = host.run_level(|_| {});
+        let outbox = host.outbox_at(level);
+        assert_eq!(20, outbox.len());
+        assert_eq!(call.encode().unwrap(), outbox[0]);
+    }
+
     #[test]
     fn extend_snapshot() {
         let acc1 = PublicKeyHash::digest(b"account1").unwrap();
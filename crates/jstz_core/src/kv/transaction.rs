@@ -11,7 +11,8 @@ use tezos_smart_rollup_host::{path::OwnedPath, runtime::Runtime};
 
 use super::{
     outbox::{
-        flush, OutboxError, OutboxMessage, PersistentOutboxQueue, SnapshotOutboxQueue,
+        self, flush, OutboxError, OutboxMessage, PersistentOutboxQueue, QueuedWithdrawal,
+        SnapshotOutboxQueue, TypedCall, WithdrawalId,
     },
     value::{BoxedValue, Value},
     Storage,
@@ -110,6 +111,10 @@ struct Snapshot {
     // A set of 'remove' edits to be applied
     remove_edits: BTreeSet<Key>,
     outbox_queue: SnapshotOutboxQueue,
+    // Withdrawals to be batched at the end of the level
+    withdrawals: Vec<QueuedWithdrawal>,
+    // Typed calls to L1 contracts
+    calls: Vec<TypedCall>,
 }
 
 impl Snapshot {
@@ -351,22 +356,71 @@ impl Transaction {
         }
     }
 
+    // Fails if queueing another message would exceed the capacity of the
+    // outbox queue, counting the withdrawals that are not batched yet and the
+    // calls waiting for space in the outbox
+    fn check_outbox_capacity(&mut self, rt: &mut impl Runtime) -> Result<()> {
+        if self.persistent_outbox.len(rt)?
+            + outbox::pending_withdrawals_len(rt)?
+            + outbox::pending_calls_len(rt)?
+            + self.snapshot_outbox_len
+            + 1
+            > self.persistent_outbox.max(rt)?
+        {
+            Err(OutboxError::OutboxQueueFull)?;
+        }
+        Ok(())
+    }
+
     pub fn queue_outbox_message(
         &mut self,
         rt: &mut impl Runtime,
         message: OutboxMessage,
     ) -> Result<()> {
-        if self.persistent_outbox.len(rt)? + self.snapshot_outbox_len + 1
-            > self.persistent_outbox.max(rt)?
-        {
-            Err(OutboxError::OutboxQueueFull)?;
-        }
+        self.check_outbox_capacity(rt)?;
         let current_outbox_queue = self.current_snapshot()?.outbox_queue_mut();
         current_outbox_queue.queue_message(message);
         self.snapshot_outbox_len += 1;
         Ok(())
     }
 
+    /// Queues a withdrawal message. Unlike [Self::queue_outbox_message], the
+    /// withdrawal is not written to the outbox when the root transaction is
+    /// committed but aggregated with the other withdrawals to the same
+    /// ticketer of the level by [Self::flush_withdrawals].
+    ///
+    /// Returns the id under which the outbox location of the withdrawal is
+    /// recorded once written to the outbox.
+    pub fn queue_withdrawal(
+        &mut self,
+        rt: &mut impl Runtime,
+        message: OutboxMessage,
+    ) -> Result<WithdrawalId> {
+        self.check_outbox_capacity(rt)?;
+        let id = outbox::next_withdrawal_id(rt)?;
+        let withdrawal = QueuedWithdrawal::new(id, &message)?;
+        self.current_snapshot()?.withdrawals.push(withdrawal);
+        self.snapshot_outbox_len += 1;
+        Ok(id)
+    }
+
+    /// Queues a typed call to an L1 contract, written to the outbox when the
+    /// root transaction is committed.
+    pub fn queue_call(&mut self, rt: &mut impl Runtime, call: TypedCall) -> Result<()> {
+        self.check_outbox_capacity(rt)?;
+        self.current_snapshot()?.calls.push(call);
+        self.snapshot_outbox_len += 1;
+        Ok(())
+    }
+
+    /// Writes the withdrawals queued during the level to the outbox, batched
+    /// per ticketer, after the calls still waiting for space in the outbox.
+    /// Should be called once the inbox of the level has been read.
+    pub fn flush_withdrawals(&mut self, rt: &mut impl Runtime) -> Result<()> {
+        outbox::flush_calls(rt, Vec::new())?;
+        outbox::flush_withdrawals(rt)
+    }
+
     /// Begin a transaction.
     pub fn begin(&mut self) {
         self.stack.push(Snapshot::default())
@@ -392,6 +446,8 @@ impl Transaction {
             }
 
             prev_ctxt.outbox_queue.extend(curr_ctxt.outbox_queue);
+            prev_ctxt.withdrawals.extend(curr_ctxt.withdrawals);
+            prev_ctxt.calls.extend(curr_ctxt.calls);
         } else {
             for key in &curr_ctxt.remove_edits {
                 Storage::remove(rt, key)?
@@ -402,6 +458,8 @@ impl Transaction {
             }
 
             flush(rt, &mut self.persistent_outbox, curr_ctxt.outbox_queue)?;
+            outbox::flush_calls(rt, curr_ctxt.calls)?;
+            outbox::queue_withdrawals(rt, curr_ctxt.withdrawals)?;
             self.snapshot_outbox_len = 0;
 
             // Update lookup map
@@ -625,15 +683,30 @@ mod test {
     use tezos_smart_rollup_mock::MockHost;
 
     use crate::kv::{
-        outbox::{OutboxMessage, PersistentOutboxQueue},
+        outbox::{
+            pending_withdrawals_len, withdrawal_location, OutboxLocation, OutboxMessage,
+            PersistentOutboxQueue,
+        },
         Storage,
     };
 
     use super::Transaction;
 
     fn make_withdrawal(account: &PublicKeyHash) -> OutboxMessage {
-        let creator =
-            Contract::from_b58check("KT1NgXQ6Mwu3XKFDcKdYFS6dkkY3iNKdBKEc").unwrap();
+        make_withdrawal_to(account, "KT1NgXQ6Mwu3XKFDcKdYFS6dkkY3iNKdBKEc")
+    }
+
+    fn make_withdrawal_to(account: &PublicKeyHash, ticketer: &str) -> OutboxMessage {
+        OutboxMessage::Withdrawal(
+            vec![make_withdrawal_transaction(account, ticketer)].into(),
+        )
+    }
+
+    fn make_withdrawal_transaction(
+        account: &PublicKeyHash,
+        ticketer: &str,
+    ) -> OutboxMessageTransaction<MichelsonPair<MichelsonContract, FA2_1Ticket>> {
+        let creator = Contract::from_b58check(ticketer).unwrap();
         let parameters = MichelsonPair(
             MichelsonContract(Contract::try_from(account.to_base58()).unwrap()),
             FA2_1Ticket::new(
@@ -643,12 +716,11 @@ mod test {
             )
             .unwrap(),
         );
-        let outbox_tx = OutboxMessageTransaction {
+        OutboxMessageTransaction {
             parameters,
             destination: creator,
             entrypoint: Entrypoint::try_from("burn".to_string()).unwrap(),
-        };
-        OutboxMessage::Withdrawal(vec![outbox_tx].into())
+        }
     }
 
     #[test]
@@ -898,4 +970,130 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn flush_withdrawals_batches_withdrawals_per_ticketer() {
+        let mut host = MockHost::default();
+        let mut tx = Transaction::default();
+        let ticketers = [
+            "KT1NgXQ6Mwu3XKFDcKdYFS6dkkY3iNKdBKEc",
+            "KT1TxqZ8QtKvLu3V3JH7Gx58n7Co8pgtpQU5",
+        ];
+
+        let mut ids = vec![];
+        for i in 0..5 {
+            tx.begin();
+            let acc = PublicKeyHash::digest(format!("account{}", i).as_bytes()).unwrap();
+            let message = make_withdrawal_to(&acc, ticketers[i % 2]);
+            ids.push(tx.queue_withdrawal(&mut host, message).unwrap());
+            tx.commit(&mut host).unwrap();
+        }
+        assert_eq!(None, withdrawal_location(&host, ids[0]).unwrap());
+
+        tx.flush_withdrawals(&mut host).unwrap();
+        let level = host.run_level(|_| {});
+        let outbox = host.outbox_at(level);
+        assert_eq!(2, outbox.len());
+
+        for (index, ticketer) in ticketers.iter().enumerate() {
+            let (_, message) =
+                OutboxMessageFull::<OutboxMessage>::nom_read(outbox[index].as_slice())
+                    .unwrap();
+            let expected: Vec<_> = (index..5)
+                .step_by(2)
+                .map(|i| {
+                    let acc = PublicKeyHash::digest(format!("account{}", i).as_bytes())
+                        .unwrap();
+                    make_withdrawal_transaction(&acc, ticketer)
+                })
+                .collect();
+            assert_eq!(message, OutboxMessage::Withdrawal(expected.into()).into());
+        }
+
+        for (i, id) in ids.into_iter().enumerate() {
+            assert_eq!(
+                Some(OutboxLocation {
+                    level: 0,
+                    index: (i % 2) as u32
+                }),
+                withdrawal_location(&host, id).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn queue_withdrawal_does_not_reuse_ids_of_rolled_back_withdrawals() {
+        let mut host = MockHost::default();
+        let mut tx = Transaction::default();
+        let acc = PublicKeyHash::digest(b"account").unwrap();
+
+        tx.begin();
+        assert_eq!(
+            0,
+            tx.queue_withdrawal(&mut host, make_withdrawal(&acc))
+                .unwrap()
+        );
+        tx.rollback().unwrap();
+
+        // The ids of rolled back withdrawals may have been exposed, so they
+        // are not reused
+        tx.begin();
+        assert_eq!(
+            1,
+            tx.queue_withdrawal(&mut host, make_withdrawal(&acc))
+                .unwrap()
+        );
+        assert_eq!(
+            2,
+            tx.queue_withdrawal(&mut host, make_withdrawal(&acc))
+                .unwrap()
+        );
+        tx.commit(&mut host).unwrap();
+
+        tx.begin();
+        assert_eq!(
+            3,
+            tx.queue_withdrawal(&mut host, make_withdrawal(&acc))
+                .unwrap()
+        );
+        tx.commit(&mut host).unwrap();
+        assert_eq!(3, pending_withdrawals_len(&host).unwrap());
+        assert_eq!(None, withdrawal_location(&host, 0).unwrap());
+    }
+
+    #[test]
+    fn queue_outbox_message_counts_pending_withdrawals() {
+        let mut host = MockHost::default();
+        let mut tx = Transaction {
+            persistent_outbox: PersistentOutboxQueue::try_new(&mut host, 10).unwrap(),
+            ..Transaction::default()
+        };
+
+        tx.begin();
+        for i in 0..10 {
+            let acc = PublicKeyHash::digest(format!("account{}", i).as_bytes()).unwrap();
+            tx.queue_withdrawal(&mut host, make_withdrawal(&acc))
+                .unwrap();
+        }
+        tx.commit(&mut host).unwrap();
+
+        // The withdrawals are not batched yet, but count towards the capacity
+        tx.begin();
+        let acc = PublicKeyHash::digest(b"failure account").unwrap();
+        let error = tx
+            .queue_withdrawal(&mut host, make_withdrawal(&acc))
+            .expect_err("Outbox should be full");
+        assert!(matches!(
+            error,
+            crate::error::Error::OutboxError {
+                source: crate::kv::outbox::OutboxError::OutboxQueueFull
+            }
+        ));
+        tx.rollback().unwrap();
+
+        tx.flush_withdrawals(&mut host).unwrap();
+        tx.begin();
+        tx.queue_withdrawal(&mut host, make_withdrawal(&acc))
+            .unwrap();
+    }
 }
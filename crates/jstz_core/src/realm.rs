@@ -17,6 +17,7 @@ use derive_more::{Deref, DerefMut, From};
 
 use crate::{
     native::{register_global_class, NativeClass},
+    runtime::Timers,
     Api,
 };
 
@@ -302,14 +303,19 @@ impl HostDefined {
 impl Realm {
     pub fn new(context: &mut Context) -> JsResult<Self> {
         // 1. Create `boa_engine` realm with defined host hooks
+        let timers = Timers::from_context(context);
         let realm = Self {
             inner: context.create_realm()?,
         };
 
-        // 2. Initialize `HostDefined`
+        // 2. Initialize `HostDefined`, sharing the timers of the current realm (if any)
         {
             let mut context = realm.context_handle(context);
-            HostDefined::new().init(&mut context);
+            let mut host_defined = HostDefined::new();
+            if let Some(timers) = timers {
+                host_defined.insert(timers);
+            }
+            host_defined.init(&mut context);
         }
 
         Ok(realm)
@@ -18,10 +18,15 @@ use getrandom::{register_custom_getrandom, Error as RandomError};
 use crate::{
     future,
     host::{HostRuntime, JsHostRuntime},
+    host_defined,
     kv::{JsTransaction, Transaction},
     realm::{Module, Realm},
 };
 
+pub mod timers;
+
+pub use timers::{TimerId, Timers, TimersApi};
+
 // This is the unix timestamp for date 31-07-2023 10:50:26 -- the date of the first commit
 const UTC_NOW: i64 = 1690797026;
 
@@ -67,27 +72,44 @@ fn always_fail(_: &mut [u8]) -> std::result::Result<(), RandomError> {
 register_custom_getrandom!(always_fail);
 
 /// A 'pollable' job queue
+///
+/// Promise jobs are always run before timers. Once no promise jobs remain,
+/// the virtual clock is advanced to the earliest pending timer.
 #[derive(Default, Debug)]
-struct JobQueue(RefCell<VecDeque<NativeJob>>);
+struct JobQueue {
+    jobs: RefCell<VecDeque<NativeJob>>,
+    timers: Timers,
+}
 
 impl JobQueue {
     pub fn new() -> Self {
         Self::default()
     }
 
-    fn next(&self) -> Option<NativeJob> {
-        self.0.borrow_mut().pop_front()
+    fn next(&self, context: &mut Context) -> Option<NativeJob> {
+        if let Some(job) = self.jobs.borrow_mut().pop_front() {
+            return Some(job);
+        }
+
+        // Timers are bounded by gas. Once exhausted, pending timers are discarded
+        // to ensure that intervals cannot keep the event loop alive forever.
+        if context.instructions_remaining() == 0 {
+            self.timers.clear_all();
+            return None;
+        }
+
+        self.timers.next_job()
     }
 
     pub fn call_next(&self, context: &mut Context) -> Option<JsResult<JsValue>> {
-        let job = self.next()?;
+        let job = self.next(context)?;
         Some(job.call(context))
     }
 }
 
 impl boa_engine::job::JobQueue for JobQueue {
     fn enqueue_promise_job(&self, job: NativeJob, _context: &mut boa_engine::Context) {
-        self.0.borrow_mut().push_back(job);
+        self.jobs.borrow_mut().push_back(job);
     }
 
     fn enqueue_future_job(
@@ -100,7 +122,7 @@ impl boa_engine::job::JobQueue for JobQueue {
     }
 
     fn run_jobs(&self, context: &mut boa_engine::Context) {
-        while let Some(job) = self.next() {
+        while let Some(job) = self.next(context) {
             // Jobs can fail, it is the final result that determines the value
             let _ = job.call(context);
         }
@@ -214,6 +236,12 @@ impl Runtime {
 
         context.enter_realm(realm.inner.clone());
 
+        // 4. Share the job queue's timers with the realm
+        {
+            host_defined!(&mut context, mut host_defined);
+            host_defined.insert(job_queue.timers.clone());
+        }
+
         Ok(Self {
             context,
             realm,
@@ -243,6 +271,11 @@ impl Runtime {
         &self.realm
     }
 
+    /// Returns the runtime's timers
+    pub fn timers(&self) -> &Timers {
+        &self.job_queue.timers
+    }
+
     /// Runs the event loop (job queue) to completion
     pub async fn run_event_loop(&mut self) {
         poll_fn(|_| self.poll_event_loop()).await
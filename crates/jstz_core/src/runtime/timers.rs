@@ -0,0 +1,424 @@
+//! # Deterministic timers
+//!
+//! This module implements `setTimeout`, `setInterval`, `clearTimeout`, `clearInterval`
+//! and `queueMicrotask` for the jstz runtime.
+//!
+//! Smart functions must execute deterministically, so timers do not observe wall-clock
+//! time. Instead, the runtime maintains a *virtual* clock. Whenever the promise job queue
+//! is empty, the event loop advances the virtual clock to the deadline of the earliest
+//! pending timer and runs its callback. Timers with equal deadlines fire in the order in
+//! which they were scheduled. Timers are only executed within the current request and
+//! are bounded by its gas limit: once the gas is exhausted, all pending timers are
+//! discarded.
+//!
+//! More information:
+//!  - [MDN documentation][mdn]
+//!  - [WHATWG timers specification][spec]
+//!
+//! [mdn]: https://developer.mozilla.org/en-US/docs/Web/API/setTimeout
+//! [spec]: https://html.spec.whatwg.org/multipage/timers-and-user-prompts.html#timers
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+
+use boa_engine::{
+    job::NativeJob, js_string, object::builtins::JsFunction, Context, JsArgs, JsData,
+    JsNativeError, JsResult, JsValue, NativeFunction,
+};
+use boa_gc::{empty_trace, Finalize, Trace};
+
+use crate::realm::HostDefined;
+
+/// Identifier of a timer, as returned by `setTimeout` and `setInterval`
+pub type TimerId = u32;
+
+#[derive(Debug)]
+struct Timer {
+    id: TimerId,
+    callback: JsFunction,
+    args: Vec<JsValue>,
+    // `Some(delay)` if the timer was scheduled using `setInterval`
+    interval: Option<u64>,
+}
+
+/// A queue of timers ordered by their deadline in virtual time
+#[derive(Debug, Default)]
+pub struct TimerQueue {
+    /// Current virtual time (in milliseconds)
+    now: u64,
+    next_id: TimerId,
+    next_seq: u64,
+    // Pending timers keyed by `(deadline, seq)`, where `seq` is the insertion order.
+    timers: BTreeMap<(u64, u64), Timer>,
+    // Maps the id of each pending timer to its key in `timers`
+    keys: HashMap<TimerId, (u64, u64)>,
+}
+
+impl TimerQueue {
+    /// Returns the current virtual time (in milliseconds)
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    fn insert(&mut self, timer: Timer, delay: u64) {
+        let key = (self.now.saturating_add(delay), self.next_seq);
+        self.next_seq += 1;
+        self.keys.insert(timer.id, key);
+        self.timers.insert(key, timer);
+    }
+
+    fn schedule(
+        &mut self,
+        callback: JsFunction,
+        args: Vec<JsValue>,
+        delay: u64,
+        repeat: bool,
+    ) -> TimerId {
+        // Timer ids are positive integers
+        self.next_id += 1;
+        let id = self.next_id;
+
+        let timer = Timer {
+            id,
+            callback,
+            args,
+            interval: repeat.then_some(delay),
+        };
+        self.insert(timer, delay);
+
+        id
+    }
+
+    /// Cancels the timer with the given `id`. Cancelling an unknown timer is a no-op.
+    pub fn clear(&mut self, id: TimerId) {
+        if let Some(key) = self.keys.remove(&id) {
+            self.timers.remove(&key);
+        }
+    }
+
+    /// Cancels all pending timers
+    pub fn clear_all(&mut self) {
+        self.timers.clear();
+        self.keys.clear();
+    }
+
+    /// Removes the earliest timer, advancing the virtual clock to its deadline.
+    ///
+    /// Intervals are rescheduled before their callback is returned, such that
+    /// the callback may cancel its own interval.
+    fn pop(&mut self) -> Option<(JsFunction, Vec<JsValue>)> {
+        let ((deadline, _), timer) = self.timers.pop_first()?;
+        self.keys.remove(&timer.id);
+        self.now = deadline;
+
+        let result = (timer.callback.clone(), timer.args.clone());
+        if let Some(delay) = timer.interval {
+            self.insert(timer, delay);
+        }
+
+        Some(result)
+    }
+}
+
+/// A shared handle to the runtime's [`TimerQueue`]
+///
+/// The handle is shared between the runtime's job queue and the `HostDefined`
+/// object of each realm created within the runtime.
+#[derive(Debug, Clone, Default, JsData)]
+pub struct Timers(Rc<RefCell<TimerQueue>>);
+
+impl Finalize for Timers {}
+
+unsafe impl Trace for Timers {
+    // The callbacks are not reachable from any gc-allocated object,
+    // hence they are treated as roots and needn't be traced.
+    empty_trace!();
+}
+
+impl Timers {
+    /// Returns the timers of the current realm, if any
+    pub fn from_context(context: &mut Context) -> Option<Self> {
+        let binding = context
+            .global_object()
+            .get(js_string!(HostDefined::NAME), context)
+            .ok()?;
+        let host_defined = binding.as_object()?.downcast_ref::<HostDefined>()?;
+        let timers = host_defined.get::<Self>()?;
+
+        Some(Self::clone(&timers))
+    }
+
+    pub fn now(&self) -> u64 {
+        self.0.borrow().now()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    /// Schedules `callback` to be called with `args` after `delay` milliseconds
+    /// of virtual time. If `repeat` is set, the callback is called every `delay`
+    /// milliseconds until the timer is cleared.
+    pub fn schedule(
+        &self,
+        callback: JsFunction,
+        args: Vec<JsValue>,
+        delay: u64,
+        repeat: bool,
+    ) -> TimerId {
+        self.0.borrow_mut().schedule(callback, args, delay, repeat)
+    }
+
+    pub fn clear(&self, id: TimerId) {
+        self.0.borrow_mut().clear(id)
+    }
+
+    pub fn clear_all(&self) {
+        self.0.borrow_mut().clear_all()
+    }
+
+    /// Returns a job for the earliest pending timer, advancing the virtual clock
+    pub(crate) fn next_job(&self) -> Option<NativeJob> {
+        // NB: The borrow must be released before the job is called
+        let (callback, args) = self.0.borrow_mut().pop()?;
+
+        Some(NativeJob::new(move |context| {
+            callback.call(&JsValue::undefined(), &args, context)
+        }))
+    }
+}
+
+fn callback_try_from_js(value: &JsValue) -> JsResult<JsFunction> {
+    value
+        .as_object()
+        .cloned()
+        .and_then(JsFunction::from_object)
+        .ok_or_else(|| {
+            JsNativeError::typ()
+                .with_message("Failed to convert js value into a callable function")
+                .into()
+        })
+}
+
+/// Converts a timeout to milliseconds, as per step 4 of the [timer initialization steps]
+///
+/// [timer initialization steps]: https://html.spec.whatwg.org/multipage/timers-and-user-prompts.html#timer-initialisation-steps
+fn delay_try_from_js(value: &JsValue, context: &mut Context) -> JsResult<u64> {
+    let delay = value.to_number(context)?;
+
+    if delay.is_nan() || delay < 0.0 {
+        return Ok(0);
+    }
+
+    Ok(delay as u64)
+}
+
+pub struct TimersApi;
+
+impl TimersApi {
+    fn timers(context: &mut Context) -> JsResult<Timers> {
+        Timers::from_context(context).ok_or_else(|| {
+            JsNativeError::error()
+                .with_message("Timers are not available in this realm")
+                .into()
+        })
+    }
+
+    fn schedule(
+        args: &[JsValue],
+        repeat: bool,
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let callback = callback_try_from_js(args.get_or_undefined(0))?;
+        let delay = delay_try_from_js(args.get_or_undefined(1), context)?;
+        let args = args.get(2..).unwrap_or_default().to_vec();
+
+        let id = Self::timers(context)?.schedule(callback, args, delay, repeat);
+
+        Ok(id.into())
+    }
+
+    fn clear(args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+        // Clearing an invalid timer id has no effect
+        if let Some(id) = args.get_or_undefined(0).as_number() {
+            Self::timers(context)?.clear(id as TimerId);
+        }
+
+        Ok(JsValue::undefined())
+    }
+
+    fn set_timeout(
+        _this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        Self::schedule(args, false, context)
+    }
+
+    fn set_interval(
+        _this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        Self::schedule(args, true, context)
+    }
+
+    fn clear_timeout(
+        _this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        Self::clear(args, context)
+    }
+
+    fn clear_interval(
+        _this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        Self::clear(args, context)
+    }
+
+    fn queue_microtask(
+        _this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let callback = callback_try_from_js(args.get_or_undefined(0))?;
+
+        let job = NativeJob::new(move |context| {
+            callback.call(&JsValue::undefined(), &[], context)
+        });
+        context.job_queue().enqueue_promise_job(job, context);
+
+        Ok(JsValue::undefined())
+    }
+}
+
+impl crate::Api for TimersApi {
+    fn init(self, context: &mut Context) {
+        context
+            .register_global_builtin_callable(
+                js_string!("setTimeout"),
+                2,
+                NativeFunction::from_fn_ptr(Self::set_timeout),
+            )
+            .expect("setTimeout should only be registered once");
+        context
+            .register_global_builtin_callable(
+                js_string!("setInterval"),
+                2,
+                NativeFunction::from_fn_ptr(Self::set_interval),
+            )
+            .expect("setInterval should only be registered once");
+        context
+            .register_global_builtin_callable(
+                js_string!("clearTimeout"),
+                1,
+                NativeFunction::from_fn_ptr(Self::clear_timeout),
+            )
+            .expect("clearTimeout should only be registered once");
+        context
+            .register_global_builtin_callable(
+                js_string!("clearInterval"),
+                1,
+                NativeFunction::from_fn_ptr(Self::clear_interval),
+            )
+            .expect("clearInterval should only be registered once");
+        context
+            .register_global_builtin_callable(
+                js_string!("queueMicrotask"),
+                1,
+                NativeFunction::from_fn_ptr(Self::queue_microtask),
+            )
+            .expect("queueMicrotask should only be registered once");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use boa_engine::{JsValue, Source};
+
+    use super::TimersApi;
+    use crate::{future, Runtime};
+
+    fn run(src: &str, gas_limit: usize) -> Runtime {
+        let mut rt = Runtime::new(gas_limit).unwrap();
+        let realm = rt.realm().clone();
+        realm.register_api(TimersApi, rt.context());
+
+        rt.eval(Source::from_bytes(src)).unwrap();
+        future::block_on(rt.run_event_loop());
+
+        rt
+    }
+
+    fn log(rt: &mut Runtime) -> JsValue {
+        rt.eval(Source::from_bytes("log.join(',')")).unwrap()
+    }
+
+    #[test]
+    fn timers_fire_in_order_of_deadline() {
+        let mut rt = run(
+            r#"
+            const log = [];
+            setTimeout(() => log.push("c"), 30);
+            setTimeout(() => log.push("a"), 10);
+            setTimeout((x) => log.push(x), 10, "b");
+            Promise.resolve().then(() => log.push("promise"));
+            queueMicrotask(() => log.push("microtask"));
+            log.push("sync");
+            "#,
+            usize::MAX,
+        );
+
+        assert_eq!(
+            log(&mut rt).as_string().unwrap(),
+            "sync,promise,microtask,a,b,c"
+        );
+        assert_eq!(rt.timers().now(), 30);
+    }
+
+    #[test]
+    fn intervals_repeat_until_cleared() {
+        let mut rt = run(
+            r#"
+            const log = [];
+            let count = 0;
+            const id = setInterval(() => {
+                log.push("i" + count++);
+                if (count === 3) clearInterval(id);
+            }, 10);
+            setTimeout(() => log.push("t"), 15);
+            const cancelled = setTimeout(() => log.push("cancelled"), 5);
+            clearTimeout(cancelled);
+            "#,
+            usize::MAX,
+        );
+
+        assert_eq!(log(&mut rt).as_string().unwrap(), "i0,t,i1,i2");
+    }
+
+    #[test]
+    fn intervals_are_bounded_by_gas() {
+        let rt = run("setInterval(() => {}, 0);", 100_000);
+
+        assert_eq!(rt.instructions_remaining(), 0);
+        assert!(rt.timers().is_empty());
+    }
+}
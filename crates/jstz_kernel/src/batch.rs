@@ -0,0 +1,263 @@
+use jstz_core::kv::{Storage, Transaction};
+use jstz_proto::{
+    operation::{batch::OperationBatch, SignedOperation},
+    receipt::Receipt,
+    Error,
+};
+use tezos_crypto_rs::hash::ContractKt1Hash;
+use tezos_smart_rollup::{
+    prelude::{debug_msg, Runtime},
+    storage::path::{self, OwnedPath, RefPath},
+};
+
+use crate::{budget::TickBudget, handle_message, inbox::Message};
+
+const QUEUE_PATH: RefPath = RefPath::assert_from(b"/jstz_batch");
+const OPERATIONS_PATH: RefPath = RefPath::assert_from(b"/jstz_batch/operations");
+// Index of the next operation to execute
+const HEAD_PATH: RefPath = RefPath::assert_from(b"/jstz_batch/head");
+// Index following the last queued operation
+const TAIL_PATH: RefPath = RefPath::assert_from(b"/jstz_batch/tail");
+
+/// Maximum number of queued operations. Operations of batches received while
+/// the queue is full are rejected.
+pub const MAX_QUEUED_OPERATIONS: u64 = 10_000;
+
+fn operation_path(index: u64) -> Option<OwnedPath> {
+    let index_path = OwnedPath::try_from(format!("/{}", index)).ok()?;
+    path::concat(&OPERATIONS_PATH, &index_path).ok()
+}
+
+fn read_index(rt: &impl Runtime, path: &RefPath) -> u64 {
+    Storage::get(rt, path).ok().flatten().unwrap_or(0)
+}
+
+fn write_operation(
+    rt: &mut impl Runtime,
+    index: u64,
+    operation: &SignedOperation,
+) -> Option<()> {
+    let bytes = bincode::serialize(operation).ok()?;
+    rt.store_write_all(&operation_path(index)?, &bytes).ok()
+}
+
+fn read_operation(rt: &impl Runtime, index: u64) -> Option<SignedOperation> {
+    let bytes = rt.store_read_all(&operation_path(index)?).ok()?;
+    bincode::deserialize(&bytes).ok()
+}
+
+// Writes the failure receipt of an operation that was not queued
+fn reject(
+    rt: &mut impl Runtime,
+    tx: &mut Transaction,
+    operation: &SignedOperation,
+    error: Error,
+) {
+    let receipt = Receipt::new(operation.hash(), Err(error));
+    if let Err(err) = receipt.write(rt, tx) {
+        debug_msg!(
+            rt,
+            "Failed to write the receipt of a rejected operation: {err:?}\n"
+        );
+    }
+}
+
+/// Decompresses the operations of `batch` and queues them for execution. The
+/// operations are executed by [`execute_pending`], possibly over several
+/// kernel runs. Operations that do not fit in the queue are rejected with a
+/// failure receipt.
+pub fn enqueue(rt: &mut impl Runtime, tx: &mut Transaction, batch: OperationBatch) {
+    let Some(operations) = batch.operations() else {
+        debug_msg!(rt, "Failed to decompress the operation batch\n");
+        return;
+    };
+    debug_msg!(rt, "Operation batch: {} operations\n", operations.len());
+
+    let head = read_index(rt, &HEAD_PATH);
+    let mut tail = read_index(rt, &TAIL_PATH);
+    for operation in operations {
+        if tail - head >= MAX_QUEUED_OPERATIONS {
+            reject(rt, tx, &operation, Error::OperationQueueFull);
+            continue;
+        }
+        match write_operation(rt, tail, &operation) {
+            Some(()) => tail += 1,
+            None => {
+                debug_msg!(rt, "Failed to queue an operation of the batch\n");
+            }
+        }
+    }
+    if let Err(err) = Storage::insert(rt, &TAIL_PATH, &tail) {
+        debug_msg!(rt, "Failed to queue the operation batch: {err:?}\n");
+    }
+}
+
+/// Executes the queued operations of batches, if any. Each operation is
+/// committed on its own with its own receipt, and the progress through the
+/// queue is persisted such that execution resumes where it left off after a
+/// reboot.
+///
+/// Returns `false` if the tick budget was exhausted before the queue was
+/// emptied.
+pub fn execute_pending(
+    rt: &mut impl Runtime,
+    ticketer: &ContractKt1Hash,
+    budget: &mut TickBudget,
+    tx: &mut Transaction,
+) -> bool {
+    let mut head = read_index(rt, &HEAD_PATH);
+    let tail = read_index(rt, &TAIL_PATH);
+    if tail == 0 {
+        return true;
+    }
+
+    while head < tail {
+        if !budget.can_read_message() {
+            return false;
+        }
+
+        match read_operation(rt, head) {
+            Some(operation) => {
+                budget.consume_operation(&operation);
+
+                tx.begin();
+                handle_message(rt, Message::External(operation), ticketer, tx)
+                    .unwrap_or_else(|err| debug_msg!(rt, "[🔴] {err:?}\n"));
+                if let Err(commit_error) = tx.commit(rt) {
+                    debug_msg!(rt, "Failed to commit transaction: {commit_error:?}\n");
+                }
+            }
+            None => debug_msg!(rt, "Failed to read queued operation {head}\n"),
+        }
+
+        if let Some(path) = operation_path(head) {
+            let _ = rt.store_delete(&path);
+        }
+        head += 1;
+        if let Err(err) = Storage::insert(rt, &HEAD_PATH, &head) {
+            debug_msg!(rt, "Failed to persist the operation queue: {err:?}\n");
+        }
+    }
+
+    let _ = rt.store_delete(&QUEUE_PATH);
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use jstz_core::kv::{Storage, Transaction};
+    use jstz_mock::host::JstzMockHost;
+    use jstz_proto::{
+        context::account::{Nonce, ParsedCode},
+        operation::{
+            batch::OperationBatch, Content, DeployFunction, ExternalMessageContents,
+            Operation, OperationHash, SignedOperation,
+        },
+        receipt::{Receipt, ReceiptResult},
+    };
+    use tezos_smart_rollup::{prelude::Runtime, storage::path::OwnedPath};
+
+    use super::{enqueue, MAX_QUEUED_OPERATIONS, QUEUE_PATH, TAIL_PATH};
+    use crate::{budget::TICK_BUDGET_PATH, entry};
+
+    fn deploy_operation(nonce: Nonce) -> SignedOperation {
+        let op = Operation {
+            source: jstz_mock::account1(),
+            nonce,
+            content: Content::DeployFunction(DeployFunction {
+                function_code: ParsedCode::try_from(
+                    "export default () => new Response('hello');".to_string(),
+                )
+                .unwrap(),
+                account_credit: 0,
+            }),
+        };
+        crate::sign_operation(op)
+    }
+
+    fn deploy_operations(count: usize) -> Vec<SignedOperation> {
+        std::iter::successors(Some(Nonce::default()), |nonce| Some(nonce.next()))
+            .take(count)
+            .map(deploy_operation)
+            .collect()
+    }
+
+    fn receipt(
+        host: &mut JstzMockHost,
+        operation_hash: &OperationHash,
+    ) -> Option<Receipt> {
+        let path =
+            OwnedPath::try_from(format!("/jstz_receipt/{}", operation_hash)).unwrap();
+        Storage::get(host.rt(), &path).unwrap()
+    }
+
+    fn has_receipt(host: &mut JstzMockHost, operation_hash: &OperationHash) -> bool {
+        receipt(host, operation_hash).is_some()
+    }
+
+    fn add_batch(host: &mut JstzMockHost, operations: &[SignedOperation]) {
+        let batch = OperationBatch::new(operations);
+        assert!(batch.fits_in_external_message());
+        let contents = ExternalMessageContents::Batch(batch);
+        host.add_external_message(&bincode::serialize(&contents).unwrap());
+    }
+
+    #[test]
+    fn operation_batch_round_trips() {
+        let operations = deploy_operations(10);
+        let batch = OperationBatch::new(&operations);
+        assert_eq!(batch.operations(), Some(operations));
+    }
+
+    #[test]
+    fn entry_executes_operations_of_a_batch() {
+        let mut host = JstzMockHost::default();
+        let operations = deploy_operations(3);
+        let hashes: Vec<_> = operations.iter().map(SignedOperation::hash).collect();
+        add_batch(&mut host, &operations);
+        host.rt().run_level(entry);
+
+        for hash in &hashes {
+            assert!(has_receipt(&mut host, hash));
+        }
+        assert!(host.rt().store_has(&QUEUE_PATH).unwrap().is_none());
+    }
+
+    #[test]
+    fn entry_resumes_batch_after_reboot() {
+        let mut host = JstzMockHost::default();
+        // Enough budget for a single operation per kernel run
+        Storage::insert(host.rt(), &TICK_BUDGET_PATH, &1_100_000_000_u64).unwrap();
+
+        let operations = deploy_operations(3);
+        let hashes: Vec<_> = operations.iter().map(SignedOperation::hash).collect();
+        add_batch(&mut host, &operations);
+        host.rt().run_level(entry);
+
+        for hash in &hashes {
+            assert!(has_receipt(&mut host, hash));
+        }
+    }
+
+    #[test]
+    fn enqueue_rejects_operations_once_the_queue_is_full() {
+        let mut host = JstzMockHost::default();
+        Storage::insert(host.rt(), &TAIL_PATH, &MAX_QUEUED_OPERATIONS).unwrap();
+
+        let operations = deploy_operations(2);
+        let mut tx = Transaction::default();
+        tx.begin();
+        enqueue(host.rt(), &mut tx, OperationBatch::new(&operations));
+        tx.commit(host.rt()).unwrap();
+
+        for operation in &operations {
+            let receipt = receipt(&mut host, &operation.hash()).unwrap();
+            assert!(matches!(
+                receipt.result,
+                ReceiptResult::Failed(ref error) if error == "OperationQueueFull"
+            ));
+        }
+        let tail: Option<u64> = Storage::get(host.rt(), &TAIL_PATH).unwrap();
+        assert_eq!(tail, Some(MAX_QUEUED_OPERATIONS));
+    }
+}
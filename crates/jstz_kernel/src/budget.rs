@@ -0,0 +1,270 @@
+use jstz_core::kv::Storage;
+use jstz_proto::{
+    executor::smart_function::deploy::MAX_REVEALED_CODE_SIZE,
+    operation::{batch::MAX_DECOMPRESSED_BATCH_SIZE, Content, SignedOperation},
+    reveal_data,
+};
+use tezos_smart_rollup::{prelude::Runtime, storage::path::RefPath};
+
+use crate::inbox::{InternalMessage, Message};
+
+/// Path of the (optional) tick budget of a kernel run. The `jstz-rollup`
+/// installer does not write it: rollups needing another budget than
+/// [`DEFAULT_TICK_BUDGET`] set it with a `set` instruction of their installer
+/// configuration, whose value is the `u64` budget encoded by
+/// [`jstz_core::kv::value::serialize`].
+pub const TICK_BUDGET_PATH: RefPath = RefPath::assert_from(b"/tick_budget");
+
+/// Maximum number of ticks of a single kernel run, imposed by the PVM.
+pub const MAX_TICKS_PER_RUN: u64 = 11_000_000_000;
+
+/// Default tick budget of a kernel run. Leaves some headroom below
+/// [`MAX_TICKS_PER_RUN`] for the kernel start up and inaccurate estimations.
+pub const DEFAULT_TICK_BUDGET: u64 = 9_000_000_000;
+
+// Estimated cost of reading an inbox message, excluding its payload
+const INBOX_MESSAGE_TICKS: u64 = 2_000_000;
+// Estimated cost of handling a byte of an inbox message payload
+const TICKS_PER_BYTE: u64 = 20_000;
+// Estimated cost of a native deposit
+const DEPOSIT_TICKS: u64 = 50_000_000;
+// Estimated cost of an operation besides the execution of smart functions:
+// signature verification, nonce checks and writing its receipt
+const OPERATION_TICKS: u64 = 100_000_000;
+// Estimated cost of a JS instruction of a smart function run, whose number is
+// bounded by the gas limit of the run
+const TICKS_PER_INSTRUCTION: u64 = 1_000;
+// Estimated cost of parsing and storing a byte of smart function code
+const TICKS_PER_CODE_BYTE: u64 = 500;
+// Estimated cost of revealing a preimage page
+const REVEAL_PAGE_TICKS: u64 = 1_000_000;
+// Upper bound of the number of pages revealed for the code of a smart
+// function: all content pages but the last are more than half full, and
+// there are far fewer hash pages than content pages
+const MAX_REVEALED_CODE_PAGES: u64 =
+    (MAX_REVEALED_CODE_SIZE / (reveal_data::MAX_PAGE_SIZE / 2)) as u64;
+// Estimated cost of revealing a page of the kernel of an upgrade and appending
+// it to durable storage
+const UPGRADE_PAGE_TICKS: u64 = 2 * REVEAL_PAGE_TICKS;
+// Estimated cost of decompressing a byte of an operation batch
+const TICKS_PER_DECOMPRESSED_BYTE: u64 = 100;
+// Upper bound of the estimated cost of a message. Operations with a larger
+// estimate (e.g. with a larger gas limit) are rejected without being executed.
+const MAX_OPERATION_TICKS: u64 = 1_000_000_000;
+// Maximum size of an inbox message
+const MAX_INPUT_SIZE: u64 = 4096;
+
+/// Ticks that must remain in the budget before reading the next inbox message.
+/// The cost of a message is only known once it has been read, so the budget
+/// must cover the most expensive message.
+const MAX_TICKS_PER_MESSAGE: u64 =
+    INBOX_MESSAGE_TICKS + MAX_INPUT_SIZE * TICKS_PER_BYTE + MAX_OPERATION_TICKS;
+
+/// Tracks the estimated number of ticks left in a kernel run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickBudget {
+    remaining: u64,
+}
+
+impl TickBudget {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            remaining: budget.min(MAX_TICKS_PER_RUN),
+        }
+    }
+
+    /// Loads the tick budget from durable storage, defaulting to
+    /// [`DEFAULT_TICK_BUDGET`].
+    pub fn load(rt: &impl Runtime) -> Self {
+        let budget = Storage::get(rt, &TICK_BUDGET_PATH)
+            .ok()
+            .flatten()
+            .unwrap_or(DEFAULT_TICK_BUDGET);
+        Self::new(budget)
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Returns `true` if the budget is large enough to handle another message.
+    /// This covers the full estimated cost of any operation that is executed,
+    /// see [`exceeds_message_reserve`].
+    pub fn can_read_message(&self) -> bool {
+        self.remaining >= MAX_TICKS_PER_MESSAGE
+    }
+
+    /// Deducts the estimated cost of handling an inbox message of `input_size`
+    /// bytes, which was parsed as `message` (if at all).
+    pub fn consume(&mut self, input_size: usize, message: Option<&Message>) {
+        let ticks = estimate_ticks(input_size, message);
+        self.remaining = self.remaining.saturating_sub(ticks);
+    }
+
+    /// Deducts the estimated cost of executing `operation`, which was not read
+    /// from the inbox (e.g. imported from the DAL).
+    pub fn consume_operation(&mut self, operation: &SignedOperation) {
+        let ticks = charged_operation_ticks(operation);
+        self.remaining = self.remaining.saturating_sub(ticks);
+    }
+
+    /// Returns `true` if the budget is large enough to reveal another page of
+    /// the kernel of an upgrade.
+    pub fn can_reveal_upgrade_page(&self) -> bool {
+        self.remaining >= UPGRADE_PAGE_TICKS
+    }
+
+    /// Deducts the estimated cost of revealing a page of the kernel of an
+    /// upgrade.
+    pub fn consume_upgrade_page(&mut self) {
+        self.remaining = self.remaining.saturating_sub(UPGRADE_PAGE_TICKS);
+    }
+}
+
+fn estimate_ticks(input_size: usize, message: Option<&Message>) -> u64 {
+    let read_ticks = INBOX_MESSAGE_TICKS + input_size as u64 * TICKS_PER_BYTE;
+    let handle_ticks = match message {
+        None => 0,
+        Some(Message::Internal(InternalMessage::Deposit(_))) => DEPOSIT_TICKS,
+        Some(Message::External(operation)) => charged_operation_ticks(operation),
+        // The size of a batch is only known once decompressed
+        Some(Message::Batch(_)) => {
+            MAX_DECOMPRESSED_BATCH_SIZE as u64 * TICKS_PER_DECOMPRESSED_BYTE
+        }
+        // FA deposits run the proxy smart function without gas limit
+        Some(Message::Internal(InternalMessage::FaDeposit(_))) => MAX_OPERATION_TICKS,
+        // The kernel of an upgrade is revealed page by page afterwards
+        Some(Message::Admin(_)) => OPERATION_TICKS,
+    };
+    read_ticks + handle_ticks
+}
+
+/// Estimates the cost of executing `operation` from its content: the gas limit
+/// of smart function runs, the size of deployed code and the pages revealed
+/// for code deployed from preimages.
+fn estimate_operation_ticks(operation: &SignedOperation) -> u64 {
+    let content_ticks = match operation.content() {
+        Content::RunFunction(run) => {
+            (run.gas_limit as u64).saturating_mul(TICKS_PER_INSTRUCTION)
+        }
+        Content::DeployFunction(deploy) => {
+            deploy.function_code.as_ref().len() as u64 * TICKS_PER_CODE_BYTE
+        }
+        Content::RevealDeployFunction(_) => {
+            MAX_REVEALED_CODE_PAGES * REVEAL_PAGE_TICKS
+                + MAX_REVEALED_CODE_SIZE as u64 * TICKS_PER_CODE_BYTE
+        }
+    };
+    OPERATION_TICKS.saturating_add(content_ticks)
+}
+
+/// Returns `true` if the estimated cost of executing `operation` exceeds the
+/// ticks reserved for a message. Such operations are rejected without being
+/// executed, as the budget of the kernel run may not cover them.
+pub fn exceeds_message_reserve(operation: &SignedOperation) -> bool {
+    estimate_operation_ticks(operation) > MAX_OPERATION_TICKS
+}
+
+// Ticks charged for `operation`: its estimated cost, or the cost of rejecting it
+fn charged_operation_ticks(operation: &SignedOperation) -> u64 {
+    if exceeds_message_reserve(operation) {
+        OPERATION_TICKS
+    } else {
+        estimate_operation_ticks(operation)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use http::{HeaderMap, Method, Uri};
+    use jstz_mock::host::JstzMockHost;
+    use jstz_proto::{
+        context::account::Nonce,
+        operation::{external::Deposit, Operation, RunFunction},
+    };
+
+    use super::*;
+
+    fn run_operation(gas_limit: usize) -> SignedOperation {
+        let op = Operation {
+            source: jstz_mock::account1(),
+            nonce: Nonce::default(),
+            content: Content::RunFunction(RunFunction {
+                uri: Uri::from_static("tezos://tz1cD5CuvAALcxgypqBXcBQEA8dkLJivoFjU/"),
+                method: Method::GET,
+                headers: HeaderMap::new(),
+                body: None,
+                gas_limit,
+            }),
+        };
+        crate::sign_operation(op)
+    }
+
+    #[test]
+    fn operation_estimate_depends_on_gas_limit() {
+        let cheap = estimate_operation_ticks(&run_operation(1_000));
+        let expensive = estimate_operation_ticks(&run_operation(500_000));
+        assert_eq!(cheap, OPERATION_TICKS + 1_000 * TICKS_PER_INSTRUCTION);
+        assert!(cheap < expensive);
+        assert_eq!(
+            estimate_operation_ticks(&run_operation(usize::MAX)),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn operations_above_the_message_reserve_are_charged_their_rejection() {
+        let max_gas_limit =
+            ((MAX_OPERATION_TICKS - OPERATION_TICKS) / TICKS_PER_INSTRUCTION) as usize;
+        let affordable = run_operation(max_gas_limit);
+        let expensive = run_operation(max_gas_limit + 1);
+
+        assert!(!exceeds_message_reserve(&affordable));
+        assert_eq!(charged_operation_ticks(&affordable), MAX_OPERATION_TICKS);
+        assert!(exceeds_message_reserve(&expensive));
+        assert_eq!(charged_operation_ticks(&expensive), OPERATION_TICKS);
+    }
+
+    #[test]
+    fn estimates_are_covered_by_the_message_reserve() {
+        let reveal_ticks = OPERATION_TICKS
+            + MAX_REVEALED_CODE_PAGES * REVEAL_PAGE_TICKS
+            + MAX_REVEALED_CODE_SIZE as u64 * TICKS_PER_CODE_BYTE;
+        let decompression_ticks =
+            MAX_DECOMPRESSED_BATCH_SIZE as u64 * TICKS_PER_DECOMPRESSED_BYTE;
+        assert!(reveal_ticks <= MAX_OPERATION_TICKS);
+        assert!(decompression_ticks <= MAX_OPERATION_TICKS);
+    }
+
+    #[test]
+    fn load_defaults_when_unset() {
+        let mut host = JstzMockHost::default();
+        let budget = TickBudget::load(host.rt());
+        assert_eq!(budget.remaining(), DEFAULT_TICK_BUDGET);
+    }
+
+    #[test]
+    fn load_is_capped_by_max_ticks_per_run() {
+        let mut host = JstzMockHost::default();
+        Storage::insert(host.rt(), &TICK_BUDGET_PATH, &u64::MAX).unwrap();
+        let budget = TickBudget::load(host.rt());
+        assert_eq!(budget.remaining(), MAX_TICKS_PER_RUN);
+    }
+
+    #[test]
+    fn consume_exhausts_budget() {
+        let mut budget =
+            TickBudget::new(MAX_TICKS_PER_MESSAGE + INBOX_MESSAGE_TICKS + DEPOSIT_TICKS);
+        let deposit = Message::Internal(InternalMessage::Deposit(Deposit {
+            inbox_id: 0,
+            amount: 100,
+            receiver: jstz_mock::account1(),
+        }));
+
+        budget.consume(0, Some(&deposit));
+        assert!(budget.can_read_message());
+
+        budget.consume(MAX_INPUT_SIZE as usize, None);
+        assert!(!budget.can_read_message());
+    }
+}
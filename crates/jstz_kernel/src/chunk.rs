@@ -0,0 +1,395 @@
+use jstz_core::kv::Storage;
+use jstz_crypto::{hash::Blake2b, public_key_hash::PublicKeyHash};
+use jstz_proto::operation::{
+    chunk::{OperationChunk, MAX_CHUNKS, MAX_CHUNK_DATA_SIZE},
+    SignedOperation,
+};
+use serde::{Deserialize, Serialize};
+use tezos_smart_rollup::{
+    prelude::{debug_msg, Runtime},
+    storage::path::{self, OwnedPath, RefPath},
+};
+
+/// Number of levels after which the chunks of an incomplete upload are removed
+pub const UPLOAD_LIFETIME: u32 = 120;
+
+const UPLOADS_PATH: RefPath = RefPath::assert_from(b"/jstz_chunks/uploads");
+const EXPIRY_PATH: RefPath = RefPath::assert_from(b"/jstz_chunks/expiry");
+const LEVEL_PATH: RefPath = RefPath::assert_from(b"/jstz_chunks/level");
+const META_PATH: RefPath = RefPath::assert_from(b"/meta");
+const COUNT_PATH: RefPath = RefPath::assert_from(b"/count");
+
+/// Progress of the upload of the chunks of an operation. The number of chunks
+/// is fixed by the first chunk received.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct Upload {
+    total: u32,
+    received: u32,
+    /// Level at which the first chunk was received
+    started_at: u32,
+}
+
+/// Identifies the upload of the chunks of an operation by a signer
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct UploadId {
+    /// Base58 encoded public key hash of the signer of the chunks
+    signer: String,
+    content_hash: Blake2b,
+}
+
+impl UploadId {
+    fn path(&self) -> Option<OwnedPath> {
+        let upload_path =
+            OwnedPath::try_from(format!("/{}/{}", self.signer, self.content_hash))
+                .ok()?;
+        path::concat(&UPLOADS_PATH, &upload_path).ok()
+    }
+}
+
+fn indexed_path(parent: &OwnedPath, index: u32) -> Option<OwnedPath> {
+    let index_path = OwnedPath::try_from(format!("/{}", index)).ok()?;
+    path::concat(parent, &index_path).ok()
+}
+
+fn expiry_path(level: u32) -> Option<OwnedPath> {
+    let level_path = OwnedPath::try_from(format!("/{}", level)).ok()?;
+    path::concat(&EXPIRY_PATH, &level_path).ok()
+}
+
+fn is_valid(chunk: &OperationChunk) -> bool {
+    let OperationChunk {
+        index, total, data, ..
+    } = chunk;
+    0 < *total
+        && *total <= MAX_CHUNKS
+        && index < total
+        && !data.is_empty()
+        && data.len() <= MAX_CHUNK_DATA_SIZE
+}
+
+/// Records that the upload `id`, started at `level`, expires
+/// [`UPLOAD_LIFETIME`] levels later.
+fn schedule_expiry(rt: &mut impl Runtime, level: u32, id: &UploadId) -> Option<()> {
+    let expiry_path = expiry_path(level)?;
+    let count_path = path::concat(&expiry_path, &COUNT_PATH).ok()?;
+    let count: u32 = Storage::get(rt, &count_path).ok()?.unwrap_or(0);
+    Storage::insert(rt, &indexed_path(&expiry_path, count)?, id).ok()?;
+    Storage::insert(rt, &count_path, &(count + 1)).ok()
+}
+
+/// Records the current `level` and removes the chunks of the uploads started
+/// [`UPLOAD_LIFETIME`] levels earlier that are still incomplete. Must be called
+/// on each start of level.
+pub fn start_level(rt: &mut impl Runtime, level: u32) {
+    if let Err(err) = Storage::insert(rt, &LEVEL_PATH, &level) {
+        debug_msg!(rt, "Failed to record the level of chunk uploads: {err:?}\n");
+    }
+
+    let Some(expired_level) = level.checked_sub(UPLOAD_LIFETIME) else {
+        return;
+    };
+    let Some(expiry_path) = expiry_path(expired_level) else {
+        return;
+    };
+    let Some(count_path) = path::concat(&expiry_path, &COUNT_PATH).ok() else {
+        return;
+    };
+    let count: u32 = Storage::get(rt, &count_path).ok().flatten().unwrap_or(0);
+
+    for index in 0..count {
+        let Some(id) = indexed_path(&expiry_path, index)
+            .and_then(|path| Storage::get::<UploadId>(rt, &path).ok().flatten())
+        else {
+            continue;
+        };
+        let Some(upload_path) = id.path() else {
+            continue;
+        };
+        let Some(meta_path) = path::concat(&upload_path, &META_PATH).ok() else {
+            continue;
+        };
+        // The upload may have completed and been started again since
+        let upload: Option<Upload> = Storage::get(rt, &meta_path).ok().flatten();
+        if upload.is_some_and(|upload| upload.started_at == expired_level) {
+            debug_msg!(
+                rt,
+                "Operation chunks expired: {} (signer: {})\n",
+                id.content_hash,
+                id.signer
+            );
+            let _ = rt.store_delete(&upload_path);
+        }
+    }
+    let _ = rt.store_delete(&expiry_path);
+}
+
+/// Stores `chunk` in durable storage, keyed by its signer and the content hash
+/// of its operation.
+///
+/// Chunks that are not signed by their public key, or whose total number of
+/// chunks differs from the first chunk received for the upload, are rejected.
+/// Once all chunks of an operation have been received, the chunks are removed
+/// from durable storage and the reassembled operation is returned if it is
+/// signed by the signer of the chunks.
+pub fn store_chunk(
+    rt: &mut impl Runtime,
+    chunk: OperationChunk,
+) -> Option<SignedOperation> {
+    if !is_valid(&chunk) {
+        debug_msg!(rt, "Invalid operation chunk\n");
+        return None;
+    }
+    if chunk.verify().is_err() {
+        debug_msg!(rt, "Invalid operation chunk signature\n");
+        return None;
+    }
+
+    let OperationChunk {
+        content_hash,
+        index,
+        total,
+        data,
+        public_key,
+        ..
+    } = chunk;
+
+    let id = UploadId {
+        signer: PublicKeyHash::from(&public_key).to_base58(),
+        content_hash,
+    };
+    let upload_path = id.path()?;
+    let chunk_path = indexed_path(&upload_path, index)?;
+    let meta_path = path::concat(&upload_path, &META_PATH).ok()?;
+
+    // 1. Store the chunk (a duplicated chunk is stored only once)
+    let upload: Option<Upload> = Storage::get(rt, &meta_path).ok()?;
+    let mut upload = match upload {
+        Some(upload) if upload.total != total => {
+            debug_msg!(
+                rt,
+                "Operation chunk does not match its upload: expected {} chunks, found {total}\n",
+                upload.total
+            );
+            return None;
+        }
+        Some(upload) => upload,
+        None => {
+            let level = Storage::get(rt, &LEVEL_PATH).ok()?.unwrap_or(0);
+            schedule_expiry(rt, level, &id)?;
+            Upload {
+                total,
+                received: 0,
+                started_at: level,
+            }
+        }
+    };
+    if rt.store_has(&chunk_path).ok()?.is_none() {
+        rt.store_write_all(&chunk_path, &data).ok()?;
+        upload.received += 1;
+    }
+    Storage::insert(rt, &meta_path, &upload).ok()?;
+
+    debug_msg!(
+        rt,
+        "Operation chunk: {} ({}/{total} chunks received)\n",
+        id.content_hash,
+        upload.received
+    );
+
+    if upload.received < total {
+        return None;
+    }
+
+    // 2. Reassemble the operation and remove its chunks
+    let mut encoded_operation = Vec::new();
+    for index in 0..total {
+        let data = rt
+            .store_read_all(&indexed_path(&upload_path, index)?)
+            .ok()?;
+        encoded_operation.extend(data);
+    }
+    let _ = rt.store_delete(&upload_path);
+
+    if Blake2b::from(&encoded_operation) != id.content_hash {
+        debug_msg!(
+            rt,
+            "Reassembled operation does not match its content hash\n"
+        );
+        return None;
+    }
+
+    let operation: SignedOperation = bincode::deserialize(&encoded_operation).ok()?;
+    if operation.public_key != public_key {
+        debug_msg!(
+            rt,
+            "Reassembled operation is not signed by the signer of its chunks\n"
+        );
+        return None;
+    }
+    Some(operation)
+}
+
+#[cfg(test)]
+mod test {
+    use jstz_crypto::keypair_from_passphrase;
+    use jstz_mock::{host::JstzMockHost, keypair1};
+    use jstz_proto::{
+        context::account::{Nonce, ParsedCode},
+        operation::{
+            chunk::OperationChunk, Content, DeployFunction, Operation, SignedOperation,
+        },
+    };
+
+    use super::{start_level, store_chunk, UPLOAD_LIFETIME};
+
+    fn large_deploy_operation() -> SignedOperation {
+        let code = format!(
+            "// {}\nexport default () => new Response('hello');",
+            "a".repeat(10_000)
+        );
+        let op = Operation {
+            source: jstz_mock::account1(),
+            nonce: Nonce::default(),
+            content: Content::DeployFunction(DeployFunction {
+                function_code: ParsedCode::try_from(code).unwrap(),
+                account_credit: 0,
+            }),
+        };
+        crate::sign_operation(op)
+    }
+
+    fn chunks(operation: &SignedOperation) -> Vec<OperationChunk> {
+        let (public_key, secret_key) = keypair1();
+        let encoded_operation = bincode::serialize(operation).unwrap();
+        OperationChunk::split(&encoded_operation, &public_key, &secret_key).unwrap()
+    }
+
+    #[test]
+    fn store_chunk_reassembles_operation() {
+        let mut host = JstzMockHost::default();
+        let operation = large_deploy_operation();
+        let mut chunks = chunks(&operation);
+        assert!(chunks.len() > 1);
+
+        // Chunks may be received out of order and more than once
+        chunks.reverse();
+        let last = chunks.pop().unwrap();
+        for chunk in chunks.iter().chain(chunks.iter()) {
+            assert_eq!(store_chunk(host.rt(), chunk.clone()), None);
+        }
+
+        assert_eq!(store_chunk(host.rt(), last), Some(operation));
+    }
+
+    #[test]
+    fn store_chunk_rejects_mismatched_content_hash() {
+        let mut host = JstzMockHost::default();
+        let (_, secret_key) = keypair1();
+        let mut chunks = chunks(&large_deploy_operation());
+        chunks[0].data[0] ^= 1;
+        chunks[0].signature = secret_key.sign(chunks[0].hash()).unwrap();
+
+        let results: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| store_chunk(host.rt(), chunk))
+            .collect();
+        assert!(results.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn store_chunk_rejects_chunks_with_invalid_signature() {
+        let mut host = JstzMockHost::default();
+        let operation = large_deploy_operation();
+        let chunks = chunks(&operation);
+
+        // A chunk with forged data, claiming to be signed by the operation signer
+        let mut forged = chunks[0].clone();
+        forged.data = vec![0; forged.data.len()];
+        assert_eq!(store_chunk(host.rt(), forged), None);
+
+        let results: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| store_chunk(host.rt(), chunk))
+            .collect();
+        assert_eq!(results.last().unwrap(), &Some(operation));
+    }
+
+    #[test]
+    fn store_chunk_ignores_chunks_of_other_signers() {
+        let mut host = JstzMockHost::default();
+        let operation = large_deploy_operation();
+        let chunks = chunks(&operation);
+
+        // Another signer uploads junk data and a different number of chunks
+        // under the content hash of the operation
+        let (secret_key, public_key) = keypair_from_passphrase("attacker").unwrap();
+        let mut junk = chunks[0].clone();
+        junk.total = 2;
+        junk.data = vec![0; junk.data.len()];
+        junk.public_key = public_key;
+        junk.signature = secret_key.sign(junk.hash()).unwrap();
+        assert_eq!(store_chunk(host.rt(), junk), None);
+
+        let results: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| store_chunk(host.rt(), chunk))
+            .collect();
+        assert_eq!(results.last().unwrap(), &Some(operation));
+    }
+
+    #[test]
+    fn store_chunk_rejects_reassembled_operation_of_another_signer() {
+        let mut host = JstzMockHost::default();
+        let encoded_operation = bincode::serialize(&large_deploy_operation()).unwrap();
+        let (secret_key, public_key) = keypair_from_passphrase("attacker").unwrap();
+        let chunks =
+            OperationChunk::split(&encoded_operation, &public_key, &secret_key).unwrap();
+
+        let results: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| store_chunk(host.rt(), chunk))
+            .collect();
+        assert!(results.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn store_chunk_rejects_chunks_with_different_total() {
+        let mut host = JstzMockHost::default();
+        let operation = large_deploy_operation();
+        let mut chunks = chunks(&operation);
+        let (_, secret_key) = keypair1();
+
+        let mut chunk = chunks.pop().unwrap();
+        chunk.total += 1;
+        chunk.signature = secret_key.sign(chunk.hash()).unwrap();
+        let first = chunks.remove(0);
+
+        assert_eq!(store_chunk(host.rt(), first), None);
+        assert_eq!(store_chunk(host.rt(), chunk), None);
+    }
+
+    #[test]
+    fn start_level_expires_incomplete_uploads() {
+        let mut host = JstzMockHost::default();
+        let operation = large_deploy_operation();
+        let mut chunks = chunks(&operation);
+        let last = chunks.pop().unwrap();
+
+        start_level(host.rt(), 10);
+        for chunk in &chunks {
+            assert_eq!(store_chunk(host.rt(), chunk.clone()), None);
+        }
+
+        // The upload is still incomplete when it expires
+        start_level(host.rt(), 10 + UPLOAD_LIFETIME - 1);
+        start_level(host.rt(), 10 + UPLOAD_LIFETIME);
+        assert_eq!(store_chunk(host.rt(), last), None);
+
+        // The operation can be uploaded again
+        let first = chunks.remove(0);
+        for chunk in chunks {
+            assert_eq!(store_chunk(host.rt(), chunk), None);
+        }
+        assert_eq!(store_chunk(host.rt(), first), Some(operation));
+    }
+}
@@ -0,0 +1,343 @@
+use jstz_core::kv::{Storage, Transaction};
+use jstz_proto::operation::{dal::DalBatch, SignedOperation};
+use serde::{Deserialize, Serialize};
+use tezos_crypto_rs::hash::ContractKt1Hash;
+use tezos_smart_rollup::{
+    host::RuntimeError,
+    prelude::{debug_msg, Runtime},
+    storage::path::RefPath,
+};
+
+use crate::{budget::TickBudget, handle_message, inbox::Message};
+
+/// Path of the DAL slots the kernel imports operations from. The `jstz-rollup`
+/// installer does not write it: rollups importing operations from the DAL set
+/// it with a `set` instruction of their installer configuration, whose value is
+/// the `Vec<u8>` of slot indices encoded by [`jstz_core::kv::value::serialize`].
+/// The kernel does not read the DAL if unset.
+pub const DAL_SLOTS_PATH: RefPath = RefPath::assert_from(b"/jstz_dal/slots");
+const DAL_IMPORT_PATH: RefPath = RefPath::assert_from(b"/jstz_dal/import");
+// Content of the slot being imported, as far as it was read
+const DAL_CONTENT_PATH: RefPath = RefPath::assert_from(b"/jstz_dal/content");
+
+// Maximum number of bytes written to durable storage at once
+const MAX_WRITE_SIZE: usize = 2048;
+
+/// Progress of the import of the slots published at `published_level`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct DalImport {
+    published_level: i32,
+    /// Position of the slot being imported in the subscribed slots
+    slot_position: usize,
+    /// Index of the next page to read from the slot, `None` once all its pages
+    /// were read
+    page_index: Option<i16>,
+    /// Size of the content read from the slot so far
+    content_size: usize,
+    /// Index of the next operation to execute in the batch of the slot
+    operation_index: usize,
+}
+
+impl DalImport {
+    fn persist(&self, rt: &mut impl Runtime) {
+        if let Err(err) = Storage::insert(rt, &DAL_IMPORT_PATH, self) {
+            debug_msg!(rt, "Failed to persist DAL import: {err:?}\n");
+        }
+    }
+
+    // Moves on to the next subscribed slot
+    fn next_slot(&mut self, rt: &mut impl Runtime) {
+        let _ = rt.store_delete(&DAL_CONTENT_PATH);
+        self.slot_position += 1;
+        self.page_index = Some(0);
+        self.content_size = 0;
+        self.operation_index = 0;
+    }
+}
+
+fn subscribed_slots(rt: &impl Runtime) -> Vec<u8> {
+    Storage::get(rt, &DAL_SLOTS_PATH)
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// Schedules the import of the subscribed slots attested at `level`, i.e.
+/// published `attestation_lag` levels earlier.
+pub fn schedule_import(rt: &mut impl Runtime, level: u32) {
+    if subscribed_slots(rt).is_empty() {
+        return;
+    }
+
+    let attestation_lag = rt.reveal_dal_parameters().attestation_lag;
+    let Some(published_level) = (level as u64).checked_sub(attestation_lag) else {
+        return;
+    };
+
+    let _ = rt.store_delete(&DAL_CONTENT_PATH);
+    let import = DalImport {
+        published_level: published_level as i32,
+        slot_position: 0,
+        page_index: Some(0),
+        content_size: 0,
+        operation_index: 0,
+    };
+    if let Err(err) = Storage::insert(rt, &DAL_IMPORT_PATH, &import) {
+        debug_msg!(rt, "Failed to schedule DAL import: {err:?}\n");
+    }
+}
+
+/// Reads the pages of `slot_index` left to read by `import`, appending them to
+/// the content of the slot. Only the pages read are charged to `budget`, and
+/// the progress is persisted after each page.
+///
+/// Returns `false` if the tick budget was exhausted before the last page.
+fn read_pages(
+    rt: &mut impl Runtime,
+    import: &mut DalImport,
+    slot_index: u8,
+    budget: &mut TickBudget,
+) -> bool {
+    let parameters = rt.reveal_dal_parameters();
+    let number_of_pages = (parameters.slot_size / parameters.page_size) as i16;
+
+    let mut page = vec![0; parameters.page_size as usize];
+    while let Some(page_index) = import.page_index {
+        if !budget.can_read_message() {
+            return false;
+        }
+
+        // Slots that were not attested have no pages
+        let size = rt
+            .reveal_dal_page(import.published_level, slot_index, page_index, &mut page)
+            .unwrap_or(0);
+        budget.consume(size, None);
+
+        import.page_index = None;
+        if size > 0 {
+            match append_content(rt, import.content_size, &page[..size]) {
+                Ok(()) => {
+                    import.content_size += size;
+                    import.page_index =
+                        Some(page_index + 1).filter(|&next| next < number_of_pages);
+                }
+                Err(err) => {
+                    debug_msg!(rt, "Failed to store DAL page: {err:?}\n");
+                    let _ = rt.store_delete(&DAL_CONTENT_PATH);
+                }
+            }
+        }
+        import.persist(rt);
+    }
+    true
+}
+
+// Writes `bytes` at `offset` of the content of the slot being imported
+fn append_content(
+    rt: &mut impl Runtime,
+    mut offset: usize,
+    bytes: &[u8],
+) -> Result<(), RuntimeError> {
+    for chunk in bytes.chunks(MAX_WRITE_SIZE) {
+        rt.store_write(&DAL_CONTENT_PATH, chunk, offset)?;
+        offset += chunk.len();
+    }
+    Ok(())
+}
+
+/// Decodes the batch read from the slot being imported. Returns `None` if the
+/// slot was not attested or does not contain a jstz batch targetting this
+/// rollup.
+fn read_batch(rt: &impl Runtime) -> Option<DalBatch> {
+    let content = rt.store_read_all(&DAL_CONTENT_PATH).ok()?;
+    let batch = DalBatch::decode(&content)?;
+    if batch.rollup_address != rt.reveal_metadata().raw_rollup_address {
+        return None;
+    }
+    Some(batch)
+}
+
+fn execute(
+    rt: &mut impl Runtime,
+    operation: SignedOperation,
+    ticketer: &ContractKt1Hash,
+    tx: &mut Transaction,
+) {
+    tx.begin();
+    handle_message(rt, Message::External(operation), ticketer, tx)
+        .unwrap_or_else(|err| debug_msg!(rt, "[🔴] {err:?}\n"));
+    if let Err(commit_error) = tx.commit(rt) {
+        debug_msg!(rt, "Failed to commit transaction: {commit_error:?}\n");
+    }
+}
+
+/// Executes the operations of the scheduled DAL import, if any. Each operation
+/// is committed on its own and the progress of the import is persisted, such
+/// that the import resumes where it left off after a reboot.
+///
+/// Returns `false` if the tick budget was exhausted before the end of the
+/// import.
+pub fn import(
+    rt: &mut impl Runtime,
+    ticketer: &ContractKt1Hash,
+    budget: &mut TickBudget,
+    tx: &mut Transaction,
+) -> bool {
+    let Ok(Some(mut import)) = Storage::get::<DalImport>(rt, &DAL_IMPORT_PATH) else {
+        return true;
+    };
+    let slots = subscribed_slots(rt);
+
+    while let Some(&slot_index) = slots.get(import.slot_position) {
+        if !read_pages(rt, &mut import, slot_index, budget) {
+            return false;
+        }
+
+        let operations = read_batch(rt)
+            .map(|batch| batch.operations)
+            .unwrap_or_default();
+        debug_msg!(
+            rt,
+            "DAL slot {slot_index} (level {}): {} operations\n",
+            import.published_level,
+            operations.len()
+        );
+
+        for operation in operations.into_iter().skip(import.operation_index) {
+            if !budget.can_read_message() {
+                return false;
+            }
+            budget.consume_operation(&operation);
+            execute(rt, operation, ticketer, tx);
+            import.operation_index += 1;
+            import.persist(rt);
+        }
+
+        import.next_slot(rt);
+        import.persist(rt);
+    }
+
+    let _ = rt.store_delete(&DAL_IMPORT_PATH);
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use jstz_core::kv::Storage;
+    use jstz_mock::host::JstzMockHost;
+    use jstz_proto::{
+        context::account::{Nonce, ParsedCode},
+        operation::{
+            dal::DalBatch, Content, DeployFunction, Operation, OperationHash,
+            SignedOperation,
+        },
+    };
+    use tezos_smart_rollup::{prelude::Runtime, storage::path::OwnedPath};
+
+    use super::{DAL_CONTENT_PATH, DAL_IMPORT_PATH, DAL_SLOTS_PATH};
+    use crate::{budget::TICK_BUDGET_PATH, entry};
+
+    const SLOT_INDEX: u8 = 3;
+
+    fn deploy_operation(nonce: Nonce) -> SignedOperation {
+        let op = Operation {
+            source: jstz_mock::account1(),
+            nonce,
+            content: Content::DeployFunction(DeployFunction {
+                function_code: ParsedCode::try_from(
+                    "export default () => new Response('hello');".to_string(),
+                )
+                .unwrap(),
+                account_credit: 0,
+            }),
+        };
+        crate::sign_operation(op)
+    }
+
+    fn has_receipt(host: &mut JstzMockHost, operation_hash: &OperationHash) -> bool {
+        let path =
+            OwnedPath::try_from(format!("/jstz_receipt/{}", operation_hash)).unwrap();
+        host.rt().store_has(&path).unwrap().is_some()
+    }
+
+    fn publish(host: &mut JstzMockHost, batch: &DalBatch) {
+        let attestation_lag = host.rt().reveal_dal_parameters().attestation_lag;
+        let published_level = host.rt().level() as i32 - attestation_lag as i32;
+        host.set_dal_slot(published_level, SLOT_INDEX, &batch.encode());
+    }
+
+    #[test]
+    fn entry_imports_operations_from_subscribed_slots() {
+        let mut host = JstzMockHost::default();
+        Storage::insert(host.rt(), &DAL_SLOTS_PATH, &vec![SLOT_INDEX]).unwrap();
+
+        let operation = deploy_operation(Nonce::default());
+        let operation_hash = operation.hash();
+        let batch = DalBatch {
+            rollup_address: host.rt().reveal_metadata().raw_rollup_address,
+            operations: vec![operation],
+        };
+        publish(&mut host, &batch);
+        host.rt().run_level(entry);
+
+        assert!(has_receipt(&mut host, &operation_hash));
+    }
+
+    #[test]
+    fn entry_ignores_batches_of_other_rollups() {
+        let mut host = JstzMockHost::default();
+        Storage::insert(host.rt(), &DAL_SLOTS_PATH, &vec![SLOT_INDEX]).unwrap();
+
+        let batch = DalBatch {
+            rollup_address: [0; 20],
+            operations: vec![deploy_operation(Nonce::default())],
+        };
+        publish(&mut host, &batch);
+        host.rt().run_level(entry);
+
+        assert!(!has_receipt(&mut host, &batch.operations[0].hash()));
+    }
+
+    #[test]
+    fn entry_ignores_unsubscribed_slots() {
+        let mut host = JstzMockHost::default();
+
+        let batch = DalBatch {
+            rollup_address: host.rt().reveal_metadata().raw_rollup_address,
+            operations: vec![deploy_operation(Nonce::default())],
+        };
+        publish(&mut host, &batch);
+        host.rt().run_level(entry);
+
+        assert!(!has_receipt(&mut host, &batch.operations[0].hash()));
+    }
+
+    #[test]
+    fn entry_resumes_import_after_reboot() {
+        let mut host = JstzMockHost::default();
+        Storage::insert(host.rt(), &DAL_SLOTS_PATH, &vec![SLOT_INDEX]).unwrap();
+        // Enough budget for a single page or operation per kernel run
+        Storage::insert(host.rt(), &TICK_BUDGET_PATH, &1_100_000_000_u64).unwrap();
+
+        // Spans several pages of the slot
+        let operations: Vec<_> =
+            std::iter::successors(Some(Nonce::default()), |nonce| Some(nonce.next()))
+                .take(50)
+                .map(deploy_operation)
+                .collect();
+        let batch = DalBatch {
+            rollup_address: host.rt().reveal_metadata().raw_rollup_address,
+            operations,
+        };
+        let page_size = host.rt().reveal_dal_parameters().page_size as usize;
+        assert!(batch.encode().len() > 2 * page_size);
+        publish(&mut host, &batch);
+        host.rt().run_level(entry);
+
+        for operation in &batch.operations {
+            assert!(has_receipt(&mut host, &operation.hash()));
+        }
+        assert!(host.rt().store_has(&DAL_IMPORT_PATH).unwrap().is_none());
+        assert!(host.rt().store_has(&DAL_CONTENT_PATH).unwrap().is_none());
+    }
+}
@@ -1,5 +1,9 @@
 use jstz_crypto::public_key_hash::PublicKeyHash;
-use jstz_proto::operation::{external::Deposit, ExternalOperation, SignedOperation};
+use jstz_proto::executor::l1_call::protect_destination;
+use jstz_proto::operation::{
+    admin::SignedKernelUpgrade, batch::OperationBatch, external::Deposit,
+    ExternalMessageContents, ExternalOperation, SignedOperation,
+};
 use num_traits::ToPrimitive;
 use tezos_crypto_rs::hash::ContractKt1Hash;
 use tezos_smart_rollup::inbox::ExternalMessageFrame;
@@ -14,7 +18,7 @@ use tezos_smart_rollup::{
     types::Contract,
 };
 
-use crate::parsing::try_parse_fa_deposit;
+use crate::{chunk::store_chunk, parsing::try_parse_fa_deposit};
 
 pub type ExternalMessage = SignedOperation;
 pub type InternalMessage = ExternalOperation;
@@ -23,6 +27,15 @@ pub type InternalMessage = ExternalOperation;
 pub enum Message {
     External(ExternalMessage),
     Internal(InternalMessage),
+    Admin(AdminMessage),
+    /// A compressed batch of operations, queued for execution by the run loop
+    Batch(OperationBatch),
+}
+
+/// Messages signed by the rollup administrators
+#[derive(Debug, PartialEq, Eq)]
+pub enum AdminMessage {
+    KernelUpgrade(SignedKernelUpgrade),
 }
 
 pub type MichelsonNativeDeposit = MichelsonPair<MichelsonContract, FA2_1Ticket>;
@@ -66,6 +79,16 @@ fn is_valid_native_deposit(
     true
 }
 
+// Forbids L1 calls to the contracts bridging deposits to the rollup and to
+// the ticketers of the deposited tickets
+fn protect_destinations(rt: &mut impl Runtime, contracts: &[&ContractKt1Hash]) {
+    for contract in contracts {
+        if let Err(err) = protect_destination(rt, contract) {
+            debug_msg!(rt, "Failed to protect {contract} from L1 calls: {err:?}\n");
+        }
+    }
+}
+
 fn read_transfer(
     rt: &mut impl Runtime,
     transfer: Transfer<RollupType>,
@@ -74,11 +97,13 @@ fn read_transfer(
 ) -> Option<Message> {
     debug_msg!(rt, "Internal message: transfer\n");
 
+    let sender = transfer.sender;
     match transfer.payload {
         MichelsonOr::Left(tez_ticket) => {
             let ticket = tez_ticket.1;
 
             if is_valid_native_deposit(rt, &ticket, ticketer) {
+                protect_destinations(rt, &[&sender]);
                 let amount = ticket.amount().to_u64()?;
                 let pkh = tez_ticket.0 .0.to_b58check();
                 let receiver = PublicKeyHash::from_base58(&pkh).ok()?;
@@ -99,15 +124,48 @@ fn read_transfer(
             let proxy = fa_ticket.1 .0 .0;
             let fa_deposit =
                 try_parse_fa_deposit(inbox_id, ticket, receiver, proxy).ok()?;
+            match &fa_deposit.ticket_metadata {
+                Some(metadata) => {
+                    protect_destinations(rt, &[&sender, &metadata.ticketer])
+                }
+                None => protect_destinations(rt, &[&sender]),
+            }
             Some(Message::Internal(InternalMessage::FaDeposit(fa_deposit)))
         }
     }
 }
 
-fn read_external_message(rt: &mut impl Runtime, bytes: &[u8]) -> Option<ExternalMessage> {
-    let msg: ExternalMessage = bincode::deserialize(bytes).ok()?;
+fn read_external_message(rt: &mut impl Runtime, bytes: &[u8]) -> Option<Message> {
+    let Ok(contents) = bincode::deserialize::<ExternalMessageContents>(bytes) else {
+        debug_msg!(rt, "Failed to parse the external message\n");
+        return None;
+    };
+    let msg = match contents {
+        ExternalMessageContents::Operation(msg) => msg,
+        ExternalMessageContents::Chunk(chunk) => store_chunk(rt, chunk)?,
+        ExternalMessageContents::KernelUpgrade(upgrade) => {
+            debug_msg!(rt, "Admin message: kernel upgrade {:?}\n", upgrade.upgrade);
+            return Some(Message::Admin(AdminMessage::KernelUpgrade(upgrade)));
+        }
+        ExternalMessageContents::Batch(batch) => {
+            debug_msg!(rt, "External message: operation batch\n");
+            return Some(Message::Batch(batch));
+        }
+    };
     debug_msg!(rt, "External message: {msg:?}\n");
-    Some(msg)
+    Some(Message::External(msg))
+}
+
+/// Returns the timestamp of the predecessor block, in seconds since the Unix
+/// epoch, if `input` is the "Info per level" message. It follows the "Start of
+/// level" message and precedes the other messages of the inbox of each level.
+pub fn level_timestamp(input: &[u8]) -> Option<i64> {
+    match InboxMessage::<RollupType>::parse(input) {
+        Ok((_, InboxMessage::Internal(InternalInboxMessage::InfoPerLevel(info)))) => {
+            Some(info.predecessor_timestamp.i64())
+        }
+        _ => None,
+    }
 }
 
 pub fn read_message(
@@ -115,9 +173,18 @@ pub fn read_message(
     ticketer: &ContractKt1Hash,
 ) -> Option<Message> {
     let input = rt.read_input().ok()??;
-    let _ = rt.mark_for_reboot();
+    parse_message(rt, input.as_ref(), input.id, ticketer)
+}
 
-    let (_, message) = InboxMessage::<RollupType>::parse(input.as_ref()).ok()?;
+/// Parses the inbox message `input` with identifier `inbox_id`. Returns `None`
+/// if the message is not addressed to jstz or is malformed.
+pub fn parse_message(
+    rt: &mut impl Runtime,
+    input: &[u8],
+    inbox_id: u32,
+    ticketer: &ContractKt1Hash,
+) -> Option<Message> {
+    let (_, message) = InboxMessage::<RollupType>::parse(input).ok()?;
 
     match message {
         InboxMessage::Internal(InternalInboxMessage::StartOfLevel) => {
@@ -154,7 +221,7 @@ pub fn read_message(
                 );
                 return None;
             };
-            read_transfer(rt, transfer, ticketer, input.id)
+            read_transfer(rt, transfer, ticketer, inbox_id)
         }
         InboxMessage::External(bytes) => match ExternalMessageFrame::parse(bytes) {
             Ok(frame) => match frame {
@@ -170,13 +237,7 @@ pub fn read_message(
                         );
                         None
                     } else {
-                        match read_external_message(rt, contents) {
-                            Some(msg) => Some(Message::External(msg)),
-                            None => {
-                                debug_msg!(rt, "Failed to parse the external message\n");
-                                None
-                            }
-                        }
+                        read_external_message(rt, contents)
                     }
                 }
             },
@@ -1,5 +1,7 @@
-use jstz_core::kv::{Storage, Transaction};
-use jstz_proto::{executor, Result};
+use jstz_core::kv::{outbox, Storage, Transaction};
+use jstz_proto::{
+    executor, receipt::Receipt, request_logger::LEVEL_START_PREFIX, Error, Result,
+};
 use tezos_crypto_rs::hash::ContractKt1Hash;
 use tezos_smart_rollup::{
     entrypoint,
@@ -7,9 +9,18 @@ use tezos_smart_rollup::{
     storage::path::RefPath,
 };
 
-use crate::inbox::{read_message, Message};
+use crate::{
+    budget::TickBudget,
+    inbox::{level_timestamp, parse_message, AdminMessage, Message},
+    upgrade::UpgradeStatus,
+};
+pub mod batch;
+pub mod budget;
+pub mod chunk;
+pub mod dal;
 pub mod inbox;
 pub mod parsing;
+pub mod upgrade;
 
 pub const TICKETER: RefPath = RefPath::assert_from(b"/ticketer");
 
@@ -31,43 +42,143 @@ fn handle_message(
         }
         Message::External(signed_operation) => {
             debug_msg!(hrt, "External operation: {signed_operation:?}\n");
-            let receipt =
-                executor::execute_operation(hrt, tx, signed_operation, ticketer);
+            let receipt = if budget::exceeds_message_reserve(&signed_operation) {
+                Receipt::new(signed_operation.hash(), Err(Error::GasLimitTooLarge))
+            } else {
+                executor::execute_operation(hrt, tx, signed_operation, ticketer)
+            };
             debug_msg!(hrt, "Receipt: {receipt:?}\n");
             receipt.write(hrt, tx)?
         }
+        Message::Admin(AdminMessage::KernelUpgrade(upgrade)) => {
+            if let Err(err) = upgrade::execute(hrt, upgrade) {
+                debug_msg!(hrt, "Kernel upgrade rejected: {err}\n");
+            }
+        }
+        // Operations of a batch are executed from the queue by the run loop
+        Message::Batch(batch) => batch::enqueue(hrt, tx, batch),
     }
     Ok(())
 }
 
+/// Handles inbox messages until the inbox of the current level is exhausted or
+/// the tick budget of the kernel run is spent. In the latter case, the kernel
+/// is rebooted and resumes from the next message in a fresh kernel run.
+///
+/// Operations of batch messages are queued and executed before the next inbox
+/// message is read. Operations published to the subscribed DAL slots are
+/// imported at the start of each level, before the remaining inbox messages.
+/// The kernel of an upgrade is revealed, possibly over several kernel runs,
+/// before any further message is handled, and handles the messages that follow.
+///
+/// Each message is handled in its own transaction, which is committed before
+/// the next message is read, so a failing message does not affect the others.
+/// Withdrawals of the level are written to the outbox once the inbox is
+/// exhausted, batched per ticketer.
+pub fn run(rt: &mut impl Runtime, ticketer: &ContractKt1Hash, budget: &mut TickBudget) {
+    let mut tx = Transaction::default();
+    loop {
+        // Messages following a kernel upgrade are handled by the new kernel
+        let upgrade = upgrade::continue_pending(rt, budget);
+        if upgrade == UpgradeStatus::Installed {
+            let _ = rt.mark_for_reboot();
+            return;
+        }
+        if upgrade == UpgradeStatus::Revealing
+            || !batch::execute_pending(rt, ticketer, budget, &mut tx)
+            || !dal::import(rt, ticketer, budget, &mut tx)
+            || !budget.can_read_message()
+        {
+            debug_msg!(rt, "Tick budget exhausted, yielding\n");
+            if let Err(err) = rt.mark_for_reboot() {
+                debug_msg!(rt, "Failed to mark the kernel for reboot: {err:?}\n");
+            }
+            return;
+        }
+
+        let input = match rt.read_input() {
+            Ok(Some(input)) => input,
+            Ok(None) => {
+                if let Err(err) = tx.flush_withdrawals(rt) {
+                    debug_msg!(rt, "Failed to flush withdrawals: {err:?}\n");
+                }
+                return;
+            }
+            Err(err) => {
+                debug_msg!(rt, "Failed to read the inbox: {err:?}\n");
+                return;
+            }
+        };
+
+        if let Some(timestamp) = level_timestamp(input.as_ref()) {
+            // Lets the jstz node attribute the requests that follow to the level
+            // and date them
+            debug_msg!(rt, "{}{} {}\n", LEVEL_START_PREFIX, input.level, timestamp);
+            if let Err(err) = outbox::start_level(rt, input.level) {
+                debug_msg!(rt, "Failed to start outbox level: {err:?}\n");
+            }
+            dal::schedule_import(rt, input.level);
+            chunk::start_level(rt, input.level);
+        }
+
+        let message = parse_message(rt, input.as_ref(), input.id, ticketer);
+        budget.consume(input.as_ref().len(), message.as_ref());
+
+        if let Some(message) = message {
+            tx.begin();
+            handle_message(rt, message, ticketer, &mut tx)
+                .unwrap_or_else(|err| debug_msg!(rt, "[🔴] {err:?}\n"));
+            if let Err(commit_error) = tx.commit(rt) {
+                debug_msg!(rt, "Failed to commit transaction: {commit_error:?}\n");
+            }
+        }
+    }
+}
+
 // kernel entry
 #[entrypoint::main]
 pub fn entry(rt: &mut impl Runtime) {
     let ticketer = read_ticketer(rt).expect("Ticketer not found");
-    let mut tx = Transaction::default();
-    tx.begin();
-    if let Some(message) = read_message(rt, &ticketer) {
-        handle_message(rt, message, &ticketer, &mut tx)
-            .unwrap_or_else(|err| debug_msg!(rt, "[🔴] {err:?}\n"));
-    }
-    if let Err(commit_error) = tx.commit(rt) {
-        debug_msg!(rt, "Failed to commit transaction: {commit_error:?}\n");
-    }
+    let mut budget = TickBudget::load(rt);
+    run(rt, &ticketer, &mut budget);
+}
+
+/// Signs `operation` with the key pair of [jstz_mock::account1]
+#[cfg(test)]
+pub(crate) fn sign_operation(
+    operation: jstz_proto::operation::Operation,
+) -> jstz_proto::operation::SignedOperation {
+    let (public_key, secret_key) = jstz_mock::keypair1();
+    let signature = secret_key.sign(operation.hash()).unwrap();
+    jstz_proto::operation::SignedOperation::new(public_key, signature, operation)
 }
 
 #[cfg(test)]
 mod test {
 
-    use jstz_core::kv::Transaction;
+    use http::{HeaderMap, Method};
+    use jstz_core::kv::{
+        outbox::{withdrawal_location, OutboxLocation},
+        Storage, Transaction,
+    };
     use jstz_mock::{
         host::{JstzMockHost, MOCK_SOURCE},
         message::{fa_deposit::MockFaDeposit, native_deposit::MockNativeDeposit},
     };
-    use jstz_proto::context::{
-        account::{Account, ParsedCode},
-        ticket_table::TicketTable,
+    use jstz_proto::{
+        context::{
+            account::{Account, Nonce, ParsedCode},
+            ticket_table::TicketTable,
+        },
+        operation::{
+            Content, ExternalMessageContents, Operation, RunFunction, SignedOperation,
+        },
+        receipt::{Receipt, ReceiptResult},
+    };
+    use tezos_smart_rollup::{
+        storage::path::OwnedPath,
+        types::{Contract, PublicKeyHash},
     };
-    use tezos_smart_rollup::types::{Contract, PublicKeyHash};
 
     use crate::{entry, parsing::try_parse_contract, read_ticketer};
 
@@ -79,6 +190,30 @@ mod test {
         assert_eq!(ticketer, expected_tickter)
     }
 
+    #[test]
+    fn entry_handles_all_messages_of_a_level() {
+        let mut host = JstzMockHost::default();
+        let deposit = MockNativeDeposit::default();
+        for _ in 0..3 {
+            host.add_internal_message(&deposit);
+        }
+        host.rt().run_level(entry);
+        let tx = &mut Transaction::default();
+        tx.begin();
+        match deposit.receiver {
+            Contract::Implicit(PublicKeyHash::Ed25519(tz1)) => {
+                let amount = Account::balance(
+                    host.rt(),
+                    tx,
+                    &jstz_crypto::public_key_hash::PublicKeyHash::Tz1(tz1),
+                )
+                .unwrap();
+                assert_eq!(amount, 300);
+            }
+            _ => panic!("Unexpected receiver"),
+        }
+    }
+
     #[test]
     fn entry_native_deposit_succeeds() {
         let mut host = JstzMockHost::default();
@@ -178,4 +313,80 @@ mod test {
             _ => panic!("Unexpected receiver"),
         }
     }
+
+    fn withdraw_operation(nonce: Nonce, amount: u64) -> SignedOperation {
+        let body = format!(
+            r#"{{"receiver":"{}","amount":{}}}"#,
+            jstz_mock::account2(),
+            amount
+        );
+        let op = Operation {
+            source: jstz_mock::account1(),
+            nonce,
+            content: Content::RunFunction(RunFunction {
+                uri: "tezos://jstz/withdraw".try_into().unwrap(),
+                method: Method::POST,
+                headers: HeaderMap::new(),
+                body: Some(body.into_bytes()),
+                gas_limit: 1000,
+            }),
+        };
+        crate::sign_operation(op)
+    }
+
+    #[test]
+    fn entry_batches_withdrawals_of_a_level() {
+        let mut host = JstzMockHost::default();
+        let deposit = MockNativeDeposit {
+            receiver: Contract::from_b58check(&jstz_mock::account1().to_base58())
+                .unwrap(),
+            ..MockNativeDeposit::default()
+        };
+        host.add_internal_message(&deposit);
+        let first_nonce = Nonce::default();
+        let second_nonce = first_nonce.next();
+        for (nonce, amount) in [(first_nonce, 10), (second_nonce, 20)] {
+            let contents =
+                ExternalMessageContents::Operation(withdraw_operation(nonce, amount));
+            host.add_external_message(&bincode::serialize(&contents).unwrap());
+        }
+        let level = host.rt().run_level(entry);
+
+        // Both withdrawals target the native ticketer
+        assert_eq!(1, host.rt().outbox_at(level).len());
+        for id in 0..2 {
+            assert_eq!(
+                Some(OutboxLocation { level, index: 0 }),
+                withdrawal_location(host.rt(), id).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn entry_rejects_operations_above_the_message_reserve() {
+        let mut host = JstzMockHost::default();
+        let op = Operation {
+            source: jstz_mock::account1(),
+            nonce: Nonce::default(),
+            content: Content::RunFunction(RunFunction {
+                uri: "tezos://jstz/withdraw".try_into().unwrap(),
+                method: Method::POST,
+                headers: HeaderMap::new(),
+                body: None,
+                gas_limit: usize::MAX,
+            }),
+        };
+        let operation = crate::sign_operation(op);
+        let hash = operation.hash();
+        let contents = ExternalMessageContents::Operation(operation);
+        host.add_external_message(&bincode::serialize(&contents).unwrap());
+        host.rt().run_level(entry);
+
+        let path = OwnedPath::try_from(format!("/jstz_receipt/{}", hash)).unwrap();
+        let receipt: Receipt = Storage::get(host.rt(), &path).unwrap().unwrap();
+        assert!(matches!(
+            receipt.result,
+            ReceiptResult::Failed(ref error) if error == "GasLimitTooLarge"
+        ));
+    }
 }
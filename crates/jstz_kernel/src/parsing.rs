@@ -1,4 +1,5 @@
 use jstz_crypto::public_key_hash::PublicKeyHash;
+use jstz_proto::context::ticket_table::TicketMetadata;
 use jstz_proto::operation::external::FaDeposit;
 use jstz_proto::{context::account::Address, Result};
 use num_traits::ToPrimitive;
@@ -29,19 +30,24 @@ pub fn try_parse_fa_deposit(
         .to_u64()
         .ok_or(jstz_proto::Error::TicketAmountTooLarge)?;
     let ticket_hash = ticket.hash()?;
+    let ticket_metadata = TicketMetadata::from_ticket(&ticket);
     Ok(FaDeposit {
         inbox_id,
         amount,
         receiver,
         proxy_smart_function,
         ticket_hash,
+        ticket_metadata,
     })
 }
 
 #[cfg(test)]
 mod test {
 
-    use jstz_proto::operation::external::FaDeposit;
+    use jstz_proto::{
+        context::ticket_table::TicketMetadata, operation::external::FaDeposit,
+    };
+    use tezos_crypto_rs::hash::ContractKt1Hash;
     use tezos_smart_rollup::{
         michelson::{
             ticket::{FA2_1Ticket, Ticket},
@@ -84,6 +90,14 @@ mod test {
             receiver: jstz_mock::account1(),
             proxy_smart_function: Some(jstz_mock::account2()),
             ticket_hash,
+            ticket_metadata: Some(TicketMetadata {
+                ticketer: ContractKt1Hash::from_base58_check(
+                    "KT1NgXQ6Mwu3XKFDcKdYFS6dkkY3iNKdBKEc",
+                )
+                .unwrap(),
+                token_id: "100".to_string(),
+                content: Some("3132333435".to_string()),
+            }),
         };
         assert_eq!(expected, fa_deposit)
     }
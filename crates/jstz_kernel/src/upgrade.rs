@@ -0,0 +1,444 @@
+use derive_more::{Display, Error, From};
+use jstz_core::kv::Storage;
+use jstz_crypto::public_key::PublicKey;
+use jstz_proto::{
+    operation::admin::{KernelUpgrade, SignedKernelUpgrade},
+    reveal_data::{self, Page, PreimageHash},
+};
+use serde::{Deserialize, Serialize};
+use tezos_smart_rollup::{
+    prelude::{debug_msg, Runtime},
+    storage::path::RefPath,
+    types::SmartRollupAddress,
+};
+
+use crate::budget::TickBudget;
+
+/// Path of the rollup administrators. The `jstz-rollup` installer does not
+/// write it: rollups accepting kernel upgrades set it with a `set` instruction
+/// of their installer configuration, whose value is the [`Administrators`]
+/// encoded by [`jstz_core::kv::value::serialize`]. Upgrades are rejected while
+/// it is unset.
+pub const ADMINISTRATORS_PATH: RefPath =
+    RefPath::assert_from(b"/jstz_admin/administrators");
+const UPGRADE_NONCE_PATH: RefPath = RefPath::assert_from(b"/jstz_admin/upgrade_nonce");
+const PENDING_UPGRADE_PATH: RefPath =
+    RefPath::assert_from(b"/jstz_admin/pending_upgrade");
+const PREPARE_KERNEL_PATH: RefPath = RefPath::assert_from(b"/jstz_admin/kernel");
+const KERNEL_BOOT_PATH: RefPath = RefPath::assert_from(b"/kernel/boot.wasm");
+
+/// Maximum size of a kernel installed by an upgrade
+pub const MAX_KERNEL_SIZE: usize = 16 << 20;
+
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+// Maximum number of bytes written to durable storage at once
+const MAX_WRITE_SIZE: usize = 2048;
+
+#[derive(Display, Debug, Error, From)]
+pub enum KernelUpgradeError {
+    ProtoError { source: jstz_proto::Error },
+    AdministratorsNotSet,
+    InvalidNonce,
+    NotEnoughSignatures,
+    InvalidKernel,
+}
+
+impl From<jstz_core::Error> for KernelUpgradeError {
+    fn from(source: jstz_core::Error) -> Self {
+        Self::ProtoError {
+            source: source.into(),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, KernelUpgradeError>;
+
+/// The administrators of the rollup. A kernel upgrade must be signed by at
+/// least `threshold` of them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Administrators {
+    pub threshold: u32,
+    pub public_keys: Vec<PublicKey>,
+}
+
+impl Administrators {
+    pub fn load(rt: &impl Runtime) -> Result<Option<Self>> {
+        Ok(Storage::get(rt, &ADMINISTRATORS_PATH)?)
+    }
+
+    pub fn store(&self, rt: &mut impl Runtime) -> Result<()> {
+        Ok(Storage::insert(rt, &ADMINISTRATORS_PATH, self)?)
+    }
+
+    /// Verifies that `upgrade` of the rollup `rollup_address` is signed by at
+    /// least `threshold` distinct administrators. Signatures from other keys
+    /// are ignored.
+    fn verify(
+        &self,
+        upgrade: &SignedKernelUpgrade,
+        rollup_address: &SmartRollupAddress,
+    ) -> Result<()> {
+        let hash = upgrade.upgrade.hash(rollup_address);
+        let mut signers: Vec<&PublicKey> = Vec::new();
+        for (public_key, signature) in &upgrade.signatures {
+            if self.public_keys.contains(public_key)
+                && !signers.contains(&public_key)
+                && signature.verify(public_key, hash.as_ref()).is_ok()
+            {
+                signers.push(public_key);
+            }
+        }
+
+        if self.threshold == 0 || (signers.len() as u32) < self.threshold {
+            return Err(KernelUpgradeError::NotEnoughSignatures);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the nonce of the next kernel upgrade.
+pub fn upgrade_nonce(rt: &impl Runtime) -> Result<u64> {
+    Ok(Storage::get(rt, &UPGRADE_NONCE_PATH)?.unwrap_or(0))
+}
+
+/// Kernel upgrade whose kernel is being revealed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct PendingUpgrade {
+    root_hash: PreimageHash,
+    nonce: u64,
+    /// Pages left to reveal with their depth in the tree, the next one last
+    pages: Vec<(PreimageHash, usize)>,
+    /// Size of the part of the kernel revealed so far
+    size: usize,
+}
+
+impl PendingUpgrade {
+    fn load(rt: &impl Runtime) -> Option<Self> {
+        let bytes = rt.store_read_all(&PENDING_UPGRADE_PATH).ok()?;
+        bincode::deserialize(&bytes).ok()
+    }
+
+    // The pages left to reveal may not fit in a single write
+    fn store(&self, rt: &mut impl Runtime) -> Result<()> {
+        let bytes = jstz_core::kv::value::serialize(self)?;
+        let _ = rt.store_delete(&PENDING_UPGRADE_PATH);
+        rt.store_write_all(&PENDING_UPGRADE_PATH, &bytes)
+            .map_err(jstz_core::Error::from)?;
+        Ok(())
+    }
+}
+
+/// Status of the pending kernel upgrade, see [`continue_pending`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpgradeStatus {
+    /// No upgrade is pending
+    Idle,
+    /// The tick budget was exhausted before the kernel was revealed
+    Revealing,
+    /// The new kernel was installed and runs after the next reboot
+    Installed,
+}
+
+/// Schedules the installation of the kernel of `upgrade` after verifying it is
+/// signed by the rollup administrators. The kernel, of up to
+/// [`MAX_KERNEL_SIZE`] bytes, is revealed by [`continue_pending`], possibly
+/// over several kernel runs.
+pub fn execute(rt: &mut impl Runtime, upgrade: SignedKernelUpgrade) -> Result<()> {
+    let administrators =
+        Administrators::load(rt)?.ok_or(KernelUpgradeError::AdministratorsNotSet)?;
+    let rollup_address = SmartRollupAddress::new(rt.reveal_metadata().address());
+    administrators.verify(&upgrade, &rollup_address)?;
+
+    let KernelUpgrade { root_hash, nonce } = upgrade.upgrade;
+    if nonce != upgrade_nonce(rt)? {
+        return Err(KernelUpgradeError::InvalidNonce);
+    }
+
+    let _ = rt.store_delete(&PREPARE_KERNEL_PATH);
+    let pending = PendingUpgrade {
+        pages: vec![(root_hash.clone(), 0)],
+        root_hash,
+        nonce,
+        size: 0,
+    };
+    pending.store(rt)
+}
+
+/// Reveals the kernel of the pending upgrade, if any, page by page while the
+/// tick budget allows. Once revealed, the kernel is installed and runs after
+/// the next reboot. Durable storage (and therefore the state of jstz accounts)
+/// is left untouched.
+pub fn continue_pending(rt: &mut impl Runtime, budget: &mut TickBudget) -> UpgradeStatus {
+    let Some(mut pending) = PendingUpgrade::load(rt) else {
+        return UpgradeStatus::Idle;
+    };
+
+    let result = loop {
+        if pending.pages.is_empty() {
+            break install(rt, &pending);
+        }
+        if !budget.can_reveal_upgrade_page() {
+            if let Err(err) = pending.store(rt) {
+                debug_msg!(rt, "Failed to persist the kernel upgrade: {err:?}\n");
+            }
+            return UpgradeStatus::Revealing;
+        }
+        budget.consume_upgrade_page();
+        if let Err(err) = reveal_next_page(rt, &mut pending) {
+            break Err(err);
+        }
+    };
+
+    let _ = rt.store_delete(&PENDING_UPGRADE_PATH);
+    match result {
+        Ok(()) => {
+            debug_msg!(rt, "Kernel upgraded to {}\n", pending.root_hash);
+            UpgradeStatus::Installed
+        }
+        Err(err) => {
+            debug_msg!(rt, "Kernel upgrade rejected: {err}\n");
+            let _ = rt.store_delete(&PREPARE_KERNEL_PATH);
+            UpgradeStatus::Idle
+        }
+    }
+}
+
+// Reveals the next page of the kernel of `pending`, appending its content to
+// the kernel being prepared
+fn reveal_next_page(rt: &mut impl Runtime, pending: &mut PendingUpgrade) -> Result<()> {
+    let Some((hash, depth)) = pending.pages.pop() else {
+        return Ok(());
+    };
+    if depth >= reveal_data::MAX_DEPTH {
+        return Err(jstz_proto::Error::InvalidPreimage.into());
+    }
+
+    match reveal_data::reveal_page(rt, &hash)? {
+        Page::Content(contents) => {
+            if pending.size + contents.len() > MAX_KERNEL_SIZE {
+                return Err(jstz_proto::Error::RevealDataTooLarge.into());
+            }
+            if pending.size == 0 && !contents.starts_with(WASM_MAGIC) {
+                return Err(KernelUpgradeError::InvalidKernel);
+            }
+            for chunk in contents.chunks(MAX_WRITE_SIZE) {
+                rt.store_write(&PREPARE_KERNEL_PATH, chunk, pending.size)
+                    .map_err(jstz_core::Error::from)?;
+                pending.size += chunk.len();
+            }
+        }
+        Page::Hashes(hashes) => pending
+            .pages
+            .extend(hashes.into_iter().rev().map(|hash| (hash, depth + 1))),
+    }
+    Ok(())
+}
+
+fn install(rt: &mut impl Runtime, pending: &PendingUpgrade) -> Result<()> {
+    if pending.size == 0 {
+        return Err(KernelUpgradeError::InvalidKernel);
+    }
+    rt.store_move(&PREPARE_KERNEL_PATH, &KERNEL_BOOT_PATH)
+        .map_err(jstz_core::Error::from)?;
+    Storage::insert(rt, &UPGRADE_NONCE_PATH, &(pending.nonce + 1))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use jstz_core::kv::Storage;
+    use jstz_core::kv::Transaction;
+    use jstz_crypto::{public_key::PublicKey, secret_key::SecretKey};
+    use jstz_mock::{host::JstzMockHost, message::native_deposit::MockNativeDeposit};
+    use jstz_proto::{
+        context::account::Account,
+        operation::{
+            admin::{KernelUpgrade, SignedKernelUpgrade},
+            ExternalMessageContents,
+        },
+        reveal_data,
+    };
+    use tezos_smart_rollup::{
+        prelude::Runtime,
+        types::{Contract, PublicKeyHash, SmartRollupAddress},
+    };
+
+    use super::{upgrade_nonce, Administrators, KERNEL_BOOT_PATH, PENDING_UPGRADE_PATH};
+    use crate::{budget::TICK_BUDGET_PATH, entry};
+
+    fn administrators() -> [(PublicKey, SecretKey); 2] {
+        [
+            jstz_mock::keypair1(),
+            (
+                PublicKey::from_base58(
+                    "edpktzNbDAUjUk697W7gYg2CRuBQjyPxbEg8dLccYYwKSKvkPvjtV9",
+                )
+                .unwrap(),
+                SecretKey::from_base58(
+                    "edsk39qAm1fiMjgmPkw1EgQYkMzkJezLNewd7PLNHTkr6w9XA2zdfo",
+                )
+                .unwrap(),
+            ),
+        ]
+    }
+
+    fn setup_administrators(host: &mut JstzMockHost) {
+        let administrators = Administrators {
+            threshold: 2,
+            public_keys: administrators().into_iter().map(|(pk, _)| pk).collect(),
+        };
+        administrators.store(host.rt()).unwrap();
+    }
+
+    fn new_kernel(version: u8) -> Vec<u8> {
+        sized_kernel(version, 10_000)
+    }
+
+    fn sized_kernel(version: u8, size: usize) -> Vec<u8> {
+        let mut kernel = b"\0asm\x01\0\0\0".to_vec();
+        kernel.extend(vec![version; size]);
+        kernel
+    }
+
+    fn rollup_address(host: &mut JstzMockHost) -> SmartRollupAddress {
+        SmartRollupAddress::new(host.rt().reveal_metadata().address())
+    }
+
+    fn add_upgrade(
+        host: &mut JstzMockHost,
+        kernel: &[u8],
+        nonce: u64,
+        signers: &[usize],
+    ) {
+        let rollup_address = rollup_address(host);
+        add_upgrade_of(host, &rollup_address, kernel, nonce, signers)
+    }
+
+    fn add_upgrade_of(
+        host: &mut JstzMockHost,
+        rollup_address: &SmartRollupAddress,
+        kernel: &[u8],
+        nonce: u64,
+        signers: &[usize],
+    ) {
+        let root_hash = reveal_data::prepare_preimages(kernel, |_, page| {
+            host.rt().set_preimage(page)
+        });
+        let upgrade = KernelUpgrade { root_hash, nonce };
+        let administrators = administrators();
+        let signatures = signers
+            .iter()
+            .map(|&i| {
+                let (pk, sk) = &administrators[i];
+                (pk.clone(), sk.sign(upgrade.hash(rollup_address)).unwrap())
+            })
+            .collect();
+        let contents = ExternalMessageContents::KernelUpgrade(SignedKernelUpgrade {
+            upgrade,
+            signatures,
+        });
+        host.add_external_message(&bincode::serialize(&contents).unwrap());
+    }
+
+    fn boot_kernel(host: &mut JstzMockHost) -> Option<Vec<u8>> {
+        host.rt().store_read_all(&KERNEL_BOOT_PATH).ok()
+    }
+
+    fn receiver_balance(host: &mut JstzMockHost, deposit: &MockNativeDeposit) -> u64 {
+        let Contract::Implicit(PublicKeyHash::Ed25519(tz1)) = &deposit.receiver else {
+            panic!("Unexpected receiver")
+        };
+        let mut tx = Transaction::default();
+        tx.begin();
+        Account::balance(
+            host.rt(),
+            &mut tx,
+            &jstz_crypto::public_key_hash::PublicKeyHash::Tz1(tz1.clone()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn kernel_upgrade_preserves_accounts() {
+        let mut host = JstzMockHost::default();
+        setup_administrators(&mut host);
+        let deposit = MockNativeDeposit::default();
+        host.add_internal_message(&deposit);
+        host.rt().run_level(entry);
+        assert_eq!(receiver_balance(&mut host, &deposit), 100);
+
+        let kernel = new_kernel(1);
+        add_upgrade(&mut host, &kernel, 0, &[0, 1]);
+        host.rt().run_level(entry);
+
+        assert_eq!(boot_kernel(&mut host), Some(kernel));
+        assert_eq!(upgrade_nonce(host.rt()).unwrap(), 1);
+        assert_eq!(receiver_balance(&mut host, &deposit), 100);
+    }
+
+    #[test]
+    fn kernel_upgrade_requires_threshold_signatures() {
+        let mut host = JstzMockHost::default();
+        setup_administrators(&mut host);
+        let original_kernel = boot_kernel(&mut host);
+
+        add_upgrade(&mut host, &new_kernel(1), 0, &[0]);
+        add_upgrade(&mut host, &new_kernel(1), 0, &[1, 1]);
+        host.rt().run_level(entry);
+
+        assert_eq!(boot_kernel(&mut host), original_kernel);
+        assert_eq!(upgrade_nonce(host.rt()).unwrap(), 0);
+    }
+
+    #[test]
+    fn kernel_upgrade_cannot_be_replayed() {
+        let mut host = JstzMockHost::default();
+        setup_administrators(&mut host);
+
+        let kernel = new_kernel(1);
+        add_upgrade(&mut host, &kernel, 0, &[0, 1]);
+        host.rt().run_level(entry);
+        add_upgrade(&mut host, &new_kernel(2), 0, &[0, 1]);
+        host.rt().run_level(entry);
+
+        assert_eq!(boot_kernel(&mut host), Some(kernel));
+        assert_eq!(upgrade_nonce(host.rt()).unwrap(), 1);
+    }
+
+    #[test]
+    fn kernel_upgrade_is_specific_to_the_rollup() {
+        let mut host = JstzMockHost::default();
+        setup_administrators(&mut host);
+        let original_kernel = boot_kernel(&mut host);
+
+        let other_rollup =
+            SmartRollupAddress::from_b58check("sr1Ghq66tYK9y3r8CC1Tf8i8m5nxh8nTvZEf")
+                .unwrap();
+        add_upgrade_of(&mut host, &other_rollup, &new_kernel(1), 0, &[0, 1]);
+        host.rt().run_level(entry);
+
+        assert_eq!(boot_kernel(&mut host), original_kernel);
+        assert_eq!(upgrade_nonce(host.rt()).unwrap(), 0);
+    }
+
+    #[test]
+    fn kernel_upgrade_is_revealed_over_several_runs() {
+        let mut host = JstzMockHost::default();
+        setup_administrators(&mut host);
+        // Enough budget to reveal about 550 pages per kernel run
+        Storage::insert(host.rt(), &TICK_BUDGET_PATH, &1_100_000_000_u64).unwrap();
+
+        let kernel = sized_kernel(1, 3 << 20);
+        add_upgrade(&mut host, &kernel, 0, &[0, 1]);
+        host.rt().run_level(entry);
+
+        assert_eq!(boot_kernel(&mut host), Some(kernel));
+        assert_eq!(upgrade_nonce(host.rt()).unwrap(), 1);
+        assert!(host
+            .rt()
+            .store_has(&PENDING_UPGRADE_PATH)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -4,11 +4,13 @@ use jstz_core::{host::HostRuntime, kv::Storage};
 
 use tezos_crypto_rs::hash::ContractKt1Hash;
 use tezos_smart_rollup::{
+    inbox::ExternalMessageFrame,
     michelson::{
         ticket::FA2_1Ticket, MichelsonContract, MichelsonOption, MichelsonOr,
         MichelsonPair,
     },
     storage::path::RefPath,
+    types::SmartRollupAddress,
 };
 use tezos_smart_rollup_mock::{MockHost, TransferMetadata};
 
@@ -67,6 +69,18 @@ impl JstzMockHost {
         self.0.add_transfer(payload, &metadata)
     }
 
+    /// Adds an external message targetting the rollup with the given contents
+    pub fn add_external_message(&mut self, contents: &[u8]) {
+        let address = SmartRollupAddress::new(self.0.reveal_metadata().address());
+        let frame = ExternalMessageFrame::Targetted { address, contents };
+        self.0.add_external(frame);
+    }
+
+    /// Publishes `content` to the DAL slot `slot_index` at `published_level`
+    pub fn set_dal_slot(&mut self, published_level: i32, slot_index: u8, content: &[u8]) {
+        self.0.set_dal_slot(published_level, slot_index, content);
+    }
+
     pub fn get_ticketer(&self) -> ContractKt1Hash {
         ContractKt1Hash::from_base58_check(NATIVE_TICKETER).unwrap()
     }
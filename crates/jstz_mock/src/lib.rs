@@ -1,3 +1,4 @@
+use jstz_crypto::{public_key::PublicKey, secret_key::SecretKey};
 use tezos_crypto_rs::hash::ContractKt1Hash;
 use tezos_smart_rollup::{
     michelson::{
@@ -30,6 +31,17 @@ pub fn account1() -> jstz_crypto::public_key_hash::PublicKeyHash {
     .unwrap()
 }
 
+/// Returns the key pair of [account1]
+pub fn keypair1() -> (PublicKey, SecretKey) {
+    let public_key =
+        PublicKey::from_base58("edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav")
+            .unwrap();
+    let secret_key =
+        SecretKey::from_base58("edsk3gUfUPyBSfrS9CCgmCiQsTCHGkviBDusMxDJstFtojtc1zcpsh")
+            .unwrap();
+    (public_key, secret_key)
+}
+
 pub fn account2() -> jstz_crypto::public_key_hash::PublicKeyHash {
     jstz_crypto::public_key_hash::PublicKeyHash::from_base58(
         "tz1QcqnzZ8pa6VuE4MSeMjsJkiW94wNrPbgX",
@@ -0,0 +1,286 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use jstz_proto::operation::{
+    batch::OperationBatch, ExternalMessageContents, SignedOperation,
+};
+use octez::OctezRollupClient;
+use tezos_data_encoding::enc::BinWriter;
+use tezos_smart_rollup::{inbox::ExternalMessageFrame, types::SmartRollupAddress};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::metrics::Metrics;
+
+/// Default duration during which injected operations are aggregated
+pub const DEFAULT_BATCH_WINDOW: Duration = Duration::from_millis(100);
+
+struct PendingOperation {
+    operation: SignedOperation,
+    injected: oneshot::Sender<std::result::Result<String, String>>,
+}
+
+/// Aggregates the operations injected within a short window into batch
+/// external messages, which are then injected into the rollup node batcher
+/// at once. This amortises the cost of inbox messages when many small
+/// operations are injected concurrently.
+#[derive(Clone)]
+pub struct Batcher {
+    sender: mpsc::UnboundedSender<PendingOperation>,
+}
+
+impl Batcher {
+    /// Spawns the task aggregating the operations injected within `window`.
+    pub fn spawn(
+        rollup_client: OctezRollupClient,
+        window: Duration,
+        metrics: Metrics,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(run(rollup_client, window, metrics, receiver));
+        Self { sender }
+    }
+
+    /// Queues `operation` for injection. Resolves once the batch containing
+    /// the operation has been injected, with the id of the batcher message
+    /// containing the operation.
+    pub async fn inject(&self, operation: SignedOperation) -> Result<String> {
+        let (injected, result) = oneshot::channel();
+        self.sender
+            .send(PendingOperation {
+                operation,
+                injected,
+            })
+            .map_err(|_| anyhow!("Batcher stopped"))?;
+        result
+            .await
+            .map_err(|_| anyhow!("Batcher stopped"))?
+            .map_err(|err| anyhow!(err))
+    }
+}
+
+async fn run(
+    rollup_client: OctezRollupClient,
+    window: Duration,
+    metrics: Metrics,
+    mut receiver: mpsc::UnboundedReceiver<PendingOperation>,
+) {
+    while let Some(first) = receiver.recv().await {
+        let mut pending = vec![first];
+        let deadline = tokio::time::sleep(window);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                _ = &mut deadline => break,
+                next = receiver.recv() => match next {
+                    Some(next) => pending.push(next),
+                    None => break,
+                },
+            }
+        }
+
+        let (operations, senders): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .map(|pending| (pending.operation, pending.injected))
+            .unzip();
+        let result = inject(&rollup_client, operations).await;
+        metrics.observe_injections(result.is_ok(), senders.len());
+        match result {
+            Ok(message_ids) => {
+                for (sender, message_id) in senders.into_iter().zip(message_ids) {
+                    let _ = sender.send(Ok(message_id));
+                }
+            }
+            Err(err) => {
+                for sender in senders {
+                    let _ = sender.send(Err(err.to_string()));
+                }
+            }
+        }
+    }
+}
+
+/// Injects `operations`, returning the id of the batcher message containing
+/// each operation
+async fn inject(
+    rollup_client: &OctezRollupClient,
+    operations: Vec<SignedOperation>,
+) -> Result<Vec<String>> {
+    let address = rollup_client.get_rollup_address().await?;
+    // Compressing the batches is CPU bound
+    let packed = tokio::task::spawn_blocking(move || pack(operations)).await?;
+    let messages = packed
+        .iter()
+        .map(|(contents, _)| encode_external_message(&address, contents))
+        .collect::<Result<Vec<_>>>()?;
+    let message_ids = rollup_client.batcher_injection(messages).await?;
+    if message_ids.len() != packed.len() {
+        return Err(anyhow!("Unexpected number of injected messages"));
+    }
+    Ok(packed
+        .iter()
+        .zip(message_ids)
+        .flat_map(|((_, count), message_id)| std::iter::repeat(message_id).take(*count))
+        .collect())
+}
+
+/// Packs `operations` into as few external messages as possible, preserving
+/// their order. Returns the contents of each message with its number of
+/// operations.
+fn pack(operations: Vec<SignedOperation>) -> Vec<(ExternalMessageContents, usize)> {
+    let mut messages = Vec::new();
+    let mut remaining = operations.as_slice();
+    while !remaining.is_empty() {
+        let count = batch_len(remaining);
+        let (batch, rest) = remaining.split_at(count);
+        messages.push((message_contents(batch), count));
+        remaining = rest;
+    }
+    messages
+}
+
+/// Returns the number of leading `operations` that fit in a single external
+/// message, and at least one. The count is searched for exponentially then by
+/// bisection, such that only a logarithmic number of candidate batches are
+/// compressed.
+fn batch_len(operations: &[SignedOperation]) -> usize {
+    let fits = |len: usize| {
+        len == 1 || OperationBatch::new(&operations[..len]).fits_in_external_message()
+    };
+    // The first `low` operations fit, the first `high` do not
+    let mut low = 1;
+    let mut high = 2;
+    while high <= operations.len() && fits(high) {
+        low = high;
+        high *= 2;
+    }
+    let mut high = high.min(operations.len() + 1);
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if fits(middle) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+fn message_contents(operations: &[SignedOperation]) -> ExternalMessageContents {
+    match operations {
+        [operation] => ExternalMessageContents::Operation(operation.clone()),
+        _ => ExternalMessageContents::Batch(OperationBatch::new(operations)),
+    }
+}
+
+/// Encodes `contents` as the binary external message frame targetting the
+/// rollup at `address`.
+pub fn encode_external_message(
+    address: &SmartRollupAddress,
+    contents: &ExternalMessageContents,
+) -> Result<Vec<u8>> {
+    let encoded_contents = bincode::serialize(contents)
+        .map_err(|_| anyhow!("Failed to serialize operation"))?;
+    let message_frame = ExternalMessageFrame::Targetted {
+        address: address.clone(),
+        contents: encoded_contents,
+    };
+    let mut binary_contents = Vec::new();
+    message_frame
+        .bin_write(&mut binary_contents)
+        .map_err(|_| anyhow!("Failed to write binary frame"))?;
+    Ok(binary_contents)
+}
+
+#[cfg(test)]
+mod test {
+    use jstz_proto::{
+        context::account::{Nonce, ParsedCode},
+        operation::{
+            batch::OperationBatch, Content, DeployFunction, ExternalMessageContents,
+            Operation, SignedOperation,
+        },
+    };
+
+    use super::pack;
+
+    fn deploy_operation(nonce: Nonce, code: &str) -> SignedOperation {
+        let op = Operation {
+            source: jstz_crypto::public_key_hash::PublicKeyHash::from_base58(
+                "tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx",
+            )
+            .unwrap(),
+            nonce,
+            content: Content::DeployFunction(DeployFunction {
+                function_code: ParsedCode::try_from(code.to_string()).unwrap(),
+                account_credit: 0,
+            }),
+        };
+        crate::sign_operation(op)
+    }
+
+    fn deploy_operations(count: usize, code: &str) -> Vec<SignedOperation> {
+        std::iter::successors(Some(Nonce::default()), |nonce| Some(nonce.next()))
+            .take(count)
+            .map(|nonce| deploy_operation(nonce, code))
+            .collect()
+    }
+
+    fn unpack(messages: Vec<(ExternalMessageContents, usize)>) -> Vec<SignedOperation> {
+        messages
+            .into_iter()
+            .flat_map(|(contents, count)| {
+                let operations = match contents {
+                    ExternalMessageContents::Operation(operation) => vec![operation],
+                    ExternalMessageContents::Batch(batch) => batch.operations().unwrap(),
+                    _ => panic!("Unexpected message contents"),
+                };
+                assert_eq!(operations.len(), count);
+                operations
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pack_sends_single_operation_unbatched() {
+        let operations = deploy_operations(1, "export default () => {}");
+        let messages = pack(operations);
+        assert!(matches!(
+            messages.as_slice(),
+            [(ExternalMessageContents::Operation(_), 1)]
+        ));
+    }
+
+    #[test]
+    fn pack_batches_operations_in_order() {
+        let operations = deploy_operations(20, "export default () => {}");
+        let hashes: Vec<_> = operations.iter().map(SignedOperation::hash).collect();
+        let messages = pack(operations);
+        assert_eq!(messages.len(), 1);
+        let unpacked: Vec<_> = unpack(messages).iter().map(|op| op.hash()).collect();
+        assert_eq!(unpacked, hashes);
+    }
+
+    #[test]
+    fn pack_splits_batches_exceeding_an_external_message() {
+        // Signatures do not compress, so this does not fit in a single message
+        let code = format!("export default () => '{}'", "a".repeat(1000));
+        let operations = deploy_operations(100, &code);
+        let hashes: Vec<_> = operations.iter().map(SignedOperation::hash).collect();
+        let messages = pack(operations.clone());
+        assert!(messages.len() > 1);
+        let mut end = 0;
+        for (contents, count) in &messages {
+            if let ExternalMessageContents::Batch(batch) = contents {
+                assert!(batch.fits_in_external_message());
+            }
+            // Batches are filled before starting the next one
+            end += count;
+            if end < operations.len() {
+                let extended = OperationBatch::new(&operations[end - count..=end]);
+                assert!(!extended.fits_in_external_message());
+            }
+        }
+        let unpacked: Vec<_> = unpack(messages).iter().map(|op| op.hash()).collect();
+        assert_eq!(unpacked, hashes);
+    }
+}
@@ -1,6 +1,19 @@
 use std::path::{Path, PathBuf};
 
-use octez::r#async::endpoint::Endpoint;
+use anyhow::{bail, Result};
+use figment::{
+    providers::{Format, Json, Toml},
+    Figment,
+};
+use octez::r#async::{client::OctezClientConfig, endpoint::Endpoint};
+use serde::{Deserialize, Serialize};
+
+// Directory of the node data, relative to the home directory
+const DEFAULT_DATA_DIR: &str = ".jstz";
+// Path of the log database, relative to the data directory
+const DEFAULT_DB_PATH: &str = "log.db";
+/// Default maximum size of a request body, in bytes
+pub const DEFAULT_BODY_LIMIT: usize = 2 * 1024 * 1024;
 
 #[derive(Clone)]
 pub struct JstzNodeConfig {
@@ -10,6 +23,12 @@ pub struct JstzNodeConfig {
     pub rollup_endpoint: Endpoint,
     /// The path to the rollup kernel log file.
     pub kernel_log_file: PathBuf,
+    /// Publishing of operation batches to the DAL, disabled if `None`.
+    pub dal_publisher: Option<DalPublisherConfig>,
+    /// Execution of cemented withdrawals on the L1, disabled if `None`.
+    pub outbox_executor: Option<OutboxExecutorConfig>,
+    /// Storage and HTTP settings of the node.
+    pub settings: NodeSettings,
 }
 
 impl JstzNodeConfig {
@@ -22,6 +41,263 @@ impl JstzNodeConfig {
             endpoint: endpoint.clone(),
             rollup_endpoint: rollup_endpoint.clone(),
             kernel_log_file: kernel_log_file.to_path_buf(),
+            dal_publisher: None,
+            outbox_executor: None,
+            settings: NodeSettings::default(),
+        }
+    }
+}
+
+/// Storage and HTTP settings of the node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeSettings {
+    /// Directory the node stores its data in.
+    pub data_dir: PathBuf,
+    /// Path of the log database. Relative paths are resolved against `data_dir`.
+    pub db_path: PathBuf,
+    /// Origins allowed to make cross-origin requests. `*` allows any origin.
+    pub allowed_origins: Vec<String>,
+    /// Certificate and key the node serves HTTPS with. The node serves plain
+    /// HTTP if `None`.
+    pub tls: Option<TlsConfig>,
+    /// Maximum size of a request body, in bytes.
+    pub body_limit: usize,
+    /// Number of days persistent logs are kept for. Logs are kept forever if
+    /// `None`.
+    pub log_retention_days: Option<u32>,
+    /// Limits on the requests of the clients of the node.
+    pub rate_limits: RateLimitConfig,
+}
+
+impl Default for NodeSettings {
+    fn default() -> Self {
+        Self {
+            data_dir: dirs::home_dir().unwrap_or_default().join(DEFAULT_DATA_DIR),
+            db_path: PathBuf::from(DEFAULT_DB_PATH),
+            allowed_origins: vec!["*".to_string()],
+            tls: None,
+            body_limit: DEFAULT_BODY_LIMIT,
+            log_retention_days: None,
+            rate_limits: RateLimitConfig::default(),
+        }
+    }
+}
+
+impl NodeSettings {
+    /// Path of the log database
+    pub fn db_path(&self) -> PathBuf {
+        self.data_dir.join(&self.db_path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// Path of the PEM encoded certificate chain.
+    pub cert_path: PathBuf,
+    /// Path of the PEM encoded private key.
+    pub key_path: PathBuf,
+}
+
+/// Limits on the requests of the clients of the node. Requests are unlimited
+/// by default.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// API keys whose requests are limited per key rather than per IP address.
+    /// Clients pass their key in the `X-API-Key` header.
+    pub api_keys: Vec<String>,
+    /// Limits of the `/operations` routes.
+    pub operations: RouteLimits,
+    /// Limits of the `/accounts` routes.
+    pub accounts: RouteLimits,
+    /// Limits of the `/logs` routes.
+    pub logs: RouteLimits,
+    /// Limits of the `/graphql` routes.
+    pub graphql: RouteLimits,
+    /// Maximum number of clients streaming logs at once, through Server-Sent
+    /// Events or WebSockets. Unlimited if `None`.
+    pub max_sse_clients: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RouteLimits {
+    /// Limit of each client IP address. Unlimited if `None`.
+    pub per_ip: Option<RateLimit>,
+    /// Limit of each API key. Unlimited if `None`.
+    pub per_api_key: Option<RateLimit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// Number of requests allowed per minute, on average.
+    pub requests_per_minute: u32,
+    /// Number of requests allowed in a burst.
+    pub burst: u32,
+}
+
+/// Configuration file of the node, written in TOML or JSON. Fields missing
+/// from the file take their default value.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    /// Address the node listens on.
+    pub addr: Option<String>,
+    /// Port the node listens on.
+    pub port: Option<u16>,
+    /// Endpoint of the rollup node.
+    pub rollup_endpoint: Option<String>,
+    /// Path of the rollup kernel log file.
+    pub kernel_log_path: Option<PathBuf>,
+    #[serde(flatten)]
+    pub settings: NodeSettings,
+}
+
+impl ConfigFile {
+    /// Loads the config file at `path`. The format of the file is given by its
+    /// extension, either `.toml` or `.json`.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            bail!("Config file '{}' does not exist", path.display());
         }
+        let figment = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Figment::new().merge(Toml::file(path)),
+            Some("json") => Figment::new().merge(Json::file(path)),
+            _ => bail!(
+                "Unsupported config file '{}', expected a .toml or .json file",
+                path.display()
+            ),
+        };
+        Ok(figment.extract()?)
+    }
+}
+
+#[derive(Clone)]
+pub struct DalPublisherConfig {
+    /// The endpoint of the DAL node the slots are posted to.
+    pub dal_node_endpoint: Endpoint,
+    /// The index of the DAL slot the batches are published to. The kernel must
+    /// be subscribed to this slot.
+    pub slot_index: u8,
+    /// Address (or alias) of the L1 account publishing the slot commitments.
+    pub publisher: String,
+    /// Configuration of the octez client publishing the slot commitments.
+    pub octez_client: OctezClientConfig,
+}
+
+#[derive(Clone)]
+pub struct OutboxExecutorConfig {
+    /// Address (or alias) of the L1 account executing the outbox messages. It
+    /// pays the fees of the executions.
+    pub executor: String,
+    /// Configuration of the octez client executing the outbox messages.
+    pub octez_client: OctezClientConfig,
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::Write, path::PathBuf};
+
+    use pretty_assertions::assert_eq;
+    use tempfile::NamedTempFile;
+
+    use super::{
+        ConfigFile, NodeSettings, RateLimit, RateLimitConfig, RouteLimits, TlsConfig,
+        DEFAULT_BODY_LIMIT,
+    };
+
+    fn write_config(suffix: &str, content: &str) -> NamedTempFile {
+        let mut file = tempfile::Builder::new().suffix(suffix).tempfile().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn loads_toml_config() {
+        let file = write_config(
+            ".toml",
+            r#"
+            port = 8934
+            data_dir = "/var/lib/jstz-node"
+            allowed_origins = ["https://example.com"]
+            log_retention_days = 7
+
+            [tls]
+            cert_path = "cert.pem"
+            key_path = "key.pem"
+
+            [rate_limits]
+            api_keys = ["secret"]
+            max_sse_clients = 100
+
+            [rate_limits.operations]
+            per_ip = { requests_per_minute = 60, burst = 10 }
+            "#,
+        );
+        let config = ConfigFile::load(file.path()).unwrap();
+
+        assert_eq!(config.addr, None);
+        assert_eq!(config.port, Some(8934));
+        assert_eq!(
+            config.settings,
+            NodeSettings {
+                data_dir: PathBuf::from("/var/lib/jstz-node"),
+                allowed_origins: vec!["https://example.com".to_string()],
+                tls: Some(TlsConfig {
+                    cert_path: PathBuf::from("cert.pem"),
+                    key_path: PathBuf::from("key.pem"),
+                }),
+                log_retention_days: Some(7),
+                rate_limits: RateLimitConfig {
+                    api_keys: vec!["secret".to_string()],
+                    operations: RouteLimits {
+                        per_ip: Some(RateLimit {
+                            requests_per_minute: 60,
+                            burst: 10,
+                        }),
+                        per_api_key: None,
+                    },
+                    max_sse_clients: Some(100),
+                    ..RateLimitConfig::default()
+                },
+                ..NodeSettings::default()
+            }
+        );
+        assert_eq!(
+            config.settings.db_path(),
+            PathBuf::from("/var/lib/jstz-node/log.db")
+        );
+    }
+
+    #[test]
+    fn loads_json_config() {
+        let file = write_config(
+            ".json",
+            r#"{"rollup_endpoint": "http://127.0.0.1:8932", "db_path": "/tmp/logs.db", "body_limit": 1024}"#,
+        );
+        let config = ConfigFile::load(file.path()).unwrap();
+
+        assert_eq!(
+            config.rollup_endpoint,
+            Some("http://127.0.0.1:8932".to_string())
+        );
+        assert_eq!(config.settings.body_limit, 1024);
+        assert_eq!(config.settings.db_path(), PathBuf::from("/tmp/logs.db"));
+        assert_eq!(config.settings.allowed_origins, vec!["*".to_string()]);
+    }
+
+    #[test]
+    fn rejects_unknown_format() {
+        let file = write_config(".yaml", "port: 8934");
+        assert!(ConfigFile::load(file.path()).is_err());
+        assert!(ConfigFile::load(&PathBuf::from("/does/not/exist.toml")).is_err());
+    }
+
+    #[test]
+    fn defaults_settings() {
+        let settings = NodeSettings::default();
+        assert_eq!(settings.body_limit, DEFAULT_BODY_LIMIT);
+        assert!(settings.db_path().ends_with(".jstz/log.db"));
     }
 }
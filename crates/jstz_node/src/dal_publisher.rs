@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use jstz_proto::operation::{dal::DalBatch, SignedOperation};
+use octez::{
+    r#async::client::OctezClient, OctezDalClient, OctezRollupClient, SlotCommitment,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::config::DalPublisherConfig;
+
+/// A batch of operations published to the DAL
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DalPublication {
+    /// Index of the DAL slot the batch was published to
+    pub slot_index: u8,
+    /// Commitment of the slot
+    pub commitment: String,
+    /// Hash of the L1 operation publishing the commitment
+    pub operation_hash: String,
+}
+
+/// Publishes batches of operations to a DAL slot the kernel is subscribed to.
+#[derive(Clone)]
+pub struct DalPublisher {
+    dal_client: OctezDalClient,
+    octez_client: Arc<OctezClient>,
+    slot_index: u8,
+    publisher: String,
+}
+
+impl DalPublisher {
+    pub fn new(config: DalPublisherConfig) -> Self {
+        Self {
+            dal_client: OctezDalClient::new(config.dal_node_endpoint.to_string()),
+            octez_client: Arc::new(OctezClient::new(config.octez_client)),
+            slot_index: config.slot_index,
+            publisher: config.publisher,
+        }
+    }
+
+    /// Posts a batch of `operations` to the DAL node and publishes its
+    /// commitment on the L1.
+    pub async fn publish(
+        &self,
+        rollup_client: &OctezRollupClient,
+        operations: Vec<SignedOperation>,
+    ) -> Result<DalPublication> {
+        let rollup_address = rollup_client.get_rollup_address().await?;
+        let batch = DalBatch {
+            rollup_address: rollup_address.hash().0.as_slice().try_into()?,
+            operations,
+        };
+
+        let SlotCommitment {
+            commitment,
+            commitment_proof,
+        } = self
+            .dal_client
+            .post_slot(self.slot_index, &batch.encode())
+            .await?;
+        let operation_hash = self
+            .octez_client
+            .publish_dal_commitment(
+                &self.publisher,
+                self.slot_index,
+                &commitment,
+                &commitment_proof,
+            )
+            .await?;
+
+        Ok(DalPublication {
+            slot_index: self.slot_index,
+            commitment,
+            operation_hash: operation_hash.to_base58_check(),
+        })
+    }
+}
@@ -0,0 +1,464 @@
+use std::{future::ready, sync::OnceLock};
+
+use async_graphql::{
+    http::{GraphiQLSource, ALL_WEBSOCKET_PROTOCOLS},
+    Context, Data, EmptyMutation, Error, Json, Object, Result, Schema, SimpleObject,
+    Subscription,
+};
+use async_graphql_axum::{
+    GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket,
+};
+use axum::{
+    extract::{State, WebSocketUpgrade},
+    response::{Html, IntoResponse, Response},
+};
+use futures_util::{stream, Stream, StreamExt};
+use jstz_api::{js_log::LogLevel, KvValue};
+use jstz_proto::{
+    context::account::{Account, Address, Nonce},
+    js_logger::LogRecord,
+    operation::OperationHash,
+    receipt::Receipt,
+    request_logger::RequestEvent,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::services::logs::RequestMetadata;
+#[cfg(feature = "persistent-logging")]
+use crate::services::logs::{LogFilter, MAX_LOGS_LIMIT};
+use crate::{
+    mempool::{OperationStatus, PendingOperation},
+    services::{
+        accounts::{construct_storage_key, read_account, resolve_level},
+        error::ServiceError,
+        logs::broadcaster::{Event, Notification},
+        websocket::Subscription as Filter,
+    },
+    AppState,
+};
+
+pub type JstzSchema = Schema<QueryRoot, EmptyMutation, SubscriptionRoot>;
+
+/// Path of the GraphQL endpoint
+pub const GRAPHQL_PATH: &str = "/graphql";
+
+/// Path of the GraphQL subscriptions endpoint
+pub const GRAPHQL_WS_PATH: &str = "/graphql/ws";
+
+// Maximum depth of queries
+const MAX_DEPTH: usize = 10;
+
+// Maximum complexity of queries, each field counting for 1 by default
+const MAX_COMPLEXITY: usize = 200;
+
+// Schema shared by all requests, which are given the state of the node
+fn schema() -> &'static JstzSchema {
+    static SCHEMA: OnceLock<JstzSchema> = OnceLock::new();
+    SCHEMA.get_or_init(|| {
+        Schema::build(QueryRoot, EmptyMutation, SubscriptionRoot)
+            .limit_depth(MAX_DEPTH)
+            .limit_complexity(MAX_COMPLEXITY)
+            .finish()
+    })
+}
+
+fn service_error(error: ServiceError) -> Error {
+    match error {
+        ServiceError::FromAnyhow(error) => Error::new(error.to_string()),
+        ServiceError::NotFound => Error::new("Not found"),
+        ServiceError::BadRequest(error) => Error::new(error),
+        ServiceError::PersistentLogsDisabled => Error::new("Persistent logs disabled"),
+        ServiceError::DalPublishingDisabled => Error::new("DAL publishing disabled"),
+        ServiceError::TooManyRequests(_) => Error::new("Too many requests"),
+    }
+}
+
+fn parse_address(address: &str) -> Result<Address> {
+    Address::from_base58(address).map_err(|e| Error::new(e.to_string()))
+}
+
+fn parse_hash(hash: String) -> Result<OperationHash> {
+    OperationHash::try_parse(hash).map_err(|_| Error::new("Invalid operation hash"))
+}
+
+fn parse_level(level: &str) -> Result<LogLevel> {
+    LogLevel::try_from(level).map_err(Error::new)
+}
+
+/// Console log of a smart function
+#[derive(SimpleObject)]
+pub struct Log {
+    address: String,
+    request_id: String,
+    level: String,
+    text: String,
+}
+
+impl From<&LogRecord> for Log {
+    fn from(log: &LogRecord) -> Self {
+        Self {
+            address: log.address.to_string(),
+            request_id: log.request_id.clone(),
+            level: log.level.to_string(),
+            text: log.text.clone(),
+        }
+    }
+}
+
+/// Log published by the node, numbered by a cursor to resume from
+#[derive(SimpleObject)]
+pub struct LogNotification {
+    cursor: u64,
+    log: Log,
+}
+
+/// Start or end of a request published by the node, numbered by a cursor to
+/// resume from
+#[derive(SimpleObject)]
+pub struct RequestNotification {
+    cursor: u64,
+    event: Json<RequestEvent>,
+}
+
+/// Account at a level of the rollup
+pub struct AccountNode {
+    address: Address,
+    level: u32,
+    account: Account,
+}
+
+#[Object]
+impl AccountNode {
+    async fn address(&self) -> String {
+        self.address.to_string()
+    }
+
+    /// Level of the rollup the account is read at
+    async fn level(&self) -> u32 {
+        self.level
+    }
+
+    async fn nonce(&self) -> Json<Nonce> {
+        Json(self.account.nonce)
+    }
+
+    async fn balance(&self) -> u64 {
+        self.account.amount
+    }
+
+    /// Code of the smart function, if the account is a smart function
+    async fn code(&self) -> Option<String> {
+        self.account.function_code.clone().map(String::from)
+    }
+
+    /// KV value under the key path `key`, the empty key path if unset
+    async fn kv(
+        &self,
+        ctx: &Context<'_>,
+        key: Option<String>,
+    ) -> Result<Option<Json<KvValue>>> {
+        let state = ctx.data::<AppState>()?;
+        let key = construct_storage_key(&self.address.to_string(), &key);
+        match state.state_cache.get_value(&key, self.level).await? {
+            Some(value) => Ok(Some(Json(
+                bincode::deserialize::<KvValue>(&value)
+                    .map_err(|_| Error::new("Failed to deserialize KV value"))?,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// KV subkeys under the key path `key`, the empty key path if unset
+    async fn kv_subkeys(
+        &self,
+        ctx: &Context<'_>,
+        key: Option<String>,
+    ) -> Result<Vec<String>> {
+        let state = ctx.data::<AppState>()?;
+        let key = construct_storage_key(&self.address.to_string(), &key);
+        Ok(state
+            .state_cache
+            .get_subkeys(&key, self.level)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Persistent console logs of the smart function, at most 1000, only if
+    /// persistent logging is enabled on this node
+    #[allow(unused_variables)]
+    async fn logs(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 100)] limit: usize,
+        #[graphql(default = 0)] offset: usize,
+        level: Option<String>,
+        since: Option<u64>,
+        until: Option<u64>,
+        from_level: Option<u32>,
+        to_level: Option<u32>,
+        q: Option<String>,
+    ) -> Result<Vec<Log>> {
+        #[cfg(feature = "persistent-logging")]
+        {
+            let state = ctx.data::<AppState>()?;
+            let filter = LogFilter {
+                level: level.as_deref().map(parse_level).transpose()?,
+                since,
+                until,
+                from_level,
+                to_level,
+                q,
+            };
+            let logs = state
+                .db
+                .logs_by_address(
+                    self.address.clone(),
+                    &filter,
+                    limit.min(MAX_LOGS_LIMIT),
+                    offset,
+                )
+                .await?;
+            Ok(logs.iter().map(Log::from).collect())
+        }
+
+        #[cfg(not(feature = "persistent-logging"))]
+        Err(service_error(ServiceError::PersistentLogsDisabled))
+    }
+
+    /// Metadata of the request `request_id` to the smart function, only if
+    /// persistent logging is enabled on this node
+    #[allow(unused_variables)]
+    async fn request(
+        &self,
+        ctx: &Context<'_>,
+        request_id: String,
+    ) -> Result<Option<Json<RequestMetadata>>> {
+        #[cfg(feature = "persistent-logging")]
+        {
+            let state = ctx.data::<AppState>()?;
+            let metadata = state
+                .db
+                .request_metadata(self.address.clone(), request_id)
+                .await?;
+            Ok(metadata.map(Json))
+        }
+
+        #[cfg(not(feature = "persistent-logging"))]
+        Err(service_error(ServiceError::PersistentLogsDisabled))
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Level of the head of the rollup
+    async fn head(&self, ctx: &Context<'_>) -> Result<u32> {
+        let state = ctx.data::<AppState>()?;
+        Ok(state.state_cache.head().await?)
+    }
+
+    /// Account `address` at `level`, or at the head of the rollup if unset
+    async fn account(
+        &self,
+        ctx: &Context<'_>,
+        address: String,
+        level: Option<u32>,
+    ) -> Result<Option<AccountNode>> {
+        let state = ctx.data::<AppState>()?;
+        let address = parse_address(&address)?;
+        let level = resolve_level(&state.state_cache, level)
+            .await
+            .map_err(service_error)?;
+        match read_account(&state.state_cache, &address.to_string(), level).await {
+            Ok(account) => Ok(Some(AccountNode {
+                address,
+                level,
+                account,
+            })),
+            Err(ServiceError::NotFound) => Ok(None),
+            Err(e) => Err(service_error(e)),
+        }
+    }
+
+    /// Receipt of the operation `hash`, once it was executed
+    async fn receipt(
+        &self,
+        ctx: &Context<'_>,
+        hash: String,
+    ) -> Result<Option<Json<Receipt>>> {
+        let state = ctx.data::<AppState>()?;
+        let receipt = state.mempool.read_receipt(&parse_hash(hash)?).await?;
+        Ok(receipt.map(Json))
+    }
+
+    /// Stage of the operation `hash` in its lifecycle
+    async fn operation_status(
+        &self,
+        ctx: &Context<'_>,
+        hash: String,
+    ) -> Result<Json<OperationStatus>> {
+        let state = ctx.data::<AppState>()?;
+        Ok(Json(state.mempool.status(&parse_hash(hash)?).await?))
+    }
+
+    /// Operations accepted by the node that have not been executed yet
+    async fn pending_operations(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Json<Vec<PendingOperation>>> {
+        let state = ctx.data::<AppState>()?;
+        Ok(Json(state.mempool.pending()))
+    }
+}
+
+// Streams the notifications matching `filter` published after `cursor`, or from
+// now on if unset
+fn notifications(
+    state: &AppState,
+    filter: Filter,
+    cursor: Option<u64>,
+) -> impl Stream<Item = std::sync::Arc<Notification>> {
+    let replay = state.broadcaster.replay(cursor);
+    let live = stream::unfold(replay.receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(notification) => return Some((notification, receiver)),
+                Err(RecvError::Lagged(missed)) => {
+                    log::warn!("A GraphQL subscriber missed {} notifications", missed)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    stream::iter(replay.notifications)
+        .chain(live)
+        .filter(move |notification| ready(filter.matches(&notification.event)))
+}
+
+fn parse_addresses(addresses: Vec<String>) -> Result<Vec<Address>> {
+    addresses
+        .iter()
+        .map(|address| parse_address(address))
+        .collect()
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Console logs of the smart functions `addresses`, of `levels` if set.
+    /// Logs published after `cursor` are replayed first.
+    async fn logs(
+        &self,
+        ctx: &Context<'_>,
+        addresses: Vec<String>,
+        levels: Option<Vec<String>>,
+        cursor: Option<u64>,
+    ) -> Result<impl Stream<Item = LogNotification>> {
+        let state = ctx.data::<AppState>()?;
+        let levels = levels
+            .map(|levels| levels.iter().map(|level| parse_level(level)).collect())
+            .transpose()?;
+        let filter = Filter::new(parse_addresses(addresses)?, levels, false);
+        Ok(
+            notifications(state, filter, cursor).filter_map(|notification| {
+                ready(match &notification.event {
+                    Event::Log(log) => Some(LogNotification {
+                        cursor: notification.cursor,
+                        log: log.into(),
+                    }),
+                    Event::Request(_) => None,
+                })
+            }),
+        )
+    }
+
+    /// Start and end of the requests to the smart functions `addresses`.
+    /// Events published after `cursor` are replayed first.
+    async fn request_events(
+        &self,
+        ctx: &Context<'_>,
+        addresses: Vec<String>,
+        cursor: Option<u64>,
+    ) -> Result<impl Stream<Item = RequestNotification>> {
+        let state = ctx.data::<AppState>()?;
+        let filter = Filter::new(parse_addresses(addresses)?, Some(vec![]), true);
+        Ok(
+            notifications(state, filter, cursor).filter_map(|notification| {
+                ready(match &notification.event {
+                    Event::Request(event) => Some(RequestNotification {
+                        cursor: notification.cursor,
+                        event: Json(event.clone()),
+                    }),
+                    Event::Log(_) => None,
+                })
+            }),
+        )
+    }
+}
+
+/// Executes GraphQL queries
+pub(crate) async fn graphql(
+    State(state): State<AppState>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    schema()
+        .execute(request.into_inner().data(state))
+        .await
+        .into()
+}
+
+/// Serves GraphQL subscriptions over WebSockets. Connections count towards the
+/// maximum number of streaming clients.
+pub(crate) async fn graphql_ws(
+    State(state): State<AppState>,
+    protocol: GraphQLProtocol,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let guard = match state.broadcaster.new_socket() {
+        Ok(guard) => guard,
+        Err(error) => return error.into_response(),
+    };
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| async move {
+            let mut data = Data::default();
+            data.insert(state);
+            GraphQLWebSocket::new(stream, schema().clone(), protocol)
+                .with_data(data)
+                .serve()
+                .await;
+            drop(guard);
+        })
+        .into_response()
+}
+
+/// Serves the GraphiQL playground
+pub(crate) async fn playground() -> Html<String> {
+    Html(
+        GraphiQLSource::build()
+            .endpoint(GRAPHQL_PATH)
+            .subscription_endpoint(GRAPHQL_WS_PATH)
+            .finish(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::schema;
+
+    #[test]
+    fn exposes_accounts_logs_and_operations() {
+        let sdl = schema().sdl();
+        for field in [
+            "account(address: String!, level: Int): AccountNode",
+            "receipt(hash: String!): JSON",
+            "operationStatus(hash: String!): JSON!",
+            "kvSubkeys(key: String): [String!]!",
+            "logs(addresses: [String!]!, levels: [String!], cursor: Int): LogNotification!",
+        ] {
+            assert!(sdl.contains(field), "Missing {}", field);
+        }
+    }
+}
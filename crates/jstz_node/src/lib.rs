@@ -1,19 +1,39 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use api_doc::{modify, ApiDoc};
-use axum::{http, routing::get};
-use config::JstzNodeConfig;
+use axum::{extract::DefaultBodyLimit, http, middleware, routing::get};
+use axum_server::tls_rustls::RustlsConfig;
+use batcher::{Batcher, DEFAULT_BATCH_WINDOW};
+use config::{DalPublisherConfig, JstzNodeConfig, NodeSettings, OutboxExecutorConfig};
+use dal_publisher::DalPublisher;
+use mempool::{Mempool, DEFAULT_SYNC_INTERVAL};
+use metrics::{serve_metrics, track_requests, Metrics};
 use octez::OctezRollupClient;
+use rate_limit::{rate_limit, RateLimiter};
+use receipt_poller::{ReceiptPoller, DEFAULT_RECEIPT_POLL_INTERVAL};
 use services::{
     accounts::AccountsService,
     logs::{broadcaster::Broadcaster, db::Db, LogsService},
     operations::OperationsService,
+    outbox::OutboxService,
+    tickets::TicketsService,
+    websocket::WebSocketService,
 };
-use std::{path::PathBuf, sync::Arc};
+use state_cache::{StateCache, DEFAULT_HEAD_POLL_INTERVAL};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 use tokio::net::TcpListener;
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use withdrawals::{OutboxExecutor, DEFAULT_POLL_INTERVAL, OUTBOX_EXECUTOR_STATE_FILE};
 
 mod api_doc;
+mod batcher;
+mod dal_publisher;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod metrics;
+mod rate_limit;
+mod receipt_poller;
 mod services;
+mod state_cache;
 mod tailed_file;
 use services::Service;
 use tokio_util::sync::CancellationToken;
@@ -21,12 +41,37 @@ use utoipa::OpenApi;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_scalar::{Scalar, Servable};
 pub mod config;
+pub mod mempool;
+pub mod withdrawals;
+
+/// Signs `operation` with the key pair of the first bootstrap account
+#[cfg(test)]
+pub(crate) fn sign_operation(
+    operation: jstz_proto::operation::Operation,
+) -> jstz_proto::operation::SignedOperation {
+    use jstz_crypto::{public_key::PublicKey, secret_key::SecretKey};
+
+    let public_key =
+        PublicKey::from_base58("edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav")
+            .unwrap();
+    let secret_key =
+        SecretKey::from_base58("edsk3gUfUPyBSfrS9CCgmCiQsTCHGkviBDusMxDJstFtojtc1zcpsh")
+            .unwrap();
+    let signature = secret_key.sign(operation.hash()).unwrap();
+    jstz_proto::operation::SignedOperation::new(public_key, signature, operation)
+}
 
 #[derive(Clone)]
 pub struct AppState {
     pub rollup_client: OctezRollupClient,
+    pub mempool: Mempool,
+    pub receipt_poller: ReceiptPoller,
     pub broadcaster: Arc<Broadcaster>,
     pub db: Db,
+    pub dal_publisher: Option<DalPublisher>,
+    pub outbox_executor: Option<OutboxExecutor>,
+    pub metrics: Metrics,
+    pub state_cache: StateCache,
 }
 
 pub async fn run_with_config(config: JstzNodeConfig) -> Result<()> {
@@ -38,6 +83,9 @@ pub async fn run_with_config(config: JstzNodeConfig) -> Result<()> {
         endpoint_port,
         rollup_endpoint,
         config.kernel_log_file.to_path_buf(),
+        config.dal_publisher,
+        config.outbox_executor,
+        config.settings,
     )
     .await
 }
@@ -47,42 +95,126 @@ pub async fn run(
     port: u16,
     rollup_endpoint: String,
     kernel_log_path: PathBuf,
+    dal_publisher: Option<DalPublisherConfig>,
+    outbox_executor: Option<OutboxExecutorConfig>,
+    settings: NodeSettings,
 ) -> Result<()> {
-    let rollup_client = OctezRollupClient::new(rollup_endpoint.to_string());
+    let metrics = Metrics::new();
+    let rollup_client = OctezRollupClient::new(rollup_endpoint.to_string())
+        .with_rpc_observer({
+            let metrics = metrics.clone();
+            move |rpc, duration| metrics.observe_rollup_rpc(rpc, duration)
+        });
 
     let cancellation_token = CancellationToken::new();
     let (broadcaster, db, tail_file_handle) =
-        LogsService::init(&kernel_log_path, &cancellation_token).await?;
+        LogsService::init(&kernel_log_path, &settings, &metrics, &cancellation_token)
+            .await?;
 
+    let outbox_executor = outbox_executor
+        .map(|config| {
+            OutboxExecutor::spawn(
+                rollup_client.clone(),
+                config,
+                DEFAULT_POLL_INTERVAL,
+                settings.data_dir.join(OUTBOX_EXECUTOR_STATE_FILE),
+            )
+        })
+        .transpose()?;
+    let mempool = Mempool::spawn(
+        rollup_client.clone(),
+        Batcher::spawn(rollup_client.clone(), DEFAULT_BATCH_WINDOW, metrics.clone()),
+        DEFAULT_SYNC_INTERVAL,
+    );
     let state = AppState {
+        receipt_poller: ReceiptPoller::spawn(
+            mempool.clone(),
+            DEFAULT_RECEIPT_POLL_INTERVAL,
+        ),
+        mempool,
+        state_cache: StateCache::spawn(rollup_client.clone(), DEFAULT_HEAD_POLL_INTERVAL),
         rollup_client,
         broadcaster,
         db,
+        dal_publisher: dal_publisher.map(DalPublisher::new),
+        outbox_executor,
+        metrics: metrics.clone(),
     };
 
     let cors = CorsLayer::new()
         .allow_methods(Any)
-        .allow_origin(Any)
+        .allow_origin(allow_origin(&settings.allowed_origins)?)
         .allow_headers(Any);
 
-    let (router, mut openapi) = router().with_state(state).layer(cors).split_for_parts();
+    let rate_limiter = RateLimiter::new(&settings.rate_limits);
+    let (router, mut openapi) = router()
+        .with_state(state)
+        .layer(middleware::from_fn_with_state(rate_limiter, rate_limit))
+        .layer(middleware::from_fn_with_state(metrics, track_requests))
+        .layer(cors)
+        .layer(DefaultBodyLimit::max(settings.body_limit))
+        .split_for_parts();
     modify(&mut openapi);
     let router = router.merge(Scalar::with_url("/scalar", openapi));
+    #[cfg(feature = "graphql")]
+    let router = router.route("/playground", get(graphql::playground));
 
     let listener = TcpListener::bind(format!("{}:{}", addr, port)).await?;
-    axum::serve(listener, router).await?;
+    match settings.tls {
+        Some(tls) => {
+            let tls_config = RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+                .await
+                .context("Failed to load TLS certificate and key")?;
+            axum_server::from_tcp_rustls(listener.into_std()?, tls_config)
+                .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+                .await?
+        }
+        None => {
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await?
+        }
+    }
 
     cancellation_token.cancel();
     tail_file_handle.await.unwrap()?;
     Ok(())
 }
 
+fn allow_origin(allowed_origins: &[String]) -> Result<AllowOrigin> {
+    if allowed_origins.iter().any(|origin| origin == "*") {
+        return Ok(AllowOrigin::any());
+    }
+    let origins = allowed_origins
+        .iter()
+        .map(|origin| {
+            origin
+                .parse::<http::HeaderValue>()
+                .with_context(|| format!("Invalid allowed origin '{}'", origin))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(AllowOrigin::list(origins))
+}
+
 fn router() -> OpenApiRouter<AppState> {
-    OpenApiRouter::with_openapi(ApiDoc::openapi())
+    let router = OpenApiRouter::with_openapi(ApiDoc::openapi())
         .merge(OperationsService::router_with_openapi())
         .merge(AccountsService::router_with_openapi())
         .merge(LogsService::router_with_openapi())
+        .merge(OutboxService::router_with_openapi())
+        .merge(TicketsService::router_with_openapi())
+        .merge(WebSocketService::router_with_openapi())
         .route("/health", get(http::StatusCode::OK))
+        .route("/metrics", get(serve_metrics));
+
+    #[cfg(feature = "graphql")]
+    let router = router
+        .route(graphql::GRAPHQL_PATH, axum::routing::post(graphql::graphql))
+        .route(graphql::GRAPHQL_WS_PATH, get(graphql::graphql_ws));
+
+    router
 }
 
 pub fn openapi_json_raw() -> anyhow::Result<String> {
@@ -1,7 +1,13 @@
 use std::path::PathBuf;
 
+use anyhow::Context;
 use clap::Parser;
 use env_logger::Env;
+use jstz_node::config::{ConfigFile, DalPublisherConfig, OutboxExecutorConfig};
+use octez::r#async::{
+    client::{OctezClientConfig, OctezClientConfigBuilder},
+    endpoint::Endpoint,
+};
 
 const DEFAULT_ROLLUP_NODE_RPC_ADDR: &str = "127.0.0.1";
 const DEFAULT_ROLLUP_RPC_PORT: u16 = 8932;
@@ -23,11 +29,18 @@ enum Command {
 
 #[derive(Debug, Parser)]
 struct Args {
-    #[arg(long, default_value = DEFAULT_JSTZ_NODE_ADDR)]
-    addr: String,
+    /// Path of a TOML or JSON config file. Flags take precedence over the
+    /// values of the file.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Address the node listens on [default: 127.0.0.1]
+    #[arg(long)]
+    addr: Option<String>,
 
-    #[arg(long, default_value_t = DEFAULT_JSTZ_NODE_PORT)]
-    port: u16,
+    /// Port the node listens on [default: 8933]
+    #[arg(long)]
+    port: Option<u16>,
 
     #[arg(long, default_value = DEFAULT_ROLLUP_NODE_RPC_ADDR)]
     rollup_node_rpc_addr: String,
@@ -38,8 +51,85 @@ struct Args {
     #[arg(short, long)]
     rollup_endpoint: Option<String>,
 
-    #[arg(long, default_value = DEFAULT_KERNEL_LOG_PATH)]
-    kernel_log_path: PathBuf,
+    /// Path of the rollup kernel log file [default: logs/kernel.log]
+    #[arg(long)]
+    kernel_log_path: Option<PathBuf>,
+
+    /// Endpoint of the DAL node. Enables publishing operation batches to the DAL.
+    #[arg(long)]
+    dal_node_endpoint: Option<Endpoint>,
+
+    /// Index of the DAL slot operation batches are published to.
+    #[arg(long, default_value_t = 0)]
+    dal_slot_index: u8,
+
+    /// Address (or alias) of the L1 account publishing DAL slot commitments.
+    #[arg(long, requires = "dal_node_endpoint")]
+    dal_publisher: Option<String>,
+
+    /// Address (or alias) of the L1 account executing the outbox messages of
+    /// withdrawals once their commitment is cemented.
+    #[arg(long)]
+    outbox_executor: Option<String>,
+
+    /// Endpoint of the octez node used to publish DAL slot commitments and
+    /// execute outbox messages.
+    #[arg(long)]
+    octez_node_endpoint: Option<Endpoint>,
+
+    /// Base directory of the octez client used to publish DAL slot commitments
+    /// and execute outbox messages.
+    #[arg(long)]
+    octez_client_dir: Option<PathBuf>,
+}
+
+impl Args {
+    fn dal_publisher_config(&self) -> anyhow::Result<Option<DalPublisherConfig>> {
+        let Some(dal_node_endpoint) = &self.dal_node_endpoint else {
+            return Ok(None);
+        };
+        let publisher = self
+            .dal_publisher
+            .clone()
+            .context("--dal-publisher is required to publish to the DAL")?;
+        let octez_node_endpoint = self
+            .octez_node_endpoint
+            .clone()
+            .context("--octez-node-endpoint is required to publish to the DAL")?;
+
+        Ok(Some(DalPublisherConfig {
+            dal_node_endpoint: dal_node_endpoint.clone(),
+            slot_index: self.dal_slot_index,
+            publisher,
+            octez_client: self.octez_client_config(octez_node_endpoint)?,
+        }))
+    }
+
+    fn outbox_executor_config(&self) -> anyhow::Result<Option<OutboxExecutorConfig>> {
+        let Some(executor) = &self.outbox_executor else {
+            return Ok(None);
+        };
+        let octez_node_endpoint = self
+            .octez_node_endpoint
+            .clone()
+            .context("--octez-node-endpoint is required to execute outbox messages")?;
+
+        Ok(Some(OutboxExecutorConfig {
+            executor: executor.clone(),
+            octez_client: self.octez_client_config(octez_node_endpoint)?,
+        }))
+    }
+
+    fn octez_client_config(
+        &self,
+        octez_node_endpoint: Endpoint,
+    ) -> anyhow::Result<OctezClientConfig> {
+        let mut octez_client = OctezClientConfigBuilder::new(octez_node_endpoint);
+        if let Some(octez_client_dir) = &self.octez_client_dir {
+            octez_client = octez_client.set_base_dir(octez_client_dir.clone());
+        }
+        octez_client.build()
+    }
 }
 
 #[tokio::main]
@@ -47,13 +137,39 @@ async fn main() -> anyhow::Result<()> {
     env_logger::init_from_env(Env::default().default_filter_or("info"));
     match Command::parse() {
         Command::Run(args) => {
-            let rollup_endpoint = args.rollup_endpoint.unwrap_or(format!(
-                "http://{}:{}",
-                args.rollup_node_rpc_addr, args.rollup_node_rpc_port
-            ));
+            let dal_publisher = args.dal_publisher_config()?;
+            let outbox_executor = args.outbox_executor_config()?;
+            let config = match &args.config {
+                Some(path) => ConfigFile::load(path)?,
+                None => ConfigFile::default(),
+            };
+            let addr = args
+                .addr
+                .or(config.addr)
+                .unwrap_or(DEFAULT_JSTZ_NODE_ADDR.to_string());
+            let port = args.port.or(config.port).unwrap_or(DEFAULT_JSTZ_NODE_PORT);
+            let rollup_endpoint = args
+                .rollup_endpoint
+                .or(config.rollup_endpoint)
+                .unwrap_or(format!(
+                    "http://{}:{}",
+                    args.rollup_node_rpc_addr, args.rollup_node_rpc_port
+                ));
+            let kernel_log_path = args
+                .kernel_log_path
+                .or(config.kernel_log_path)
+                .unwrap_or(PathBuf::from(DEFAULT_KERNEL_LOG_PATH));
 
-            jstz_node::run(&args.addr, args.port, rollup_endpoint, args.kernel_log_path)
-                .await
+            jstz_node::run(
+                &addr,
+                port,
+                rollup_endpoint,
+                kernel_log_path,
+                dal_publisher,
+                outbox_executor,
+                config.settings,
+            )
+            .await
         }
         Command::Spec { out } => {
             let spec = jstz_node::openapi_json_raw()?;
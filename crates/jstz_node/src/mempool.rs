@@ -0,0 +1,777 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use derive_more::{Display, Error};
+use futures_util::future::try_join_all;
+use jstz_proto::{
+    context::account::{Account, Address, Nonce},
+    operation::{OperationHash, SignedOperation},
+    receipt::{Receipt, ReceiptResult},
+};
+use log::warn;
+use octez::OctezRollupClient;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::batcher::Batcher;
+
+/// Default interval between two synchronisations of the mempool with the
+/// nonces of the rollup
+pub const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(2);
+
+// Maximum number of operations of an account waiting for their predecessors
+const MAX_QUEUED_PER_ACCOUNT: usize = 64;
+
+// Maximum number of operations waiting for their predecessors, beyond which the
+// oldest queued operation is dropped to make room for a new one
+const MAX_QUEUED_OPERATIONS: usize = 10_000;
+
+// Time after which an injected operation that has not landed is considered lost
+const INJECTED_OPERATION_TTL: Duration = Duration::from_secs(300);
+
+// Time during which the node remembers the operations that left the mempool
+const SETTLED_OPERATION_RETENTION: Duration = Duration::from_secs(3600);
+
+const DROPPED_REASON: &str = "Dropped before reaching the rollup inbox";
+
+/// Reasons for the mempool to reject an operation
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum MempoolError {
+    #[display(fmt = "Invalid signature")]
+    InvalidSignature,
+    #[display(fmt = "Operation already in the mempool")]
+    Duplicate,
+    #[display(fmt = "Nonce already used")]
+    NonceTooLow,
+    #[display(fmt = "Nonce used by another pending operation")]
+    NonceInUse,
+    #[display(fmt = "Too many operations waiting for their predecessors")]
+    QueueFull,
+}
+
+/// Stage of an operation in the mempool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum PendingStatus {
+    /// The operation waits for the operations with lower nonces to land
+    Queued,
+    /// The operation was injected into the rollup and waits to be executed
+    Injected,
+}
+
+/// An operation accepted by the node that has not landed yet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PendingOperation {
+    pub hash: OperationHash,
+    pub source: Address,
+    pub nonce: Nonce,
+    pub status: PendingStatus,
+}
+
+/// Stage of an operation in its lifecycle, from its injection into the node to
+/// its execution by the rollup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "status")]
+pub enum OperationStatus {
+    /// The operation waits in the mempool for the operations with lower nonces to
+    /// land
+    Pending,
+    /// The operation was injected into the batcher of the rollup node and waits
+    /// to be included in an L1 block
+    Injected {
+        /// Status of the message containing the operation in the batcher
+        batcher_status: Option<String>,
+    },
+    /// The operation was included in the rollup inbox. It was successfully
+    /// executed if `executed` is set, otherwise its receipt is not available yet.
+    Included {
+        inbox_level: Option<u32>,
+        executed: bool,
+    },
+    /// The execution of the operation failed, or the operation was dropped
+    /// before reaching the rollup inbox
+    Failed {
+        inbox_level: Option<u32>,
+        reason: String,
+    },
+    /// The operation is unknown to the node
+    Unknown,
+}
+
+/// Validates the operations injected into the node before they reach the
+/// rollup inbox. Operations must be signed by their source and use the next
+/// nonce of their source. Operations with a future nonce are queued until
+/// their predecessors land.
+#[derive(Clone)]
+pub struct Mempool {
+    rollup_client: OctezRollupClient,
+    batcher: Batcher,
+    state: Arc<Mutex<State>>,
+}
+
+impl Mempool {
+    /// Creates the mempool and spawns the task synchronising it with the nonces
+    /// of the rollup every `sync_interval`.
+    pub fn spawn(
+        rollup_client: OctezRollupClient,
+        batcher: Batcher,
+        sync_interval: Duration,
+    ) -> Self {
+        let mempool = Self {
+            rollup_client,
+            batcher,
+            state: Arc::default(),
+        };
+        tokio::spawn(mempool.clone().run(sync_interval));
+        mempool
+    }
+
+    /// Admits `operation` into the mempool. The operation is injected into the
+    /// rollup if its nonce is the next nonce of its source, and queued if it is
+    /// a future nonce. Rejected operations fail with a [`MempoolError`].
+    pub async fn add(&self, operation: SignedOperation) -> Result<PendingStatus> {
+        let hash = operation.hash();
+        let inner = operation
+            .clone()
+            .verify()
+            .map_err(|_| MempoolError::InvalidSignature)?;
+        let chain_nonce = self.read_nonce(inner.source()).await?;
+        let (status, operations) = self.state.lock().admit(
+            hash,
+            operation,
+            inner.source().clone(),
+            *inner.nonce(),
+            chain_nonce,
+            Instant::now(),
+        )?;
+        self.inject(inner.source(), operations).await?;
+        Ok(status)
+    }
+
+    /// Returns the operations that have not landed yet
+    pub fn pending(&self) -> Vec<PendingOperation> {
+        self.state.lock().pending()
+    }
+
+    /// Returns the stage of the operation `hash` in its lifecycle
+    pub async fn status(&self, hash: &OperationHash) -> Result<OperationStatus> {
+        let receipt = self.read_receipt(hash).await?;
+        let tracked = self.state.lock().lookup(hash);
+        let batcher_status = match tracked.as_ref().and_then(Tracked::message_id) {
+            Some(message_id) => {
+                self.rollup_client
+                    .get_batcher_message_status(message_id)
+                    .await?
+            }
+            None => None,
+        };
+        let inbox_level = batcher_status.as_ref().and_then(|status| status.l1_level);
+
+        let status = match (receipt, tracked) {
+            (Some(receipt), _) => match receipt.result {
+                ReceiptResult::Success(_) => OperationStatus::Included {
+                    inbox_level,
+                    executed: true,
+                },
+                ReceiptResult::Failed(reason) => OperationStatus::Failed {
+                    inbox_level,
+                    reason,
+                },
+            },
+            (None, Some(Tracked::Queued)) => OperationStatus::Pending,
+            (None, Some(Tracked::Dropped { .. })) => OperationStatus::Failed {
+                inbox_level: None,
+                reason: DROPPED_REASON.to_string(),
+            },
+            (None, Some(Tracked::Injected { .. })) => match inbox_level {
+                Some(_) => OperationStatus::Included {
+                    inbox_level,
+                    executed: false,
+                },
+                None => OperationStatus::Injected {
+                    batcher_status: batcher_status.map(|status| status.status),
+                },
+            },
+            (None, None) => OperationStatus::Unknown,
+        };
+        Ok(status)
+    }
+
+    async fn run(self, sync_interval: Duration) {
+        let mut interval = tokio::time::interval(sync_interval);
+        loop {
+            interval.tick().await;
+            self.sync().await;
+        }
+    }
+
+    /// Forgets the operations that landed and injects the queued operations
+    /// whose predecessors landed. Accounts that fail to synchronise are retried
+    /// on the next synchronisation, without holding back the others.
+    async fn sync(&self) {
+        self.state.lock().prune(Instant::now());
+        let sources = self.state.lock().sources();
+        for source in sources {
+            if let Err(err) = self.sync_account(&source).await {
+                warn!("Failed to synchronise the mempool of {}: {:?}", source, err);
+            }
+        }
+    }
+
+    async fn sync_account(&self, source: &Address) -> Result<()> {
+        let chain_nonce = self.read_nonce(source).await?;
+        let operations = self.state.lock().sync(source, chain_nonce, Instant::now());
+        self.inject(source, operations).await
+    }
+
+    async fn inject(
+        &self,
+        source: &Address,
+        operations: Vec<SignedOperation>,
+    ) -> Result<()> {
+        let injections = operations.into_iter().map(|operation| async move {
+            let hash = operation.hash();
+            let message_id = self.batcher.inject(operation).await?;
+            Ok::<_, anyhow::Error>((hash, message_id))
+        });
+        match try_join_all(injections).await {
+            Ok(message_ids) => {
+                let mut state = self.state.lock();
+                for (hash, message_id) in message_ids {
+                    state.set_message_id(&hash, message_id);
+                }
+                Ok(())
+            }
+            Err(err) => {
+                // The nonces of the account are re-synchronised on the next operation
+                self.state.lock().forget(source, Instant::now());
+                Err(err)
+            }
+        }
+    }
+
+    /// Reads the receipt of the operation `hash`, if it was executed
+    pub(crate) async fn read_receipt(
+        &self,
+        hash: &OperationHash,
+    ) -> Result<Option<Receipt>> {
+        let key = format!("/jstz_receipt/{}", hash);
+        self.rollup_client
+            .get_value(&key)
+            .await?
+            .map(|value| {
+                bincode::deserialize::<Receipt>(&value)
+                    .map_err(|_| anyhow!("Failed to deserialize receipt"))
+            })
+            .transpose()
+    }
+
+    async fn read_nonce(&self, address: &Address) -> Result<Nonce> {
+        let key = format!("/jstz_account/{}", address);
+        match self.rollup_client.get_value(&key).await? {
+            Some(value) => Ok(bincode::deserialize::<Account>(&value)
+                .map_err(|_| anyhow!("Failed to deserialize account"))?
+                .nonce),
+            None => Ok(Nonce::default()),
+        }
+    }
+}
+
+struct Entry {
+    source: Address,
+    nonce: Nonce,
+    status: PendingStatus,
+    since: Instant,
+    // Id of the batcher message containing the operation, once injected
+    message_id: Option<String>,
+}
+
+// An operation that left the mempool, either landed or dropped
+struct Settled {
+    message_id: Option<String>,
+    dropped: bool,
+    since: Instant,
+}
+
+/// What the mempool knows about an operation
+#[derive(Debug, Clone, PartialEq)]
+enum Tracked {
+    Queued,
+    Injected { message_id: Option<String> },
+    Dropped { message_id: Option<String> },
+}
+
+impl Tracked {
+    fn message_id(&self) -> Option<&str> {
+        match self {
+            Tracked::Queued => None,
+            Tracked::Injected { message_id } | Tracked::Dropped { message_id } => {
+                message_id.as_deref()
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct AccountQueue {
+    // Next nonce of the account, once the injected operations land
+    next_nonce: Nonce,
+    // Operations waiting for their predecessors, by nonce
+    queued: BTreeMap<Nonce, (OperationHash, SignedOperation)>,
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<OperationHash, Entry>,
+    accounts: HashMap<Address, AccountQueue>,
+    settled: HashMap<OperationHash, Settled>,
+}
+
+impl State {
+    /// Admits the operation `hash` of `source`, given the nonce of `source` on
+    /// chain. Returns the status of the operation and the operations to inject.
+    fn admit(
+        &mut self,
+        hash: OperationHash,
+        operation: SignedOperation,
+        source: Address,
+        nonce: Nonce,
+        chain_nonce: Nonce,
+        now: Instant,
+    ) -> std::result::Result<(PendingStatus, Vec<SignedOperation>), MempoolError> {
+        if self.entries.contains_key(&hash) {
+            return Err(MempoolError::Duplicate);
+        }
+        if nonce < chain_nonce {
+            return Err(MempoolError::NonceTooLow);
+        }
+        self.update(&source, chain_nonce, now);
+        let account = self.accounts.entry(source.clone()).or_insert(AccountQueue {
+            next_nonce: chain_nonce,
+            ..AccountQueue::default()
+        });
+        if nonce < account.next_nonce || account.queued.contains_key(&nonce) {
+            return Err(MempoolError::NonceInUse);
+        }
+
+        if nonce > account.next_nonce {
+            if account.queued.len() >= MAX_QUEUED_PER_ACCOUNT {
+                return Err(MempoolError::QueueFull);
+            }
+            if self.queued_len() >= MAX_QUEUED_OPERATIONS {
+                self.evict_oldest_queued(now);
+            }
+            self.accounts
+                .entry(source.clone())
+                .or_default()
+                .queued
+                .insert(nonce, (hash.clone(), operation));
+            self.settled.remove(&hash);
+            self.entries.insert(
+                hash,
+                Entry {
+                    source,
+                    nonce,
+                    status: PendingStatus::Queued,
+                    since: now,
+                    message_id: None,
+                },
+            );
+            return Ok((PendingStatus::Queued, vec![]));
+        }
+
+        account.next_nonce = nonce.next();
+        self.settled.remove(&hash);
+        self.entries.insert(
+            hash,
+            Entry {
+                source: source.clone(),
+                nonce,
+                status: PendingStatus::Injected,
+                since: now,
+                message_id: None,
+            },
+        );
+        let mut operations = vec![operation];
+        operations.extend(self.promote(&source, now));
+        Ok((PendingStatus::Injected, operations))
+    }
+
+    /// Updates the account `source` with its nonce on chain. Returns the queued
+    /// operations to inject.
+    fn sync(
+        &mut self,
+        source: &Address,
+        chain_nonce: Nonce,
+        now: Instant,
+    ) -> Vec<SignedOperation> {
+        self.update(source, chain_nonce, now);
+        let operations = self.promote(source, now);
+        if self.entries.values().all(|entry| &entry.source != source) {
+            self.accounts.remove(source);
+        }
+        operations
+    }
+
+    /// Forgets the operations of `source` that landed or were lost
+    fn update(&mut self, source: &Address, chain_nonce: Nonce, now: Instant) {
+        let Some(account) = self.accounts.get_mut(source) else {
+            return;
+        };
+
+        // Injected operations that did not land in time are lost, their nonces
+        // can be used again
+        let lost = self.entries.values().any(|entry| {
+            &entry.source == source
+                && entry.status == PendingStatus::Injected
+                && entry.nonce >= chain_nonce
+                && now.duration_since(entry.since) > INJECTED_OPERATION_TTL
+        });
+        if lost || account.next_nonce < chain_nonce {
+            account.next_nonce = chain_nonce;
+        }
+        account.queued.retain(|nonce, _| *nonce >= chain_nonce);
+
+        // Injected operations with a used nonce landed, queued ones can no longer
+        // be executed
+        let settled: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| {
+                &entry.source == source
+                    && (entry.nonce < chain_nonce
+                        || (lost && entry.status == PendingStatus::Injected))
+            })
+            .map(|(hash, _)| hash.clone())
+            .collect();
+        for hash in settled {
+            if let Some(entry) = self.entries.remove(&hash) {
+                let dropped =
+                    entry.status == PendingStatus::Queued || entry.nonce >= chain_nonce;
+                self.settle(hash, entry.message_id, dropped, now);
+            }
+        }
+    }
+
+    fn settle(
+        &mut self,
+        hash: OperationHash,
+        message_id: Option<String>,
+        dropped: bool,
+        now: Instant,
+    ) {
+        self.settled.insert(
+            hash,
+            Settled {
+                message_id,
+                dropped,
+                since: now,
+            },
+        );
+    }
+
+    /// Forgets the settled operations older than the retention period
+    fn prune(&mut self, now: Instant) {
+        self.settled.retain(|_, settled| {
+            now.duration_since(settled.since) <= SETTLED_OPERATION_RETENTION
+        });
+    }
+
+    fn set_message_id(&mut self, hash: &OperationHash, message_id: String) {
+        if let Some(entry) = self.entries.get_mut(hash) {
+            entry.message_id = Some(message_id);
+        } else if let Some(settled) = self.settled.get_mut(hash) {
+            settled.message_id = Some(message_id);
+        }
+    }
+
+    fn lookup(&self, hash: &OperationHash) -> Option<Tracked> {
+        if let Some(entry) = self.entries.get(hash) {
+            return Some(match entry.status {
+                PendingStatus::Queued => Tracked::Queued,
+                PendingStatus::Injected => Tracked::Injected {
+                    message_id: entry.message_id.clone(),
+                },
+            });
+        }
+        self.settled.get(hash).map(|settled| {
+            let message_id = settled.message_id.clone();
+            if settled.dropped {
+                Tracked::Dropped { message_id }
+            } else {
+                Tracked::Injected { message_id }
+            }
+        })
+    }
+
+    /// Moves the queued operations of `source` following its next nonce to the
+    /// injected operations
+    fn promote(&mut self, source: &Address, now: Instant) -> Vec<SignedOperation> {
+        let Some(account) = self.accounts.get_mut(source) else {
+            return vec![];
+        };
+        let mut operations = vec![];
+        while let Some((hash, operation)) = account.queued.remove(&account.next_nonce) {
+            if let Some(entry) = self.entries.get_mut(&hash) {
+                entry.status = PendingStatus::Injected;
+                entry.since = now;
+            }
+            account.next_nonce = account.next_nonce.next();
+            operations.push(operation);
+        }
+        operations
+    }
+
+    fn queued_len(&self) -> usize {
+        self.accounts
+            .values()
+            .map(|account| account.queued.len())
+            .sum()
+    }
+
+    /// Drops the queued operation that waited the longest for its predecessors
+    fn evict_oldest_queued(&mut self, now: Instant) {
+        let oldest = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.status == PendingStatus::Queued)
+            .min_by_key(|(_, entry)| entry.since)
+            .map(|(hash, _)| hash.clone());
+        let Some(hash) = oldest else {
+            return;
+        };
+        let Some(entry) = self.entries.remove(&hash) else {
+            return;
+        };
+        if let Some(account) = self.accounts.get_mut(&entry.source) {
+            account.queued.remove(&entry.nonce);
+        }
+        self.settle(hash, entry.message_id, true, now);
+    }
+
+    /// Forgets the operations of `source`, which are considered dropped
+    fn forget(&mut self, source: &Address, now: Instant) {
+        self.accounts.remove(source);
+        let forgotten: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| &entry.source == source)
+            .map(|(hash, _)| hash.clone())
+            .collect();
+        for hash in forgotten {
+            if let Some(entry) = self.entries.remove(&hash) {
+                self.settle(hash, entry.message_id, true, now);
+            }
+        }
+    }
+
+    fn sources(&self) -> Vec<Address> {
+        self.accounts.keys().cloned().collect()
+    }
+
+    fn pending(&self) -> Vec<PendingOperation> {
+        let mut pending: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(hash, entry)| PendingOperation {
+                hash: hash.clone(),
+                source: entry.source.clone(),
+                nonce: entry.nonce,
+                status: entry.status,
+            })
+            .collect();
+        pending.sort_by(|a, b| (&a.source, a.nonce).cmp(&(&b.source, b.nonce)));
+        pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use jstz_crypto::public_key_hash::PublicKeyHash;
+    use jstz_proto::{
+        context::account::{Address, Nonce, ParsedCode},
+        operation::{Content, DeployFunction, Operation, OperationHash, SignedOperation},
+    };
+
+    use super::{
+        MempoolError, PendingStatus, State, Tracked, INJECTED_OPERATION_TTL,
+        MAX_QUEUED_OPERATIONS, MAX_QUEUED_PER_ACCOUNT,
+    };
+
+    fn source() -> Address {
+        PublicKeyHash::from_base58("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx").unwrap()
+    }
+
+    fn deploy_operation(nonce: Nonce) -> SignedOperation {
+        let op = Operation {
+            source: source(),
+            nonce,
+            content: Content::DeployFunction(DeployFunction {
+                function_code: ParsedCode::try_from(
+                    "export default () => new Response()".to_string(),
+                )
+                .unwrap(),
+                account_credit: 0,
+            }),
+        };
+        crate::sign_operation(op)
+    }
+
+    fn nonce(n: usize) -> Nonce {
+        (0..n).fold(Nonce::default(), |nonce, _| nonce.next())
+    }
+
+    fn admit(
+        state: &mut State,
+        n: usize,
+        chain_nonce: Nonce,
+        now: Instant,
+    ) -> Result<(PendingStatus, Vec<SignedOperation>), MempoolError> {
+        let operation = deploy_operation(nonce(n));
+        state.admit(
+            operation.hash(),
+            operation,
+            source(),
+            nonce(n),
+            chain_nonce,
+            now,
+        )
+    }
+
+    #[test]
+    fn injects_operations_with_next_nonce() {
+        let mut state = State::default();
+        let now = Instant::now();
+
+        let (status, operations) = admit(&mut state, 0, nonce(0), now).unwrap();
+        assert_eq!(status, PendingStatus::Injected);
+        assert_eq!(operations, vec![deploy_operation(nonce(0))]);
+
+        // The nonce of the injected operation is taken into account before it lands
+        let (status, operations) = admit(&mut state, 1, nonce(0), now).unwrap();
+        assert_eq!(status, PendingStatus::Injected);
+        assert_eq!(operations, vec![deploy_operation(nonce(1))]);
+        assert_eq!(state.pending().len(), 2);
+    }
+
+    #[test]
+    fn rejects_duplicates_and_used_nonces() {
+        let mut state = State::default();
+        let now = Instant::now();
+
+        admit(&mut state, 1, nonce(1), now).unwrap();
+        assert_eq!(
+            admit(&mut state, 1, nonce(1), now),
+            Err(MempoolError::Duplicate)
+        );
+        assert_eq!(
+            admit(&mut state, 0, nonce(1), now),
+            Err(MempoolError::NonceTooLow)
+        );
+    }
+
+    #[test]
+    fn queues_future_nonces_until_predecessors_land() {
+        let mut state = State::default();
+        let now = Instant::now();
+
+        let (status, operations) = admit(&mut state, 2, nonce(0), now).unwrap();
+        assert_eq!(status, PendingStatus::Queued);
+        assert!(operations.is_empty());
+
+        // Operation 0 landed through another node
+        assert!(state.sync(&source(), nonce(1), now).is_empty());
+
+        let (status, operations) = admit(&mut state, 1, nonce(1), now).unwrap();
+        assert_eq!(status, PendingStatus::Injected);
+        assert_eq!(
+            operations,
+            vec![deploy_operation(nonce(1)), deploy_operation(nonce(2))]
+        );
+        assert!(state
+            .pending()
+            .iter()
+            .all(|pending| pending.status == PendingStatus::Injected));
+
+        // Both operations landed
+        let hash = deploy_operation(nonce(2)).hash();
+        state.set_message_id(&hash, "scmsg".to_string());
+        state.sync(&source(), nonce(3), now);
+        assert!(state.pending().is_empty());
+        assert!(state.sources().is_empty());
+        assert_eq!(
+            state.lookup(&hash),
+            Some(Tracked::Injected {
+                message_id: Some("scmsg".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn releases_nonces_of_lost_operations() {
+        let mut state = State::default();
+        let now = Instant::now();
+
+        admit(&mut state, 0, nonce(0), now).unwrap();
+        assert_eq!(
+            admit(&mut state, 0, nonce(0), now),
+            Err(MempoolError::Duplicate)
+        );
+
+        let later = now + INJECTED_OPERATION_TTL + Duration::from_secs(1);
+        state.sync(&source(), nonce(0), later);
+        assert!(state.pending().is_empty());
+        assert_eq!(
+            state.lookup(&deploy_operation(nonce(0)).hash()),
+            Some(Tracked::Dropped { message_id: None })
+        );
+
+        let (status, _) = admit(&mut state, 0, nonce(0), later).unwrap();
+        assert_eq!(status, PendingStatus::Injected);
+    }
+    #[test]
+    fn evicts_the_oldest_queued_operation_once_full() {
+        let mut state = State::default();
+        let now = Instant::now();
+        let operation = deploy_operation(nonce(1));
+
+        // Fill the mempool with queued operations of many accounts
+        let hashes: Vec<_> = (0..=MAX_QUEUED_OPERATIONS)
+            .map(|i| {
+                let source =
+                    PublicKeyHash::digest(&(i / MAX_QUEUED_PER_ACCOUNT).to_le_bytes())
+                        .unwrap();
+                let hash = OperationHash::from(i.to_le_bytes().as_slice());
+                let (status, _) = state
+                    .admit(
+                        hash.clone(),
+                        operation.clone(),
+                        source,
+                        nonce(i % MAX_QUEUED_PER_ACCOUNT + 1),
+                        nonce(0),
+                        now + Duration::from_millis(i as u64),
+                    )
+                    .unwrap();
+                assert_eq!(status, PendingStatus::Queued);
+                hash
+            })
+            .collect();
+
+        assert_eq!(state.pending().len(), MAX_QUEUED_OPERATIONS);
+        assert_eq!(
+            state.lookup(&hashes[0]),
+            Some(Tracked::Dropped { message_id: None })
+        );
+        assert_eq!(state.lookup(&hashes[1]), Some(Tracked::Queued));
+        assert_eq!(
+            state.lookup(&hashes[MAX_QUEUED_OPERATIONS]),
+            Some(Tracked::Queued)
+        );
+    }
+}
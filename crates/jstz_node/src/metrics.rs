@@ -0,0 +1,237 @@
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    Opts, Registry, TextEncoder,
+};
+
+use crate::{services::error::ServiceResult, AppState};
+
+/// Metrics of the node, served in the Prometheus text format at `/metrics`
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    injected_operations: IntCounterVec,
+    rollup_rpc_duration: HistogramVec,
+    sse_clients: IntGauge,
+    websocket_clients: IntGauge,
+    log_lines: IntCounter,
+    db_write_duration: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("jstz_node".to_string()), None)
+            .expect("Invalid metrics prefix");
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of HTTP requests served"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Duration of HTTP requests, in seconds",
+            ),
+            &["method", "route"],
+        )
+        .unwrap();
+        let injected_operations = IntCounterVec::new(
+            Opts::new(
+                "injected_operations_total",
+                "Number of operations injected into the rollup node batcher",
+            ),
+            &["result"],
+        )
+        .unwrap();
+        let rollup_rpc_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "rollup_rpc_duration_seconds",
+                "Duration of the RPC calls to the rollup node, in seconds",
+            ),
+            &["rpc"],
+        )
+        .unwrap();
+        let sse_clients = IntGauge::new(
+            "sse_clients",
+            "Number of clients streaming logs through Server-Sent Events",
+        )
+        .unwrap();
+        let websocket_clients = IntGauge::new(
+            "websocket_clients",
+            "Number of clients subscribed to events through WebSockets",
+        )
+        .unwrap();
+        let log_lines = IntCounter::new(
+            "log_lines_total",
+            "Number of lines parsed from the kernel log file",
+        )
+        .unwrap();
+        let db_write_duration = Histogram::with_opts(HistogramOpts::new(
+            "db_write_duration_seconds",
+            "Duration of the writes to the log database, in seconds",
+        ))
+        .unwrap();
+
+        // The metrics are registered once into a fresh registry
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(injected_operations.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(rollup_rpc_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(sse_clients.clone())).unwrap();
+        registry
+            .register(Box::new(websocket_clients.clone()))
+            .unwrap();
+        registry.register(Box::new(log_lines.clone())).unwrap();
+        registry
+            .register(Box::new(db_write_duration.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            injected_operations,
+            rollup_rpc_duration,
+            sse_clients,
+            websocket_clients,
+            log_lines,
+            db_write_duration,
+        }
+    }
+
+    fn observe_request(
+        &self,
+        method: &str,
+        route: &str,
+        status: u16,
+        duration: Duration,
+    ) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[method, route])
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Records the injection of `count` operations into the batcher
+    pub(crate) fn observe_injections(&self, succeeded: bool, count: usize) {
+        let result = if succeeded { "success" } else { "failure" };
+        self.injected_operations
+            .with_label_values(&[result])
+            .inc_by(count as u64);
+    }
+
+    /// Records the duration of the RPC call `rpc` to the rollup node
+    pub(crate) fn observe_rollup_rpc(&self, rpc: &str, duration: Duration) {
+        self.rollup_rpc_duration
+            .with_label_values(&[rpc])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub(crate) fn set_sse_clients(&self, count: usize) {
+        self.sse_clients.set(count as i64);
+    }
+
+    pub(crate) fn set_websocket_clients(&self, count: usize) {
+        self.websocket_clients.set(count as i64);
+    }
+
+    pub(crate) fn observe_log_line(&self) {
+        self.log_lines.inc();
+    }
+
+    #[cfg_attr(not(feature = "persistent-logging"), allow(dead_code))]
+    pub(crate) fn observe_db_write(&self, duration: Duration) {
+        self.db_write_duration.observe(duration.as_secs_f64());
+    }
+
+    /// Encodes the metrics in the Prometheus text format
+    pub fn encode(&self) -> anyhow::Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Middleware recording the number and duration of the requests to each route
+pub async fn track_requests(
+    State(metrics): State<Metrics>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    // Routes are labelled by their template to keep the number of series bounded
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched".to_string(), |path| path.as_str().to_string());
+    let start = Instant::now();
+    let response = next.run(request).await;
+    metrics.observe_request(&method, &route, response.status().as_u16(), start.elapsed());
+    response
+}
+
+/// Serves the metrics of the node in the Prometheus text format
+pub(crate) async fn serve_metrics(
+    State(AppState { metrics, .. }): State<AppState>,
+) -> ServiceResult<impl IntoResponse> {
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            TextEncoder::new().format_type().to_string(),
+        )],
+        metrics.encode()?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Metrics;
+
+    #[test]
+    fn encodes_metrics_in_text_format() {
+        let metrics = Metrics::new();
+        metrics.observe_request("GET", "/accounts/{address}/nonce", 200, Duration::ZERO);
+        metrics.observe_injections(true, 3);
+        metrics.observe_injections(false, 1);
+        metrics.set_sse_clients(2);
+        metrics.observe_log_line();
+
+        let text = metrics.encode().unwrap();
+        assert!(text.contains(
+            r#"jstz_node_http_requests_total{method="GET",route="/accounts/{address}/nonce",status="200"} 1"#
+        ));
+        assert!(
+            text.contains(r#"jstz_node_injected_operations_total{result="success"} 3"#)
+        );
+        assert!(
+            text.contains(r#"jstz_node_injected_operations_total{result="failure"} 1"#)
+        );
+        assert!(text.contains("jstz_node_sse_clients 2"));
+        assert!(text.contains("jstz_node_log_lines_total 1"));
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use parking_lot::Mutex;
+
+use crate::{
+    config::{RateLimit, RateLimitConfig, RouteLimits},
+    services::error::ServiceError,
+};
+
+/// Header clients pass their API key in
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+// Number of tracked clients beyond which the clients whose limit is fully
+// replenished are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+// Routes limited together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum RouteGroup {
+    Operations,
+    Accounts,
+    Logs,
+    GraphQl,
+}
+
+impl RouteGroup {
+    fn of(path: &str) -> Option<Self> {
+        match path.trim_start_matches('/').split('/').next()? {
+            "operations" => Some(Self::Operations),
+            "accounts" => Some(Self::Accounts),
+            // WebSockets stream logs
+            "logs" | "ws" => Some(Self::Logs),
+            // Including GraphQL subscriptions over WebSockets
+            "graphql" => Some(Self::GraphQl),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    Ip(IpAddr),
+    ApiKey(String),
+}
+
+// Token bucket holding the requests a client can make
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    // Time at which the bucket is full again
+    full_at: Instant,
+}
+
+impl Bucket {
+    fn new(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: burst(limit),
+            updated: now,
+            full_at: now,
+        }
+    }
+
+    // Takes a token from the bucket. Returns the time to wait for the next
+    // token if the bucket is empty.
+    fn take(&mut self, limit: &RateLimit, now: Instant) -> Result<(), Duration> {
+        let rate = f64::from(limit.requests_per_minute) / 60.0;
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst(limit));
+        self.updated = now;
+        if self.tokens < 1.0 {
+            return Err(if rate > 0.0 {
+                Duration::from_secs_f64((1.0 - self.tokens) / rate)
+            } else {
+                Duration::from_secs(60)
+            });
+        }
+        self.tokens -= 1.0;
+        if rate > 0.0 {
+            self.full_at =
+                now + Duration::from_secs_f64((burst(limit) - self.tokens) / rate);
+        }
+        Ok(())
+    }
+}
+
+fn burst(limit: &RateLimit) -> f64 {
+    f64::from(limit.burst.max(1))
+}
+
+/// Limits the rate of the requests of each client IP address and API key
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: Arc<RateLimitConfig>,
+    api_keys: Arc<HashSet<String>>,
+    buckets: Arc<Mutex<HashMap<(RouteGroup, Client), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            api_keys: Arc::new(config.api_keys.iter().cloned().collect()),
+            config: Arc::new(config.clone()),
+            buckets: Arc::default(),
+        }
+    }
+
+    fn limits(&self, group: RouteGroup) -> &RouteLimits {
+        match group {
+            RouteGroup::Operations => &self.config.operations,
+            RouteGroup::Accounts => &self.config.accounts,
+            RouteGroup::Logs => &self.config.logs,
+            RouteGroup::GraphQl => &self.config.graphql,
+        }
+    }
+
+    // Checks a request of the client with `api_key` or `ip` to `path`. Returns
+    // the number of seconds to wait before retrying if the client exceeded its
+    // limit. Requests with an unknown API key are limited by IP address.
+    fn check(
+        &self,
+        path: &str,
+        api_key: Option<&str>,
+        ip: Option<IpAddr>,
+        now: Instant,
+    ) -> Result<(), u64> {
+        let Some(group) = RouteGroup::of(path) else {
+            return Ok(());
+        };
+        let limits = self.limits(group);
+        let (client, limit) = match api_key.filter(|key| self.api_keys.contains(*key)) {
+            Some(key) => (Client::ApiKey(key.to_string()), limits.per_api_key),
+            None => match ip {
+                Some(ip) => (Client::Ip(ip), limits.per_ip),
+                None => return Ok(()),
+            },
+        };
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+
+        let mut buckets = self.buckets.lock();
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+        buckets
+            .entry((group, client))
+            .or_insert_with(|| Bucket::new(&limit, now))
+            .take(&limit, now)
+            .map_err(|retry_after| retry_after.as_secs_f64().ceil().max(1.0) as u64)
+    }
+}
+
+/// Middleware rejecting the requests of clients exceeding their rate limit with
+/// 429 Too Many Requests
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let api_key = request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if let Err(retry_after) =
+        limiter.check(request.uri().path(), api_key, ip, Instant::now())
+    {
+        return ServiceError::TooManyRequests(retry_after).into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, Instant},
+    };
+
+    use super::RateLimiter;
+    use crate::config::{RateLimit, RateLimitConfig, RouteLimits};
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    const OTHER_IP: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(&RateLimitConfig {
+            api_keys: vec!["secret".to_string()],
+            operations: RouteLimits {
+                per_ip: Some(RateLimit {
+                    requests_per_minute: 60,
+                    burst: 2,
+                }),
+                per_api_key: Some(RateLimit {
+                    requests_per_minute: 600,
+                    burst: 3,
+                }),
+            },
+            ..RateLimitConfig::default()
+        })
+    }
+
+    #[test]
+    fn limits_requests_per_ip() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        assert_eq!(limiter.check("/operations", None, Some(IP), now), Ok(()));
+        assert_eq!(limiter.check("/operations", None, Some(IP), now), Ok(()));
+        assert_eq!(limiter.check("/operations", None, Some(IP), now), Err(1));
+        // Other clients are limited separately
+        assert_eq!(
+            limiter.check("/operations", None, Some(OTHER_IP), now),
+            Ok(())
+        );
+        // A token is replenished every second
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.check("/operations", None, Some(IP), later), Ok(()));
+        assert_eq!(limiter.check("/operations", None, Some(IP), later), Err(1));
+    }
+
+    #[test]
+    fn limits_requests_per_api_key() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(
+                limiter.check("/operations", Some("secret"), Some(IP), now),
+                Ok(())
+            );
+        }
+        assert_eq!(
+            limiter.check("/operations", Some("secret"), Some(OTHER_IP), now),
+            Err(1)
+        );
+        // Unknown keys are limited by IP address
+        assert_eq!(
+            limiter.check("/operations", Some("guess"), Some(IP), now),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn limits_graphql_routes() {
+        let limiter = RateLimiter::new(&RateLimitConfig {
+            graphql: RouteLimits {
+                per_ip: Some(RateLimit {
+                    requests_per_minute: 60,
+                    burst: 1,
+                }),
+                per_api_key: None,
+            },
+            ..RateLimitConfig::default()
+        });
+        let now = Instant::now();
+
+        assert_eq!(limiter.check("/graphql", None, Some(IP), now), Ok(()));
+        assert_eq!(limiter.check("/graphql/ws", None, Some(IP), now), Err(1));
+    }
+
+    #[test]
+    fn ignores_unlimited_routes() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert_eq!(
+                limiter.check("/accounts/tz1/nonce", None, Some(IP), now),
+                Ok(())
+            );
+            assert_eq!(limiter.check("/health", None, Some(IP), now), Ok(()));
+        }
+    }
+}
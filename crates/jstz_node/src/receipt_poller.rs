@@ -0,0 +1,81 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use jstz_proto::{operation::OperationHash, receipt::Receipt};
+use log::warn;
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+
+use crate::mempool::Mempool;
+
+pub const DEFAULT_RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sends the receipts a client waits for
+pub type ReceiptSender = mpsc::UnboundedSender<(OperationHash, Arc<Receipt>)>;
+
+/// Reads the receipts clients wait for on their behalf, so that each pending
+/// receipt is read once per poll no matter how many clients wait for it.
+#[derive(Clone)]
+pub struct ReceiptPoller {
+    mempool: Mempool,
+    // Clients waiting for the receipt of each operation
+    waiters: Arc<Mutex<HashMap<OperationHash, Vec<ReceiptSender>>>>,
+}
+
+impl ReceiptPoller {
+    /// Creates the poller and spawns the task reading the pending receipts
+    /// every `poll_interval`.
+    pub fn spawn(mempool: Mempool, poll_interval: Duration) -> Self {
+        let poller = Self {
+            mempool,
+            waiters: Arc::default(),
+        };
+        tokio::spawn(poller.clone().run(poll_interval));
+        poller
+    }
+
+    /// Sends the receipt of `hash` to `sender` once available. Clients stop
+    /// waiting by dropping the receiving end.
+    pub fn watch(&self, hash: OperationHash, sender: ReceiptSender) {
+        self.waiters.lock().entry(hash).or_default().push(sender);
+    }
+
+    async fn run(self, poll_interval: Duration) {
+        let mut interval = tokio::time::interval(poll_interval);
+        loop {
+            interval.tick().await;
+            self.poll().await;
+        }
+    }
+
+    async fn poll(&self) {
+        for hash in self.pending() {
+            match self.mempool.read_receipt(&hash).await {
+                Ok(Some(receipt)) => self.notify(hash, receipt),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to read the receipt of {}: {:?}", hash, e),
+            }
+        }
+    }
+
+    // Operations waited for by connected clients
+    fn pending(&self) -> Vec<OperationHash> {
+        let mut waiters = self.waiters.lock();
+        waiters.retain(|_, senders| {
+            senders.retain(|sender| !sender.is_closed());
+            !senders.is_empty()
+        });
+        waiters.keys().cloned().collect()
+    }
+
+    // Sends the receipt of `hash` to the clients waiting for it
+    fn notify(&self, hash: OperationHash, receipt: Receipt) {
+        let Some(senders) = self.waiters.lock().remove(&hash) else {
+            return;
+        };
+        let receipt = Arc::new(receipt);
+        for sender in senders {
+            // Disconnected clients are ignored
+            let _ = sender.send((hash.clone(), Arc::clone(&receipt)));
+        }
+    }
+}
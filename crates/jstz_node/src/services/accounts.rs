@@ -1,22 +1,35 @@
+use std::future::Future;
+
 use anyhow::anyhow;
 use axum::{
     extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
 use jstz_api::KvValue;
-use jstz_proto::context::account::{Account, Nonce, ParsedCode};
-use serde::Deserialize;
+use jstz_proto::context::{
+    account::{Account, Nonce, ParsedCode},
+    ticket_table::{TicketHolding, TicketKey},
+};
+use octez::OctezRollupClient;
+use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use super::{
     error::{ServiceError, ServiceResult},
+    tickets::read_ticket_metadata,
     Service,
 };
-use crate::AppState;
+use crate::{
+    state_cache::{etag, is_not_modified, StateCache},
+    AppState,
+};
 
 const ACCOUNTS_TAG: &str = "Accounts";
 
-fn construct_storage_key(address: &str, key: &Option<String>) -> String {
+pub(crate) fn construct_storage_key(address: &str, key: &Option<String>) -> String {
     match key {
         Some(value) if !value.is_empty() => format!("/jstz_kv/{}/{}", address, value),
         _ => format!("/jstz_kv/{}", address),
@@ -28,6 +41,67 @@ struct KvQuery {
     key: Option<String>,
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LevelQuery {
+    /// Level of the rollup to read the state at. Defaults to the head of the rollup.
+    level: Option<u32>,
+}
+
+// Answers with the state read by `read` at `level`, or at the head of the rollup if
+// unset. The response is tagged by the level, and conditional requests for the
+// same level are answered with 304 Not Modified without reading the state.
+async fn respond_at_level<T, F, Fut>(
+    state_cache: &StateCache,
+    headers: &HeaderMap,
+    level: Option<u32>,
+    read: F,
+) -> ServiceResult<Response>
+where
+    T: Serialize,
+    F: FnOnce(u32) -> Fut,
+    Fut: Future<Output = ServiceResult<T>>,
+{
+    let level = resolve_level(state_cache, level).await?;
+    let etag = [(header::ETAG, etag(level))];
+    if is_not_modified(headers, level) {
+        return Ok((StatusCode::NOT_MODIFIED, etag).into_response());
+    }
+    let value = read(level).await?;
+    Ok((etag, Json(value)).into_response())
+}
+
+/// Returns `level`, or the level of the head of the rollup if unset. Fails if
+/// `level` is ahead of the head.
+pub(crate) async fn resolve_level(
+    state_cache: &StateCache,
+    level: Option<u32>,
+) -> ServiceResult<u32> {
+    let head = state_cache.head().await?;
+    match level {
+        Some(level) if level > head => Err(ServiceError::BadRequest(format!(
+            "Level {} is ahead of the head of the rollup",
+            level
+        ))),
+        Some(level) => Ok(level),
+        None => Ok(head),
+    }
+}
+
+/// Reads the account `address` at `level`
+pub(crate) async fn read_account(
+    state_cache: &StateCache,
+    address: &str,
+    level: u32,
+) -> ServiceResult<Account> {
+    let key = format!("/jstz_account/{}", address);
+    match state_cache.get_value(&key, level).await? {
+        Some(value) => Ok(bincode::deserialize::<Account>(&value)
+            .map_err(|_| anyhow!("Failed to deserialize account"))?),
+        None => Err(ServiceError::NotFound),
+    }
+}
+
 pub struct AccountsService;
 
 /// Get nonce of an account
@@ -35,27 +109,27 @@ pub struct AccountsService;
     get,
     path = "/{address}/nonce",
     tag = ACCOUNTS_TAG,
+    params(LevelQuery),
     responses(
         (status = 200, body = Nonce),
+        (status = 304),
+        (status = 400),
         (status = 404),
+        (status = 429),
         (status = 500)
     )
 )]
 async fn get_nonce(
-    State(AppState { rollup_client, .. }): State<AppState>,
+    State(AppState { state_cache, .. }): State<AppState>,
     Path(address): Path<String>,
-) -> ServiceResult<Json<Nonce>> {
-    let key = format!("/jstz_account/{}", address);
-    let value = rollup_client.get_value(&key).await?;
-    let account_nonce = match value {
-        Some(value) => {
-            bincode::deserialize::<Account>(&value)
-                .map_err(|_| anyhow!("Failed to deserialize account"))?
-                .nonce
-        }
-        None => Err(ServiceError::NotFound)?,
-    };
-    Ok(Json(account_nonce))
+    Query(LevelQuery { level }): Query<LevelQuery>,
+    headers: HeaderMap,
+) -> ServiceResult<Response> {
+    let state_cache = &state_cache;
+    respond_at_level(state_cache, &headers, level, |level| async move {
+        Ok(read_account(state_cache, &address, level).await?.nonce)
+    })
+    .await
 }
 
 /// Get code of an account
@@ -63,31 +137,32 @@ async fn get_nonce(
     get,
     path = "/{address}/code",
     tag = ACCOUNTS_TAG,
+    params(LevelQuery),
     responses(
         (status = 200, body = ParsedCode),
+        (status = 304),
         (status = 400),
         (status = 404),
+        (status = 429),
         (status = 500)
     )
 )]
 async fn get_code(
-    State(AppState { rollup_client, .. }): State<AppState>,
+    State(AppState { state_cache, .. }): State<AppState>,
     Path(address): Path<String>,
-) -> ServiceResult<Json<ParsedCode>> {
-    let key = format!("/jstz_account/{}", address);
-    let value = rollup_client.get_value(&key).await?;
-    let account_code = match value {
-        Some(value) => {
-            bincode::deserialize::<Account>(&value)
-                .map_err(|_| anyhow!("Failed to deserialize account"))?
-                .function_code
-        }
-        None => Err(ServiceError::NotFound)?,
-    }
-    .ok_or_else(|| {
-        ServiceError::BadRequest("Account is not a smart function".to_string())
-    })?;
-    Ok(Json(account_code))
+    Query(LevelQuery { level }): Query<LevelQuery>,
+    headers: HeaderMap,
+) -> ServiceResult<Response> {
+    let state_cache = &state_cache;
+    respond_at_level(state_cache, &headers, level, |level| async move {
+        read_account(state_cache, &address, level)
+            .await?
+            .function_code
+            .ok_or_else(|| {
+                ServiceError::BadRequest("Account is not a smart function".to_string())
+            })
+    })
+    .await
 }
 
 /// Get balance of an account
@@ -95,27 +170,27 @@ async fn get_code(
     get,
     path = "/{address}/balance",
     tag = ACCOUNTS_TAG,
+    params(LevelQuery),
     responses(
         (status = 200, body = u64),
+        (status = 304),
+        (status = 400),
         (status = 404),
+        (status = 429),
         (status = 500)
     )
 )]
 async fn get_balance(
-    State(AppState { rollup_client, .. }): State<AppState>,
+    State(AppState { state_cache, .. }): State<AppState>,
     Path(address): Path<String>,
-) -> ServiceResult<Json<u64>> {
-    let key = format!("/jstz_account/{}", address);
-    let value = rollup_client.get_value(&key).await?;
-    let account_balance = match value {
-        Some(value) => {
-            bincode::deserialize::<Account>(&value)
-                .map_err(|_| anyhow!("Failed to deserialize account"))?
-                .amount
-        }
-        None => Err(ServiceError::NotFound)?,
-    };
-    Ok(Json(account_balance))
+    Query(LevelQuery { level }): Query<LevelQuery>,
+    headers: HeaderMap,
+) -> ServiceResult<Response> {
+    let state_cache = &state_cache;
+    respond_at_level(state_cache, &headers, level, |level| async move {
+        Ok(read_account(state_cache, &address, level).await?.amount)
+    })
+    .await
 }
 
 /// Get KV value under a given key path
@@ -126,25 +201,33 @@ async fn get_balance(
     get,
     path = "/{address}/kv",
     tag = ACCOUNTS_TAG,
+    params(LevelQuery),
     responses(
         (status = 200, body = KvValue),
+        (status = 304),
+        (status = 400),
         (status = 404),
+        (status = 429),
         (status = 500)
     )
 )]
 async fn get_kv_value(
-    State(AppState { rollup_client, .. }): State<AppState>,
+    State(AppState { state_cache, .. }): State<AppState>,
     Path(address): Path<String>,
     Query(KvQuery { key }): Query<KvQuery>,
-) -> ServiceResult<Json<KvValue>> {
+    Query(LevelQuery { level }): Query<LevelQuery>,
+    headers: HeaderMap,
+) -> ServiceResult<Response> {
     let key = construct_storage_key(&address, &key);
-    let value = rollup_client.get_value(&key).await?;
-    let kv_value = match value {
-        Some(value) => bincode::deserialize::<KvValue>(&value)
-            .map_err(|_| anyhow!("Failed to deserialize account"))?,
-        None => Err(ServiceError::NotFound)?,
-    };
-    Ok(Json(kv_value))
+    let state_cache = &state_cache;
+    respond_at_level(state_cache, &headers, level, |level| async move {
+        match state_cache.get_value(&key, level).await? {
+            Some(value) => Ok(bincode::deserialize::<KvValue>(&value)
+                .map_err(|_| anyhow!("Failed to deserialize account"))?),
+            None => Err(ServiceError::NotFound),
+        }
+    })
+    .await
 }
 
 /// Get array of KV subkeys under a given key path
@@ -155,24 +238,107 @@ async fn get_kv_value(
     get,
     path = "/{address}/kv/subkeys",
     tag = ACCOUNTS_TAG,
+    params(LevelQuery),
     responses(
         (status = 200, body = Vec<String>),
+        (status = 304),
+        (status = 400),
         (status = 404),
+        (status = 429),
         (status = 500)
     )
 )]
 async fn get_kv_subkeys(
-    State(AppState { rollup_client, .. }): State<AppState>,
+    State(AppState { state_cache, .. }): State<AppState>,
     Path(address): Path<String>,
     Query(KvQuery { key }): Query<KvQuery>,
-) -> ServiceResult<Json<Vec<String>>> {
+    Query(LevelQuery { level }): Query<LevelQuery>,
+    headers: HeaderMap,
+) -> ServiceResult<Response> {
     let key = construct_storage_key(&address, &key);
-    let value = rollup_client.get_subkeys(&key).await?;
-    let subkeys = match value {
-        Some(value) => value,
-        None => Err(ServiceError::NotFound)?,
+    let state_cache = &state_cache;
+    respond_at_level(state_cache, &headers, level, |level| async move {
+        state_cache
+            .get_subkeys(&key, level)
+            .await?
+            .ok_or(ServiceError::NotFound)
+    })
+    .await
+}
+
+/// Get tickets held by an account
+///
+/// Get the balances of the tickets held by an account, in the order they were
+/// first received, along with their metadata if it was recorded. Tickets held
+/// since before the kernel recorded the order are listed last.
+#[utoipa::path(
+    get,
+    path = "/{address}/tickets",
+    tag = ACCOUNTS_TAG,
+    responses(
+        (status = 200, body = Vec<TicketHolding>),
+        (status = 429),
+        (status = 500)
+    )
+)]
+async fn get_tickets(
+    State(AppState { rollup_client, .. }): State<AppState>,
+    Path(address): Path<String>,
+) -> ServiceResult<Json<Vec<TicketHolding>>> {
+    let key = format!("/ticket_owners/{}", address);
+    let mut ticket_hashes = match rollup_client.get_value(&key).await? {
+        Some(value) => bincode::deserialize::<Vec<TicketKey>>(&value)
+            .map_err(|_| anyhow!("Failed to deserialize owned tickets"))?,
+        None => vec![],
     };
-    Ok(Json(subkeys))
+
+    // The index of owned tickets only records the tickets received since it
+    // was introduced, so tickets held before are looked up in the ticket table
+    let mut balances = Vec::with_capacity(ticket_hashes.len());
+    for ticket_hash in &ticket_hashes {
+        balances.push(read_ticket_balance(&rollup_client, ticket_hash, &address).await?);
+    }
+    let all_ticket_hashes = rollup_client
+        .get_subkeys("/ticket_table")
+        .await?
+        .unwrap_or_default();
+    for ticket_hash in all_ticket_hashes {
+        let Ok(ticket_hash) = ticket_hash.parse::<TicketKey>() else {
+            continue;
+        };
+        if ticket_hashes.contains(&ticket_hash) {
+            continue;
+        }
+        let amount = read_ticket_balance(&rollup_client, &ticket_hash, &address).await?;
+        if amount > 0 {
+            ticket_hashes.push(ticket_hash);
+            balances.push(amount);
+        }
+    }
+
+    let mut holdings = Vec::with_capacity(ticket_hashes.len());
+    for (ticket_hash, amount) in ticket_hashes.into_iter().zip(balances) {
+        let metadata = read_ticket_metadata(&rollup_client, &ticket_hash).await?;
+        holdings.push(TicketHolding {
+            ticket_hash,
+            amount,
+            metadata,
+        });
+    }
+    Ok(Json(holdings))
+}
+
+async fn read_ticket_balance(
+    rollup_client: &OctezRollupClient,
+    ticket_hash: &TicketKey,
+    address: &str,
+) -> ServiceResult<u64> {
+    let key = format!("/ticket_table/{}/{}", ticket_hash, address);
+    match rollup_client.get_value(&key).await? {
+        Some(value) => Ok(bincode::deserialize::<u64>(&value)
+            .map_err(|_| anyhow!("Failed to deserialize ticket balance"))?),
+        None => Ok(0),
+    }
 }
 
 impl Service for AccountsService {
@@ -182,7 +348,8 @@ impl Service for AccountsService {
             .routes(routes!(get_code))
             .routes(routes!(get_balance))
             .routes(routes!(get_kv_value))
-            .routes(routes!(get_kv_subkeys));
+            .routes(routes!(get_kv_subkeys))
+            .routes(routes!(get_tickets));
 
         OpenApiRouter::new().nest("/accounts", routes)
     }
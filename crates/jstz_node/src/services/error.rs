@@ -1,6 +1,6 @@
 use axum::{
     body::Body,
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
 use derive_more::From;
@@ -12,6 +12,11 @@ pub enum ServiceError {
     NotFound,
     BadRequest(String),
     PersistentLogsDisabled,
+    DalPublishingDisabled,
+    /// The client exceeded its rate limit and may retry after the given number
+    /// of seconds
+    #[from(ignore)]
+    TooManyRequests(u64),
 }
 
 pub type ServiceResult<T> = anyhow::Result<T, ServiceError>;
@@ -30,6 +35,16 @@ impl IntoResponse for ServiceError {
                 ServiceError::BadRequest("Persistent logs disabled".to_string())
                     .into_response()
             }
+            ServiceError::DalPublishingDisabled => {
+                ServiceError::BadRequest("DAL publishing disabled".to_string())
+                    .into_response()
+            }
+            ServiceError::TooManyRequests(retry_after) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                error_body("Too many requests"),
+            )
+                .into_response(),
         }
     }
 }
@@ -1,29 +1,111 @@
-use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use axum::response::{sse, Sse};
 use futures_util::future;
-use jstz_proto::context::account::Address;
-use parking_lot::Mutex;
-use tokio::sync::mpsc::{self, Sender};
+use jstz_proto::{
+    context::account::Address, js_logger::LogRecord, request_logger::RequestEvent,
+};
+use parking_lot::RwLock;
+use tokio::sync::{
+    broadcast,
+    mpsc::{self, Sender},
+};
 use tokio::time::interval;
 use tokio_stream::wrappers::ReceiverStream;
 
+use crate::{
+    metrics::Metrics,
+    services::error::{ServiceError, ServiceResult},
+};
+
 type InfallibleSseEvent = Result<sse::Event, Infallible>;
 pub type InfallibleSSeStream = ReceiverStream<Result<sse::Event, Infallible>>;
 
+/// Event read from the kernel log
+#[derive(Debug)]
+pub enum Event {
+    Log(LogRecord),
+    Request(RequestEvent),
+}
+
+impl Event {
+    /// Address of the smart function the event belongs to
+    pub fn address(&self) -> &Address {
+        match self {
+            Event::Log(log) => &log.address,
+            Event::Request(
+                RequestEvent::Start { address, .. } | RequestEvent::End { address, .. },
+            ) => address,
+        }
+    }
+}
+
+/// Event numbered by a cursor, which increases with each published event
+#[derive(Debug)]
+pub struct Notification {
+    pub cursor: u64,
+    pub event: Event,
+}
+
+/// Notifications published since a cursor, followed by the receiver of the
+/// notifications published next
+pub struct Replay {
+    pub notifications: Vec<Arc<Notification>>,
+    pub receiver: broadcast::Receiver<Arc<Notification>>,
+    /// Cursor of the last published notification
+    pub cursor: u64,
+    /// Whether no notification since the cursor was evicted from the history
+    pub complete: bool,
+}
+
+#[derive(Default)]
+struct History {
+    // Cursor of the last published notification, 0 if none was published
+    cursor: u64,
+    notifications: VecDeque<Arc<Notification>>,
+}
+
 /// Broadcasts messages to all connected clients through Server-sent Events
-/// <https://developer.mozilla.org/en-US/docs/Web/API/Server-sent_events/Using_server-sent_events>.
+/// <https://developer.mozilla.org/en-US/docs/Web/API/Server-sent_events/Using_server-sent_events>,
+/// and publishes the events of the kernel log to WebSocket clients.
 pub struct Broadcaster {
-    clients: Mutex<HashMap<Address, Vec<Sender<InfallibleSseEvent>>>>, // TODO: Use a read-write lock instead?
+    clients: RwLock<HashMap<Address, Vec<Sender<InfallibleSseEvent>>>>,
+    // Number of connected WebSocket clients
+    sockets: AtomicUsize,
+    // Maximum number of connected clients, unlimited if `None`
+    max_clients: Option<usize>,
+    // Last published notifications, replayed to the clients resuming from a cursor
+    history: RwLock<History>,
+    notifications: broadcast::Sender<Arc<Notification>>,
+    metrics: Metrics,
 }
 
 // Pings clients every 10 seconds
-const PING_INTERVAL: u64 = 10;
+pub(crate) const PING_INTERVAL: u64 = 10;
+
+// Number of notifications kept to be replayed
+const HISTORY_LEN: usize = 1024;
+
+// Number of notifications a WebSocket client can lag behind before missing some
+const CHANNEL_CAPACITY: usize = 256;
 
 impl Broadcaster {
-    /// Constructs new broadcaster and spawns ping loop responsible for removing stale clients.
-    pub(crate) fn new() -> Arc<Self> {
-        let this = Arc::new(Broadcaster::default());
+    /// Constructs new broadcaster accepting up to `max_clients` clients and spawns ping loop
+    /// responsible for removing stale clients.
+    pub(crate) fn new(max_clients: Option<usize>, metrics: Metrics) -> Arc<Self> {
+        let this = Arc::new(Broadcaster {
+            max_clients,
+            metrics,
+            ..Default::default()
+        });
 
         Broadcaster::spawn_ping(Arc::clone(&this));
 
@@ -45,7 +127,7 @@ impl Broadcaster {
 
     /// Removes all non-responsive clients from broadcast list.
     async fn remove_stale_clients(&self) {
-        let clients = self.clients.lock().clone();
+        let clients = self.clients.read().clone();
 
         let mut responsive_clients: HashMap<Address, Vec<Sender<InfallibleSseEvent>>> =
             HashMap::new();
@@ -66,38 +148,122 @@ impl Broadcaster {
             }
         }
 
-        *self.clients.lock() = responsive_clients;
+        self.metrics
+            .set_sse_clients(responsive_clients.values().map(Vec::len).sum());
+        *self.clients.write() = responsive_clients;
     }
 
-    /// Registers client with broadcaster, returning an SSE response body.
+    /// Registers client with broadcaster, returning an SSE response body. Fails with
+    /// `ServiceError::TooManyRequests` if the maximum number of clients is connected.
     pub async fn new_client(
         &self,
         contract_address: Address,
-    ) -> Sse<InfallibleSSeStream> {
+    ) -> ServiceResult<Sse<InfallibleSSeStream>> {
         let (tx, rx) = mpsc::channel(10);
 
         tx.send(Ok(sse::Event::default().data("connected")))
             .await
             .unwrap();
 
-        self.clients
-            .lock()
-            .entry(contract_address)
-            .or_default()
-            .push(tx);
+        {
+            let mut clients = self.clients.write();
+            let count = clients.values().map(Vec::len).sum::<usize>();
+            if self.is_full(count) {
+                // Disconnected clients are removed on the next ping
+                return Err(ServiceError::TooManyRequests(PING_INTERVAL));
+            }
+            clients.entry(contract_address).or_default().push(tx);
+            self.metrics.set_sse_clients(count + 1);
+        }
 
         let stream = ReceiverStream::new(rx);
         let sse_response = Sse::new(stream);
-        sse_response.keep_alive(
+        Ok(sse_response.keep_alive(
             sse::KeepAlive::new()
                 .interval(Duration::from_secs(3))
                 .text("keep-alive-ping"),
-        )
+        ))
+    }
+
+    // Whether the maximum number of clients is connected, given `sse_clients`
+    // SSE clients
+    fn is_full(&self, sse_clients: usize) -> bool {
+        self.max_clients.is_some_and(|max_clients| {
+            sse_clients + self.sockets.load(Ordering::Relaxed) >= max_clients
+        })
+    }
+
+    /// Registers a WebSocket client, which is unregistered once the returned
+    /// guard is dropped. Fails with `ServiceError::TooManyRequests` if the
+    /// maximum number of clients is connected.
+    pub(crate) fn new_socket(self: &Arc<Self>) -> ServiceResult<SocketGuard> {
+        let sse_clients = self.clients.read().values().map(Vec::len).sum::<usize>();
+        if self.is_full(sse_clients) {
+            return Err(ServiceError::TooManyRequests(PING_INTERVAL));
+        }
+        let sockets = self.sockets.fetch_add(1, Ordering::Relaxed) + 1;
+        self.metrics.set_websocket_clients(sockets);
+        Ok(SocketGuard(Arc::clone(self)))
+    }
+
+    /// Publishes `event` to the WebSocket clients.
+    pub fn publish(&self, event: Event) {
+        let mut history = self.history.write();
+        history.cursor += 1;
+        let notification = Arc::new(Notification {
+            cursor: history.cursor,
+            event,
+        });
+        if history.notifications.len() == HISTORY_LEN {
+            history.notifications.pop_front();
+        }
+        history.notifications.push_back(notification.clone());
+        // Sending fails if no client is subscribed
+        let _ = self.notifications.send(notification);
+    }
+
+    /// Subscribes to the notifications published after `cursor`. The
+    /// notifications of the history published after `cursor`, if any, are
+    /// replayed first.
+    pub fn replay(&self, cursor: Option<u64>) -> Replay {
+        let history = self.history.read();
+        // Subscribing while holding the lock ensures that no notification is
+        // missed or received twice
+        let receiver = self.notifications.subscribe();
+        let notifications = match cursor {
+            Some(cursor) => history
+                .notifications
+                .iter()
+                .filter(|notification| notification.cursor > cursor)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        // A cursor ahead of the last published notification was not issued by
+        // this node (e.g. before a restart), so the notifications it missed
+        // are unknown
+        let complete = match (cursor, history.notifications.front()) {
+            (Some(cursor), _) if cursor > history.cursor => false,
+            (Some(cursor), Some(oldest)) => cursor + 1 >= oldest.cursor,
+            (Some(cursor), None) => cursor == history.cursor,
+            (None, _) => true,
+        };
+        Replay {
+            notifications,
+            receiver,
+            cursor: history.cursor,
+            complete,
+        }
+    }
+
+    /// Cursor of the last published notification
+    pub fn cursor(&self) -> u64 {
+        self.history.read().cursor
     }
 
     /// Broadcasts `msg` to all clients.
     pub async fn broadcast(&self, contract_address: &Address, msg: &str) {
-        let clients = self.clients.lock().clone();
+        let clients = self.clients.read().clone();
 
         if let Some(clients) = clients.get(contract_address) {
             let send_futures = clients
@@ -113,7 +279,92 @@ impl Broadcaster {
 impl Default for Broadcaster {
     fn default() -> Self {
         Broadcaster {
-            clients: Mutex::new(Default::default()),
+            clients: RwLock::new(Default::default()),
+            sockets: AtomicUsize::new(0),
+            max_clients: None,
+            history: RwLock::new(Default::default()),
+            notifications: broadcast::channel(CHANNEL_CAPACITY).0,
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+/// Connected WebSocket client, unregistered on drop
+pub(crate) struct SocketGuard(Arc<Broadcaster>);
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        let sockets = self.0.sockets.fetch_sub(1, Ordering::Relaxed) - 1;
+        self.0.metrics.set_websocket_clients(sockets);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use jstz_api::js_log::LogLevel;
+    use jstz_crypto::public_key_hash::PublicKeyHash;
+    use jstz_proto::js_logger::LogRecord;
+
+    use super::{Broadcaster, Event, HISTORY_LEN};
+
+    fn publish_log(broadcaster: &Broadcaster) {
+        broadcaster.publish(Event::Log(LogRecord {
+            address: PublicKeyHash::from_base58("tz1cD5CuvAALcxgypqBXcBQEA8dkLJivoFjU")
+                .unwrap(),
+            request_id: "1".to_string(),
+            level: LogLevel::LOG,
+            text: "hello".to_string(),
+        }));
+    }
+
+    #[test]
+    fn replays_notifications_after_cursor() {
+        let broadcaster = Broadcaster::default();
+        for _ in 0..3 {
+            publish_log(&broadcaster);
+        }
+
+        let replay = broadcaster.replay(Some(1));
+        let cursors: Vec<u64> = replay
+            .notifications
+            .iter()
+            .map(|notification| notification.cursor)
+            .collect();
+        assert_eq!(cursors, vec![2, 3]);
+        assert_eq!(replay.cursor, 3);
+        assert!(replay.complete);
+
+        // Notifications published next are received once
+        let mut receiver = replay.receiver;
+        publish_log(&broadcaster);
+        assert_eq!(receiver.try_recv().unwrap().cursor, 4);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn reports_evicted_notifications() {
+        let broadcaster = Broadcaster::default();
+        for _ in 0..HISTORY_LEN + 2 {
+            publish_log(&broadcaster);
+        }
+
+        let replay = broadcaster.replay(Some(1));
+        assert!(!replay.complete);
+        assert_eq!(replay.notifications.len(), HISTORY_LEN);
+        assert!(broadcaster.replay(Some(2)).complete);
+        assert!(broadcaster.replay(None).notifications.is_empty());
+    }
+
+    #[test]
+    fn reports_unknown_cursors() {
+        let broadcaster = Broadcaster::default();
+        assert!(broadcaster.replay(Some(0)).complete);
+        assert!(!broadcaster.replay(Some(5)).complete);
+
+        for _ in 0..3 {
+            publish_log(&broadcaster);
         }
+        assert!(broadcaster.replay(Some(3)).complete);
+        assert!(!broadcaster.replay(Some(4)).complete);
     }
 }
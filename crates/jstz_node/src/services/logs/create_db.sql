@@ -1,6 +1,13 @@
 CREATE TABLE IF NOT EXISTS request (
     id TEXT NOT NULL PRIMARY KEY,
-    function_address TEXT NOT NULL
+    function_address TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT 0,
+    caller TEXT,
+    start_level INTEGER,
+    end_level INTEGER,
+    status_code INTEGER,
+    start_timestamp INTEGER,
+    end_timestamp INTEGER
 );
 
 CREATE TABLE IF NOT EXISTS log (
@@ -9,5 +16,26 @@ CREATE TABLE IF NOT EXISTS log (
     content TEXT,
     function_address TEXT NOT NULL,
     request_id TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT 0,
+    file_inode INTEGER,
+    file_offset INTEGER,
+    file_generation INTEGER,
+    inbox_level INTEGER,
+    timestamp INTEGER,
+    line_in_level INTEGER,
         FOREIGN KEY (request_id) REFERENCES request (id)
-);
\ No newline at end of file
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS log_fts USING fts5 (
+    content,
+    content = 'log',
+    content_rowid = 'id'
+);
+
+CREATE TRIGGER IF NOT EXISTS log_fts_insert AFTER INSERT ON log BEGIN
+    INSERT INTO log_fts (rowid, content) VALUES (new.id, new.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS log_fts_delete AFTER DELETE ON log BEGIN
+    INSERT INTO log_fts (log_fts, rowid, content) VALUES ('delete', old.id, old.content);
+END;
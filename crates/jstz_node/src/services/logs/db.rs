@@ -1,7 +1,12 @@
 #![cfg(feature = "persistent-logging")]
-use std::fs;
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use super::Line;
+use super::{LevelInfo, Line, LogFilter, RequestMetadata};
+use crate::tailed_file::FilePosition;
 use anyhow::{anyhow, Result};
 use jstz_api::js_log::LogLevel;
 use jstz_crypto::public_key_hash::PublicKeyHash;
@@ -10,28 +15,22 @@ use jstz_proto::{
 };
 use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, Params, Statement};
+use rusqlite::{params_from_iter, types::Value, OptionalExtension, Params, Statement};
 use tokio::task::spawn_blocking;
 
 pub type SqliteConnectionPool = Pool<SqliteConnectionManager>;
 pub type SqliteConnection = PooledConnection<r2d2_sqlite::SqliteConnectionManager>;
 type QueryResponseResult = Result<Vec<LogRecord>>;
 
-const DB_PATH: &str = ".jstz/log.db";
-
 #[derive(Clone)]
 pub struct Db {
     pool: SqliteConnectionPool,
 }
 
 impl Db {
-    // Initialize the sql databse by createing a connection pool.
+    // Initialize the sql databse at `db_path` by createing a connection pool.
     // if the database does not exist, it will be created.
-    pub async fn init() -> Result<Self> {
-        let db_path = dirs::home_dir()
-            .expect("failed to get home directory")
-            .join(DB_PATH);
-
+    pub async fn init(db_path: &Path) -> Result<Self> {
         if let Some(parent) = db_path.parent() {
             if !parent.exists() {
                 fs::create_dir_all(parent)?;
@@ -49,11 +48,91 @@ impl Db {
     async fn create_table(pool: Pool<SqliteConnectionManager>) -> Result<()> {
         let connection = Self::get_connection_from_pool(pool).await?;
 
+        let indexed: bool = connection.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE name = 'log_fts'",
+            [],
+            |row| row.get(0),
+        )?;
         connection.execute_batch(include_str!("./create_db.sql"))?;
+        // Logs inserted before the full-text index existed are indexed once
+        if !indexed {
+            connection
+                .execute_batch("INSERT INTO log_fts (log_fts) VALUES ('rebuild')")?;
+        }
+
+        // Databases created by earlier versions lack the creation time of rows,
+        // the position of logs in the kernel log file and the levels logs and
+        // requests belong to
+        Self::add_column(
+            &connection,
+            "request",
+            "created_at INTEGER NOT NULL DEFAULT 0",
+        )?;
+        Self::add_column(&connection, "log", "created_at INTEGER NOT NULL DEFAULT 0")?;
+        Self::add_column(&connection, "log", "file_inode INTEGER")?;
+        Self::add_column(&connection, "log", "file_offset INTEGER")?;
+        Self::add_column(&connection, "log", "file_generation INTEGER")?;
+        Self::add_column(&connection, "log", "inbox_level INTEGER")?;
+        Self::add_column(&connection, "log", "timestamp INTEGER")?;
+        Self::add_column(&connection, "log", "line_in_level INTEGER")?;
+        Self::add_column(&connection, "request", "caller TEXT")?;
+        Self::add_column(&connection, "request", "start_level INTEGER")?;
+        Self::add_column(&connection, "request", "end_level INTEGER")?;
+        Self::add_column(&connection, "request", "status_code INTEGER")?;
+        Self::add_column(&connection, "request", "start_timestamp INTEGER")?;
+        Self::add_column(&connection, "request", "end_timestamp INTEGER")?;
+        // Logs are inserted once, whether they are read twice from the kernel
+        // log file (e.g. after a restart of the node) or logged twice by the
+        // kernel (when the rollup node executes a level again). The former are
+        // identified by their position in the file, which includes the
+        // generation of the file as offsets are reused once it is truncated and
+        // inodes once it is rotated. The latter are identified by their line in
+        // the level, which is only known once the start of the level was read.
+        connection.execute_batch(
+            "DROP INDEX IF EXISTS log_position;
+             CREATE UNIQUE INDEX IF NOT EXISTS log_file_position ON log (file_inode, file_generation, file_offset);
+             CREATE UNIQUE INDEX IF NOT EXISTS log_level_line ON log (inbox_level, line_in_level);",
+        )?;
 
         Ok(())
     }
 
+    // Adds the column defined by `definition` to `table` if it does not exist
+    fn add_column(
+        connection: &SqliteConnection,
+        table: &str,
+        definition: &str,
+    ) -> Result<()> {
+        let column = definition.split_whitespace().next().unwrap_or_default();
+        let exists: bool = connection.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+            [table, column],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            connection.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {}",
+                table, definition
+            ))?;
+        }
+        Ok(())
+    }
+
+    // Deletes the logs, and the requests without logs, older than `retention`.
+    // Returns the number of deleted logs.
+    pub async fn prune(&self, retention: Duration) -> Result<usize> {
+        let connection = self.connection().await?;
+        let cutoff = now().saturating_sub(retention.as_secs());
+        let deleted =
+            connection.execute("DELETE FROM log WHERE created_at < ?1", [cutoff])?;
+        connection.execute(
+            "DELETE FROM request WHERE created_at < ?1 \
+             AND id NOT IN (SELECT request_id FROM log)",
+            [cutoff],
+        )?;
+        Ok(deleted)
+    }
+
     pub async fn connection(&self) -> Result<SqliteConnection> {
         Self::get_connection_from_pool(self.pool.clone()).await
     }
@@ -69,33 +148,66 @@ impl Db {
             .map_err(|e| anyhow!("Failed to get connection from pool: {}", e.to_string()))
     }
 
-    // On success, returns the number of rows that were changed/inserted.
-    pub(super) async fn flush(&self, line: &Line) -> Result<()> {
+    // Inserts the `line` read at `position` of the kernel log file during
+    // `level`. Lines that were already inserted, at the same position or line
+    // of the level, are ignored.
+    pub(super) async fn flush(
+        &self,
+        line: &Line,
+        position: FilePosition,
+        level: Option<LevelInfo>,
+    ) -> Result<()> {
         let connection = self.connection().await?;
+        let timestamp = level.and_then(|level| level.timestamp);
+        let line_in_level = level.map(|level| level.line);
+        let level = level.map(|level| level.level);
         match line {
             Line::Request(RequestEvent::Start {
                 request_id,
                 address,
+                caller,
             }) => connection.execute(
-                "INSERT INTO request (id, function_address) VALUES (?1, ?2)",
-                (request_id, address.to_string()),
+                "INSERT OR IGNORE INTO request (id, function_address, created_at, caller, start_level, start_timestamp) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                (
+                    request_id,
+                    address.to_string(),
+                    now(),
+                    caller.as_ref().map(ToString::to_string),
+                    level,
+                    timestamp,
+                ),
+            )?,
+            Line::Request(RequestEvent::End {
+                request_id,
+                status_code,
+                ..
+            }) => connection.execute(
+                "UPDATE request SET end_level = ?1, status_code = ?2, end_timestamp = ?3 WHERE id = ?4",
+                (level, status_code, timestamp, request_id),
             )?,
             Line::Js(LogRecord {
                 request_id,
                 address,
-                level,
+                level: log_level,
                 text,
             }) => connection.execute(
-                "INSERT INTO log (level, content, function_address, request_id) VALUES (?1, ?2, ?3, ?4)",
+                "INSERT OR IGNORE INTO log (level, content, function_address, request_id, created_at, file_inode, file_generation, file_offset, inbox_level, timestamp, line_in_level) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
                 (
-                    level.to_string(),
+                    log_level.to_string(),
                     text,
                     address.to_string(),
-                    request_id
+                    request_id,
+                    now(),
+                    position.inode,
+                    position.generation,
+                    position.offset,
+                    level,
+                    timestamp,
+                    line_in_level,
                 ),
             )?,
-            // TODO: Update the request row with more fields.
-            Line::Request(_) => 0,
+            // Levels are tracked by the caller
+            Line::Level(_) => 0,
         };
 
         Ok(())
@@ -104,15 +216,46 @@ impl Db {
     pub async fn logs_by_address(
         &self,
         function_address: Address,
+        filter: &LogFilter,
         limit: usize,
         offset: usize,
     ) -> QueryResponseResult {
         let conn = self.connection().await?;
 
-        let stmt = conn
-            .prepare("SELECT * FROM log WHERE function_address = ? LIMIT ? OFFSET ?")?;
+        let mut query = "SELECT * FROM log WHERE function_address = ?".to_string();
+        let mut params = vec![Value::from(function_address.to_string())];
+        if let Some(level) = &filter.level {
+            query.push_str(" AND level = ?");
+            params.push(Value::from(level.to_string()));
+        }
+        if let Some(since) = filter.since {
+            query.push_str(" AND timestamp >= ?");
+            params.push(Value::from(since as i64));
+        }
+        if let Some(until) = filter.until {
+            query.push_str(" AND timestamp <= ?");
+            params.push(Value::from(until as i64));
+        }
+        if let Some(from_level) = filter.from_level {
+            query.push_str(" AND inbox_level >= ?");
+            params.push(Value::from(from_level));
+        }
+        if let Some(to_level) = filter.to_level {
+            query.push_str(" AND inbox_level <= ?");
+            params.push(Value::from(to_level));
+        }
+        if let Some(pattern) = filter.q.as_deref().and_then(match_pattern) {
+            query
+                .push_str(" AND id IN (SELECT rowid FROM log_fts WHERE log_fts MATCH ?)");
+            params.push(Value::from(pattern));
+        }
+        query.push_str(" ORDER BY id LIMIT ? OFFSET ?");
+        params.push(Value::from(limit as i64));
+        params.push(Value::from(offset as i64));
+
+        let stmt = conn.prepare(&query)?;
 
-        Self::collect_logs(stmt, params![function_address.to_string(), limit, offset])
+        Self::collect_logs(stmt, params_from_iter(params))
     }
 
     pub async fn logs_by_address_and_request_id(
@@ -128,6 +271,49 @@ impl Db {
         Self::collect_logs(stmt, [function_address.to_string(), request_id])
     }
 
+    pub async fn request_metadata(
+        &self,
+        function_address: Address,
+        request_id: String,
+    ) -> Result<Option<RequestMetadata>> {
+        let conn = self.connection().await?;
+
+        let row = conn
+            .query_row(
+                "SELECT caller, start_level, end_level, status_code, start_timestamp, end_timestamp FROM request WHERE function_address = ?1 AND id = ?2",
+                [function_address.to_string(), request_id.clone()],
+                |row| {
+                    Ok((
+                        row.get::<usize, Option<String>>(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get::<usize, Option<u64>>(4)?,
+                        row.get::<usize, Option<u64>>(5)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        let Some((caller, start_level, end_level, status_code, started_at, ended_at)) =
+            row
+        else {
+            return Ok(None);
+        };
+        Ok(Some(RequestMetadata {
+            request_id,
+            address: function_address,
+            caller: caller
+                .map(|caller| PublicKeyHash::from_base58(&caller))
+                .transpose()?,
+            start_level,
+            end_level,
+            status_code,
+            started_at,
+            ended_at,
+        }))
+    }
+
     fn collect_logs<P: Params>(
         mut stmt: Statement<'_>,
         params: P,
@@ -160,3 +346,225 @@ impl Db {
         Ok(logs)
     }
 }
+
+// Seconds elapsed since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+// Full-text query matching the logs containing all the terms of `q`. Terms are
+// quoted so that they are not parsed as FTS5 operators.
+fn match_pattern(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs::OpenOptions, io::Write, path::Path};
+
+    use jstz_api::js_log::LogLevel;
+    use jstz_crypto::public_key_hash::PublicKeyHash;
+    use jstz_proto::{js_logger::LogRecord, request_logger::RequestEvent};
+    use tempfile::TempDir;
+
+    use super::{match_pattern, Db};
+    use crate::{
+        services::logs::{LevelInfo, Line, LogFilter},
+        tailed_file::TailedFile,
+    };
+
+    fn append(path: &Path, content: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    fn log_line(address: &PublicKeyHash, text: &str) -> String {
+        let record = LogRecord {
+            address: address.clone(),
+            request_id: "request".to_string(),
+            level: LogLevel::LOG,
+            text: text.to_string(),
+        };
+        format!("{}\n", serde_json::to_string(&record).unwrap())
+    }
+
+    // Reads the next line of `file` and inserts it as a log of `level`
+    async fn flush_next_line(db: &Db, file: &mut TailedFile, level: Option<LevelInfo>) {
+        let (position, line) = file.next_line().await.unwrap().unwrap();
+        let record: LogRecord = serde_json::from_str(&line).unwrap();
+        db.flush(&Line::Js(record), position, level).await.unwrap();
+    }
+
+    // Texts of the logs of `address` matching `filter`
+    async fn texts(db: &Db, address: &PublicKeyHash, filter: LogFilter) -> Vec<String> {
+        let logs = db
+            .logs_by_address(address.clone(), &filter, 10, 0)
+            .await
+            .unwrap();
+        logs.into_iter().map(|log| log.text).collect()
+    }
+
+    #[tokio::test]
+    async fn keeps_logs_written_after_truncation() {
+        let dir = TempDir::new().unwrap();
+        let db = Db::init(&dir.path().join("log.db")).await.unwrap();
+        let path = dir.path().join("kernel.log");
+        let address =
+            PublicKeyHash::from_base58("tz1cD5CuvAALcxgypqBXcBQEA8dkLJivoFjU").unwrap();
+        let request = RequestEvent::Start {
+            address: address.clone(),
+            request_id: "request".to_string(),
+            caller: None,
+        };
+        append(&path, "");
+
+        let mut file = TailedFile::init(&path, None).await.unwrap();
+        db.flush(&Line::Request(request), file.position(), None)
+            .await
+            .unwrap();
+        append(&path, &log_line(&address, "first"));
+        flush_next_line(&db, &mut file, None).await;
+
+        // The file is truncated, then a line is written at the same offset
+        std::fs::write(&path, "").unwrap();
+        assert!(file.next_line().await.unwrap().is_none());
+        append(&path, &log_line(&address, "second"));
+        flush_next_line(&db, &mut file, None).await;
+
+        let logs = db
+            .logs_by_address(address, &LogFilter::default(), 10, 0)
+            .await
+            .unwrap();
+        let texts: Vec<_> = logs.into_iter().map(|log| log.text).collect();
+        assert_eq!(texts, ["first", "second"]);
+    }
+
+    #[tokio::test]
+    async fn ignores_logs_of_a_level_executed_again() {
+        let dir = TempDir::new().unwrap();
+        let db = Db::init(&dir.path().join("log.db")).await.unwrap();
+        let path = dir.path().join("kernel.log");
+        let address =
+            PublicKeyHash::from_base58("tz1cD5CuvAALcxgypqBXcBQEA8dkLJivoFjU").unwrap();
+        let request = RequestEvent::Start {
+            address: address.clone(),
+            request_id: "request".to_string(),
+            caller: None,
+        };
+        let level = |line| LevelInfo {
+            level: 10,
+            timestamp: Some(1000),
+            line,
+        };
+        append(&path, "");
+
+        let mut file = TailedFile::init(&path, None).await.unwrap();
+        db.flush(&Line::Request(request), file.position(), Some(level(1)))
+            .await
+            .unwrap();
+        // The level is executed twice, logging the same lines at other positions
+        for _ in 0..2 {
+            append(&path, &log_line(&address, "first"));
+            flush_next_line(&db, &mut file, Some(level(2))).await;
+            append(&path, &log_line(&address, "second"));
+            flush_next_line(&db, &mut file, Some(level(3))).await;
+        }
+
+        let texts = texts(&db, &address, LogFilter::default()).await;
+        assert_eq!(texts, ["first", "second"]);
+    }
+
+    #[tokio::test]
+    async fn dates_logs_and_requests_with_their_level() {
+        let dir = TempDir::new().unwrap();
+        let db = Db::init(&dir.path().join("log.db")).await.unwrap();
+        let path = dir.path().join("kernel.log");
+        let address =
+            PublicKeyHash::from_base58("tz1cD5CuvAALcxgypqBXcBQEA8dkLJivoFjU").unwrap();
+        let first = LevelInfo {
+            level: 10,
+            timestamp: Some(1000),
+            line: 1,
+        };
+        let second = LevelInfo {
+            level: 11,
+            timestamp: Some(1010),
+            line: 1,
+        };
+        append(&path, "");
+
+        let mut file = TailedFile::init(&path, None).await.unwrap();
+        let start = RequestEvent::Start {
+            address: address.clone(),
+            request_id: "request".to_string(),
+            caller: None,
+        };
+        db.flush(&Line::Request(start), file.position(), Some(first))
+            .await
+            .unwrap();
+        append(&path, &log_line(&address, "first"));
+        flush_next_line(&db, &mut file, Some(first)).await;
+        append(&path, &log_line(&address, "second"));
+        flush_next_line(&db, &mut file, Some(second)).await;
+        let end = RequestEvent::End {
+            address: address.clone(),
+            request_id: "request".to_string(),
+            status_code: Some(200),
+        };
+        db.flush(&Line::Request(end), file.position(), Some(second))
+            .await
+            .unwrap();
+
+        let since = LogFilter {
+            since: Some(1005),
+            ..LogFilter::default()
+        };
+        assert_eq!(texts(&db, &address, since).await, ["second"]);
+        let until = LogFilter {
+            until: Some(1005),
+            ..LogFilter::default()
+        };
+        assert_eq!(texts(&db, &address, until).await, ["first"]);
+        let from_level = LogFilter {
+            from_level: Some(11),
+            ..LogFilter::default()
+        };
+        assert_eq!(texts(&db, &address, from_level).await, ["second"]);
+        let to_level = LogFilter {
+            to_level: Some(10),
+            ..LogFilter::default()
+        };
+        assert_eq!(texts(&db, &address, to_level).await, ["first"]);
+
+        let metadata = db
+            .request_metadata(address, "request".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(metadata.start_level, Some(10));
+        assert_eq!(metadata.end_level, Some(11));
+        assert_eq!(metadata.started_at, Some(1000));
+        assert_eq!(metadata.ended_at, Some(1010));
+        assert_eq!(metadata.status_code, Some(200));
+    }
+
+    #[test]
+    fn quotes_full_text_terms() {
+        assert_eq!(
+            match_pattern(" insufficient  \"funds OR"),
+            Some(r#""insufficient" """funds" "OR""#.to_string())
+        );
+        assert_eq!(match_pattern("  "), None);
+    }
+}
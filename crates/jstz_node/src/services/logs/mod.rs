@@ -6,22 +6,24 @@ use axum::{
     response::Sse,
     Json,
 };
-use broadcaster::InfallibleSSeStream;
+use broadcaster::{Event, InfallibleSSeStream};
+use jstz_api::js_log::LogLevel;
 #[cfg(feature = "persistent-logging")]
-use jstz_proto::request_logger::{
-    RequestEvent, REQUEST_END_PREFIX, REQUEST_START_PREFIX,
-};
+use jstz_proto::request_logger::LEVEL_START_PREFIX;
 use jstz_proto::{
     context::account::Address,
     js_logger::{LogRecord, LOG_PREFIX},
+    request_logger::{RequestEvent, REQUEST_END_PREFIX, REQUEST_START_PREFIX},
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
-use utoipa::IntoParams;
+use utoipa::{IntoParams, ToSchema};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
-use crate::{tailed_file::TailedFile, AppState, Service};
+use crate::{
+    config::NodeSettings, metrics::Metrics, tailed_file::TailedFile, AppState, Service,
+};
 
 pub mod broadcaster;
 
@@ -33,7 +35,7 @@ pub mod db {
     #[derive(Clone)]
     pub struct Db {}
     impl Db {
-        pub async fn init() -> anyhow::Result<Self> {
+        pub async fn init(_db_path: &std::path::Path) -> anyhow::Result<Self> {
             Ok(Db {})
         }
     }
@@ -43,7 +45,9 @@ use self::{broadcaster::Broadcaster, db::Db};
 
 #[cfg(feature = "persistent-logging")]
 mod persistent_logging {
-    use crate::services::logs::{LogRecord, Pagination};
+    use crate::services::logs::{
+        LogFilter, LogRecord, Pagination, RequestMetadata, MAX_LOGS_LIMIT,
+    };
     use crate::{
         services::error::{ServiceError, ServiceResult},
         AppState,
@@ -58,10 +62,13 @@ mod persistent_logging {
         State(AppState { db, .. }): State<AppState>,
         Path(address): Path<String>,
         Query(Pagination { limit, offset }): Query<Pagination>,
+        Query(filter): Query<LogFilter>,
     ) -> ServiceResult<Json<Vec<LogRecord>>> {
         let address = Address::from_base58(&address)
             .map_err(|e| ServiceError::BadRequest(e.to_string()))?;
-        let result = db.logs_by_address(address, offset, limit).await?;
+        let result = db
+            .logs_by_address(address, &filter, limit.min(MAX_LOGS_LIMIT), offset)
+            .await?;
 
         Ok(Json(result))
     }
@@ -80,6 +87,19 @@ mod persistent_logging {
 
         Ok(Json(result))
     }
+
+    pub async fn request_metadata(
+        State(AppState { db, .. }): State<AppState>,
+        Path((address, request_id)): Path<(String, String)>,
+    ) -> ServiceResult<Json<RequestMetadata>> {
+        let address = Address::from_base58(&address)
+            .map_err(|e| ServiceError::BadRequest(e.to_string()))?;
+
+        db.request_metadata(address, request_id)
+            .await?
+            .map(Json)
+            .ok_or(ServiceError::NotFound)
+    }
 }
 
 #[cfg(feature = "persistent-logging")]
@@ -89,35 +109,76 @@ use super::error::{ServiceError, ServiceResult};
 
 // Represents each line in the log file.
 pub enum Line {
-    #[cfg(feature = "persistent-logging")]
     // Indicates the start and end of a smart function call (request).
-    #[cfg(feature = "persistent-logging")]
     Request(RequestEvent),
+    // Indicates the start of a level, which the requests that follow belong to.
+    #[cfg(feature = "persistent-logging")]
+    Level(LevelInfo),
     // Indicates the js log message from the smart function (e.g. log).
     Js(LogRecord),
 }
 
+/// Level of the rollup, as logged by the kernel at its start
+#[cfg(feature = "persistent-logging")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelInfo {
+    pub level: u32,
+    /// Timestamp of the predecessor block, in seconds since the Unix epoch.
+    /// Kernels of earlier versions do not log it.
+    pub timestamp: Option<u64>,
+    /// Number of lines read since the start of the level. Identifies the line
+    /// being handled within the level, which is logged again by the kernel if
+    /// the rollup node executes the level again.
+    pub line: u32,
+}
+
+// File of the data directory the position in the kernel log file is persisted to
+const KERNEL_LOG_POSITION_FILE: &str = "kernel_log.position";
+
+// Interval between two reads of the kernel log file once its end is reached
+const TAIL_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+// Number of lines read between two persisted positions
+const CHECKPOINT_LINES: usize = 1000;
+
 pub struct LogsService;
 
 impl LogsService {
     // Initalise the LogService by spawning a future that reads and broadcasts the file
     pub async fn init(
         path: &std::path::Path,
+        settings: &NodeSettings,
+        metrics: &Metrics,
         cancellation_token: &CancellationToken,
     ) -> anyhow::Result<(Arc<Broadcaster>, Db, JoinHandle<std::io::Result<()>>)> {
         // Create a broadcaster for streaming logs.
-        let broadcaster = Broadcaster::new();
+        let broadcaster =
+            Broadcaster::new(settings.rate_limits.max_sse_clients, metrics.clone());
 
         // Create a connection with the sqlite database.
-        let db = Db::init().await?;
+        let db = Db::init(&settings.db_path()).await?;
 
-        let file = TailedFile::init(path).await?;
+        #[cfg(feature = "persistent-logging")]
+        if let Some(days) = settings.log_retention_days {
+            Self::prune_logs(
+                db.clone(),
+                std::time::Duration::from_secs(u64::from(days) * 24 * 60 * 60),
+                cancellation_token.clone(),
+            );
+        }
+
+        let file = TailedFile::init(
+            path,
+            Some(&settings.data_dir.join(KERNEL_LOG_POSITION_FILE)),
+        )
+        .await?;
         // Spawn a future that reads from the log file.
         // The line is broadcast to client / flushed to storage.
         let tail_file_handle = Self::tail_file(
             file,
             broadcaster.clone(),
             db.clone(),
+            metrics.clone(),
             cancellation_token.clone(),
         )
         .await;
@@ -128,45 +189,84 @@ impl LogsService {
     /// Spawn a future that tails log file.
     /// The line is broadcast to client / flushed to storage.
     async fn tail_file(
-        file: TailedFile,
+        mut file: TailedFile,
         broadcaster: Arc<Broadcaster>,
         #[allow(unused_variables)] db: Db,
+        metrics: Metrics,
         cancellation_token: CancellationToken,
     ) -> JoinHandle<std::io::Result<()>> {
         tokio::task::spawn(async move {
-            let mut lines = file.lines();
+            // Number of lines read since the file position was last persisted
+            let mut unsaved_lines = 0;
+            // Level the lines are read from, unknown until its start is read
+            #[cfg(feature = "persistent-logging")]
+            let mut level = None;
             loop {
                 tokio::select! {
-                    current_line = lines.next_line() => {
-                        if let Ok(Some(line_str)) = current_line {
-                            // CLIPPY
-                            // The collapsible-match lint gives a false positive for this line since
-                            // it doesn't consider the line below (guarded by the 'persistent-logging' feature flag)
-                            #[allow(clippy::collapsible_match)]
+                    current_line = file.next_line() => match current_line {
+                        #[allow(unused_variables)]
+                        Ok(Some((position, line_str))) => {
+                            unsaved_lines += 1;
+                            #[cfg(feature = "persistent-logging")]
+                            if let Some(level) = &mut level {
+                                level.line += 1;
+                            }
                             if let Some(line) = Self::parse_line(&line_str) {
+                                metrics.observe_log_line();
 
                                 #[cfg(feature = "persistent-logging")]
-                                {
-                                    let _ = db.flush(&line).await.map_err(|e|
+                                if let Line::Level(current) = line {
+                                    level = Some(current);
+                                } else {
+                                    let start = std::time::Instant::now();
+                                    let _ = db.flush(&line, position, level).await.map_err(|e|
                                         {
                                             log::warn!("Failed to flush log to database: {:?}", e.to_string());
                                         }
                                     );
+                                    metrics.observe_db_write(start.elapsed());
                                 }
 
-                                // Stream the log
-                                #[cfg(not(feature = "persistent-logging"))]
-                                #[allow(irrefutable_let_patterns)]
-                                if let Line::Js(log) = line {
-                                    broadcaster
-                                        .broadcast(&log.address, &line_str[LOG_PREFIX.len()..])
-                                       .await;
+                                match line {
+                                    Line::Js(log) => {
+                                        // Stream the log
+                                        #[cfg(not(feature = "persistent-logging"))]
+                                        broadcaster
+                                            .broadcast(&log.address, &line_str[LOG_PREFIX.len()..])
+                                            .await;
+                                        broadcaster.publish(Event::Log(log));
+                                    }
+                                    Line::Request(event) => {
+                                        broadcaster.publish(Event::Request(event))
+                                    }
+                                    #[cfg(feature = "persistent-logging")]
+                                    Line::Level(_) => {}
                                 }
                             }
+
+                            if unsaved_lines >= CHECKPOINT_LINES {
+                                Self::save_position(&file).await;
+                                unsaved_lines = 0;
+                            }
+                        }
+                        // Caught up with the end of the file
+                        Ok(None) => {
+                            if unsaved_lines > 0 {
+                                Self::save_position(&file).await;
+                                unsaved_lines = 0;
+                            }
+                            tokio::time::sleep(TAIL_POLL_INTERVAL).await;
+                        }
+                        Err(e) => {
+                            log::warn!("Failed to read the kernel log file: {:?}", e);
+                            tokio::time::sleep(TAIL_POLL_INTERVAL).await;
                         }
                     },
                     _ = cancellation_token.cancelled() => {
                         // The stop signal has been triggered.
+                        if unsaved_lines > 0 {
+                            Self::save_position(&file).await;
+                        }
                         break;
                     }
                 }
@@ -176,29 +276,76 @@ impl LogsService {
         })
     }
 
+    /// Persist the position in the log file so that lines are neither missed nor
+    /// read twice after a restart.
+    async fn save_position(file: &TailedFile) {
+        if let Err(e) = file.save_position().await {
+            log::warn!("Failed to save the kernel log position: {:?}", e);
+        }
+    }
+
+    /// Spawn a future that deletes the persistent logs older than `retention`
+    /// every hour.
+    #[cfg(feature = "persistent-logging")]
+    fn prune_logs(
+        db: Db,
+        retention: std::time::Duration,
+        cancellation_token: CancellationToken,
+    ) {
+        tokio::task::spawn(async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        match db.prune(retention).await {
+                            Ok(0) => {}
+                            Ok(deleted) => log::info!("Pruned {} expired logs", deleted),
+                            Err(e) => log::warn!("Failed to prune logs: {:?}", e),
+                        }
+                    },
+                    _ = cancellation_token.cancelled() => break,
+                }
+            }
+        });
+    }
+
     fn parse_line(line: &str) -> Option<Line> {
         if let Some(log) = line.strip_prefix(LOG_PREFIX) {
             return LogRecord::try_from_string(log).map(Line::Js);
         }
 
-        #[cfg(feature = "persistent-logging")]
-        {
-            if let Some(request) = line.strip_prefix(REQUEST_START_PREFIX) {
-                return RequestEvent::try_from_string(request).map(Line::Request);
-            }
+        if let Some(request) = line.strip_prefix(REQUEST_START_PREFIX) {
+            return RequestEvent::try_from_string(request).map(Line::Request);
+        }
 
-            if let Some(request) = line.strip_prefix(REQUEST_END_PREFIX) {
-                return RequestEvent::try_from_string(request).map(Line::Request);
-            }
+        if let Some(request) = line.strip_prefix(REQUEST_END_PREFIX) {
+            return RequestEvent::try_from_string(request).map(Line::Request);
+        }
+
+        #[cfg(feature = "persistent-logging")]
+        if let Some(level) = line.strip_prefix(LEVEL_START_PREFIX) {
+            let mut fields = level.split_whitespace();
+            let level = fields.next()?.parse().ok()?;
+            let timestamp = fields.next().and_then(|timestamp| timestamp.parse().ok());
+            return Some(Line::Level(LevelInfo {
+                level,
+                timestamp,
+                line: 0,
+            }));
         }
 
         None
     }
 }
 
+/// Maximum number of persistent logs returned at once
+pub const MAX_LOGS_LIMIT: usize = 1000;
+
 #[derive(Deserialize, Debug, IntoParams)]
 #[serde(default)]
 pub struct Pagination {
+    /// Maximum number of logs to return, at most 1000
     limit: usize,
     offset: usize,
 }
@@ -212,6 +359,47 @@ impl Default for Pagination {
     }
 }
 
+/// Filters of the persistent logs
+#[derive(Deserialize, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LogFilter {
+    /// Only return the logs of this level
+    pub level: Option<LogLevel>,
+    /// Only return the logs of the levels whose predecessor block was produced at
+    /// or after this time, in seconds since the Unix epoch
+    pub since: Option<u64>,
+    /// Only return the logs of the levels whose predecessor block was produced at
+    /// or before this time, in seconds since the Unix epoch
+    pub until: Option<u64>,
+    /// Only return the logs written at or after this level of the rollup
+    pub from_level: Option<u32>,
+    /// Only return the logs written at or before this level of the rollup
+    pub to_level: Option<u32>,
+    /// Only return the logs containing all these words
+    pub q: Option<String>,
+}
+
+/// Metadata of a smart function request
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct RequestMetadata {
+    pub request_id: String,
+    pub address: Address,
+    /// Address of the caller, if it is known
+    pub caller: Option<Address>,
+    /// Level the request started at
+    pub start_level: Option<u32>,
+    /// Level the request ended at, if it ended
+    pub end_level: Option<u32>,
+    /// Status code of the response, if the request succeeded
+    pub status_code: Option<u16>,
+    /// Timestamp of the predecessor block of the level the request started at,
+    /// in seconds since the Unix epoch
+    pub started_at: Option<u64>,
+    /// Timestamp of the predecessor block of the level the request ended at,
+    /// in seconds since the Unix epoch
+    pub ended_at: Option<u64>,
+}
+
 /// Stream console logs
 ///
 /// Returns a stream of console logs from the given Smart Function as Server-Sent Events.
@@ -222,7 +410,8 @@ impl Default for Pagination {
     responses(
         (status = 200, description = "Successfully connected to log stream as Server-Sent Events"),
         (status = 400),
-        (status = 404)
+        (status = 404),
+        (status = 429)
     )
 )]
 async fn stream_log(
@@ -231,7 +420,7 @@ async fn stream_log(
 ) -> ServiceResult<Sse<InfallibleSSeStream>> {
     let address = Address::from_base58(&address)
         .map_err(|e| ServiceError::BadRequest(e.to_string()))?;
-    Ok(broadcaster.new_client(address).await)
+    broadcaster.new_client(address).await
 }
 
 /// Fetch console logs by address
@@ -241,12 +430,13 @@ async fn stream_log(
 #[utoipa::path(
         get,
         path = "/{address}/persistent/requests",
-        params(Pagination),
+        params(Pagination, LogFilter),
         tag = "Logs",
         responses(
             (status = 200, body = Vec<LogRecord>),
             (status = 400),
-            (status = 404)
+            (status = 404),
+            (status = 429)
         )
     )]
 #[allow(unused_variables)]
@@ -254,10 +444,16 @@ pub async fn persistent_logs(
     app_state: State<AppState>,
     path_params: Path<String>,
     query_params: Query<Pagination>,
+    filter_params: Query<LogFilter>,
 ) -> ServiceResult<Json<Vec<LogRecord>>> {
     #[cfg(feature = "persistent-logging")]
-    return persistent_logging::persistent_logs(app_state, path_params, query_params)
-        .await;
+    return persistent_logging::persistent_logs(
+        app_state,
+        path_params,
+        query_params,
+        filter_params,
+    )
+    .await;
 
     #[cfg(not(feature = "persistent-logging"))]
     Err(ServiceError::PersistentLogsDisabled)
@@ -274,7 +470,8 @@ pub async fn persistent_logs(
         responses(
             (status = 200, body = Vec<LogRecord>),
             (status = 400),
-            (status = 404)
+            (status = 404),
+            (status = 429)
         )
     )]
 #[allow(unused_variables)]
@@ -295,12 +492,40 @@ pub async fn persistent_logs_by_request_id(
     Err(ServiceError::PersistentLogsDisabled)
 }
 
+/// Fetch the metadata of a request
+///
+/// Fetch the caller, levels, status code and duration of a request from the log
+/// store only if persistent logging is enabled on this Jstz node instance
+#[utoipa::path(
+        get,
+        path = "/{address}/persistent/requests/{request_id}/metadata",
+        tag = "Logs",
+        responses(
+            (status = 200, body = RequestMetadata),
+            (status = 400),
+            (status = 404),
+            (status = 429)
+        )
+    )]
+#[allow(unused_variables)]
+pub async fn request_metadata(
+    app_state: State<AppState>,
+    path_params: Path<(String, String)>,
+) -> ServiceResult<Json<RequestMetadata>> {
+    #[cfg(feature = "persistent-logging")]
+    return persistent_logging::request_metadata(app_state, path_params).await;
+
+    #[cfg(not(feature = "persistent-logging"))]
+    Err(ServiceError::PersistentLogsDisabled)
+}
+
 impl Service for LogsService {
     fn router_with_openapi() -> OpenApiRouter<AppState> {
         let router = OpenApiRouter::new()
             .routes(routes!(stream_log))
             .routes(routes!(persistent_logs))
-            .routes(routes!(persistent_logs_by_request_id));
+            .routes(routes!(persistent_logs_by_request_id))
+            .routes(routes!(request_metadata));
 
         OpenApiRouter::new().nest("/logs", router)
     }
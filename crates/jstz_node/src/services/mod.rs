@@ -6,6 +6,9 @@ pub mod accounts;
 pub mod error;
 pub mod logs;
 pub mod operations;
+pub mod outbox;
+pub mod tickets;
+pub mod websocket;
 
 pub trait Service {
     fn router_with_openapi() -> OpenApiRouter<AppState>;
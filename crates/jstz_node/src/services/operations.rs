@@ -1,14 +1,20 @@
 use super::error::{ServiceError, ServiceResult};
 use super::{AppState, Service};
+use crate::{
+    batcher::encode_external_message,
+    dal_publisher::DalPublication,
+    mempool::{MempoolError, OperationStatus, PendingOperation, PendingStatus},
+};
 use anyhow::anyhow;
 use axum::{
     extract::{Path, State},
     Json,
 };
-use jstz_proto::operation::SignedOperation;
+use jstz_proto::operation::{
+    chunk::OperationChunk, ExternalMessageContents, OperationHash, SignedOperation,
+};
 use jstz_proto::receipt::Receipt;
-use tezos_data_encoding::enc::BinWriter;
-use tezos_smart_rollup::inbox::ExternalMessageFrame;
+use octez::OctezRollupClient;
 
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
@@ -18,32 +24,127 @@ pub struct OperationsService;
 const OPERATIONS_TAG: &str = "Operations";
 
 /// Inject an operation into Jstz
+///
+/// The operation is validated against the signature and the nonce of its source
+/// before reaching the rollup. Operations with a future nonce are kept pending until
+/// the operations with lower nonces land. Operations injected within a short window
+/// are aggregated into compressed batches, each sent in a single external message.
 #[utoipa::path(
         post,
         path = "",
         tag = OPERATIONS_TAG,
         responses(
-            (status = 200, description = "Operation successfully injectedd"),
+            (status = 200, body = PendingStatus, description = "Operation successfully accepted"),
             (status = 400),
+            (status = 429),
             (status = 500)
         )
     )]
 async fn inject(
-    State(AppState { rollup_client, .. }): State<AppState>,
+    State(AppState { mempool, .. }): State<AppState>,
     Json(operation): Json<SignedOperation>,
-) -> ServiceResult<()> {
+) -> ServiceResult<Json<PendingStatus>> {
     let encoded_operation = bincode::serialize(&operation)
         .map_err(|_| anyhow!("Failed to serialize operation"))?;
+    if OperationChunk::is_required(&encoded_operation) {
+        return Err(ServiceError::BadRequest(
+            "Operation too large, inject it in chunks".to_string(),
+        ));
+    }
+    match mempool.add(operation).await {
+        Ok(status) => Ok(Json(status)),
+        Err(err) => match err.downcast::<MempoolError>() {
+            Ok(err) => Err(ServiceError::BadRequest(err.to_string())),
+            Err(err) => Err(err.into()),
+        },
+    }
+}
+
+/// Get the pending operations
+///
+/// Get the operations accepted by the node that have not been executed yet, either
+/// waiting for the operations with lower nonces to land or injected into the rollup.
+#[utoipa::path(
+        get,
+        path = "/pending",
+        tag = OPERATIONS_TAG,
+        responses(
+            (status = 200, body = Vec<PendingOperation>),
+            (status = 429)
+        )
+    )]
+async fn pending(
+    State(AppState { mempool, .. }): State<AppState>,
+) -> Json<Vec<PendingOperation>> {
+    Json(mempool.pending())
+}
+
+/// Inject a chunk of an operation into Jstz
+///
+/// Operations too large to fit in a single external message are split into chunks,
+/// each signed by the signer of the operation. The operation is executed once all of
+/// its chunks have been injected.
+#[utoipa::path(
+        post,
+        path = "/chunks",
+        tag = OPERATIONS_TAG,
+        responses(
+            (status = 200, description = "Operation chunk successfully injected"),
+            (status = 400),
+            (status = 429),
+            (status = 500)
+        )
+    )]
+async fn inject_chunk(
+    State(AppState { rollup_client, .. }): State<AppState>,
+    Json(chunk): Json<OperationChunk>,
+) -> ServiceResult<()> {
+    if chunk.verify().is_err() {
+        return Err(ServiceError::BadRequest(
+            "Invalid operation chunk signature".to_string(),
+        ));
+    }
+    inject_contents(&rollup_client, ExternalMessageContents::Chunk(chunk)).await
+}
+
+/// Publish a batch of operations to the Data Availability Layer (DAL)
+///
+/// The operations are posted to a DAL slot the kernel is subscribed to and are executed
+/// once the slot is attested. Only available if the node is configured to publish to the DAL.
+#[utoipa::path(
+        post,
+        path = "/dal",
+        tag = OPERATIONS_TAG,
+        responses(
+            (status = 200, body = DalPublication),
+            (status = 400),
+            (status = 429),
+            (status = 500)
+        )
+    )]
+async fn publish_dal_batch(
+    State(AppState {
+        rollup_client,
+        dal_publisher,
+        ..
+    }): State<AppState>,
+    Json(operations): Json<Vec<SignedOperation>>,
+) -> ServiceResult<Json<DalPublication>> {
+    let dal_publisher = dal_publisher.ok_or(ServiceError::DalPublishingDisabled)?;
+    if operations.is_empty() {
+        return Err(ServiceError::BadRequest("Empty batch".to_string()));
+    }
+    let publication = dal_publisher.publish(&rollup_client, operations).await?;
+    Ok(Json(publication))
+}
+
+async fn inject_contents(
+    rollup_client: &OctezRollupClient,
+    contents: ExternalMessageContents,
+) -> ServiceResult<()> {
     let address = rollup_client.get_rollup_address().await?;
-    let message_frame = ExternalMessageFrame::Targetted {
-        address,
-        contents: encoded_operation,
-    };
-    let mut binary_contents = Vec::new();
-    message_frame
-        .bin_write(&mut binary_contents)
-        .map_err(|_| anyhow!("Failed to write binary frame"))?;
-    rollup_client.batcher_injection([binary_contents]).await?;
+    let message = encode_external_message(&address, &contents)?;
+    rollup_client.batcher_injection([message]).await?;
     Ok(())
 }
 
@@ -58,6 +159,7 @@ async fn inject(
         responses(
             (status = 200, body = Receipt),
             (status = 400),
+            (status = 429),
             (status = 500)
         )
     )]
@@ -78,11 +180,43 @@ async fn receipt(
     Ok(Json(receipt))
 }
 
+/// Get the status of an operation
+///
+/// Get the stage of an operation in its lifecycle: pending in the mempool of the node,
+/// injected into the rollup node batcher, included in the rollup inbox, failed or
+/// dropped, or unknown to the node.
+#[utoipa::path(
+        get,
+        path = "/{operation_hash}/status",
+        tag = OPERATIONS_TAG,
+        params(
+            ("operation_hash" = String, description = "Operation hash")
+        ),
+        responses(
+            (status = 200, body = OperationStatus),
+            (status = 400),
+            (status = 429),
+            (status = 500)
+        )
+    )]
+async fn status(
+    State(AppState { mempool, .. }): State<AppState>,
+    Path(hash): Path<String>,
+) -> ServiceResult<Json<OperationStatus>> {
+    let hash = OperationHash::try_parse(hash)
+        .map_err(|_| ServiceError::BadRequest("Invalid operation hash".to_string()))?;
+    Ok(Json(mempool.status(&hash).await?))
+}
+
 impl Service for OperationsService {
     fn router_with_openapi() -> OpenApiRouter<AppState> {
         let routes = OpenApiRouter::new()
             .routes(routes!(inject))
-            .routes(routes!(receipt));
+            .routes(routes!(pending))
+            .routes(routes!(inject_chunk))
+            .routes(routes!(publish_dal_batch))
+            .routes(routes!(receipt))
+            .routes(routes!(status));
 
         OpenApiRouter::new().nest("/operations", routes)
     }
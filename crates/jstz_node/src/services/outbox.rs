@@ -0,0 +1,145 @@
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::{
+    error::{ServiceError, ServiceResult},
+    Service,
+};
+use crate::{
+    withdrawals::{
+        read_withdrawal_location, withdrawal_status, OutboxLocation, WithdrawalStatus,
+    },
+    AppState,
+};
+
+const OUTBOX_TAG: &str = "Outbox";
+
+// Capacity of the outbox queue when it has not been initialised by the kernel yet
+const DEFAULT_OUTBOX_QUEUE_CAPACITY: u32 = u16::MAX as u32;
+
+// Mirrors the outbox queue metadata stored by the kernel
+#[derive(Deserialize)]
+struct OutboxQueueMeta {
+    len: u32,
+    max: u32,
+}
+
+/// Depth of the kernel outbox queue
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OutboxQueueDepth {
+    /// Number of messages waiting in the outbox queue, or in the withdrawals
+    /// to be batched
+    pub depth: u32,
+    /// Number of withdrawals waiting to be batched at the end of the level
+    pub pending_withdrawals: u32,
+    /// Maximum depth of the outbox queue, beyond which withdrawals are rejected
+    pub capacity: u32,
+}
+
+pub struct OutboxService;
+
+/// Get depth of the outbox queue
+#[utoipa::path(
+    get,
+    path = "/queue",
+    tag = OUTBOX_TAG,
+    responses(
+        (status = 200, body = OutboxQueueDepth),
+        (status = 500)
+    )
+)]
+async fn get_queue_depth(
+    State(AppState { rollup_client, .. }): State<AppState>,
+) -> ServiceResult<Json<OutboxQueueDepth>> {
+    let meta = match rollup_client.get_value("/outbox/meta").await? {
+        Some(value) => bincode::deserialize::<OutboxQueueMeta>(&value)
+            .map_err(|_| anyhow!("Failed to deserialize outbox queue metadata"))?,
+        None => OutboxQueueMeta {
+            len: 0,
+            max: DEFAULT_OUTBOX_QUEUE_CAPACITY,
+        },
+    };
+    let pending_withdrawals =
+        match rollup_client.get_value("/outbox/withdrawals/len").await? {
+            Some(value) => bincode::deserialize::<u32>(&value)
+                .map_err(|_| anyhow!("Failed to deserialize pending withdrawals"))?,
+            None => 0,
+        };
+    Ok(Json(OutboxQueueDepth {
+        depth: meta.len + pending_withdrawals,
+        pending_withdrawals,
+        capacity: meta.max,
+    }))
+}
+
+/// Get outbox location of a withdrawal
+///
+/// Get the level and index of the outbox message containing the withdrawal
+/// with the given outbox message id, as found in the withdrawal receipt. The
+/// message can be executed on L1 once the commitment of its level is cemented.
+/// Returns 404 if the withdrawal has not been written to the outbox yet.
+#[utoipa::path(
+    get,
+    path = "/withdrawals/{outbox_message_id}",
+    tag = OUTBOX_TAG,
+    responses(
+        (status = 200, body = OutboxLocation),
+        (status = 404),
+        (status = 500)
+    )
+)]
+async fn get_withdrawal_location(
+    State(AppState { rollup_client, .. }): State<AppState>,
+    Path(outbox_message_id): Path<u64>,
+) -> ServiceResult<Json<OutboxLocation>> {
+    match read_withdrawal_location(&rollup_client, outbox_message_id).await? {
+        Some(location) => Ok(Json(location)),
+        None => Err(ServiceError::NotFound),
+    }
+}
+
+/// Get status of a withdrawal
+///
+/// Get the progress of the withdrawal with the given outbox message id towards
+/// the L1. Once the commitment of its outbox message is cemented, the status
+/// contains the proof to execute the message with. If the node executes
+/// cemented withdrawals, the status reports the executing L1 operation.
+#[utoipa::path(
+    get,
+    path = "/withdrawals/{outbox_message_id}/status",
+    tag = OUTBOX_TAG,
+    responses(
+        (status = 200, body = WithdrawalStatus),
+        (status = 500)
+    )
+)]
+async fn get_withdrawal_status(
+    State(AppState {
+        rollup_client,
+        outbox_executor,
+        ..
+    }): State<AppState>,
+    Path(outbox_message_id): Path<u64>,
+) -> ServiceResult<Json<WithdrawalStatus>> {
+    let status =
+        withdrawal_status(&rollup_client, outbox_executor.as_ref(), outbox_message_id)
+            .await?;
+    Ok(Json(status))
+}
+
+impl Service for OutboxService {
+    fn router_with_openapi() -> OpenApiRouter<AppState> {
+        let routes = OpenApiRouter::new()
+            .routes(routes!(get_queue_depth))
+            .routes(routes!(get_withdrawal_location))
+            .routes(routes!(get_withdrawal_status));
+
+        OpenApiRouter::new().nest("/outbox", routes)
+    }
+}
@@ -0,0 +1,76 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use jstz_proto::context::ticket_table::{TicketKey, TicketMetadata};
+use octez::OctezRollupClient;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::{
+    error::{ServiceError, ServiceResult},
+    Service,
+};
+use crate::AppState;
+
+const TICKETS_TAG: &str = "Tickets";
+
+pub struct TicketsService;
+
+/// Parses `ticket_hash` as the hex encoded hash of a ticket
+pub(super) fn parse_ticket_hash(ticket_hash: &str) -> ServiceResult<TicketKey> {
+    TicketKey::from_str(ticket_hash)
+        .map_err(|_| ServiceError::BadRequest("Invalid ticket hash".to_string()))
+}
+
+/// Reads the metadata recorded for the ticket `ticket_hash`, if any
+pub(super) async fn read_ticket_metadata(
+    rollup_client: &OctezRollupClient,
+    ticket_hash: &TicketKey,
+) -> ServiceResult<Option<TicketMetadata>> {
+    let key = format!("/ticket_metadata/{}", ticket_hash);
+    let value = rollup_client.get_value(&key).await?;
+    let metadata = value
+        .map(|value| {
+            bincode::deserialize::<TicketMetadata>(&value)
+                .map_err(|_| anyhow!("Failed to deserialize ticket metadata"))
+        })
+        .transpose()?;
+    Ok(metadata)
+}
+
+/// Get metadata of a ticket
+///
+/// Get the ticketer, token id and contents of a ticket. The metadata is
+/// recorded on the first deposit of the ticket.
+#[utoipa::path(
+    get,
+    path = "/{ticket_hash}",
+    tag = TICKETS_TAG,
+    responses(
+        (status = 200, body = TicketMetadata),
+        (status = 400),
+        (status = 404),
+        (status = 500)
+    )
+)]
+async fn get_ticket_metadata(
+    State(AppState { rollup_client, .. }): State<AppState>,
+    Path(ticket_hash): Path<String>,
+) -> ServiceResult<Json<TicketMetadata>> {
+    let ticket_hash = parse_ticket_hash(&ticket_hash)?;
+    match read_ticket_metadata(&rollup_client, &ticket_hash).await? {
+        Some(metadata) => Ok(Json(metadata)),
+        None => Err(ServiceError::NotFound),
+    }
+}
+
+impl Service for TicketsService {
+    fn router_with_openapi() -> OpenApiRouter<AppState> {
+        let routes = OpenApiRouter::new().routes(routes!(get_ticket_metadata));
+
+        OpenApiRouter::new().nest("/tickets", routes)
+    }
+}
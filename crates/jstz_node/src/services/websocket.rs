@@ -0,0 +1,456 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+};
+use jstz_api::js_log::LogLevel;
+use jstz_proto::{
+    context::account::Address, js_logger::LogRecord, operation::OperationHash,
+    receipt::Receipt, request_logger::RequestEvent,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    time::interval,
+};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::{
+    error::ServiceResult,
+    logs::broadcaster::{Broadcaster, Event, Notification, PING_INTERVAL},
+};
+use crate::{
+    receipt_poller::{ReceiptPoller, ReceiptSender},
+    AppState, Service,
+};
+
+pub struct WebSocketService;
+
+// Maximum number of receipts a client can wait for at once
+const MAX_RECEIPTS: usize = 100;
+
+/// Message sent by clients
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Replaces the subscription of the client
+    Subscribe {
+        /// Smart functions whose logs and events are streamed
+        #[serde(default)]
+        addresses: Vec<Address>,
+        /// Levels of the streamed logs, all levels if `None`
+        levels: Option<Vec<LogLevel>>,
+        /// Whether the start and end of requests are streamed
+        #[serde(default)]
+        events: bool,
+        /// Hashes of the operations whose receipt is sent once available
+        #[serde(default)]
+        receipts: Vec<String>,
+        /// Cursor of the last notification received, to resume from
+        cursor: Option<u64>,
+    },
+    /// Cancels the subscription of the client
+    Unsubscribe,
+}
+
+/// Message sent to clients
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    /// The subscription was accepted. `complete` is unset if some notifications
+    /// following the cursor to resume from are no longer available.
+    Subscribed {
+        cursor: u64,
+        complete: bool,
+    },
+    /// Sent periodically with the cursor of the last published notification
+    Heartbeat {
+        cursor: u64,
+    },
+    Log {
+        cursor: u64,
+        log: &'a LogRecord,
+    },
+    Request {
+        cursor: u64,
+        event: &'a RequestEvent,
+    },
+    Receipt {
+        hash: String,
+        receipt: &'a Receipt,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Notifications a client subscribed to
+#[derive(Default)]
+pub(crate) struct Subscription {
+    addresses: HashSet<Address>,
+    levels: Option<Vec<LogLevel>>,
+    events: bool,
+    // Hex encoded hashes of the operations whose receipt is not available yet
+    receipts: HashMap<OperationHash, String>,
+}
+
+impl Subscription {
+    /// Subscription to the logs of `levels`, all levels if `None`, and to the
+    /// start and end of requests if `events` is set, of the smart functions
+    /// `addresses`
+    pub fn new(
+        addresses: impl IntoIterator<Item = Address>,
+        levels: Option<Vec<LogLevel>>,
+        events: bool,
+    ) -> Self {
+        Self {
+            addresses: addresses.into_iter().collect(),
+            levels,
+            events,
+            receipts: HashMap::new(),
+        }
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        if !self.addresses.contains(event.address()) {
+            return false;
+        }
+        match event {
+            Event::Log(log) => self
+                .levels
+                .as_ref()
+                .map_or(true, |levels| levels.contains(&log.level)),
+            Event::Request(_) => self.events,
+        }
+    }
+}
+
+/// Subscribe to logs, events and receipts
+///
+/// Upgrades the connection to a WebSocket. Clients send `subscribe` messages
+/// listing the smart functions whose logs and request events they follow, and the
+/// operations whose receipt they wait for. Notifications carry a cursor that clients
+/// can resume from after reconnecting.
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "Logs",
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+        (status = 400),
+        (status = 429)
+    )
+)]
+async fn websocket(
+    State(AppState {
+        broadcaster,
+        receipt_poller,
+        ..
+    }): State<AppState>,
+    upgrade: WebSocketUpgrade,
+) -> ServiceResult<Response> {
+    let guard = broadcaster.new_socket()?;
+    Ok(upgrade.on_upgrade(move |socket| async move {
+        serve(socket, broadcaster, receipt_poller).await;
+        drop(guard);
+    }))
+}
+
+// WebSocket connection of a client
+struct Connection {
+    socket: WebSocket,
+    broadcaster: Arc<Broadcaster>,
+    receipt_poller: ReceiptPoller,
+    subscription: Subscription,
+    // Receiver of the notifications, `None` if the client is not subscribed
+    receiver: Option<broadcast::Receiver<Arc<Notification>>>,
+    // Receiver of the receipts, `None` if the client waits for none
+    receipt_receiver: Option<mpsc::UnboundedReceiver<(OperationHash, Arc<Receipt>)>>,
+    // Cursor of the last notification received
+    cursor: u64,
+}
+
+async fn serve(
+    socket: WebSocket,
+    broadcaster: Arc<Broadcaster>,
+    receipt_poller: ReceiptPoller,
+) {
+    let mut connection = Connection {
+        socket,
+        cursor: broadcaster.cursor(),
+        broadcaster,
+        receipt_poller,
+        subscription: Subscription::default(),
+        receiver: None,
+        receipt_receiver: None,
+    };
+    let mut heartbeat = interval(Duration::from_secs(PING_INTERVAL));
+
+    loop {
+        let result = tokio::select! {
+            message = connection.socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => connection.handle(&text).await,
+                // Pings are answered by the WebSocket implementation
+                Some(Ok(Message::Binary(_) | Message::Ping(_) | Message::Pong(_))) => Ok(()),
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+            },
+            notification = next(&mut connection.receiver) => match notification {
+                Ok(notification) => connection.notify(notification).await,
+                Err(RecvError::Lagged(_)) => connection.catch_up().await,
+                Err(RecvError::Closed) => break,
+            },
+            _ = heartbeat.tick() => {
+                let cursor = connection.broadcaster.cursor();
+                connection.send(&ServerMessage::Heartbeat { cursor }).await
+            },
+            Some((hash, receipt)) = next_receipt(&mut connection.receipt_receiver) => {
+                connection.send_receipt(hash, &receipt).await
+            },
+        };
+        if result.is_err() {
+            break;
+        }
+    }
+}
+
+impl Connection {
+    async fn handle(&mut self, text: &str) -> Result<(), axum::Error> {
+        match serde_json::from_str::<ClientMessage>(text) {
+            Ok(ClientMessage::Subscribe {
+                addresses,
+                levels,
+                events,
+                receipts,
+                cursor,
+            }) => {
+                let receipts = match parse_receipts(receipts) {
+                    Ok(receipts) => receipts,
+                    Err(message) => {
+                        return self.send(&ServerMessage::Error { message }).await
+                    }
+                };
+                // Replacing the receiver stops waiting for the previous receipts
+                self.receipt_receiver = if receipts.is_empty() {
+                    None
+                } else {
+                    let (sender, receiver) = mpsc::unbounded_channel();
+                    self.watch(&receipts, sender);
+                    Some(receiver)
+                };
+                self.subscription = Subscription {
+                    receipts,
+                    ..Subscription::new(addresses, levels, events)
+                };
+                let replay = self.broadcaster.replay(cursor);
+                self.receiver = Some(replay.receiver);
+                self.cursor = replay.cursor;
+                self.send(&ServerMessage::Subscribed {
+                    cursor: replay.cursor,
+                    complete: replay.complete,
+                })
+                .await?;
+                self.send_all(&replay.notifications).await
+            }
+            Ok(ClientMessage::Unsubscribe) => {
+                self.subscription = Subscription::default();
+                self.receiver = None;
+                self.receipt_receiver = None;
+                Ok(())
+            }
+            Err(e) => {
+                self.send(&ServerMessage::Error {
+                    message: e.to_string(),
+                })
+                .await
+            }
+        }
+    }
+
+    async fn notify(
+        &mut self,
+        notification: Arc<Notification>,
+    ) -> Result<(), axum::Error> {
+        self.cursor = notification.cursor;
+        self.send_all(&[notification]).await
+    }
+
+    // Replays the notifications missed by a client lagging behind
+    async fn catch_up(&mut self) -> Result<(), axum::Error> {
+        let replay = self.broadcaster.replay(Some(self.cursor));
+        if !replay.complete {
+            log::warn!("A WebSocket client missed notifications");
+        }
+        self.receiver = Some(replay.receiver);
+        self.cursor = replay.cursor;
+        self.send_all(&replay.notifications).await
+    }
+
+    async fn send(&mut self, message: &ServerMessage<'_>) -> Result<(), axum::Error> {
+        let text = serde_json::to_string(message).map_err(axum::Error::new)?;
+        self.socket.send(Message::Text(text)).await
+    }
+
+    // Sends the notifications matching the subscription
+    async fn send_all(
+        &mut self,
+        notifications: &[Arc<Notification>],
+    ) -> Result<(), axum::Error> {
+        for notification in notifications {
+            if !self.subscription.matches(&notification.event) {
+                continue;
+            }
+            let cursor = notification.cursor;
+            let message = match &notification.event {
+                Event::Log(log) => ServerMessage::Log { cursor, log },
+                Event::Request(event) => ServerMessage::Request { cursor, event },
+            };
+            self.send(&message).await?;
+        }
+        Ok(())
+    }
+
+    // Waits for the receipts of `receipts` through the shared receipt poller
+    fn watch(&self, receipts: &HashMap<OperationHash, String>, sender: ReceiptSender) {
+        for operation_hash in receipts.keys() {
+            self.receipt_poller
+                .watch(operation_hash.clone(), sender.clone());
+        }
+    }
+
+    // Sends a receipt that became available, which is then unsubscribed from
+    async fn send_receipt(
+        &mut self,
+        operation_hash: OperationHash,
+        receipt: &Receipt,
+    ) -> Result<(), axum::Error> {
+        let Some(hash) = self.subscription.receipts.remove(&operation_hash) else {
+            return Ok(());
+        };
+        if self.subscription.receipts.is_empty() {
+            self.receipt_receiver = None;
+        }
+        self.send(&ServerMessage::Receipt { hash, receipt }).await
+    }
+}
+
+// Parses the hex encoded hashes of the operations whose receipt is subscribed to
+fn parse_receipts(
+    receipts: Vec<String>,
+) -> Result<HashMap<OperationHash, String>, String> {
+    if receipts.len() > MAX_RECEIPTS {
+        return Err(format!(
+            "At most {} receipts can be subscribed to",
+            MAX_RECEIPTS
+        ));
+    }
+    receipts
+        .into_iter()
+        .map(|hash| match OperationHash::try_parse(hash.clone()) {
+            Ok(parsed) => Ok((parsed, hash)),
+            Err(_) => Err(format!("Invalid operation hash '{}'", hash)),
+        })
+        .collect()
+}
+
+// Waits for the next notification, forever if the client is not subscribed
+async fn next(
+    receiver: &mut Option<broadcast::Receiver<Arc<Notification>>>,
+) -> Result<Arc<Notification>, RecvError> {
+    match receiver {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Waits for the next receipt, forever if the client waits for none
+async fn next_receipt(
+    receiver: &mut Option<mpsc::UnboundedReceiver<(OperationHash, Arc<Receipt>)>>,
+) -> Option<(OperationHash, Arc<Receipt>)> {
+    match receiver {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+impl Service for WebSocketService {
+    fn router_with_openapi() -> OpenApiRouter<AppState> {
+        OpenApiRouter::new().routes(routes!(websocket))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use jstz_api::js_log::LogLevel;
+    use jstz_crypto::public_key_hash::PublicKeyHash;
+    use jstz_proto::{js_logger::LogRecord, request_logger::RequestEvent};
+
+    use super::{ClientMessage, Event, Subscription};
+
+    fn address() -> PublicKeyHash {
+        PublicKeyHash::from_base58("tz1cD5CuvAALcxgypqBXcBQEA8dkLJivoFjU").unwrap()
+    }
+
+    fn log(level: LogLevel) -> Event {
+        Event::Log(LogRecord {
+            address: address(),
+            request_id: "1".to_string(),
+            level,
+            text: "hello".to_string(),
+        })
+    }
+
+    #[test]
+    fn parses_subscriptions() {
+        let message: ClientMessage = serde_json::from_str(
+            r#"{"type":"subscribe","addresses":["tz1cD5CuvAALcxgypqBXcBQEA8dkLJivoFjU"],"levels":["ERROR"],"cursor":42}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            message,
+            ClientMessage::Subscribe {
+                ref addresses,
+                levels: Some(_),
+                events: false,
+                cursor: Some(42),
+                ..
+            } if addresses == &vec![address()]
+        ));
+    }
+
+    #[test]
+    fn filters_notifications() {
+        let mut subscription = Subscription {
+            addresses: [address()].into_iter().collect(),
+            levels: Some(vec![LogLevel::ERROR]),
+            ..Subscription::default()
+        };
+        let request = Event::Request(RequestEvent::Start {
+            address: address(),
+            request_id: "1".to_string(),
+            caller: None,
+        });
+
+        assert!(subscription.matches(&log(LogLevel::ERROR)));
+        assert!(!subscription.matches(&log(LogLevel::INFO)));
+        assert!(!subscription.matches(&request));
+
+        subscription.events = true;
+        subscription.levels = None;
+        assert!(subscription.matches(&log(LogLevel::INFO)));
+        assert!(subscription.matches(&request));
+
+        subscription.addresses.clear();
+        assert!(!subscription.matches(&log(LogLevel::ERROR)));
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    hash::Hash,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use axum::http::{header, HeaderMap, HeaderValue};
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use log::warn;
+use octez::OctezRollupClient;
+use parking_lot::Mutex;
+
+pub const DEFAULT_HEAD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Number of cached entries beyond which the least recently used are evicted
+const MAX_ENTRIES: usize = 10_000;
+
+/// A map evicting its least recently used entries beyond `capacity` entries
+struct Lru<K, V> {
+    capacity: usize,
+    // Values with the time of their last use
+    entries: HashMap<K, (V, u64)>,
+    // Keys by time of last use
+    uses: BTreeMap<u64, K>,
+    clock: u64,
+}
+
+impl<K: Clone + Eq + Hash, V> Lru<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            uses: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let (_, used_at) = self.entries.get_mut(key)?;
+        self.uses.remove(used_at);
+        self.clock += 1;
+        *used_at = self.clock;
+        self.uses.insert(self.clock, key.clone());
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        self.clock += 1;
+        if let Some((_, used_at)) = self.entries.insert(key.clone(), (value, self.clock))
+        {
+            self.uses.remove(&used_at);
+        }
+        self.uses.insert(self.clock, key);
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.uses.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+type Entry = (u32, String);
+
+// A read from the rollup node, shared by the concurrent requests for an entry
+type Fetch<T> = Shared<BoxFuture<'static, std::result::Result<T, String>>>;
+
+struct Entries<T> {
+    // Entries of finalized levels, which never change
+    finalized: Lru<Entry, T>,
+    in_flight: HashMap<Entry, Fetch<T>>,
+}
+
+impl<T> Default for Entries<T> {
+    fn default() -> Self {
+        Self {
+            finalized: Lru::new(MAX_ENTRIES),
+            in_flight: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    // Level of the head of the rollup node, `None` until it is first read
+    head: Option<u32>,
+    // Level of the last finalized block of the rollup node
+    finalized: Option<u32>,
+    values: Entries<Option<Vec<u8>>>,
+    subkeys: Entries<Option<Vec<String>>>,
+}
+
+impl State {
+    fn set_head(&mut self, head: u32, finalized: u32) {
+        self.head = Some(head);
+        self.finalized = Some(finalized);
+    }
+
+    // Whether the state at `level` can no longer be reorganised
+    fn is_finalized(&self, level: u32) -> bool {
+        self.finalized.is_some_and(|finalized| level <= finalized)
+    }
+}
+
+/// Caches the durable storage of the rollup read by level. Only the state of
+/// finalized levels is kept, since the blocks of more recent levels may still be
+/// reorganised. Concurrent reads of the same entry share a single request to the
+/// rollup node.
+#[derive(Clone)]
+pub struct StateCache {
+    rollup_client: OctezRollupClient,
+    state: Arc<Mutex<State>>,
+}
+
+impl StateCache {
+    /// Creates the cache and spawns the task following the head of the rollup
+    /// node every `poll_interval`.
+    pub fn spawn(rollup_client: OctezRollupClient, poll_interval: Duration) -> Self {
+        let cache = Self {
+            rollup_client,
+            state: Arc::default(),
+        };
+        tokio::spawn(cache.clone().run(poll_interval));
+        cache
+    }
+
+    async fn run(self, poll_interval: Duration) {
+        let mut interval = tokio::time::interval(poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.sync_head().await {
+                warn!("Failed to read the head of the rollup node: {:?}", err);
+            }
+        }
+    }
+
+    async fn sync_head(&self) -> Result<u32> {
+        let head = self.rollup_client.get_head_level().await?;
+        let finalized = self.rollup_client.get_finalized_level().await?;
+        self.state.lock().set_head(head, finalized);
+        Ok(head)
+    }
+
+    /// Returns the level of the head of the rollup node
+    pub async fn head(&self) -> Result<u32> {
+        let head = self.state.lock().head;
+        match head {
+            Some(head) => Ok(head),
+            None => self.sync_head().await,
+        }
+    }
+
+    /// Returns the value of `key` at `level`
+    pub async fn get_value(&self, key: &str, level: u32) -> Result<Option<Vec<u8>>> {
+        self.get(
+            |state| &mut state.values,
+            key,
+            level,
+            |rollup_client, key, level| async move {
+                rollup_client.get_value_at(&key, &level.to_string()).await
+            },
+        )
+        .await
+    }
+
+    /// Returns the subkeys of `key` at `level`
+    pub async fn get_subkeys(
+        &self,
+        key: &str,
+        level: u32,
+    ) -> Result<Option<Vec<String>>> {
+        self.get(
+            |state| &mut state.subkeys,
+            key,
+            level,
+            |rollup_client, key, level| async move {
+                rollup_client.get_subkeys_at(&key, &level.to_string()).await
+            },
+        )
+        .await
+    }
+
+    // Returns the entry `key` at `level` of `entries`, reading it with `read`
+    // unless it is cached or already being read
+    async fn get<T, R, F>(
+        &self,
+        entries: fn(&mut State) -> &mut Entries<T>,
+        key: &str,
+        level: u32,
+        read: R,
+    ) -> Result<T>
+    where
+        T: Clone + Send + Sync + 'static,
+        R: FnOnce(OctezRollupClient, String, u32) -> F,
+        F: Future<Output = Result<T>> + Send + 'static,
+    {
+        let entry = (level, key.to_string());
+        let fetch = {
+            let mut state = self.state.lock();
+            let entries = entries(&mut state);
+            if let Some(value) = entries.finalized.get(&entry) {
+                return Ok(value.clone());
+            }
+            entries
+                .in_flight
+                .entry(entry.clone())
+                .or_insert_with(|| {
+                    read(self.rollup_client.clone(), key.to_string(), level)
+                        .map(|result| result.map_err(|err| format!("{:#}", err)))
+                        .boxed()
+                        .shared()
+                })
+                .clone()
+        };
+        let result = fetch.clone().await;
+
+        let mut state = self.state.lock();
+        let is_finalized = state.is_finalized(level);
+        let entries = entries(&mut state);
+        if entries
+            .in_flight
+            .get(&entry)
+            .is_some_and(|in_flight| in_flight.ptr_eq(&fetch))
+        {
+            entries.in_flight.remove(&entry);
+        }
+        let value = result.map_err(|err| anyhow!(err))?;
+        if is_finalized {
+            entries.finalized.insert(entry, value.clone());
+        }
+        Ok(value)
+    }
+}
+
+/// ETag of the state at `level`
+pub fn etag(level: u32) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"{}\"", level)).expect("Invalid ETag")
+}
+
+/// Whether the `If-None-Match` header of a request matches the state at `level`
+pub fn is_not_modified(headers: &HeaderMap, level: u32) -> bool {
+    let etag = etag(level);
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || etag == tag.trim_start_matches("W/"))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::{header, HeaderMap, HeaderValue};
+
+    use super::{is_not_modified, Lru, State};
+
+    #[test]
+    fn caches_finalized_levels_only() {
+        let mut state = State::default();
+        assert!(!state.is_finalized(1));
+
+        state.set_head(5, 3);
+        assert!(state.is_finalized(3));
+        assert!(!state.is_finalized(4));
+        assert_eq!(state.head, Some(5));
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries() {
+        let mut lru = Lru::new(2);
+        lru.insert(1, "a");
+        lru.insert(2, "b");
+        assert_eq!(lru.get(&1), Some(&"a"));
+
+        lru.insert(3, "c");
+        assert_eq!(lru.entries.len(), 2);
+        assert_eq!(lru.get(&2), None);
+        assert_eq!(lru.get(&1), Some(&"a"));
+        assert_eq!(lru.get(&3), Some(&"c"));
+
+        // Replacing an entry does not evict another
+        lru.insert(3, "d");
+        assert_eq!(lru.entries.len(), 2);
+        assert_eq!(lru.get(&3), Some(&"d"));
+    }
+
+    #[test]
+    fn matches_etags_of_level() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_not_modified(&headers, 3));
+
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"2\", W/\"3\""),
+        );
+        assert!(is_not_modified(&headers, 3));
+        assert!(is_not_modified(&headers, 2));
+        assert!(!is_not_modified(&headers, 4));
+
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(is_not_modified(&headers, 4));
+    }
+}
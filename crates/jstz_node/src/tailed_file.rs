@@ -1,24 +1,275 @@
-use std::{io::SeekFrom, path::Path};
+use std::{
+    io::SeekFrom,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use serde::{Deserialize, Serialize};
 use tokio::{
-    fs::File,
-    io::Lines,
-    io::{AsyncSeekExt, BufReader, Result},
+    fs::{self, File},
+    io::{AsyncBufReadExt, AsyncSeekExt, BufReader, Result},
 };
 
-pub struct TailedFile(BufReader<File>);
+/// Position of a line in a tailed file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilePosition {
+    /// Inode of the file, which changes when the file is rotated
+    pub inode: u64,
+    /// Generation of the file, which changes each time the file is rotated or
+    /// truncated. Positions of lines written after a truncation, or to a file
+    /// reusing the inode of a rotated file, differ from earlier positions.
+    #[serde(default)]
+    pub generation: u64,
+    /// Byte offset of the line in the file
+    pub offset: u64,
+}
 
-pub use tokio::io::AsyncBufReadExt;
+/// Follows the lines appended to a file. The file is followed across rotations
+/// and truncations, and the position of the last line read can be persisted to
+/// resume from it after a restart.
+pub struct TailedFile {
+    path: PathBuf,
+    reader: BufReader<File>,
+    // Position of the next line
+    position: FilePosition,
+    // Bytes of the incomplete line being written at the end of the file
+    partial: Vec<u8>,
+    // File the position is persisted to
+    checkpoint: Option<PathBuf>,
+}
 
 impl TailedFile {
-    pub async fn init(path: &Path) -> Result<Self> {
+    /// Opens the file at `path`. Lines are read from the position persisted in
+    /// `checkpoint` if it refers to the current file, from the start of the
+    /// file if it was rotated or truncated since, and from the end of the file
+    /// otherwise.
+    pub async fn init(path: &Path, checkpoint: Option<&Path>) -> Result<Self> {
         let file = File::open(path).await?;
+        let metadata = file.metadata().await?;
+        let saved = match checkpoint {
+            Some(checkpoint) => load_position(checkpoint).await,
+            None => None,
+        };
+        let (generation, offset) = match saved {
+            Some(saved)
+                if saved.inode == metadata.ino() && saved.offset <= metadata.len() =>
+            {
+                (saved.generation, saved.offset)
+            }
+            Some(saved) => (next_generation(saved.generation), 0),
+            None => (next_generation(0), metadata.len()),
+        };
+        let mut reader = BufReader::new(file);
+        reader.seek(SeekFrom::Start(offset)).await?;
+        Ok(TailedFile {
+            path: path.to_path_buf(),
+            reader,
+            position: FilePosition {
+                inode: metadata.ino(),
+                generation,
+                offset,
+            },
+            partial: Vec::new(),
+            checkpoint: checkpoint.map(Path::to_path_buf),
+        })
+    }
+
+    /// Position of the next line to read
+    pub fn position(&self) -> FilePosition {
+        self.position
+    }
+
+    /// Reads the next complete line, without its line ending, with its
+    /// position. Returns `None` if no complete line was appended to the file.
+    pub async fn next_line(&mut self) -> Result<Option<(FilePosition, String)>> {
+        self.reader.read_until(b'\n', &mut self.partial).await?;
+        if self.partial.last() == Some(&b'\n') {
+            let position = self.position;
+            self.position.offset += self.partial.len() as u64;
+            let line = String::from_utf8_lossy(&self.partial)
+                .trim_end_matches(['\n', '\r'])
+                .to_string();
+            self.partial.clear();
+            return Ok(Some((position, line)));
+        }
+
+        // At the end of the file, check whether it was rotated or truncated
+        let metadata = match fs::metadata(&self.path).await {
+            Ok(metadata) => metadata,
+            // The file is being rotated
+            Err(_) => return Ok(None),
+        };
+        let read = self.position.offset + self.partial.len() as u64;
+        if metadata.ino() != self.position.inode {
+            log::info!("{} was rotated", self.path.display());
+            self.reopen(File::open(&self.path).await?, metadata.ino())
+                .await?;
+        } else if metadata.len() < read {
+            log::info!("{} was truncated", self.path.display());
+            let file = self.reader.get_ref().try_clone().await?;
+            self.reopen(file, metadata.ino()).await?;
+        }
+        Ok(None)
+    }
+
+    async fn reopen(&mut self, file: File, inode: u64) -> Result<()> {
         let mut reader = BufReader::new(file);
-        let _ = reader.seek(SeekFrom::End(0)).await?;
-        Ok(TailedFile(reader))
+        reader.seek(SeekFrom::Start(0)).await?;
+        self.reader = reader;
+        self.position = FilePosition {
+            inode,
+            generation: next_generation(self.position.generation),
+            offset: 0,
+        };
+        self.partial.clear();
+        Ok(())
     }
 
-    pub fn lines(self) -> Lines<BufReader<File>> {
-        self.0.lines()
+    /// Persists the position of the next line to the checkpoint file, if any
+    pub async fn save_position(&self) -> Result<()> {
+        let Some(checkpoint) = &self.checkpoint else {
+            return Ok(());
+        };
+        if let Some(parent) = checkpoint.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let position = serde_json::to_vec(&self.position)?;
+        // Write then rename so that the checkpoint is never half written
+        let tmp = checkpoint.with_extension("tmp");
+        fs::write(&tmp, position).await?;
+        fs::rename(tmp, checkpoint).await
+    }
+}
+
+// Generation following `generation`. Generations are derived from the clock so
+// that they also differ from the generations of earlier runs.
+fn next_generation(generation: u64) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default();
+    now.max(generation + 1)
+}
+
+async fn load_position(checkpoint: &Path) -> Option<FilePosition> {
+    let content = fs::read(checkpoint).await.ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs::OpenOptions, io::Write, path::Path};
+
+    use tempfile::TempDir;
+
+    use super::{FilePosition, TailedFile};
+
+    fn append(path: &Path, content: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    async fn next_line(file: &mut TailedFile) -> Option<String> {
+        file.next_line().await.unwrap().map(|(_, line)| line)
+    }
+
+    #[tokio::test]
+    async fn reads_appended_lines() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("kernel.log");
+        append(&path, "before\n");
+
+        let mut file = TailedFile::init(&path, None).await.unwrap();
+        assert_eq!(next_line(&mut file).await, None);
+
+        append(&path, "first\nsec");
+        assert_eq!(next_line(&mut file).await, Some("first".to_string()));
+        // Incomplete lines are read once complete
+        assert_eq!(next_line(&mut file).await, None);
+        append(&path, "ond\n");
+        let (position, line) = file.next_line().await.unwrap().unwrap();
+        assert_eq!(line, "second");
+        assert_eq!(position.offset, 13);
+        assert_eq!(file.position().offset, 20);
+    }
+
+    #[tokio::test]
+    async fn follows_truncation() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("kernel.log");
+        append(&path, "");
+
+        let mut file = TailedFile::init(&path, None).await.unwrap();
+        append(&path, "first\n");
+        let (first, line) = file.next_line().await.unwrap().unwrap();
+        assert_eq!(line, "first");
+
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(next_line(&mut file).await, None);
+        append(&path, "second\n");
+        let (second, line) = file.next_line().await.unwrap().unwrap();
+        assert_eq!(line, "second");
+        assert_eq!(file.position().offset, 7);
+
+        // Lines written at the same offset before and after the truncation
+        // have different positions
+        assert_eq!((first.inode, first.offset), (second.inode, second.offset));
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    async fn follows_rotation() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("kernel.log");
+        append(&path, "");
+
+        let mut file = TailedFile::init(&path, None).await.unwrap();
+        let inode = file.position().inode;
+        append(&path, "first\n");
+        std::fs::rename(&path, dir.path().join("kernel.log.1")).unwrap();
+        append(&path, "second\n");
+
+        // Lines written before the rotation are read first
+        assert_eq!(next_line(&mut file).await, Some("first".to_string()));
+        assert_eq!(next_line(&mut file).await, None);
+        assert_eq!(next_line(&mut file).await, Some("second".to_string()));
+        assert_ne!(file.position().inode, inode);
+    }
+
+    #[tokio::test]
+    async fn resumes_from_checkpoint() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("kernel.log");
+        let checkpoint = dir.path().join("kernel.log.position");
+        append(&path, "");
+
+        let mut file = TailedFile::init(&path, Some(&checkpoint)).await.unwrap();
+        append(&path, "first\n");
+        assert_eq!(next_line(&mut file).await, Some("first".to_string()));
+        file.save_position().await.unwrap();
+        drop(file);
+
+        append(&path, "second\n");
+        let mut file = TailedFile::init(&path, Some(&checkpoint)).await.unwrap();
+        assert_eq!(next_line(&mut file).await, Some("second".to_string()));
+
+        // A checkpoint of another file is ignored
+        std::fs::write(
+            &checkpoint,
+            serde_json::to_vec(&FilePosition {
+                inode: file.position().inode + 1,
+                generation: file.position().generation,
+                offset: 6,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        let mut file = TailedFile::init(&path, Some(&checkpoint)).await.unwrap();
+        assert_eq!(next_line(&mut file).await, Some("first".to_string()));
     }
 }
@@ -0,0 +1,506 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use octez::{r#async::client::OctezClient, OctezRollupClient, OutboxProof};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, time::Instant};
+use utoipa::ToSchema;
+
+use crate::config::OutboxExecutorConfig;
+
+/// Default interval between two checks of the cemented level
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// File of the data directory the progress of the outbox executor is persisted to
+pub const OUTBOX_EXECUTOR_STATE_FILE: &str = "outbox_executor.json";
+
+// Number of times the execution of an outbox message is attempted before
+// leaving it to be executed manually. With the retry delays below, failures
+// are retried for about an hour.
+const MAX_EXECUTION_ATTEMPTS: u32 = 12;
+
+// Delay before retrying a failed execution, doubled after each failed attempt
+// up to `MAX_RETRY_DELAY`
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(2);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(600);
+
+// Maximum storage fees paid for the execution of an outbox message
+const EXECUTION_BURN_CAP: f64 = 1.0;
+
+/// Position of a message in the rollup outbox
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub struct OutboxLocation {
+    /// Level at which the message was written to the outbox
+    pub level: u32,
+    /// Index of the message in the outbox of `level`
+    pub index: u32,
+}
+
+/// Progress of a withdrawal towards the L1
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "status")]
+pub enum WithdrawalStatus {
+    /// The withdrawal is waiting to be written to the outbox at the end of the
+    /// level
+    Pending,
+    /// The withdrawal is in the outbox, waiting for the commitment of its level
+    /// to be cemented
+    Queued { level: u32, index: u32 },
+    /// The commitment of the outbox message is cemented, the message can be
+    /// executed on the L1 with `proof`
+    Executable {
+        level: u32,
+        index: u32,
+        commitment: String,
+        proof: String,
+    },
+    /// The outbox message was executed on the L1 by the node
+    Executed {
+        level: u32,
+        index: u32,
+        operation_hash: String,
+    },
+    /// The node gave up on executing the outbox message after repeated
+    /// failures with `error`. The message can still be executed on the L1 with
+    /// `proof`.
+    Failed {
+        level: u32,
+        index: u32,
+        commitment: String,
+        proof: String,
+        error: String,
+    },
+}
+
+/// Reads the outbox location of the withdrawal `outbox_message_id`. Returns
+/// `None` if the withdrawal has not been written to the outbox yet.
+pub(crate) async fn read_withdrawal_location(
+    rollup_client: &OctezRollupClient,
+    outbox_message_id: u64,
+) -> Result<Option<OutboxLocation>> {
+    let key = format!("/outbox/withdrawals/locations/{}", outbox_message_id);
+    rollup_client
+        .get_value(&key)
+        .await?
+        .map(|value| {
+            bincode::deserialize::<OutboxLocation>(&value)
+                .map_err(|_| anyhow!("Failed to deserialize outbox location"))
+        })
+        .transpose()
+}
+
+/// Reports the progress of the withdrawal `outbox_message_id`
+pub(crate) async fn withdrawal_status(
+    rollup_client: &OctezRollupClient,
+    outbox_executor: Option<&OutboxExecutor>,
+    outbox_message_id: u64,
+) -> Result<WithdrawalStatus> {
+    let Some(OutboxLocation { level, index }) =
+        read_withdrawal_location(rollup_client, outbox_message_id).await?
+    else {
+        return Ok(WithdrawalStatus::Pending);
+    };
+    let executed = outbox_executor
+        .and_then(|executor| executor.operation_hash(&OutboxLocation { level, index }));
+    if let Some(operation_hash) = executed {
+        return Ok(WithdrawalStatus::Executed {
+            level,
+            index,
+            operation_hash,
+        });
+    }
+    let failure = outbox_executor
+        .and_then(|executor| executor.failure(&OutboxLocation { level, index }));
+    if let Some(error) = failure {
+        let OutboxProof { commitment, proof } =
+            rollup_client.get_outbox_proof(level, index).await?;
+        return Ok(WithdrawalStatus::Failed {
+            level,
+            index,
+            commitment,
+            proof,
+            error,
+        });
+    }
+    match rollup_client.get_cemented_level().await? {
+        Some(cemented_level) if level <= cemented_level => {
+            let OutboxProof { commitment, proof } =
+                rollup_client.get_outbox_proof(level, index).await?;
+            Ok(WithdrawalStatus::Executable {
+                level,
+                index,
+                commitment,
+                proof,
+            })
+        }
+        _ => Ok(WithdrawalStatus::Queued { level, index }),
+    }
+}
+
+/// Progress of the outbox executor, persisted so that withdrawals are not
+/// executed again after a restart
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct ExecutorState {
+    /// Outbox message id of the next withdrawal to execute
+    next_id: u64,
+    /// Outbox messages executed by the node, with the hashes of the executing
+    /// L1 operations
+    executed: Vec<(OutboxLocation, String)>,
+    /// Outbox messages the node gave up on executing, with the error of the
+    /// last attempt
+    #[serde(default)]
+    failed: Vec<(OutboxLocation, String)>,
+}
+
+impl ExecutorState {
+    fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(content) => Ok(serde_json::from_slice(&content)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        // Write then rename so that the state is never half written
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?).await?;
+        fs::rename(tmp, path).await?;
+        Ok(())
+    }
+}
+
+/// Executes the outbox messages of withdrawals on the L1 once the commitment
+/// of their level is cemented. Withdrawals are executed in the order of their
+/// outbox message ids.
+#[derive(Clone)]
+pub struct OutboxExecutor {
+    executed: Arc<RwLock<HashMap<OutboxLocation, String>>>,
+    failed: Arc<RwLock<HashMap<OutboxLocation, String>>>,
+}
+
+impl OutboxExecutor {
+    /// Spawns the task checking for cemented withdrawals every `poll_interval`.
+    /// The progress of the executor is persisted to `state_path`, and resumed
+    /// from it.
+    pub fn spawn(
+        rollup_client: OctezRollupClient,
+        config: OutboxExecutorConfig,
+        poll_interval: Duration,
+        state_path: PathBuf,
+    ) -> Result<Self> {
+        let ExecutorState {
+            next_id,
+            executed,
+            failed,
+        } = ExecutorState::load(&state_path)?;
+        let executed = Arc::new(RwLock::new(executed.into_iter().collect()));
+        let failed = Arc::new(RwLock::new(failed.into_iter().collect()));
+        let task = ExecutorTask {
+            rollup_client,
+            octez_client: OctezClient::new(config.octez_client),
+            executor: config.executor,
+            executed: executed.clone(),
+            failed: failed.clone(),
+            state_path,
+            next_id,
+            attempts: Attempts::default(),
+        };
+        tokio::spawn(task.run(poll_interval));
+        Ok(Self { executed, failed })
+    }
+
+    /// Hash of the L1 operation executing the outbox message at `location`, if
+    /// it was executed by the node
+    pub fn operation_hash(&self, location: &OutboxLocation) -> Option<String> {
+        self.executed.read().get(location).cloned()
+    }
+
+    /// Error of the last attempt at executing the outbox message at
+    /// `location`, if the node gave up on executing it
+    pub fn failure(&self, location: &OutboxLocation) -> Option<String> {
+        self.failed.read().get(location).cloned()
+    }
+}
+
+/// Failed attempts at executing an outbox message
+#[derive(Debug, Default)]
+struct Attempts {
+    count: u32,
+    // Instant before which the failed execution is not retried
+    retry_at: Option<Instant>,
+}
+
+impl Attempts {
+    fn is_delayed(&self) -> bool {
+        self.retry_at
+            .is_some_and(|retry_at| Instant::now() < retry_at)
+    }
+
+    // Records a failed attempt, delaying the next one. Returns `false` once
+    // `MAX_EXECUTION_ATTEMPTS` attempts failed, in which case the execution is
+    // given up on.
+    fn fail(&mut self) -> bool {
+        self.count += 1;
+        if self.count < MAX_EXECUTION_ATTEMPTS {
+            self.retry_at = Some(Instant::now() + retry_delay(self.count));
+            return true;
+        }
+        false
+    }
+}
+
+struct ExecutorTask {
+    rollup_client: OctezRollupClient,
+    octez_client: OctezClient,
+    executor: String,
+    executed: Arc<RwLock<HashMap<OutboxLocation, String>>>,
+    failed: Arc<RwLock<HashMap<OutboxLocation, String>>>,
+    state_path: PathBuf,
+    // Outbox message id of the next withdrawal to execute
+    next_id: u64,
+    // Failed attempts at executing the withdrawal `next_id`
+    attempts: Attempts,
+}
+
+impl ExecutorTask {
+    async fn run(mut self, poll_interval: Duration) {
+        let mut interval = tokio::time::interval(poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.execute_cemented().await {
+                warn!("Failed to execute cemented withdrawals: {:?}", err);
+            }
+        }
+    }
+
+    async fn execute_cemented(&mut self) -> Result<()> {
+        if self.attempts.is_delayed() {
+            return Ok(());
+        }
+        let Some(cemented_level) = self.rollup_client.get_cemented_level().await? else {
+            return Ok(());
+        };
+        let next_id = self.next_id;
+        let result = self.execute_up_to(cemented_level).await;
+        if self.next_id != next_id {
+            if let Err(err) = self.save_state().await {
+                warn!("Failed to save the outbox executor state: {:?}", err);
+            }
+        }
+        result
+    }
+
+    async fn execute_up_to(&mut self, cemented_level: u32) -> Result<()> {
+        while let Some(location) =
+            read_withdrawal_location(&self.rollup_client, self.next_id).await?
+        {
+            if location.level > cemented_level {
+                break;
+            }
+            // Withdrawals batched into the same outbox message share its location
+            if self.operation_hash(&location).is_none()
+                && !self.failed.read().contains_key(&location)
+            {
+                match self.execute(&location).await {
+                    Ok(operation_hash) => {
+                        info!(
+                            "Executed outbox message {} of level {} in {}",
+                            location.index, location.level, operation_hash
+                        );
+                        self.executed.write().insert(location, operation_hash);
+                    }
+                    Err(err) => {
+                        if self.attempts.fail() {
+                            return Err(err);
+                        }
+                        warn!(
+                            "Giving up on executing outbox message {} of level {}: {:?}",
+                            location.index, location.level, err
+                        );
+                        self.failed.write().insert(location, err.to_string());
+                    }
+                }
+            }
+            self.attempts = Attempts::default();
+            self.next_id += 1;
+        }
+        Ok(())
+    }
+
+    fn operation_hash(&self, location: &OutboxLocation) -> Option<String> {
+        self.executed.read().get(location).cloned()
+    }
+
+    async fn save_state(&self) -> Result<()> {
+        let state = ExecutorState {
+            next_id: self.next_id,
+            executed: self
+                .executed
+                .read()
+                .iter()
+                .map(|(location, operation_hash)| (*location, operation_hash.clone()))
+                .collect(),
+            failed: self
+                .failed
+                .read()
+                .iter()
+                .map(|(location, error)| (*location, error.clone()))
+                .collect(),
+        };
+        state.save(&self.state_path).await
+    }
+
+    async fn execute(&self, location: &OutboxLocation) -> Result<String> {
+        let OutboxProof { commitment, proof } = self
+            .rollup_client
+            .get_outbox_proof(location.level, location.index)
+            .await?;
+        let rollup_address = self.rollup_client.get_rollup_address().await?;
+        let (_, operation_hash) = self
+            .octez_client
+            .execute_rollup_outbox_message(
+                rollup_address.hash(),
+                &self.executor,
+                &commitment,
+                &proof,
+                Some(EXECUTION_BURN_CAP),
+            )
+            .await?;
+        Ok(operation_hash.to_base58_check())
+    }
+}
+
+// Delay before retrying an execution that failed `attempts` times
+fn retry_delay(attempts: u32) -> Duration {
+    let factor = 1 << attempts.saturating_sub(1).min(16);
+    INITIAL_RETRY_DELAY
+        .saturating_mul(factor)
+        .min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    use super::{
+        retry_delay, Attempts, ExecutorState, OutboxExecutor, OutboxLocation,
+        WithdrawalStatus, MAX_EXECUTION_ATTEMPTS, MAX_RETRY_DELAY,
+    };
+
+    #[tokio::test]
+    async fn executor_state_round_trips() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("outbox_executor.json");
+        assert_eq!(
+            ExecutorState::load(&path).unwrap(),
+            ExecutorState::default()
+        );
+
+        let state = ExecutorState {
+            next_id: 3,
+            executed: vec![(
+                OutboxLocation {
+                    level: 12,
+                    index: 0,
+                },
+                "oo1".to_string(),
+            )],
+            failed: vec![(
+                OutboxLocation {
+                    level: 13,
+                    index: 1,
+                },
+                "Insufficient balance".to_string(),
+            )],
+        };
+        state.save(&path).await.unwrap();
+        assert_eq!(ExecutorState::load(&path).unwrap(), state);
+    }
+
+    #[test]
+    fn executor_state_loads_without_failed_messages() {
+        let state: ExecutorState =
+            serde_json::from_value(json!({"next_id": 3, "executed": []})).unwrap();
+        assert_eq!(
+            state,
+            ExecutorState {
+                next_id: 3,
+                ..ExecutorState::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn attempts_give_up_after_max_attempts() {
+        let mut attempts = Attempts::default();
+        assert!(!attempts.is_delayed());
+        for _ in 1..MAX_EXECUTION_ATTEMPTS {
+            assert!(attempts.fail());
+            assert!(attempts.is_delayed());
+        }
+        assert!(!attempts.fail());
+    }
+
+    #[test]
+    fn outbox_executor_reports_failed_messages() {
+        let location = OutboxLocation {
+            level: 13,
+            index: 1,
+        };
+        let executor = OutboxExecutor {
+            executed: Default::default(),
+            failed: Default::default(),
+        };
+        assert_eq!(executor.failure(&location), None);
+
+        executor
+            .failed
+            .write()
+            .insert(location, "Insufficient balance".to_string());
+        assert_eq!(
+            executor.failure(&location),
+            Some("Insufficient balance".to_string())
+        );
+        assert_eq!(executor.operation_hash(&location), None);
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_max() {
+        assert_eq!(retry_delay(1), Duration::from_secs(2));
+        assert_eq!(retry_delay(2), Duration::from_secs(4));
+        assert_eq!(retry_delay(5), Duration::from_secs(32));
+        assert_eq!(retry_delay(11), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn withdrawal_status_is_tagged_with_status() {
+        let status = WithdrawalStatus::Executed {
+            level: 12,
+            index: 0,
+            operation_hash: "oo1".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            json!({"status": "Executed", "level": 12, "index": 0, "operation_hash": "oo1"})
+        );
+        assert_eq!(
+            serde_json::to_value(WithdrawalStatus::Pending).unwrap(),
+            json!({"status": "Pending"})
+        );
+    }
+}
@@ -12,15 +12,22 @@ use jstz_core::{
     accessor, host::HostRuntime, kv::Transaction, native::Accessor, runtime,
     value::IntoJs,
 };
+use serde_json::json;
 
 use crate::{
-    context::account::{Account, Address, Amount},
+    context::{
+        account::{Account, Address, Amount},
+        ticket_table::{TicketKey, TicketTable},
+    },
     error::Result,
 };
 
 // Ledger.selfAddress
 // Ledger.balance(pkh)
 // Ledger.transfer(dst, amount)
+// Ledger.ticketBalance(pkh, ticketHash)
+// Ledger.transferTicket(dst, ticketHash, amount)
+// Ledger.tickets(pkh)
 
 #[derive(JsData)]
 struct Ledger {
@@ -59,6 +66,44 @@ impl Ledger {
 
         Ok(())
     }
+
+    fn ticket_balance(
+        rt: &mut impl HostRuntime,
+        tx: &mut Transaction,
+        addr: &Address,
+        ticket_key: &TicketKey,
+    ) -> Result<Amount> {
+        TicketTable::get_balance(rt, tx, addr, ticket_key)
+    }
+
+    /// Transfers tickets atomically: the ticket balances are left untouched
+    /// if the transfer fails.
+    fn transfer_ticket(
+        &self,
+        rt: &mut impl HostRuntime,
+        tx: &mut Transaction,
+        dst: &Address,
+        ticket_key: &TicketKey,
+        amount: Amount,
+    ) -> Result<()> {
+        tx.begin();
+        let result =
+            TicketTable::transfer(rt, tx, &self.address, dst, ticket_key, amount);
+        if result.is_ok() {
+            tx.commit(rt)?;
+        } else {
+            tx.rollback()?;
+        }
+        result
+    }
+
+    fn tickets(
+        rt: &mut impl HostRuntime,
+        tx: &mut Transaction,
+        addr: &Address,
+    ) -> Result<Vec<(TicketKey, Amount)>> {
+        TicketTable::tickets(rt, tx, addr)
+    }
 }
 
 pub struct LedgerApi {
@@ -77,6 +122,18 @@ pub(crate) fn js_value_to_pkh(value: &JsValue) -> Result<Address> {
     Ok(Address::from_base58(&pkh_string)?)
 }
 
+fn js_value_to_ticket_key(value: &JsValue) -> Result<TicketKey> {
+    let ticket_hash = value
+        .as_string()
+        .ok_or_else(|| {
+            JsNativeError::typ()
+                .with_message("Failed to convert js value into rust type `String`")
+        })
+        .map(JsString::to_std_string_escaped)?;
+
+    ticket_hash.parse()
+}
+
 impl Ledger {
     fn try_from_js(value: &JsValue) -> JsResult<GcRefMut<'_, ErasedObject, Self>> {
         value
@@ -134,6 +191,60 @@ impl LedgerApi {
 
         Ok(JsValue::undefined())
     }
+
+    fn ticket_balance(
+        _this: &JsValue,
+        args: &[JsValue],
+        _context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let pkh = js_value_to_pkh(args.get_or_undefined(0))?;
+        let ticket_key = js_value_to_ticket_key(args.get_or_undefined(1))?;
+
+        let balance = runtime::with_js_hrt_and_tx(|hrt, tx| {
+            Ledger::ticket_balance(hrt, tx, &pkh, &ticket_key)
+        })?;
+
+        Ok(balance.into())
+    }
+
+    fn transfer_ticket(
+        this: &JsValue,
+        args: &[JsValue],
+        _context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let ledger = Ledger::try_from_js(this)?;
+        let dst = js_value_to_pkh(args.get_or_undefined(0))?;
+        let ticket_key = js_value_to_ticket_key(args.get_or_undefined(1))?;
+        let amount = args
+            .get_or_undefined(2)
+            .as_number()
+            .ok_or_else(JsNativeError::typ)?;
+
+        runtime::with_js_hrt_and_tx(|hrt, tx| {
+            ledger.transfer_ticket(hrt, tx, &dst, &ticket_key, amount as Amount)
+        })?;
+
+        Ok(JsValue::undefined())
+    }
+
+    fn tickets(
+        _this: &JsValue,
+        args: &[JsValue],
+        context: &mut Context,
+    ) -> JsResult<JsValue> {
+        let pkh = js_value_to_pkh(args.get_or_undefined(0))?;
+
+        let tickets =
+            runtime::with_js_hrt_and_tx(|hrt, tx| Ledger::tickets(hrt, tx, &pkh))?;
+        let tickets: Vec<_> = tickets
+            .into_iter()
+            .map(|(ticket_key, amount)| {
+                json!({ "ticketHash": ticket_key.to_string(), "amount": amount })
+            })
+            .collect();
+
+        JsValue::from_json(&serde_json::Value::Array(tickets), context)
+    }
 }
 
 impl jstz_core::Api for LedgerApi {
@@ -162,6 +273,21 @@ impl jstz_core::Api for LedgerApi {
             js_string!("transfer"),
             3,
         )
+        .function(
+            NativeFunction::from_fn_ptr(Self::ticket_balance),
+            js_string!("ticketBalance"),
+            2,
+        )
+        .function(
+            NativeFunction::from_fn_ptr(Self::transfer_ticket),
+            js_string!("transferTicket"),
+            3,
+        )
+        .function(
+            NativeFunction::from_fn_ptr(Self::tickets),
+            js_string!("tickets"),
+            1,
+        )
         .build();
 
         context
@@ -7,7 +7,7 @@ use boa_engine::{
     Context, JsArgs, JsData, JsError, JsNativeError, JsResult, JsValue, NativeFunction,
 };
 
-use jstz_api::http::request::Request;
+use jstz_api::http::{header::HeadersGuard, request::Request, response::Response};
 use jstz_core::{
     host::HostRuntime, host_defined, kv::Transaction, native::JsNativeObject, runtime,
     value::IntoJs,
@@ -29,6 +29,8 @@ use boa_gc::{empty_trace, Finalize, GcRefMut, Trace};
 pub struct TraceData {
     pub address: Address,
     pub operation_hash: OperationHash,
+    /// Id of the request, unique across the requests of the operation
+    pub request_id: String,
 }
 
 impl Finalize for TraceData {}
@@ -100,7 +102,14 @@ impl SmartFunction {
         operation_hash: OperationHash,
         context: &mut Context,
     ) -> JsResult<JsValue> {
-        // 1. Get address from request
+        // 1. If the request's signal is aborted, reject with its abort reason
+        let signal = request.deref().signal().clone();
+        let reason = signal.deref().reason().clone();
+        if !reason.is_undefined() {
+            return Ok(JsPromise::reject(JsError::from_opaque(reason), context).into());
+        }
+
+        // 2. Get address from request
         let mut request_deref = request.deref_mut();
         match request_deref.url().domain() {
             Some(JSTZ_HOST) => HostScript::run(self_address, &mut request_deref, context),
@@ -110,11 +119,56 @@ impl SmartFunction {
                         JsNativeError::error().with_message("Invalid host"),
                     )
                 })?;
-                // 2. Set the referer of the request to the current smart function address
-                headers::test_and_set_referrer(&request_deref, self_address)?;
+                drop(request_deref);
+
+                // 3. Pass a structured clone of the request to the callee and set its
+                //    referer to the current smart function address
+                let request = Request::structured_clone(request, context)?;
+                headers::test_and_set_referrer(&request.deref(), self_address)?;
+
+                // 4. Load, init and run!
+                let result = Script::load_init_run(
+                    address,
+                    operation_hash,
+                    request.inner(),
+                    context,
+                )?;
+
+                // 5. If the request's signal is aborted before the callee's transaction
+                //    is committed, `Script::run` rolls it back and rejects with the abort
+                //    reason. Once committed, the call resolves with the response.
+                let on_fulfilled = NativeFunction::from_fn_ptr(|_, args, _| {
+                    // The headers of the response are immutable for the caller
+                    let response = args.get_or_undefined(0);
+                    Response::try_from_js(response)?
+                        .headers()
+                        .deref_mut()
+                        .set_guard(HeadersGuard::Immutable);
+
+                    Ok(response.clone())
+                })
+                .to_js_function(context.realm());
+                let on_rejected = NativeFunction::from_copy_closure_with_captures(
+                    |_, args, signal, _| {
+                        let signal = signal.deref();
+                        let reason = if signal.aborted() {
+                            signal.reason().clone()
+                        } else {
+                            args.get_or_undefined(0).clone()
+                        };
+                        Err(JsError::from_opaque(reason))
+                    },
+                    signal,
+                )
+                .to_js_function(context.realm());
+
+                let promise = JsPromise::resolve(result, context).then(
+                    Some(on_fulfilled),
+                    Some(on_rejected),
+                    context,
+                );
 
-                // 3. Load, init and run!
-                Script::load_init_run(address, operation_hash, request.inner(), context)
+                Ok(promise.into())
             }
             None => Err(JsError::from_native(
                 JsNativeError::error().with_message("Invalid host"),
@@ -271,6 +325,7 @@ mod test {
         },
         executor::smart_function::{self, register_web_apis, Script},
         operation::RunFunction,
+        receipt::RunFunctionReceipt,
     };
 
     use super::SmartFunction;
@@ -378,6 +433,7 @@ mod test {
         )
         .expect("Withdrawal expected to succeed");
         tx.commit(host).unwrap();
+        tx.flush_withdrawals(host).unwrap();
 
         let level = host.run_level(|_| {});
         let outbox = host.outbox_at(level);
@@ -491,6 +547,7 @@ mod test {
         .expect("Fa withdraw expected");
 
         tx.commit(host).unwrap();
+        tx.flush_withdrawals(host).unwrap();
 
         let level = host.run_level(|_| {});
         let outbox = host.outbox_at(level);
@@ -518,4 +575,559 @@ mod test {
             error.to_string()
         );
     }
+
+    #[test]
+    fn host_script_l1_call_from_smart_function_succeeds() {
+        let destination = jstz_mock::kt1_account1();
+        let mut jstz_mock_host = JstzMockHost::default();
+        let host = jstz_mock_host.rt();
+
+        // Micheline encoding of `Pair 1 "a"` and `pair int string`
+        let code = format!(
+            r#"
+                export default () => {{
+                    const callRequest = new Request("tezos://jstz/l1-call", {{
+                        method: "POST",
+                        headers: {{
+                            "Content-type": "application/json",
+                        }},
+                        body: JSON.stringify({{
+                            destination: "{destination}",
+                            entrypoint: "settle",
+                            parameters: "07070001010000000161",
+                            parameters_type: "07655b0368",
+                        }}),
+                    }});
+                    return fetch(callRequest);
+                }}
+            "#,
+        );
+        let address = deploy(host, &code);
+
+        let receipt = run_receipt(host, &address);
+        assert_eq!(200, receipt.status_code);
+
+        let level = host.run_level(|_| {});
+        let outbox = host.outbox_at(level);
+        assert_eq!(1, outbox.len());
+    }
+
+    #[test]
+    fn host_script_ticket_transfer_from_smart_function_succeeds() {
+        let receiver = jstz_mock::account1();
+        let ticket_hash = jstz_mock::ticket_hash1();
+        let mut jstz_mock_host = JstzMockHost::default();
+        let host = jstz_mock_host.rt();
+
+        let code = format!(
+            r#"
+                export default () => {{
+                    Ledger.transferTicket("{receiver}", "{ticket_hash}", 40);
+                    const balance = Ledger.ticketBalance(Ledger.selfAddress, "{ticket_hash}");
+                    const tickets = Ledger.tickets("{receiver}");
+                    return new Response(JSON.stringify({{ balance, tickets }}));
+                }}
+            "#,
+        );
+        let address = deploy(host, &code);
+        let mut tx = Transaction::default();
+        tx.begin();
+        TicketTable::add(host, &mut tx, &address, &ticket_hash, 100).unwrap();
+        tx.commit(host).unwrap();
+
+        let body = run(host, &address);
+        assert_eq!(
+            json!({
+                "balance": 60,
+                "tickets": [{ "ticketHash": ticket_hash.to_string(), "amount": 40 }],
+            }),
+            serde_json::from_str::<serde_json::Value>(&body).unwrap()
+        );
+    }
+
+    fn deploy(host: &mut impl jstz_core::host::HostRuntime, code: &str) -> Address {
+        let source = jstz_mock::account1();
+        let parsed_code = ParsedCode::try_from(code.to_string()).unwrap();
+        let mut tx = Transaction::default();
+        tx.begin();
+        let address = Script::deploy(host, &mut tx, &source, parsed_code, 0).unwrap();
+        tx.commit(host).unwrap();
+        address
+    }
+
+    fn run(host: &mut impl jstz_core::host::HostRuntime, address: &Address) -> String {
+        let receipt = run_receipt(host, address);
+        String::from_utf8(receipt.body.unwrap()).unwrap()
+    }
+
+    fn run_receipt(
+        host: &mut impl jstz_core::host::HostRuntime,
+        address: &Address,
+    ) -> RunFunctionReceipt {
+        let mut tx = Transaction::default();
+        tx.begin();
+        let run_function = RunFunction {
+            uri: format!("tezos://{}/", address).try_into().unwrap(),
+            method: Method::GET,
+            headers: HeaderMap::new(),
+            body: None,
+            gas_limit: 100_000,
+        };
+        let fake_op_hash = Blake2b::from(b"fake_op_hash".as_ref());
+        let receipt = smart_function::run::execute(
+            host,
+            &mut tx,
+            &jstz_mock::account1(),
+            run_function,
+            fake_op_hash,
+        )
+        .unwrap();
+        tx.commit(host).unwrap();
+
+        receipt
+    }
+
+    #[test]
+    fn call_with_aborted_signal_rejects() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let callee = deploy(
+            host,
+            r#"
+            export default () => {
+                Kv.set("called", true);
+                return new Response();
+            }
+            "#,
+        );
+        let caller = deploy(
+            host,
+            &format!(
+                r#"
+                export default async () => {{
+                    const controller = new AbortController();
+                    controller.abort();
+                    try {{
+                        await SmartFunction.call(
+                            new Request("tezos://{callee}/", {{ signal: controller.signal }}),
+                        );
+                        return new Response("completed");
+                    }} catch (e) {{
+                        return new Response(e.name);
+                    }}
+                }}
+                "#
+            ),
+        );
+
+        assert_eq!("AbortError", run(host, &caller));
+
+        let mut tx = Transaction::default();
+        assert!(!jstz_api::Kv::new(callee.to_string())
+            .has(host, &mut tx, "called")
+            .unwrap());
+    }
+
+    #[test]
+    fn call_aborted_by_timeout_rolls_back_callee() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let callee = deploy(
+            host,
+            r#"
+            export default async () => {
+                Kv.set("called", true);
+                await new Promise((resolve) => setTimeout(resolve, 100));
+                return new Response();
+            }
+            "#,
+        );
+        let caller = deploy(
+            host,
+            &format!(
+                r#"
+                export default async () => {{
+                    try {{
+                        await SmartFunction.call(
+                            new Request("tezos://{callee}/", {{ signal: AbortSignal.timeout(10) }}),
+                        );
+                        return new Response("completed");
+                    }} catch (e) {{
+                        return new Response(e.name);
+                    }}
+                }}
+                "#
+            ),
+        );
+
+        assert_eq!("TimeoutError", run(host, &caller));
+
+        let mut tx = Transaction::default();
+        assert!(!jstz_api::Kv::new(callee.to_string())
+            .has(host, &mut tx, "called")
+            .unwrap());
+    }
+
+    #[test]
+    fn kv_round_trips_structured_values() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let address = deploy(
+            host,
+            r#"
+            export default () => {
+                const value = {
+                    missing: undefined,
+                    map: new Map([[1, "one"]]),
+                    set: new Set(["a"]),
+                    date: new Date(0),
+                    bytes: new Uint8Array([1, 2, 3]),
+                    big: 10n ** 20n,
+                };
+                value.self = value;
+                Kv.set("value", value);
+
+                const stored = Kv.get("value");
+                const ok =
+                    "missing" in stored &&
+                    stored.missing === undefined &&
+                    stored.map.get(1) === "one" &&
+                    stored.set.has("a") &&
+                    stored.date.getTime() === 0 &&
+                    stored.bytes instanceof Uint8Array &&
+                    stored.bytes[2] === 3 &&
+                    stored.big === 10n ** 20n &&
+                    stored.self === stored;
+                return new Response(String(ok));
+            }
+            "#,
+        );
+
+        assert_eq!("true", run(host, &address));
+    }
+
+    #[test]
+    fn structured_clone_preserves_cycles_and_rejects_functions() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let address = deploy(
+            host,
+            r#"
+            export default () => {
+                const original = { list: [1, , 3] };
+                original.list.push(original);
+                const clone = structuredClone(original);
+
+                let error;
+                try {
+                    structuredClone({ f: () => {} });
+                } catch (e) {
+                    error = e.name;
+                }
+
+                const ok =
+                    clone !== original &&
+                    clone.list[3] === clone &&
+                    !(1 in clone.list) &&
+                    error === "DataCloneError";
+                return new Response(String(ok));
+            }
+            "#,
+        );
+
+        assert_eq!("true", run(host, &address));
+    }
+
+    #[test]
+    fn structured_clone_clones_sparse_arrays_without_their_holes() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let address = deploy(
+            host,
+            r#"
+            export default () => {
+                const sparse = [];
+                sparse[1e6] = "last";
+                const clone = structuredClone(sparse);
+
+                const tooLong = [];
+                tooLong[4e9] = 1;
+                let error;
+                try {
+                    structuredClone(tooLong);
+                } catch (e) {
+                    error = e.name;
+                }
+
+                const ok =
+                    clone.length === 1e6 + 1 &&
+                    clone[1e6] === "last" &&
+                    !(0 in clone) &&
+                    error === "DataCloneError";
+                return new Response(String(ok));
+            }
+            "#,
+        );
+
+        assert_eq!("true", run(host, &address));
+    }
+
+    #[test]
+    fn call_passes_a_clone_of_the_request() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let callee = deploy(
+            host,
+            r#"
+            export default async (request) => {
+                return new Response(await request.text());
+            }
+            "#,
+        );
+        let caller = deploy(
+            host,
+            &format!(
+                r#"
+                export default async () => {{
+                    const request = new Request("tezos://{callee}/", {{
+                        method: "POST",
+                        body: "hello",
+                    }});
+                    const response = await SmartFunction.call(request);
+                    const text = await response.text();
+                    return new Response(
+                        `${{text}} ${{request.bodyUsed}} ${{request.headers.has("Referer")}}`,
+                    );
+                }}
+                "#
+            ),
+        );
+
+        assert_eq!("hello false false", run(host, &caller));
+    }
+
+    #[test]
+    fn call_response_headers_are_immutable() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let callee = deploy(
+            host,
+            r#"
+            export default () => new Response("ok", { headers: { "X-Foo": "foo" } });
+            "#,
+        );
+        let caller = deploy(
+            host,
+            &format!(
+                r#"
+                export default async () => {{
+                    const response = await SmartFunction.call(
+                        new Request("tezos://{callee}/"),
+                    );
+                    try {{
+                        response.headers.set("X-Foo", "bar");
+                        return new Response("mutable");
+                    }} catch (e) {{
+                        return new Response(`${{e.name}} ${{response.headers.get("X-Foo")}}`);
+                    }}
+                }}
+                "#
+            ),
+        );
+
+        assert_eq!("TypeError foo", run(host, &caller));
+    }
+
+    #[test]
+    fn call_response_supports_clone_and_status_text() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let callee = deploy(
+            host,
+            r#"
+            export default () =>
+                new Response("hello", { status: 201, statusText: "Made It" });
+            "#,
+        );
+        let caller = deploy(
+            host,
+            &format!(
+                r#"
+                export default async () => {{
+                    const response = await SmartFunction.call(
+                        new Request("tezos://{callee}/"),
+                    );
+                    const clone = response.clone();
+                    const first = await response.text();
+                    const second = await clone.text();
+                    let reclone;
+                    try {{
+                        response.clone();
+                        reclone = "cloned";
+                    }} catch (e) {{
+                        reclone = e.name;
+                    }}
+                    const error = Response.error();
+                    const json = Response.json({{}}, {{ status: 202 }});
+                    return new Response([
+                        first,
+                        second,
+                        reclone,
+                        response.type,
+                        response.status,
+                        response.statusText,
+                        JSON.stringify(response.url),
+                        response.redirected,
+                        error.type,
+                        error.status,
+                        json.status,
+                        json.headers.get("Content-Type"),
+                    ].join(" "));
+                }}
+                "#
+            ),
+        );
+
+        assert_eq!(
+            r#"hello hello TypeError default 201 Made It "" false error 0 202 application/json"#,
+            run(host, &caller)
+        );
+    }
+
+    #[test]
+    fn run_receipt_records_status_text() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let address = deploy(
+            host,
+            r#"
+            export default () =>
+                new Response(null, { status: 404, statusText: "Nothing Here" });
+            "#,
+        );
+
+        let receipt = run_receipt(host, &address);
+        assert_eq!(404, receipt.status_code);
+        assert_eq!("Nothing Here", receipt.status_text);
+    }
+
+    fn deploy_and_run(
+        host: &mut impl jstz_core::host::HostRuntime,
+        code: &str,
+    ) -> String {
+        let address = deploy(host, code);
+        run(host, &address)
+    }
+
+    #[test]
+    fn abort_signal_is_aborted_once() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let result = deploy_and_run(
+            host,
+            r#"
+            export default () => {
+                const controller = new AbortController();
+                const results = [controller.signal.aborted];
+                controller.signal.throwIfAborted();
+                controller.abort();
+                results.push(controller.signal.aborted);
+                results.push(AbortSignal.abort().aborted);
+                return new Response(results.join(","));
+            }
+            "#,
+        );
+
+        assert_eq!("false,true,true", result);
+    }
+
+    #[test]
+    fn abort_signal_keeps_its_first_reason() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let result = deploy_and_run(
+            host,
+            r#"
+            export default () => {
+                const controller = new AbortController();
+                const results = [String(controller.signal.reason)];
+                controller.abort("first");
+                controller.abort("second");
+                results.push(controller.signal.reason);
+                try {
+                    controller.signal.throwIfAborted();
+                } catch (e) {
+                    results.push(e);
+                }
+                results.push(AbortSignal.abort().reason.name);
+                results.push(AbortSignal.abort(42).reason);
+                const any = AbortSignal.any([new AbortController().signal, controller.signal]);
+                results.push(any.reason);
+                return new Response(results.join(","));
+            }
+            "#,
+        );
+
+        assert_eq!("undefined,first,first,AbortError,42,first", result);
+    }
+
+    #[test]
+    fn abort_listeners_are_called_in_order() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let result = deploy_and_run(
+            host,
+            r#"
+            export default () => {
+                const controller = new AbortController();
+                const order = [];
+                controller.signal.addEventListener("abort", () => order.push("first"));
+                controller.signal.onabort = () => order.push("onabort");
+                controller.signal.addEventListener("abort", (e) => order.push(e.type));
+                // Replacing the handler keeps its position
+                controller.signal.onabort = () => order.push("handler");
+                controller.abort();
+                return new Response(order.join(","));
+            }
+            "#,
+        );
+
+        assert_eq!("first,handler,abort", result);
+    }
+
+    #[test]
+    fn once_listeners_are_called_once() {
+        let mut mock_host = JstzMockHost::default();
+        let host = mock_host.rt();
+
+        let result = deploy_and_run(
+            host,
+            r#"
+            export default () => {
+                const target = new EventTarget();
+                let once = 0;
+                let always = 0;
+                target.addEventListener("ping", () => once++, { once: true });
+                target.addEventListener("ping", () => always++);
+                target.dispatchEvent(new Event("ping"));
+                target.dispatchEvent(new Event("ping"));
+                return new Response(`${once},${always}`);
+            }
+            "#,
+        );
+
+        assert_eq!("1,2", result);
+    }
 }
@@ -19,7 +19,18 @@ pub type Address = PublicKeyHash;
 pub type Amount = u64;
 
 #[derive(
-    Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema,
+    Clone,
+    Copy,
+    Default,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    ToSchema,
 )]
 pub struct Nonce(u64);
 
@@ -65,6 +76,11 @@ impl TryFrom<String> for ParsedCode {
         Ok(Self(code))
     }
 }
+impl AsRef<str> for ParsedCode {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Account {
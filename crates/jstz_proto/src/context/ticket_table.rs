@@ -1,11 +1,17 @@
+use std::{fmt, str::FromStr};
+
 use derive_more::{Display, Error, From};
 use jstz_core::kv::{Entry, Transaction};
 use jstz_crypto::public_key_hash::PublicKeyHash;
+use serde::{Deserialize, Serialize};
+use tezos_crypto_rs::hash::ContractKt1Hash;
 use tezos_smart_rollup::{
     host::Runtime,
-    michelson::ticket::TicketHash,
+    michelson::ticket::{FA2_1Ticket, TicketHash},
     storage::path::{self, OwnedPath, RefPath},
+    types::Contract,
 };
+use utoipa::ToSchema;
 
 use super::account::Amount;
 
@@ -16,15 +22,107 @@ pub enum TicketTableError {
     InsufficientFunds,
     AccountNotFound,
     TicketHashNotFound,
+    InvalidTicketHash,
 }
 
 const TICKET_TABLE_PATH: RefPath = RefPath::assert_from(b"/ticket_table");
+const TICKET_OWNERS_PATH: RefPath = RefPath::assert_from(b"/ticket_owners");
+const TICKET_METADATA_PATH: RefPath = RefPath::assert_from(b"/ticket_metadata");
+
+// Size of a ticket hash, hex encoded
+const TICKET_HASH_HEX_SIZE: usize = 64;
+
+/// Hex encoded hash of a ticket, identifying the ticket in the ticket table.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+pub struct TicketKey(String);
+
+impl fmt::Display for TicketKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&TicketHash> for TicketKey {
+    fn from(ticket_hash: &TicketHash) -> Self {
+        Self(ticket_hash.to_string())
+    }
+}
+
+impl From<&TicketKey> for TicketKey {
+    fn from(ticket_key: &TicketKey) -> Self {
+        ticket_key.clone()
+    }
+}
+
+impl FromStr for TicketKey {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.len() != TICKET_HASH_HEX_SIZE
+            || !s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        {
+            Err(TicketTableError::InvalidTicketHash)?
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+/// Metadata of an FA ticket, recorded on its first deposit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TicketMetadata {
+    /// Address of the L1 contract that minted the ticket
+    #[schema(value_type = String)]
+    pub ticketer: ContractKt1Hash,
+    /// Token id of the ticket contents, as a decimal string
+    pub token_id: String,
+    /// Hex encoded bytes of the ticket contents, if any
+    pub content: Option<String>,
+}
+
+impl TicketMetadata {
+    /// Returns the metadata of `ticket`, or `None` if it was not minted by an
+    /// originated contract.
+    pub fn from_ticket(ticket: &FA2_1Ticket) -> Option<Self> {
+        let ticketer = match &ticket.creator().0 {
+            Contract::Originated(kt1) => kt1.clone(),
+            Contract::Implicit(_) => return None,
+        };
+        let contents = ticket.contents();
+        Some(Self {
+            ticketer,
+            token_id: contents.0 .0 .0.to_string(),
+            content: contents.1 .0.as_ref().map(|bytes| hex::encode(&bytes.0)),
+        })
+    }
+}
+
+/// Balance of a ticket held by an account, along with the ticket metadata if
+/// it was recorded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TicketHolding {
+    pub ticket_hash: TicketKey,
+    pub amount: Amount,
+    pub metadata: Option<TicketMetadata>,
+}
+
+/// Tickets held by an account, in the order they were first received. Only
+/// the tickets received since the index was introduced are recorded: tickets
+/// held before are recorded once the account receives more of them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct OwnedTickets(Vec<TicketKey>);
 
 pub struct TicketTable;
 
 impl TicketTable {
-    fn path(ticket_hash: &TicketHash, owner: &PublicKeyHash) -> Result<OwnedPath> {
-        let ticket_hash_path = OwnedPath::try_from(format!("/{}", ticket_hash))?;
+    fn path(
+        ticket_hash: impl Into<TicketKey>,
+        owner: &PublicKeyHash,
+    ) -> Result<OwnedPath> {
+        let ticket_hash_path = OwnedPath::try_from(format!("/{}", ticket_hash.into()))?;
         let owner_path = OwnedPath::try_from(format!("/{}", owner))?;
 
         Ok(path::concat(
@@ -33,11 +131,46 @@ impl TicketTable {
         )?)
     }
 
+    fn owned_tickets_path(owner: &PublicKeyHash) -> Result<OwnedPath> {
+        let owner_path = OwnedPath::try_from(format!("/{}", owner))?;
+        Ok(path::concat(&TICKET_OWNERS_PATH, &owner_path)?)
+    }
+
+    fn metadata_path(ticket_hash: impl Into<TicketKey>) -> Result<OwnedPath> {
+        let ticket_hash_path = OwnedPath::try_from(format!("/{}", ticket_hash.into()))?;
+        Ok(path::concat(&TICKET_METADATA_PATH, &ticket_hash_path)?)
+    }
+
+    /// Returns the metadata of the ticket `ticket_hash`, if it was recorded.
+    pub fn metadata(
+        rt: &mut impl Runtime,
+        tx: &mut Transaction,
+        ticket_hash: impl Into<TicketKey>,
+    ) -> Result<Option<TicketMetadata>> {
+        let path = Self::metadata_path(ticket_hash)?;
+        Ok(tx.get::<TicketMetadata>(rt, path)?.cloned())
+    }
+
+    /// Records the metadata of the ticket `ticket_hash`, unless it was
+    /// already recorded.
+    pub fn register(
+        rt: &mut impl Runtime,
+        tx: &mut Transaction,
+        ticket_hash: impl Into<TicketKey>,
+        metadata: TicketMetadata,
+    ) -> Result<()> {
+        let path = Self::metadata_path(ticket_hash)?;
+        if let Entry::Vacant(vacant_entry) = tx.entry::<TicketMetadata>(rt, path)? {
+            vacant_entry.insert(metadata);
+        }
+        Ok(())
+    }
+
     pub fn get_balance(
         rt: &mut impl Runtime,
         tx: &mut Transaction,
         owner: &PublicKeyHash,
-        ticket_hash: &TicketHash,
+        ticket_hash: impl Into<TicketKey>,
     ) -> Result<Amount> {
         let path = Self::path(ticket_hash, owner)?;
         let result = tx.get::<Amount>(rt, path)?;
@@ -47,6 +180,50 @@ impl TicketTable {
         }
     }
 
+    /// Returns the tickets held by `owner` along with their balances. Tickets
+    /// held since before the index of owned tickets was introduced are
+    /// omitted until `owner` receives more of them.
+    pub fn tickets(
+        rt: &mut impl Runtime,
+        tx: &mut Transaction,
+        owner: &PublicKeyHash,
+    ) -> Result<Vec<(TicketKey, Amount)>> {
+        let path = Self::owned_tickets_path(owner)?;
+        let ticket_keys = match tx.get::<OwnedTickets>(rt, path)? {
+            Some(owned_tickets) => owned_tickets.0.clone(),
+            None => return Ok(vec![]),
+        };
+        ticket_keys
+            .into_iter()
+            .map(|ticket_key| {
+                let balance = Self::get_balance(rt, tx, owner, &ticket_key)?;
+                Ok((ticket_key, balance))
+            })
+            .collect()
+    }
+
+    // Records in the index of `owner` that it holds (or no longer holds)
+    // the ticket `ticket_key`
+    fn update_owned_tickets(
+        rt: &mut impl Runtime,
+        tx: &mut Transaction,
+        owner: &PublicKeyHash,
+        ticket_key: &TicketKey,
+        held: bool,
+    ) -> Result<()> {
+        let path = Self::owned_tickets_path(owner)?;
+        let owned_tickets = tx.entry::<OwnedTickets>(rt, path)?.or_insert_default();
+        let position = owned_tickets.0.iter().position(|key| key == ticket_key);
+        match (held, position) {
+            (true, None) => owned_tickets.0.push(ticket_key.clone()),
+            (false, Some(position)) => {
+                owned_tickets.0.remove(position);
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
     /// Adds the given `amount` from the ticket balance of `owner`
     /// for the ticket `ticket_hash` and returns the account's new balance.
     /// Creates the account if it doesn't exist. Fails if the addition causes
@@ -55,14 +232,15 @@ impl TicketTable {
         rt: &mut impl Runtime,
         tx: &mut Transaction,
         owner: &PublicKeyHash,
-        ticket_hash: &TicketHash,
+        ticket_hash: impl Into<TicketKey>,
         amount: Amount, // TODO: check if its the correct size
     ) -> Result<Amount> {
-        let path = Self::path(ticket_hash, owner)?;
-        match tx.entry::<Amount>(rt, path)? {
+        let ticket_key = ticket_hash.into();
+        let path = Self::path(&ticket_key, owner)?;
+        let balance = match tx.entry::<Amount>(rt, path)? {
             Entry::Vacant(vacant_entry) => {
                 vacant_entry.insert(amount);
-                Ok(amount)
+                amount
             }
             Entry::Occupied(mut occupied) => {
                 let balance = occupied.get_mut();
@@ -70,9 +248,13 @@ impl TicketTable {
                     .checked_add(amount)
                     .ok_or(crate::error::Error::BalanceOverflow)?;
                 *balance = checked_balance;
-                Ok(checked_balance)
+                checked_balance
             }
+        };
+        if balance > 0 {
+            Self::update_owned_tickets(rt, tx, owner, &ticket_key, true)?;
         }
+        Ok(balance)
     }
 
     /// Subtracts the given `amount` from the ticket balance of `owner`
@@ -82,11 +264,12 @@ impl TicketTable {
         rt: &mut impl Runtime,
         tx: &mut Transaction,
         owner: &PublicKeyHash,
-        ticket_hash: &TicketHash,
+        ticket_hash: impl Into<TicketKey>,
         amount: u64,
     ) -> Result<Amount> {
-        let path = Self::path(ticket_hash, owner)?;
-        match tx.entry::<Amount>(rt, path)? {
+        let ticket_key = ticket_hash.into();
+        let path = Self::path(&ticket_key, owner)?;
+        let balance = match tx.entry::<Amount>(rt, path)? {
             Entry::Vacant(_) => Err(TicketTableError::AccountNotFound)?,
             Entry::Occupied(mut occupied) => {
                 let balance = occupied.get_mut();
@@ -94,15 +277,36 @@ impl TicketTable {
                     return Err(TicketTableError::InsufficientFunds)?;
                 }
                 *balance -= amount;
-                Ok(*balance)
+                *balance
             }
+        };
+        if balance == 0 {
+            Self::update_owned_tickets(rt, tx, owner, &ticket_key, false)?;
         }
+        Ok(balance)
+    }
+
+    /// Transfers `amount` of the ticket `ticket_hash` from `src` to `dst`.
+    /// Fails if `src` has insufficient funds or if the balance of `dst`
+    /// overflows.
+    pub fn transfer(
+        rt: &mut impl Runtime,
+        tx: &mut Transaction,
+        src: &PublicKeyHash,
+        dst: &PublicKeyHash,
+        ticket_hash: impl Into<TicketKey>,
+        amount: Amount,
+    ) -> Result<()> {
+        let ticket_key = ticket_hash.into();
+        Self::sub(rt, tx, src, &ticket_key, amount)?;
+        Self::add(rt, tx, dst, &ticket_key, amount)?;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::TicketTable;
+    use super::{TicketKey, TicketMetadata, TicketTable};
     use jstz_core::kv::Transaction;
     use jstz_mock::host::JstzMockHost;
     use tezos_smart_rollup_mock::MockHost;
@@ -217,4 +421,112 @@ mod test {
             TicketTable::get_balance(&mut rt, &mut tx, &owner, &ticket_hash).unwrap();
         assert_eq!(130, result)
     }
+
+    #[test]
+    fn transfer_tickets_succeeds() {
+        let mut host = JstzMockHost::default();
+        let mut tx = Transaction::default();
+
+        tx.begin();
+        let src = jstz_mock::account1();
+        let dst = jstz_mock::account2();
+        let ticket_hash = jstz_mock::ticket_hash1();
+        TicketTable::add(host.rt(), &mut tx, &src, &ticket_hash, 100).unwrap();
+        TicketTable::transfer(host.rt(), &mut tx, &src, &dst, &ticket_hash, 40).unwrap();
+
+        let src_balance =
+            TicketTable::get_balance(host.rt(), &mut tx, &src, &ticket_hash).unwrap();
+        let dst_balance =
+            TicketTable::get_balance(host.rt(), &mut tx, &dst, &ticket_hash).unwrap();
+        assert_eq!(60, src_balance);
+        assert_eq!(40, dst_balance);
+
+        let err =
+            TicketTable::transfer(host.rt(), &mut tx, &src, &dst, &ticket_hash, 100)
+                .expect_err("Expected error");
+        assert_eq!("InsufficientFunds", err.to_string());
+    }
+
+    #[test]
+    fn tickets_lists_held_tickets() {
+        let mut host = JstzMockHost::default();
+        let mut tx = Transaction::default();
+
+        tx.begin();
+        let owner = jstz_mock::account1();
+        let ticket_key = TicketKey::from(&jstz_mock::ticket_hash1());
+        let other_ticket_key: TicketKey = "ab".repeat(32).parse().unwrap();
+        assert!(TicketTable::tickets(host.rt(), &mut tx, &owner)
+            .unwrap()
+            .is_empty());
+
+        TicketTable::add(host.rt(), &mut tx, &owner, &ticket_key, 100).unwrap();
+        TicketTable::add(host.rt(), &mut tx, &owner, &other_ticket_key, 5).unwrap();
+        TicketTable::add(host.rt(), &mut tx, &owner, &ticket_key, 10).unwrap();
+        tx.commit(host.rt()).unwrap();
+
+        tx.begin();
+        assert_eq!(
+            vec![(ticket_key.clone(), 110), (other_ticket_key.clone(), 5)],
+            TicketTable::tickets(host.rt(), &mut tx, &owner).unwrap()
+        );
+
+        TicketTable::sub(host.rt(), &mut tx, &owner, &ticket_key, 110).unwrap();
+        assert_eq!(
+            vec![(other_ticket_key, 5)],
+            TicketTable::tickets(host.rt(), &mut tx, &owner).unwrap()
+        );
+    }
+
+    #[test]
+    fn ticket_key_rejects_invalid_hashes() {
+        assert!("ab".repeat(32).parse::<TicketKey>().is_ok());
+        assert!("ab".repeat(31).parse::<TicketKey>().is_err());
+        assert!("AB".repeat(32).parse::<TicketKey>().is_err());
+        assert!(format!("{}/b", "a".repeat(62))
+            .parse::<TicketKey>()
+            .is_err());
+    }
+
+    #[test]
+    fn register_keeps_first_metadata() {
+        let mut host = JstzMockHost::default();
+        let mut tx = Transaction::default();
+
+        tx.begin();
+        let ticket = jstz_mock::parse_ticket(
+            jstz_mock::kt1_account1(),
+            1,
+            (0, Some(b"1234".to_vec())),
+        );
+        let ticket_hash = ticket.hash().unwrap();
+        assert_eq!(
+            None,
+            TicketTable::metadata(host.rt(), &mut tx, &ticket_hash).unwrap()
+        );
+
+        let metadata = TicketMetadata::from_ticket(&ticket).unwrap();
+        assert_eq!(
+            TicketMetadata {
+                ticketer: jstz_mock::kt1_account1(),
+                token_id: "0".to_string(),
+                content: Some("31323334".to_string()),
+            },
+            metadata
+        );
+        TicketTable::register(host.rt(), &mut tx, &ticket_hash, metadata.clone())
+            .unwrap();
+        let other_metadata = TicketMetadata {
+            token_id: "1".to_string(),
+            ..metadata.clone()
+        };
+        TicketTable::register(host.rt(), &mut tx, &ticket_hash, other_metadata).unwrap();
+        tx.commit(host.rt()).unwrap();
+
+        tx.begin();
+        assert_eq!(
+            Some(metadata),
+            TicketTable::metadata(host.rt(), &mut tx, &ticket_hash).unwrap()
+        );
+    }
 }
@@ -4,7 +4,7 @@ use tezos_smart_rollup::michelson::ticket::TicketHashError;
 
 use crate::{
     context::ticket_table,
-    executor::{fa_deposit, fa_withdraw},
+    executor::{fa_deposit, fa_withdraw, l1_call},
 };
 
 #[derive(Display, Debug, Error, From)]
@@ -38,9 +38,17 @@ pub enum Error {
     FaWithdrawError {
         source: fa_withdraw::FaWithdrawError,
     },
+    L1CallError {
+        source: l1_call::L1CallError,
+    },
     TicketHashError(TicketHashError),
     TicketAmountTooLarge,
     ZeroAmountNotAllowed,
+    InvalidPreimageHash,
+    InvalidPreimage,
+    RevealDataTooLarge,
+    OperationQueueFull,
+    GasLimitTooLarge,
 }
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -93,6 +101,9 @@ impl From<Error> for JsError {
             Error::FaWithdrawError { source } => JsNativeError::eval()
                 .with_message(format!("FaWithdrawError: {}", source))
                 .into(),
+            Error::L1CallError { source } => JsNativeError::eval()
+                .with_message(format!("L1CallError: {}", source))
+                .into(),
             Error::TicketHashError(inner) => JsNativeError::eval()
                 .with_message(format!("{}", inner))
                 .into(),
@@ -109,6 +120,21 @@ impl From<Error> for JsError {
             Error::ZeroAmountNotAllowed => JsNativeError::eval()
                 .with_message("ZeroAmountNotAllowed")
                 .into(),
+            Error::InvalidPreimageHash => JsNativeError::eval()
+                .with_message("InvalidPreimageHash")
+                .into(),
+            Error::InvalidPreimage => {
+                JsNativeError::eval().with_message("InvalidPreimage").into()
+            }
+            Error::RevealDataTooLarge => JsNativeError::eval()
+                .with_message("RevealDataTooLarge")
+                .into(),
+            Error::OperationQueueFull => JsNativeError::eval()
+                .with_message("OperationQueueFull")
+                .into(),
+            Error::GasLimitTooLarge => JsNativeError::eval()
+                .with_message("GasLimitTooLarge")
+                .into(),
         }
     }
 }
@@ -134,6 +134,9 @@ fn execute_inner(
     tx: &mut Transaction,
     deposit: &FaDeposit,
 ) -> Result<FaDepositReceipt> {
+    if let Some(metadata) = &deposit.ticket_metadata {
+        TicketTable::register(rt, tx, &deposit.ticket_hash, metadata.clone())?;
+    }
     match &deposit.proxy_smart_function {
         None => deposit_to_receiver(
             rt,
@@ -172,7 +175,10 @@ mod test {
     use tezos_smart_rollup_mock::MockHost;
 
     use crate::{
-        context::{account::ParsedCode, ticket_table::TicketTable},
+        context::{
+            account::ParsedCode,
+            ticket_table::{TicketMetadata, TicketTable},
+        },
         executor::fa_deposit::{FaDeposit, FaDepositReceipt},
         receipt::{Receipt, ReceiptContent, ReceiptResult},
     };
@@ -184,6 +190,7 @@ mod test {
             receiver: jstz_mock::account2(),
             proxy_smart_function: proxy,
             ticket_hash: jstz_mock::ticket_hash1(),
+            ticket_metadata: None,
         }
     }
 
@@ -224,6 +231,27 @@ mod test {
         }
     }
 
+    #[test]
+    fn execute_fa_deposit_records_ticket_metadata() {
+        let metadata = TicketMetadata {
+            ticketer: jstz_mock::kt1_account1(),
+            token_id: "1".to_string(),
+            content: None,
+        };
+        let fa_deposit = FaDeposit {
+            ticket_metadata: Some(metadata.clone()),
+            ..mock_fa_deposit(None)
+        };
+        let ticket_hash = fa_deposit.ticket_hash.clone();
+        let mut host = MockHost::default();
+        let mut tx = Transaction::default();
+        tx.begin();
+        let _ = super::execute(&mut host, &mut tx, fa_deposit);
+
+        let recorded = TicketTable::metadata(&mut host, &mut tx, &ticket_hash).unwrap();
+        assert_eq!(Some(metadata), recorded);
+    }
+
     #[test]
     fn execute_multiple_fa_deposit_into_account_succeeds() {
         let fa_deposit1 = mock_fa_deposit(None);
@@ -117,8 +117,7 @@ fn create_fa_withdrawal_message(
 }
 
 // Deducts `amount` from the ticket balance of `ticket_owner` for `ticket.hash`
-// and pushes a withdraw outbox message to the outbox queue, returning the outbox
-// message id.
+// and queues a withdraw outbox message, returning the id of the withdrawal.
 fn withdraw_from_ticket_owner(
     rt: &mut impl HostRuntime,
     tx: &mut Transaction,
@@ -129,15 +128,14 @@ fn withdraw_from_ticket_owner(
 ) -> Result<OutboxMessageId> {
     TicketTable::sub(rt, tx, ticket_owner, &ticket.hash, amount)?;
     let message = create_fa_withdrawal_message(routing_info, ticket.value)?;
-    tx.queue_outbox_message(rt, message)?;
-    // TODO: https://linear.app/tezos/issue/JSTZ-113/implement-outbox-message-id
-    // Implement outbox message id
-    Ok("".to_string())
+    let withdrawal_id = tx.queue_withdrawal(rt, message)?;
+    Ok(withdrawal_id.to_string())
 }
 
 impl FaWithdraw {
     /// Execute the [FaWithdrawal] request by deducting ticket balance from `source`` and
-    /// pushing a withdraw message to the outbox queue. `proxy_l1_contract` is expected to
+    /// queueing a withdraw message, which is batched with the other withdrawals
+    /// to `proxy_l1_contract` of the level. `proxy_l1_contract` is expected to
     /// implement the %withdraw entrypoint. See /jstz/contracts/examples/fa_ticketer/fa_ticketer.mligo.
     ///
     /// Fails if:
@@ -250,11 +248,12 @@ mod test {
         assert_eq!(
             FaWithdrawReceipt {
                 source,
-                outbox_message_id: "".to_string() // outbox message not implemented yet
+                outbox_message_id: "0".to_string()
             },
             fa_withdrawal_receipt_content,
         );
 
+        tx.flush_withdrawals(&mut rt).unwrap();
         let level = rt.run_level(|_| {});
         let outbox = rt.outbox_at(level);
 
@@ -0,0 +1,443 @@
+use derive_more::{Display, Error, From};
+use jstz_core::{
+    host::HostRuntime,
+    kv::{
+        outbox::{MichelineExpr, TypedCall},
+        Storage, Transaction,
+    },
+};
+use serde::{Deserialize, Serialize};
+use tezos_crypto_rs::hash::ContractKt1Hash;
+use tezos_smart_rollup::storage::path::{self, OwnedPath, RefPath};
+
+use crate::{
+    context::{
+        account::{Address, Amount},
+        ticket_table::TicketTable,
+    },
+    executor::fa_withdraw::TicketInfo,
+    Error, Result,
+};
+
+const DEFAULT_ENTRYPOINT: &str = "default";
+
+const PROTECTED_DESTINATIONS_PATH: RefPath =
+    RefPath::assert_from(b"/l1_calls/protected_destinations");
+
+fn default_entrypoint() -> String {
+    DEFAULT_ENTRYPOINT.to_string()
+}
+
+/// A call to an L1 contract, sent through the outbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct L1Call {
+    pub destination: ContractKt1Hash,
+    #[serde(default = "default_entrypoint")]
+    pub entrypoint: String,
+    /// Hex encoded parameters, in the binary encoding of Micheline expressions
+    pub parameters: String,
+    /// Hex encoded type of the parameters, in the binary encoding of Micheline
+    /// expressions. Must be the type of the destination entrypoint and must
+    /// not contain tickets.
+    pub parameters_type: String,
+    /// Tickets sent along with the call
+    #[serde(default)]
+    pub tickets: Vec<L1CallTicket>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct L1CallTicket {
+    pub amount: Amount,
+    pub ticket_info: TicketInfo,
+}
+
+#[derive(Display, Debug, Error, From)]
+pub enum L1CallError {
+    /// Parameters or their type are not hex encoded Micheline expressions
+    InvalidMichelineExpr,
+    /// Tickets can only be sent as attached tickets
+    TicketInParametersType,
+    /// The destination is a bridge or a ticketer of the rollup
+    ProtectedDestination,
+}
+
+fn protected_destination_path(destination: &ContractKt1Hash) -> Result<OwnedPath> {
+    let destination_path = OwnedPath::try_from(format!("/{}", destination))?;
+    Ok(path::concat(
+        &PROTECTED_DESTINATIONS_PATH,
+        &destination_path,
+    )?)
+}
+
+/// Forbids L1 calls to `contract`. Contracts that send deposits to the
+/// rollup and ticketers of deposited tickets are protected, as they may
+/// trust calls from the rollup. Ticketers are also protected once their
+/// tickets are attached to an L1 call, which covers the tickets deposited
+/// before destinations were protected. Bridges are only protected once they
+/// send a deposit.
+pub fn protect_destination(
+    rt: &mut impl HostRuntime,
+    contract: &ContractKt1Hash,
+) -> Result<()> {
+    let path = protected_destination_path(contract)?;
+    if !Storage::contains_key(rt, &path)? {
+        Storage::insert(rt, &path, &true)?;
+    }
+    Ok(())
+}
+
+fn decode_micheline_expr(hex_expr: &str) -> Result<MichelineExpr> {
+    let bytes = hex::decode(hex_expr).map_err(|_| L1CallError::InvalidMichelineExpr)?;
+    MichelineExpr::try_from_bytes(bytes)
+        .map_err(|_| L1CallError::InvalidMichelineExpr.into())
+}
+
+impl L1Call {
+    /// Checks the call and decodes its parameters and their type
+    fn validate(
+        &self,
+        rt: &mut impl HostRuntime,
+        tx: &mut Transaction,
+        ticketer: &ContractKt1Hash,
+    ) -> Result<(MichelineExpr, MichelineExpr)> {
+        if self.destination == *ticketer
+            || tx.contains_key(rt, &protected_destination_path(&self.destination)?)?
+        {
+            Err(L1CallError::ProtectedDestination)?
+        }
+        let parameters = decode_micheline_expr(&self.parameters)?;
+        let parameters_type = decode_micheline_expr(&self.parameters_type)?;
+        if parameters_type.contains_ticket_type() {
+            Err(L1CallError::TicketInParametersType)?
+        }
+        Ok((parameters, parameters_type))
+    }
+
+    fn call(
+        self,
+        rt: &mut impl HostRuntime,
+        tx: &mut Transaction,
+        source: &Address,
+        ticketer: &ContractKt1Hash,
+    ) -> Result<()> {
+        let (mut parameters, mut parameters_type) = self.validate(rt, tx, ticketer)?;
+        let L1Call {
+            destination,
+            entrypoint,
+            tickets,
+            ..
+        } = self;
+
+        if !tickets.is_empty() {
+            let mut values = Vec::with_capacity(tickets.len());
+            for L1CallTicket {
+                amount,
+                ticket_info,
+            } in tickets
+            {
+                if amount == 0 {
+                    Err(Error::ZeroAmountNotAllowed)?
+                }
+                let ticket = ticket_info.to_ticket(amount)?;
+                TicketTable::sub(rt, tx, source, &ticket.hash, amount)?;
+                // The ticket was deposited, possibly before its ticketer was
+                // protected on deposit
+                protect_destination(rt, &ticket_info.ticketer)?;
+                if ticket_info.ticketer == destination {
+                    Err(L1CallError::ProtectedDestination)?
+                }
+                values.push(ticket.value);
+            }
+            parameters = parameters.pair_with_tickets(&values)?;
+            parameters_type = parameters_type.pair_with_tickets_type();
+        }
+
+        let call =
+            TypedCall::new(&destination, &entrypoint, parameters, parameters_type)?;
+        tx.queue_call(rt, call)?;
+        Ok(())
+    }
+
+    /// Execute the [L1Call] request by deducting the attached tickets from the
+    /// ticket balance of `source` and pushing a typed call message to the
+    /// outbox queue. If tickets are attached, the destination entrypoint
+    /// receives `Pair parameters { ticket_1; ...; ticket_n }` of type
+    /// `pair parameters_type (list (ticket (pair nat (option bytes))))`.
+    ///
+    /// Fails if:
+    /// * Parameters or their type are not valid Micheline expressions
+    /// * The parameters type contains tickets
+    /// * The destination is the native ticketer, a protected destination or
+    ///   the ticketer of an attached ticket
+    /// * Source account has insufficient ticket funds
+    /// * The amount of an attached ticket is zero
+    /// * Outbox queue is full
+    pub fn execute(
+        self,
+        rt: &mut impl HostRuntime,
+        tx: &mut Transaction,
+        source: &Address,
+        ticketer: &ContractKt1Hash,
+    ) -> Result<()> {
+        tx.begin();
+        let result = self.call(rt, tx, source, ticketer);
+        if result.is_ok() {
+            tx.commit(rt)?;
+        } else {
+            tx.rollback()?;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tezos_smart_rollup_mock::MockHost;
+
+    use crate::context::ticket_table::TicketTableError;
+
+    use super::*;
+
+    // Micheline encoding of `Pair 1 "a"`
+    const PARAMETERS: &str = "07070001010000000161";
+    // Micheline encoding of `pair int string`
+    const PARAMETERS_TYPE: &str = "07655b0368";
+
+    fn ticket_info() -> TicketInfo {
+        TicketInfo {
+            id: 1234,
+            content: Some(b"random ticket content".to_vec()),
+            ticketer: fa_ticketer(),
+        }
+    }
+
+    fn fa_ticketer() -> ContractKt1Hash {
+        ContractKt1Hash::from_base58_check("KT1NgXQ6Mwu3XKFDcKdYFS6dkkY3iNKdBKEc")
+            .unwrap()
+    }
+
+    fn native_ticketer() -> ContractKt1Hash {
+        ContractKt1Hash::from_base58_check(jstz_mock::host::NATIVE_TICKETER).unwrap()
+    }
+
+    fn l1_call(tickets: Vec<L1CallTicket>) -> L1Call {
+        L1Call {
+            destination: jstz_mock::kt1_account1(),
+            entrypoint: "settle".to_string(),
+            parameters: PARAMETERS.to_string(),
+            parameters_type: PARAMETERS_TYPE.to_string(),
+            tickets,
+        }
+    }
+
+    fn add_ticket_balance(
+        rt: &mut MockHost,
+        tx: &mut Transaction,
+        source: &Address,
+        amount: Amount,
+    ) {
+        tx.begin();
+        TicketTable::add(
+            rt,
+            tx,
+            source,
+            &ticket_info().to_ticket(1).unwrap().hash,
+            amount,
+        )
+        .expect("Adding ticket balance should succeed");
+        tx.commit(rt).unwrap();
+    }
+
+    fn decode(hex_expr: &str) -> MichelineExpr {
+        MichelineExpr::try_from_bytes(hex::decode(hex_expr).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn execute_l1_call_succeeds() {
+        let mut rt = MockHost::default();
+        let mut tx = Transaction::default();
+        let source = jstz_mock::account1();
+        add_ticket_balance(&mut rt, &mut tx, &source, 100);
+
+        let call = l1_call(vec![L1CallTicket {
+            amount: 10,
+            ticket_info: ticket_info(),
+        }]);
+        tx.begin();
+        call.execute(&mut rt, &mut tx, &source, &native_ticketer())
+            .expect("Should succeed");
+        tx.commit(&mut rt).unwrap();
+
+        tx.begin();
+        let balance = TicketTable::get_balance(
+            &mut rt,
+            &mut tx,
+            &source,
+            &ticket_info().to_ticket(1).unwrap().hash,
+        )
+        .unwrap();
+        assert_eq!(90, balance);
+
+        let level = rt.run_level(|_| {});
+        let outbox = rt.outbox_at(level);
+        assert_eq!(1, outbox.len());
+
+        let expected = TypedCall::new(
+            &jstz_mock::kt1_account1(),
+            "settle",
+            decode(PARAMETERS)
+                .pair_with_tickets(&[ticket_info().to_ticket(10).unwrap().value])
+                .unwrap(),
+            decode(PARAMETERS_TYPE).pair_with_tickets_type(),
+        )
+        .unwrap();
+        assert_eq!(expected.encode().unwrap(), outbox[0]);
+    }
+
+    #[test]
+    fn execute_l1_call_fails_on_insufficient_funds() {
+        let mut rt = MockHost::default();
+        let mut tx = Transaction::default();
+        let source = jstz_mock::account1();
+        add_ticket_balance(&mut rt, &mut tx, &source, 5);
+
+        let call = l1_call(vec![L1CallTicket {
+            amount: 10,
+            ticket_info: ticket_info(),
+        }]);
+        let result = call.execute(&mut rt, &mut tx, &source, &native_ticketer());
+        assert!(matches!(
+            result,
+            Err(Error::TicketTableError {
+                source: TicketTableError::InsufficientFunds
+            })
+        ));
+    }
+
+    #[test]
+    fn execute_l1_call_fails_on_invalid_parameters() {
+        let mut rt = MockHost::default();
+        let mut tx = Transaction::default();
+        let source = jstz_mock::account1();
+
+        let call = L1Call {
+            parameters: "0707".to_string(),
+            ..l1_call(vec![])
+        };
+        let result = call.execute(&mut rt, &mut tx, &source, &native_ticketer());
+        assert!(matches!(
+            result,
+            Err(Error::L1CallError {
+                source: L1CallError::InvalidMichelineExpr
+            })
+        ));
+    }
+
+    #[test]
+    fn execute_l1_call_rejects_tickets_in_parameters_type() {
+        let mut rt = MockHost::default();
+        let mut tx = Transaction::default();
+        let source = jstz_mock::account1();
+
+        // `ticket (pair nat (option bytes))`
+        let call = L1Call {
+            parameters_type: "05870765036205630369".to_string(),
+            ..l1_call(vec![])
+        };
+        let result = call.execute(&mut rt, &mut tx, &source, &native_ticketer());
+        assert!(matches!(
+            result,
+            Err(Error::L1CallError {
+                source: L1CallError::TicketInParametersType
+            })
+        ));
+    }
+
+    #[test]
+    fn execute_l1_call_rejects_protected_destinations() {
+        let mut rt = MockHost::default();
+        let mut tx = Transaction::default();
+        let source = jstz_mock::account1();
+
+        let call = L1Call {
+            destination: native_ticketer(),
+            ..l1_call(vec![])
+        };
+        let result = call.execute(&mut rt, &mut tx, &source, &native_ticketer());
+        assert!(matches!(
+            result,
+            Err(Error::L1CallError {
+                source: L1CallError::ProtectedDestination
+            })
+        ));
+
+        l1_call(vec![])
+            .execute(&mut rt, &mut tx, &source, &native_ticketer())
+            .expect("Should succeed");
+        protect_destination(&mut rt, &jstz_mock::kt1_account1()).unwrap();
+        let result =
+            l1_call(vec![]).execute(&mut rt, &mut tx, &source, &native_ticketer());
+        assert!(matches!(
+            result,
+            Err(Error::L1CallError {
+                source: L1CallError::ProtectedDestination
+            })
+        ));
+    }
+    #[test]
+    fn execute_l1_call_protects_ticketers_of_attached_tickets() {
+        let mut rt = MockHost::default();
+        let mut tx = Transaction::default();
+        let source = jstz_mock::account1();
+        add_ticket_balance(&mut rt, &mut tx, &source, 100);
+
+        let call = L1Call {
+            destination: fa_ticketer(),
+            ..l1_call(vec![L1CallTicket {
+                amount: 10,
+                ticket_info: ticket_info(),
+            }])
+        };
+        let result = call.execute(&mut rt, &mut tx, &source, &native_ticketer());
+        assert!(matches!(
+            result,
+            Err(Error::L1CallError {
+                source: L1CallError::ProtectedDestination
+            })
+        ));
+
+        let call = L1Call {
+            destination: fa_ticketer(),
+            ..l1_call(vec![])
+        };
+        let result = call.execute(&mut rt, &mut tx, &source, &native_ticketer());
+        assert!(matches!(
+            result,
+            Err(Error::L1CallError {
+                source: L1CallError::ProtectedDestination
+            })
+        ));
+    }
+
+    #[test]
+    fn execute_l1_call_does_not_protect_ticketers_of_missing_tickets() {
+        let mut rt = MockHost::default();
+        let mut tx = Transaction::default();
+        let source = jstz_mock::account1();
+
+        let call = l1_call(vec![L1CallTicket {
+            amount: 10,
+            ticket_info: ticket_info(),
+        }]);
+        assert!(call
+            .execute(&mut rt, &mut tx, &source, &native_ticketer())
+            .is_err());
+
+        let call = L1Call {
+            destination: fa_ticketer(),
+            ..l1_call(vec![])
+        };
+        call.execute(&mut rt, &mut tx, &source, &native_ticketer())
+            .expect("Should succeed");
+    }
+}
@@ -4,12 +4,13 @@ use tezos_crypto_rs::hash::ContractKt1Hash;
 use crate::{
     operation::{self, ExternalOperation, Operation, SignedOperation},
     receipt::{self, Receipt},
-    Result,
+    request_logger, Result,
 };
 
 pub mod deposit;
 pub mod fa_deposit;
 pub mod fa_withdraw;
+pub mod l1_call;
 pub mod smart_function;
 pub mod withdraw;
 pub const JSTZ_HOST: &str = "jstz";
@@ -36,6 +37,17 @@ fn execute_operation_inner(
             Ok(receipt::ReceiptContent::DeployFunction(result))
         }
 
+        Operation {
+            source,
+            content: operation::Content::RevealDeployFunction(deployment),
+            ..
+        } => {
+            let result =
+                smart_function::deploy::execute_reveal(hrt, tx, &source, deployment)?;
+
+            Ok(receipt::ReceiptContent::DeployFunction(result))
+        }
+
         Operation {
             content: operation::Content::RunFunction(run),
             source,
@@ -57,6 +69,7 @@ pub fn execute_external_operation(
     tx: &mut Transaction,
     external_operation: ExternalOperation,
 ) -> Receipt {
+    request_logger::reset_request_ids();
     match external_operation {
         ExternalOperation::Deposit(deposit) => deposit::execute(hrt, tx, deposit),
         ExternalOperation::FaDeposit(fa_deposit) => {
@@ -72,6 +85,7 @@ pub fn execute_operation(
     ticketer: &ContractKt1Hash,
 ) -> Receipt {
     let hash = signed_operation.hash();
+    request_logger::reset_request_ids();
     let inner = execute_operation_inner(hrt, tx, signed_operation, ticketer);
     Receipt::new(hash, inner)
 }
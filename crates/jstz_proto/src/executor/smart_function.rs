@@ -12,6 +12,7 @@ use http::Uri;
 use jstz_api::{
     http::{
         body::{Body, BodyWithType, HttpBody},
+        header::HeadersGuard,
         request::{Request, RequestClass},
         response::{Response, ResponseClass, ResponseOptions},
     },
@@ -29,7 +30,7 @@ use crate::{
     js_logger::JsonLogger,
     operation::{OperationHash, RunFunction},
     receipt,
-    request_logger::{log_request_end, log_request_start},
+    request_logger::{log_request_end, log_request_start, next_request_id},
     Error, Result,
 };
 
@@ -39,7 +40,7 @@ pub mod headers {
     pub const REFERRER: &str = "Referer";
 
     pub fn test_and_set_referrer(request: &Request, referer: &Address) -> JsResult<()> {
-        if request.headers().deref().contains_key(REFERRER) {
+        if request.headers().deref().contains(REFERRER)? {
             return Err(JsError::from_native(
                 JsNativeError::error().with_message("Referer already set"),
             ));
@@ -56,8 +57,8 @@ pub mod headers {
 // If the value is a promise, then we apply the on_fulfilled and on_rejected to the promise.
 fn try_apply_to_value_or_promise(
     value_or_promise: JsResult<JsValue>,
-    on_fulfilled: fn(&JsValue, &mut Context) -> JsResult<()>,
-    on_rejected: fn(&mut Context) -> JsResult<()>,
+    on_fulfilled: impl Fn(&JsValue, &mut Context) -> JsResult<()> + 'static,
+    on_rejected: impl Fn(&mut Context) -> JsResult<()> + 'static,
     context: &mut Context,
 ) -> JsResult<JsValue> {
     match value_or_promise {
@@ -115,13 +116,34 @@ fn compute_seed(address: &Address, operation_hash: &OperationHash) -> u64 {
     seed
 }
 
+/// Returns the abort reason of the signal of the given request, if it has been
+/// aborted
+fn abort_reason(request: &JsValue) -> Option<JsValue> {
+    let request = JsNativeObject::<Request>::try_from(request.clone()).ok()?;
+    let request = request.deref();
+    let signal = request.signal().deref();
+    signal.aborted().then(|| signal.reason().clone())
+}
+
+// Address of the caller of `request`, as set in its referer
+fn referrer(request: &JsValue) -> Option<Address> {
+    let request = JsNativeObject::<Request>::try_from(request.clone()).ok()?;
+    let http_headers = request.deref().headers().deref().to_http_headers().ok()?;
+    let referrer = http_headers.get(headers::REFERRER)?.to_str().ok()?;
+    Address::from_base58(referrer).ok()
+}
+
 pub fn register_web_apis(realm: &Realm, context: &mut Context) {
     realm.register_api(jstz_api::url::UrlApi, context);
     realm.register_api(jstz_api::urlpattern::UrlPatternApi, context);
+    realm.register_api(jstz_api::event::EventApi, context);
+    realm.register_api(jstz_api::abort::AbortApi, context);
     realm.register_api(jstz_api::http::HttpApi, context);
+    realm.register_api(jstz_api::StructuredCloneApi, context);
     realm.register_api(jstz_api::encoding::EncodingApi, context);
     realm.register_api(jstz_api::ConsoleApi, context);
     realm.register_api(jstz_api::file::FileApi, context);
+    realm.register_api(jstz_core::runtime::TimersApi, context);
 }
 
 pub fn register_jstz_apis(
@@ -265,6 +287,7 @@ impl Script {
         runtime::with_js_tx(|tx| tx.begin());
 
         // 2. Initialize host defined data
+        let request_id = next_request_id(operation_hash);
 
         {
             host_defined!(context, mut host_defined);
@@ -272,6 +295,7 @@ impl Script {
             let trace_data = TraceData {
                 address: address.clone(),
                 operation_hash: operation_hash.clone(),
+                request_id: request_id.clone(),
             };
 
             host_defined.insert(trace_data);
@@ -279,23 +303,38 @@ impl Script {
 
         // 3. Set logger
         set_js_logger(&JsonLogger);
-        log_request_start(address.clone(), operation_hash.to_string());
+        log_request_start(address.clone(), request_id.clone(), referrer(request));
 
         // 4. Invoke the script's handler
         let result =
             self.invoke_handler(&JsValue::undefined(), &[request.clone()], context);
 
-        // TODO: decode request and add more fields to the request (status, header etc).
-        log_request_end(address.clone(), operation_hash.to_string());
-
-        // 4. Ensure that the transaction is committed
+        // 4. Ensure that the transaction is committed, and log the end of the
+        //    request once the response is known
+        let request = request.clone();
+        let (fulfilled_address, rejected_address) = (address.clone(), address.clone());
+        let (fulfilled_id, rejected_id) = (request_id.clone(), request_id);
         try_apply_to_value_or_promise(
             result,
-            |value, _context| {
+            move |value, _context| {
+                let response = Response::try_from_js(value);
+                log_request_end(
+                    fulfilled_address.clone(),
+                    fulfilled_id.clone(),
+                    response.as_ref().ok().map(|response| response.status()),
+                );
+
+                // The signal is checked right before committing, such that an
+                // aborted call is never committed
+                if let Some(reason) = abort_reason(&request) {
+                    runtime::with_js_tx(|tx| tx.rollback())?;
+                    return Err(JsError::from_opaque(reason));
+                }
+
                 runtime::with_js_hrt_and_tx(|hrt, tx| -> JsResult<()> {
-                    let response = Response::try_from_js(value)?;
+                    let response = response?;
 
-                    // If status code is 2xx, commit transaction
+                    // If status code is 2xx, commit
                     if response.ok() {
                         tx.commit(hrt)?;
                     } else {
@@ -305,7 +344,10 @@ impl Script {
                     Ok(())
                 })
             },
-            |_context| Ok(runtime::with_js_tx(|tx| tx.rollback())?),
+            move |_context| {
+                log_request_end(rejected_address.clone(), rejected_id.clone(), None);
+                Ok(runtime::with_js_tx(|tx| tx.rollback())?)
+            },
             context,
         )
     }
@@ -361,7 +403,7 @@ impl HostScript {
                 JsError::from_native(JsNativeError::error().with_message("Invalid host"))
             })?;
         let body = request_deref.body().clone().to_http_body();
-        let headers = request_deref.headers().deref_mut().to_http_headers();
+        let headers = request_deref.headers().deref().to_http_headers()?;
         Ok(RunFunction {
             uri,
             method,
@@ -376,7 +418,11 @@ impl HostScript {
         context: &mut Context,
     ) -> JsResult<Response> {
         let body = Body::from_http_body(run_receipt.body, context)?;
-        let options = ResponseOptions::from(run_receipt.status_code, run_receipt.headers);
+        let options = ResponseOptions::from(
+            run_receipt.status_code,
+            run_receipt.status_text,
+            run_receipt.headers,
+        );
         Response::new(
             BodyWithType {
                 body,
@@ -417,6 +463,12 @@ impl HostScript {
             }
         })?;
 
+        // The headers of the response are immutable for the caller
+        response
+            .headers()
+            .deref_mut()
+            .set_guard(HeadersGuard::Immutable);
+
         let js_response = JsNativeObject::new::<ResponseClass>(response, context)?;
         Ok(js_response.inner().clone())
     }
@@ -508,11 +560,12 @@ pub mod run {
         // 6. Serialize response
         let response = Response::try_from_js(&result)?;
 
-        let (http_parts, body) = Response::to_http_response(&response).into_parts();
+        let (http_parts, body) = Response::to_http_response(&response)?.into_parts();
 
         Ok(receipt::RunFunctionReceipt {
             body,
             status_code: http_parts.status,
+            status_text: response.status_text().to_string(),
             headers: http_parts.headers,
         })
     }
@@ -526,13 +579,16 @@ pub mod jstz_run {
 
     use super::*;
     use crate::{
-        executor::{fa_withdraw::FaWithdraw, withdraw::Withdrawal, JSTZ_HOST},
+        executor::{
+            fa_withdraw::FaWithdraw, l1_call::L1Call, withdraw::Withdrawal, JSTZ_HOST,
+        },
         operation::RunFunction,
         receipt,
     };
 
     const WITHDRAW_PATH: &str = "/withdraw";
     const FA_WITHDRAW_PATH: &str = "/fa-withdraw";
+    const L1_CALL_PATH: &str = "/l1-call";
 
     fn validate_withdraw_request<'de, T>(run: &'de RunFunction) -> Result<T>
     where
@@ -556,6 +612,22 @@ pub mod jstz_run {
         Ok(withdrawal)
     }
 
+    // The parameters of the call are checked by [L1Call::execute], which
+    // needs the ticketer and the protected destinations
+    fn validate_l1_call_request(run: &RunFunction) -> Result<L1Call> {
+        let method = run
+            .method
+            .as_str()
+            .parse::<http::Method>()
+            .map_err(|_| Error::InvalidHttpRequestMethod)?;
+        if method != http::Method::POST {
+            return Err(Error::InvalidHttpRequestMethod);
+        }
+
+        let body = run.body.as_ref().ok_or(Error::InvalidHttpRequestBody)?;
+        serde_json::from_slice(body).map_err(|_| Error::InvalidHttpRequestBody)
+    }
+
     pub fn execute(
         hrt: &mut impl HostRuntime,
         tx: &mut Transaction,
@@ -573,12 +645,15 @@ pub mod jstz_run {
                 // Check gas limit
 
                 let withdrawal = validate_withdraw_request::<Withdrawal>(&run)?;
-                crate::executor::withdraw::execute_withdraw(
+                let withdrawal_id = crate::executor::withdraw::execute_withdraw(
                     hrt, tx, source, withdrawal, ticketer,
                 )?;
+                let body =
+                    serde_json::json!({ "outbox_message_id": withdrawal_id.to_string() });
                 let receipt = receipt::RunFunctionReceipt {
-                    body: None,
+                    body: Some(body.to_string().into_bytes()),
                     status_code: http::StatusCode::OK,
+                    status_text: String::new(),
                     headers: http::HeaderMap::new(),
                 };
                 Ok(receipt)
@@ -591,6 +666,18 @@ pub mod jstz_run {
                 let receipt = receipt::RunFunctionReceipt {
                     body: fa_withdraw_receipt_content.to_http_body(),
                     status_code: http::StatusCode::OK,
+                    status_text: String::new(),
+                    headers: http::HeaderMap::new(),
+                };
+                Ok(receipt)
+            }
+            L1_CALL_PATH => {
+                let l1_call = validate_l1_call_request(&run)?;
+                l1_call.execute(hrt, tx, source, ticketer)?;
+                let receipt = receipt::RunFunctionReceipt {
+                    body: None,
+                    status_code: http::StatusCode::OK,
+                    status_text: String::new(),
                     headers: http::HeaderMap::new(),
                 };
                 Ok(receipt)
@@ -784,6 +871,7 @@ pub mod jstz_run {
             tx.begin();
             assert_eq!(0, Account::balance(&host, &mut tx, &source).unwrap());
 
+            tx.flush_withdrawals(&mut host).unwrap();
             let level = host.run_level(|_| {});
             assert_eq!(1, host.outbox_at(level).len());
         }
@@ -807,6 +895,7 @@ pub mod jstz_run {
             tx.begin();
             assert_eq!(0, Account::balance(rt, &mut tx, &source).unwrap());
 
+            tx.flush_withdrawals(rt).unwrap();
             let level = rt.run_level(|_| {});
             assert_eq!(1, rt.outbox_at(level).len());
         }
@@ -890,6 +979,7 @@ pub mod jstz_run {
             tx.begin();
             assert_eq!(0, Account::balance(&host, &mut tx, &source).unwrap());
 
+            tx.flush_withdrawals(&mut host).unwrap();
             let level = host.run_level(|_| {});
             assert_eq!(1, host.outbox_at(level).len());
         }
@@ -898,7 +988,7 @@ pub mod jstz_run {
 
 pub mod deploy {
     use super::*;
-    use crate::{operation, receipt};
+    use crate::{operation, receipt, reveal_data};
 
     pub fn execute(
         hrt: &impl HostRuntime,
@@ -915,4 +1005,33 @@ pub mod deploy {
 
         Ok(receipt::DeployFunctionReceipt { address })
     }
+
+    /// Maximum size of smart function code revealed from preimages
+    pub const MAX_REVEALED_CODE_SIZE: usize = 1 << 20;
+
+    pub fn execute_reveal(
+        hrt: &impl HostRuntime,
+        tx: &mut Transaction,
+        source: &Address,
+        deployment: operation::RevealDeployFunction,
+    ) -> Result<receipt::DeployFunctionReceipt> {
+        let operation::RevealDeployFunction {
+            root_hash,
+            account_credit,
+        } = deployment;
+
+        let code = reveal_data::reveal(hrt, &root_hash, MAX_REVEALED_CODE_SIZE)?;
+        let code = String::from_utf8(code).map_err(|_| Error::InvalidPreimage)?;
+        let function_code = ParsedCode::try_from(code)?;
+
+        execute(
+            hrt,
+            tx,
+            source,
+            operation::DeployFunction {
+                function_code,
+                account_credit,
+            },
+        )
+    }
 }
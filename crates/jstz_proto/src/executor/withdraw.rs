@@ -1,6 +1,9 @@
 use jstz_core::{
     host::HostRuntime,
-    kv::{outbox::OutboxMessage, Transaction},
+    kv::{
+        outbox::{OutboxMessage, WithdrawalId},
+        Transaction,
+    },
 };
 use serde::{Deserialize, Serialize};
 use tezos_smart_rollup::{
@@ -50,27 +53,29 @@ fn withdraw(
     source: &Address,
     withdrawal: Withdrawal,
     ticketer: &ContractKt1Hash,
-) -> Result<()> {
+) -> Result<WithdrawalId> {
     let Withdrawal { amount, receiver } = withdrawal;
     Account::sub_balance(rt, tx, source, amount)?;
     let message = create_withdrawal(amount, &receiver, ticketer)?;
-    tx.queue_outbox_message(rt, message)?;
-    Ok(())
+    let withdrawal_id = tx.queue_withdrawal(rt, message)?;
+    Ok(withdrawal_id)
 }
 
 /// Process the native withdrawal request by atomically deducting user balance
-/// then pushing a withdraw message to the outbox queue.`ticketer` is expected
-/// to be the XTZ Exchanger contract. See /jstz/contracts/exchanger.mligo.
+/// then queueing a withdraw message, which is batched with the other
+/// withdrawals of the level. `ticketer` is expected to be the XTZ Exchanger
+/// contract. See /jstz/contracts/exchanger.mligo.
 ///
-/// Fails if the source account has insufficient funds or if the outbox
-/// queue is full.
+/// Returns the id under which the outbox location of the withdrawal is
+/// recorded. Fails if the source account has insufficient funds or if the
+/// outbox queue is full.
 pub(crate) fn execute_withdraw(
     rt: &mut impl HostRuntime,
     tx: &mut Transaction,
     source: &Address,
     withdrawal: Withdrawal,
     ticketer: &ContractKt1Hash,
-) -> Result<()> {
+) -> Result<WithdrawalId> {
     tx.begin();
     let result = withdraw(rt, tx, source, withdrawal, ticketer);
     if result.is_ok() {
@@ -138,6 +143,7 @@ mod test {
         execute_withdraw(&mut host, &mut tx, &source, withdrawal, &ticketer).unwrap();
 
         tx.commit(&mut host).unwrap();
+        tx.flush_withdrawals(&mut host).unwrap();
         let level = host.run_level(|_| {});
         assert_eq!(1, host.outbox_at(level).len());
 
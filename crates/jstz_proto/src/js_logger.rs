@@ -44,7 +44,7 @@ impl LogRecord {
         let indent = 2 * groups_len;
         LogRecord {
             address: trace_data.address.clone(),
-            request_id: trace_data.operation_hash.to_string(),
+            request_id: trace_data.request_id.clone(),
             level,
             text: " ".repeat(indent) + &text,
         }
@@ -7,4 +7,5 @@ pub mod js_logger;
 pub mod operation;
 pub mod receipt;
 pub mod request_logger;
+pub mod reveal_data;
 pub use error::{Error, Result};
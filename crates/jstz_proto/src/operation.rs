@@ -1,15 +1,18 @@
 use crate::{
     context::account::{Account, Address, Amount, Nonce, ParsedCode},
+    reveal_data::PreimageHash,
     Error, Result,
 };
 use http::{HeaderMap, Method, Uri};
 use jstz_api::http::body::HttpBody;
 use jstz_core::{host::HostRuntime, kv::Transaction};
-use jstz_crypto::{hash::Blake2b, public_key::PublicKey, signature::Signature};
+use jstz_crypto::{
+    hash::Blake2b, public_key::PublicKey, secret_key::SecretKey, signature::Signature,
+};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub struct Operation {
     pub source: Address,
     pub nonce: Nonce,
@@ -62,6 +65,12 @@ impl Operation {
                 format!("{}{}{}{}", source, nonce, function_code, account_credit)
                     .as_bytes(),
             ),
+            Content::RevealDeployFunction(RevealDeployFunction {
+                root_hash,
+                account_credit,
+            }) => Blake2b::from(
+                format!("{}{}{}{}", source, nonce, root_hash, account_credit).as_bytes(),
+            ),
             Content::RunFunction(RunFunction {
                 uri,
                 method,
@@ -88,6 +97,21 @@ pub struct DeployFunction {
     pub account_credit: Amount,
 }
 
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, ToSchema)]
+#[schema(
+    description = "Deploys a smart function whose code is too large to be \
+    included in an operation. The code is revealed by the rollup node from the \
+    preimages of the given root hash."
+)]
+#[serde(tag = "_type")]
+pub struct RevealDeployFunction {
+    /// Root hash of the preimages of the smart function code
+    #[schema(value_type = String)]
+    pub root_hash: PreimageHash,
+    /// Amount of tez to credit to the smart function account, debited from the sender
+    pub account_credit: Amount,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, ToSchema)]
 #[schema(description = "Request used to run a smart function. \
     The target smart function is given by the host part of the uri. \
@@ -126,9 +150,11 @@ pub enum Content {
     DeployFunction(DeployFunction),
     #[schema(title = "RunFunction")]
     RunFunction(RunFunction),
+    #[schema(title = "RevealDeployFunction")]
+    RevealDeployFunction(RevealDeployFunction),
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub struct SignedOperation {
     pub public_key: PublicKey,
     signature: Signature,
@@ -148,6 +174,11 @@ impl SignedOperation {
         self.inner.hash()
     }
 
+    /// Returns the content of the operation, which is not verified yet
+    pub fn content(&self) -> &Content {
+        &self.inner.content
+    }
+
     pub fn verify(self) -> Result<Operation> {
         // FIXME: Adding signature verification kills to the rollup???!??!?!?!
         let hash = self.inner.hash();
@@ -160,6 +191,8 @@ impl SignedOperation {
 pub mod external {
     use tezos_smart_rollup::michelson::ticket::TicketHash;
 
+    use crate::context::ticket_table::TicketMetadata;
+
     use super::*;
 
     #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -186,6 +219,8 @@ pub mod external {
         pub proxy_smart_function: Option<Address>,
         // Ticket hash
         pub ticket_hash: TicketHash,
+        // Ticket metadata, recorded on the first deposit of the ticket
+        pub ticket_metadata: Option<TicketMetadata>,
     }
 
     impl FaDeposit {
@@ -209,6 +244,262 @@ pub mod external {
     }
 }
 
+pub mod chunk {
+    use super::*;
+
+    /// Maximum size of the contents of an external message frame targetting
+    /// the rollup: 4096 bytes, less the inbox message tag (1 byte), the frame
+    /// tag (1 byte) and the rollup address (20 bytes).
+    pub const MAX_EXTERNAL_MESSAGE_CONTENTS_SIZE: usize = 4074;
+
+    /// Maximum size of the data of a chunk, such that an encoded chunk, with
+    /// the public key and signature of its signer, fits in an external message.
+    pub const MAX_CHUNK_DATA_SIZE: usize = 3800;
+
+    /// Maximum number of chunks an operation can be split into.
+    pub const MAX_CHUNKS: u32 = 1024;
+
+    // Size of the tag of `ExternalMessageContents` when encoded with `bincode`
+    pub(super) const CONTENTS_TAG_SIZE: usize = 4;
+
+    // Domain separation tag of the hash signed by the signer of a chunk
+    const CHUNK_SIGNATURE_TAG: &[u8] = b"jstz_operation_chunk";
+
+    /// A part of an encoded signed operation that is too large to fit in a
+    /// single external message.
+    ///
+    /// Each chunk is signed by the signer of the operation. The kernel stores
+    /// the chunks of an upload, identified by its signer and content hash,
+    /// until all parts of the operation have been received, then reassembles
+    /// and executes the operation.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, ToSchema)]
+    pub struct OperationChunk {
+        /// Hash of the complete encoded operation
+        pub content_hash: Blake2b,
+        /// Index of the chunk
+        pub index: u32,
+        /// Total number of chunks of the operation
+        pub total: u32,
+        /// Data of the chunk
+        pub data: Vec<u8>,
+        /// Public key of the signer of the operation
+        pub public_key: PublicKey,
+        /// Signature of the chunk by the signer of the operation
+        pub signature: Signature,
+    }
+
+    impl OperationChunk {
+        /// Returns `true` if `encoded_operation` (a `bincode` encoded
+        /// [`SignedOperation`]) does not fit in a single external message.
+        pub fn is_required(encoded_operation: &[u8]) -> bool {
+            CONTENTS_TAG_SIZE + encoded_operation.len()
+                > MAX_EXTERNAL_MESSAGE_CONTENTS_SIZE
+        }
+
+        /// Computes the hash signed by the signer of the chunk
+        pub fn hash(&self) -> Blake2b {
+            Self::signed_hash(&self.content_hash, self.index, self.total, &self.data)
+        }
+
+        fn signed_hash(
+            content_hash: &Blake2b,
+            index: u32,
+            total: u32,
+            data: &[u8],
+        ) -> Blake2b {
+            let mut preimage = CHUNK_SIGNATURE_TAG.to_vec();
+            preimage.extend_from_slice(content_hash.as_ref());
+            preimage.extend_from_slice(&index.to_be_bytes());
+            preimage.extend_from_slice(&total.to_be_bytes());
+            preimage.extend_from_slice(data);
+            Blake2b::from(&preimage)
+        }
+
+        /// Verifies that the chunk is signed by its public key
+        pub fn verify(&self) -> Result<()> {
+            self.signature
+                .verify(&self.public_key, self.hash().as_ref())?;
+            Ok(())
+        }
+
+        /// Splits `encoded_operation` (a `bincode` encoded [`SignedOperation`])
+        /// into chunks signed with `secret_key`, the secret key of `public_key`.
+        pub fn split(
+            encoded_operation: &[u8],
+            public_key: &PublicKey,
+            secret_key: &SecretKey,
+        ) -> Result<Vec<Self>> {
+            let content_hash = Blake2b::from(encoded_operation);
+            let total = encoded_operation.len().div_ceil(MAX_CHUNK_DATA_SIZE) as u32;
+
+            encoded_operation
+                .chunks(MAX_CHUNK_DATA_SIZE)
+                .enumerate()
+                .map(|(index, data)| {
+                    let index = index as u32;
+                    let hash = Self::signed_hash(&content_hash, index, total, data);
+                    Ok(Self {
+                        content_hash: content_hash.clone(),
+                        index,
+                        total,
+                        data: data.to_vec(),
+                        public_key: public_key.clone(),
+                        signature: secret_key.sign(hash)?,
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+pub mod dal {
+    use super::*;
+
+    /// Size of a smart rollup address
+    pub const ROLLUP_ADDRESS_SIZE: usize = 20;
+
+    // Version of the encoding of `DalBatch`
+    const DAL_BATCH_VERSION: u8 = 0;
+
+    /// A batch of operations published to a slot of the Data Availability Layer
+    /// (DAL). Slots are shared by all rollups, so a batch names the rollup it
+    /// targets.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+    pub struct DalBatch {
+        /// Raw address of the rollup targetted by the batch
+        pub rollup_address: [u8; ROLLUP_ADDRESS_SIZE],
+        pub operations: Vec<SignedOperation>,
+    }
+
+    impl DalBatch {
+        /// Encodes the batch as a version byte followed by the `bincode`
+        /// encoding of the batch.
+        pub fn encode(&self) -> Vec<u8> {
+            let mut bytes = vec![DAL_BATCH_VERSION];
+            bytes.extend(bincode::serialize(self).expect("Failed to encode DAL batch"));
+            bytes
+        }
+
+        /// Decodes a batch from the contents of a slot. Trailing bytes (the
+        /// padding of the slot) are ignored.
+        pub fn decode(bytes: &[u8]) -> Option<Self> {
+            match bytes.split_first()? {
+                (&DAL_BATCH_VERSION, batch) => bincode::deserialize(batch).ok(),
+                _ => None,
+            }
+        }
+    }
+}
+
+pub mod batch {
+    use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+
+    use super::*;
+
+    /// Maximum size of the operations of a batch once decompressed
+    pub const MAX_DECOMPRESSED_BATCH_SIZE: usize = 1 << 20;
+
+    // DEFLATE compression level, from 0 (none) to 10 (best)
+    const COMPRESSION_LEVEL: u8 = 9;
+
+    // Size of the length prefix of a `Vec` when encoded with `bincode`
+    const LENGTH_PREFIX_SIZE: usize = 8;
+
+    /// A batch of signed operations sent in a single external message, such
+    /// that sequencers can amortise the cost of an inbox message over many
+    /// small operations.
+    ///
+    /// The operations are `bincode` encoded, then compressed with DEFLATE.
+    /// The kernel executes them in order, each with its own receipt.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    pub struct OperationBatch {
+        compressed_operations: Vec<u8>,
+    }
+
+    impl OperationBatch {
+        pub fn new(operations: &[SignedOperation]) -> Self {
+            let encoded =
+                bincode::serialize(operations).expect("Failed to encode operations");
+            Self {
+                compressed_operations: compress_to_vec(&encoded, COMPRESSION_LEVEL),
+            }
+        }
+
+        /// Decompresses the operations of the batch. Returns `None` if the
+        /// batch is malformed or larger than [`MAX_DECOMPRESSED_BATCH_SIZE`]
+        /// once decompressed.
+        pub fn operations(&self) -> Option<Vec<SignedOperation>> {
+            let encoded = decompress_to_vec_with_limit(
+                &self.compressed_operations,
+                MAX_DECOMPRESSED_BATCH_SIZE,
+            )
+            .ok()?;
+            bincode::deserialize(&encoded).ok()
+        }
+
+        /// Returns `true` if the batch fits in a single external message.
+        pub fn fits_in_external_message(&self) -> bool {
+            chunk::CONTENTS_TAG_SIZE
+                + LENGTH_PREFIX_SIZE
+                + self.compressed_operations.len()
+                <= chunk::MAX_EXTERNAL_MESSAGE_CONTENTS_SIZE
+        }
+    }
+}
+
+pub mod admin {
+    use tezos_smart_rollup::types::SmartRollupAddress;
+
+    use super::*;
+
+    const KERNEL_UPGRADE_SIGNATURE_TAG: &[u8] = b"jstz_kernel_upgrade";
+
+    /// An upgrade of the kernel to the kernel whose code is revealed from the
+    /// preimages of `root_hash`.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    pub struct KernelUpgrade {
+        /// Root hash of the preimages of the new kernel
+        pub root_hash: PreimageHash,
+        /// Upgrade nonce, preventing an upgrade from being replayed
+        pub nonce: u64,
+    }
+
+    impl KernelUpgrade {
+        /// Computes the hash which the administrators should sign to upgrade
+        /// the rollup `rollup_address`. The hash is specific to the rollup, so
+        /// that the upgrade cannot be replayed on another rollup sharing the
+        /// administrators.
+        pub fn hash(&self, rollup_address: &SmartRollupAddress) -> Blake2b {
+            let KernelUpgrade { root_hash, nonce } = self;
+            let mut preimage = KERNEL_UPGRADE_SIGNATURE_TAG.to_vec();
+            preimage.extend_from_slice(rollup_address.to_b58check().as_bytes());
+            preimage.extend_from_slice(root_hash.as_array());
+            preimage.extend_from_slice(&nonce.to_be_bytes());
+            Blake2b::from(preimage.as_slice())
+        }
+    }
+
+    /// A kernel upgrade signed by (a subset of) the rollup administrators.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+    pub struct SignedKernelUpgrade {
+        pub upgrade: KernelUpgrade,
+        pub signatures: Vec<(PublicKey, Signature)>,
+    }
+}
+
+/// The contents of an external message targetting the rollup.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ExternalMessageContents {
+    /// A complete signed operation
+    Operation(SignedOperation),
+    /// A chunk of a signed operation
+    Chunk(chunk::OperationChunk),
+    /// A kernel upgrade signed by the rollup administrators
+    KernelUpgrade(admin::SignedKernelUpgrade),
+    /// A compressed batch of signed operations
+    Batch(batch::OperationBatch),
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum ExternalOperation {
     Deposit(external::Deposit),
@@ -6,7 +6,7 @@ use crate::{
 };
 use http::{HeaderMap, StatusCode};
 use jstz_api::http::body::HttpBody;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use utoipa::ToSchema;
 
 // pub type ReceiptResult<T> = std::result::Result<T, ReceiptError>;
@@ -64,6 +64,21 @@ pub struct RunFunctionReceipt {
     #[serde(with = "http_serde::header_map")]
     #[schema(schema_with = crate::operation::openapi::http_headers)]
     pub headers: HeaderMap,
+    /// Status message of the response
+    // Receipts are stored with bincode, which cannot skip missing fields. The
+    // status text was added last so that receipts stored before it still
+    // decode, with an empty status text.
+    #[serde(default, deserialize_with = "deserialize_status_text")]
+    pub status_text: String,
+}
+
+fn deserialize_status_text<'de, D>(
+    deserializer: D,
+) -> std::result::Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(String::deserialize(deserializer).unwrap_or_default())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -86,3 +101,59 @@ pub enum ReceiptContent {
     #[schema(title = "FaWithdraw")]
     FaWithdraw(FaWithdrawReceipt),
 }
+
+#[cfg(test)]
+mod test {
+    use http::{HeaderMap, HeaderValue, StatusCode};
+    use jstz_api::http::body::HttpBody;
+    use serde::Serialize;
+
+    use super::RunFunctionReceipt;
+
+    // Layout of run function receipts before the status text was added
+    #[derive(Serialize)]
+    struct RunFunctionReceiptV0 {
+        body: HttpBody,
+        #[serde(with = "http_serde::status_code")]
+        status_code: StatusCode,
+        #[serde(with = "http_serde::header_map")]
+        headers: HeaderMap,
+    }
+
+    #[test]
+    fn decodes_run_function_receipts_without_status_text() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("text/plain"));
+        let receipt = RunFunctionReceiptV0 {
+            body: Some(b"hello".to_vec()),
+            status_code: StatusCode::CREATED,
+            headers: headers.clone(),
+        };
+
+        let bytes = bincode::serialize(&receipt).unwrap();
+        let decoded: RunFunctionReceipt = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(decoded.body, Some(b"hello".to_vec()));
+        assert_eq!(decoded.status_code, StatusCode::CREATED);
+        assert_eq!(decoded.headers, headers);
+        assert_eq!(decoded.status_text, "");
+    }
+
+    #[test]
+    fn run_function_receipts_round_trip() {
+        let receipt = RunFunctionReceipt {
+            body: None,
+            status_code: StatusCode::IM_A_TEAPOT,
+            headers: HeaderMap::new(),
+            status_text: "Short and stout".to_string(),
+        };
+
+        let bytes = bincode::serialize(&receipt).unwrap();
+        let decoded: RunFunctionReceipt = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.status_text, "Short and stout");
+
+        let json = serde_json::to_value(&receipt).unwrap();
+        let decoded: RunFunctionReceipt = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.status_text, "Short and stout");
+    }
+}
@@ -1,24 +1,41 @@
-use std::fmt::{self, Display};
+use std::{
+    cell::Cell,
+    fmt::{self, Display},
+};
 
 use jstz_core::{host::HostRuntime, runtime};
 use serde::{Deserialize, Serialize};
 
-use crate::context::account::Address;
+use crate::{context::account::Address, operation::OperationHash};
 
 pub const REQUEST_START_PREFIX: &str = "[JSTZ:SMART_FUNCTION:REQUEST_START] ";
 pub const REQUEST_END_PREFIX: &str = "[JSTZ:SMART_FUNCTION:REQUEST_END] ";
+/// Prefix of the line logged by the kernel at the start of each level, followed
+/// by the level and the timestamp of its predecessor block, in seconds since
+/// the Unix epoch
+pub const LEVEL_START_PREFIX: &str = "[JSTZ:LEVEL_START] ";
 
-#[derive(Serialize, Deserialize, Debug)]
+thread_local! {
+    /// Number of requests started during the current operation
+    static REQUEST_COUNT: Cell<u32> = const { Cell::new(0) };
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum RequestEvent {
     Start {
         address: Address,
         request_id: String,
+        /// Address of the caller, as set in the referer of the request
+        #[serde(default)]
+        caller: Option<Address>,
     },
     End {
         address: Address,
         request_id: String,
-        // TODO: Add more fields
+        /// Status code of the response, `None` if the handler failed
+        #[serde(default)]
+        status_code: Option<u16>,
     },
 }
 
@@ -36,10 +53,29 @@ impl RequestEvent {
     }
 }
 
-pub fn log_request_start(address: Address, request_id: String) {
+/// Restarts the numbering of requests, at the start of each operation
+pub fn reset_request_ids() {
+    REQUEST_COUNT.with(|count| count.set(0));
+}
+
+/// Returns a new id for a request of the operation `operation_hash`. The first
+/// request of an operation is identified by the hash of the operation, and the
+/// requests it makes to other smart functions by the hash followed by their
+/// number.
+pub fn next_request_id(operation_hash: &OperationHash) -> String {
+    let number = REQUEST_COUNT.with(|count| count.replace(count.get() + 1));
+    if number == 0 {
+        operation_hash.to_string()
+    } else {
+        format!("{}:{}", operation_hash, number)
+    }
+}
+
+pub fn log_request_start(address: Address, request_id: String, caller: Option<Address>) {
     let request_log = RequestEvent::Start {
         address,
         request_id,
+        caller,
     }
     .to_string();
 
@@ -48,10 +84,11 @@ pub fn log_request_start(address: Address, request_id: String) {
     });
 }
 
-pub fn log_request_end(address: Address, request_id: String) {
+pub fn log_request_end(address: Address, request_id: String, status_code: Option<u16>) {
     let request_log = RequestEvent::End {
         address,
         request_id,
+        status_code,
     }
     .to_string();
 
@@ -59,3 +96,47 @@ pub fn log_request_end(address: Address, request_id: String) {
         hrt.write_debug(&(REQUEST_END_PREFIX.to_string() + &request_log + "\n"));
     });
 }
+
+#[cfg(test)]
+mod test {
+    use crate::operation::OperationHash;
+
+    use super::{next_request_id, reset_request_ids, RequestEvent};
+
+    #[test]
+    fn numbers_the_requests_of_an_operation() {
+        let hash = OperationHash::default();
+        reset_request_ids();
+        assert_eq!(next_request_id(&hash), hash.to_string());
+        assert_eq!(next_request_id(&hash), format!("{}:1", hash));
+        assert_eq!(next_request_id(&hash), format!("{}:2", hash));
+
+        reset_request_ids();
+        assert_eq!(next_request_id(&hash), hash.to_string());
+    }
+
+    #[test]
+    fn parses_events_without_metadata() {
+        let address = "tz1cD5CuvAALcxgypqBXcBQEA8dkLJivoFjU";
+        let start = RequestEvent::try_from_string(&format!(
+            r#"{{"type":"Start","address":"{}","request_id":"1"}}"#,
+            address
+        ));
+        assert!(matches!(
+            start,
+            Some(RequestEvent::Start { caller: None, .. })
+        ));
+
+        let end = RequestEvent::try_from_string(&format!(
+            r#"{{"type":"End","address":"{}","request_id":"1","status_code":200}}"#,
+            address
+        ));
+        assert!(matches!(
+            end,
+            Some(RequestEvent::End {
+                status_code: Some(200),
+                ..
+            })
+        ));
+    }
+}
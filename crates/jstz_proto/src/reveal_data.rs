@@ -0,0 +1,384 @@
+//! # Reveal data
+//!
+//! Payloads that are too large for a single inbox message can be made available
+//! to the kernel through the reveal data channel. The payload is split into
+//! pages of at most [`MAX_PAGE_SIZE`] bytes which form a Merkle tree:
+//!
+//!   - _content pages_ hold a slice of the payload, and
+//!   - _hashes pages_ hold the preimage hashes of the pages below them.
+//!
+//! Each page is encoded as a one byte tag, followed by the (big-endian, 4 byte)
+//! length of its contents and the contents themselves. The payload is identified
+//! by the preimage hash of the root page. Pages are provided to the rollup node
+//! as files named after their (hex encoded) preimage hash.
+
+use std::{fmt, str::FromStr};
+
+use jstz_core::host::HostRuntime;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Error, Result};
+
+/// Size of a preimage hash: a one byte hashing scheme tag followed by a
+/// Blake2b (256 bit) digest.
+pub const PREIMAGE_HASH_SIZE: usize = 33;
+
+/// Maximum size of a page revealed by the rollup node.
+pub const MAX_PAGE_SIZE: usize = 4096;
+
+/// Maximum depth of the page tree. A tree of depth 4 holds payloads of several
+/// gigabytes, which is well above any payload accepted by the kernel.
+pub const MAX_DEPTH: usize = 4;
+
+const BLAKE2B_HASH_TAG: u8 = 0;
+const CONTENT_PAGE_TAG: u8 = 0;
+const HASHES_PAGE_TAG: u8 = 1;
+const PAGE_PREFIX_SIZE: usize = 5;
+const MAX_PAGE_CONTENT_SIZE: usize = MAX_PAGE_SIZE - PAGE_PREFIX_SIZE;
+const MAX_HASHES_PER_PAGE: usize = MAX_PAGE_CONTENT_SIZE / PREIMAGE_HASH_SIZE;
+
+/// The hash identifying a page revealed by the rollup node.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PreimageHash([u8; PREIMAGE_HASH_SIZE]);
+
+impl PreimageHash {
+    /// Computes the preimage hash of `page`.
+    pub fn of(page: &[u8]) -> Self {
+        let digest = tezos_crypto_rs::blake2b::digest_256(page);
+
+        let mut hash = [0; PREIMAGE_HASH_SIZE];
+        hash[0] = BLAKE2B_HASH_TAG;
+        hash[1..].copy_from_slice(&digest);
+        Self(hash)
+    }
+
+    pub fn as_array(&self) -> &[u8; PREIMAGE_HASH_SIZE] {
+        &self.0
+    }
+}
+
+impl From<[u8; PREIMAGE_HASH_SIZE]> for PreimageHash {
+    fn from(hash: [u8; PREIMAGE_HASH_SIZE]) -> Self {
+        Self(hash)
+    }
+}
+
+impl fmt::Display for PreimageHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl FromStr for PreimageHash {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = hex::decode(s).map_err(|_| Error::InvalidPreimageHash)?;
+        let hash = bytes.try_into().map_err(|_| Error::InvalidPreimageHash)?;
+        Ok(Self(hash))
+    }
+}
+
+impl Serialize for PreimageHash {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for PreimageHash {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+fn encode_page(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut page = Vec::with_capacity(PAGE_PREFIX_SIZE + contents.len());
+    page.push(tag);
+    page.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+    page.extend_from_slice(contents);
+    page
+}
+
+fn decode_page(page: &[u8]) -> Result<(u8, &[u8])> {
+    if page.len() < PAGE_PREFIX_SIZE {
+        return Err(Error::InvalidPreimage);
+    }
+    let (prefix, contents) = page.split_at(PAGE_PREFIX_SIZE);
+    let length = u32::from_be_bytes(prefix[1..].try_into().unwrap()) as usize;
+    if length != contents.len() {
+        return Err(Error::InvalidPreimage);
+    }
+    Ok((prefix[0], contents))
+}
+
+/// Splits `content` into pages, calling `save_page` with each page and its
+/// preimage hash. Returns the preimage hash of the root page.
+pub fn prepare_preimages(
+    content: &[u8],
+    mut save_page: impl FnMut(&PreimageHash, Vec<u8>),
+) -> PreimageHash {
+    let mut save = |tag, contents: &[u8]| {
+        let page = encode_page(tag, contents);
+        let hash = PreimageHash::of(&page);
+        save_page(&hash, page);
+        hash
+    };
+
+    let mut hashes: Vec<PreimageHash> = if content.is_empty() {
+        vec![save(CONTENT_PAGE_TAG, content)]
+    } else {
+        content
+            .chunks(MAX_PAGE_CONTENT_SIZE)
+            .map(|chunk| save(CONTENT_PAGE_TAG, chunk))
+            .collect()
+    };
+
+    while hashes.len() > 1 {
+        hashes = hashes
+            .chunks(MAX_HASHES_PER_PAGE)
+            .map(|chunk| {
+                let contents: Vec<u8> = chunk.iter().flat_map(|hash| hash.0).collect();
+                save(HASHES_PAGE_TAG, &contents)
+            })
+            .collect();
+    }
+
+    hashes.remove(0)
+}
+
+/// A page of the tree of a payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Page {
+    /// A slice of the payload
+    Content(Vec<u8>),
+    /// The preimage hashes of the pages below, in order
+    Hashes(Vec<PreimageHash>),
+}
+
+/// Reveals the single page whose preimage hash is `hash`. Payloads too large
+/// to be revealed at once can be revealed page by page, walking the tree depth
+/// first.
+pub fn reveal_page(hrt: &impl HostRuntime, hash: &PreimageHash) -> Result<Page> {
+    let mut buffer = [0; MAX_PAGE_SIZE];
+    let size = hrt
+        .reveal_preimage(hash.as_array(), &mut buffer)
+        .map_err(jstz_core::Error::from)?;
+
+    match decode_page(&buffer[..size])? {
+        (CONTENT_PAGE_TAG, contents) => Ok(Page::Content(contents.to_vec())),
+        (HASHES_PAGE_TAG, hashes) => {
+            if hashes.len() % PREIMAGE_HASH_SIZE != 0 {
+                return Err(Error::InvalidPreimage);
+            }
+            Ok(Page::Hashes(
+                hashes
+                    .chunks(PREIMAGE_HASH_SIZE)
+                    .map(|hash| PreimageHash(hash.try_into().unwrap()))
+                    .collect(),
+            ))
+        }
+        _ => Err(Error::InvalidPreimage),
+    }
+}
+
+/// Reveals the payload whose root page has preimage hash `root_hash`. Fails if
+/// the payload is larger than `max_size` bytes.
+///
+/// Only trees produced by [`prepare_preimages`] are accepted: every content
+/// page but the last is full, and a content page is only empty when it is the
+/// root of an empty payload. This bounds the number of pages revealed by the
+/// size of the payload.
+pub fn reveal(
+    hrt: &impl HostRuntime,
+    root_hash: &PreimageHash,
+    max_size: usize,
+) -> Result<Vec<u8>> {
+    let mut tree = Tree {
+        content: Vec::new(),
+        max_size,
+        max_content_pages: max_size / MAX_PAGE_CONTENT_SIZE + 1,
+        content_pages: 0,
+        complete: false,
+    };
+    tree.reveal(hrt, root_hash, 0)?;
+    Ok(tree.content)
+}
+
+// State of the reveal of a tree of pages
+struct Tree {
+    content: Vec<u8>,
+    max_size: usize,
+    max_content_pages: usize,
+    content_pages: usize,
+    // Set once a content page shorter than a full page was revealed
+    complete: bool,
+}
+
+impl Tree {
+    fn reveal(
+        &mut self,
+        hrt: &impl HostRuntime,
+        hash: &PreimageHash,
+        depth: usize,
+    ) -> Result<()> {
+        // Pages after a short content page do not belong to a valid tree
+        if depth >= MAX_DEPTH || self.complete {
+            return Err(Error::InvalidPreimage);
+        }
+        if self.content_pages >= self.max_content_pages {
+            return Err(Error::RevealDataTooLarge);
+        }
+
+        match reveal_page(hrt, hash)? {
+            Page::Content(contents) => {
+                if contents.is_empty() && depth > 0 {
+                    return Err(Error::InvalidPreimage);
+                }
+                if self.content.len() + contents.len() > self.max_size {
+                    return Err(Error::RevealDataTooLarge);
+                }
+                self.content_pages += 1;
+                self.complete = contents.len() < MAX_PAGE_CONTENT_SIZE;
+                self.content.extend_from_slice(&contents);
+                Ok(())
+            }
+            Page::Hashes(hashes) => {
+                if hashes.is_empty() {
+                    return Err(Error::InvalidPreimage);
+                }
+                for hash in hashes {
+                    self.reveal(hrt, &hash, depth + 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tezos_smart_rollup_mock::MockHost;
+
+    use super::{
+        encode_page, prepare_preimages, reveal, PreimageHash, CONTENT_PAGE_TAG,
+        HASHES_PAGE_TAG, MAX_PAGE_CONTENT_SIZE, MAX_PAGE_SIZE,
+    };
+    use crate::Error;
+
+    fn set_preimages(host: &mut MockHost, content: &[u8]) -> PreimageHash {
+        prepare_preimages(content, |_, page| {
+            assert!(page.len() <= MAX_PAGE_SIZE);
+            host.set_preimage(page);
+        })
+    }
+
+    fn set_page(host: &mut MockHost, tag: u8, contents: &[u8]) -> PreimageHash {
+        let page = encode_page(tag, contents);
+        let hash = PreimageHash::of(&page);
+        host.set_preimage(page);
+        hash
+    }
+
+    fn set_hashes_page(host: &mut MockHost, hashes: &[PreimageHash]) -> PreimageHash {
+        let contents: Vec<u8> = hashes.iter().flat_map(|hash| hash.0).collect();
+        set_page(host, HASHES_PAGE_TAG, &contents)
+    }
+
+    #[test]
+    fn preimage_hash_round_trips_through_string() {
+        let hash = PreimageHash::of(b"page");
+        let parsed: PreimageHash = hash.to_string().parse().unwrap();
+        assert_eq!(hash, parsed);
+        assert_eq!(66, hash.to_string().len());
+    }
+
+    #[test]
+    fn reveal_single_page() {
+        let mut host = MockHost::default();
+        let content = b"export default () => new Response('hello');";
+
+        let root_hash = set_preimages(&mut host, content);
+
+        let revealed = reveal(&host, &root_hash, usize::MAX).unwrap();
+        assert_eq!(content.to_vec(), revealed);
+    }
+
+    #[test]
+    fn reveal_multiple_levels() {
+        let mut host = MockHost::default();
+        // Large enough to require two levels of hashes pages
+        let content: Vec<u8> = (0..1_000_000).map(|i| (i % 251) as u8).collect();
+
+        let root_hash = set_preimages(&mut host, &content);
+
+        let revealed = reveal(&host, &root_hash, usize::MAX).unwrap();
+        assert_eq!(content, revealed);
+    }
+
+    #[test]
+    fn reveal_fails_when_content_is_too_large() {
+        let mut host = MockHost::default();
+        let content = vec![0; 10_000];
+
+        let root_hash = set_preimages(&mut host, &content);
+
+        let error = reveal(&host, &root_hash, 9_999).unwrap_err();
+        assert!(matches!(error, Error::RevealDataTooLarge));
+    }
+
+    #[test]
+    fn reveal_empty_content() {
+        let mut host = MockHost::default();
+
+        let root_hash = set_preimages(&mut host, b"");
+
+        assert!(reveal(&host, &root_hash, usize::MAX).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reveal_rejects_empty_content_pages() {
+        let mut host = MockHost::default();
+        let empty = set_page(&mut host, CONTENT_PAGE_TAG, b"");
+        let root_hash = set_hashes_page(&mut host, &vec![empty; 100]);
+
+        let error = reveal(&host, &root_hash, usize::MAX).unwrap_err();
+        assert!(matches!(error, Error::InvalidPreimage));
+    }
+
+    #[test]
+    fn reveal_rejects_short_pages_before_the_last() {
+        let mut host = MockHost::default();
+        let short = set_page(&mut host, CONTENT_PAGE_TAG, b"short");
+        let root_hash = set_hashes_page(&mut host, &[short.clone(), short]);
+
+        let error = reveal(&host, &root_hash, usize::MAX).unwrap_err();
+        assert!(matches!(error, Error::InvalidPreimage));
+    }
+
+    #[test]
+    fn reveal_rejects_empty_hashes_pages() {
+        let mut host = MockHost::default();
+        let root_hash = set_hashes_page(&mut host, &[]);
+
+        let error = reveal(&host, &root_hash, usize::MAX).unwrap_err();
+        assert!(matches!(error, Error::InvalidPreimage));
+    }
+
+    #[test]
+    fn reveal_caps_the_number_of_pages() {
+        let mut host = MockHost::default();
+        // A small tree repeating the same full page describes a large payload
+        let full = set_page(&mut host, CONTENT_PAGE_TAG, &[0; MAX_PAGE_CONTENT_SIZE]);
+        let hashes = set_hashes_page(&mut host, &vec![full; 100]);
+        let root_hash = set_hashes_page(&mut host, &vec![hashes; 100]);
+
+        let error = reveal(&host, &root_hash, 10 * MAX_PAGE_CONTENT_SIZE).unwrap_err();
+        assert!(matches!(error, Error::RevealDataTooLarge));
+    }
+}
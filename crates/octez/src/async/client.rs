@@ -603,6 +603,32 @@ impl OctezClient {
         let output = self.spawn_and_wait_command(args).await?;
         Ok((parse_block_hash(&output)?, parse_operation_hash(&output)?))
     }
+
+    pub async fn publish_dal_commitment(
+        &self,
+        src: &str,
+        slot_index: u8,
+        commitment: &str,
+        commitment_proof: &str,
+    ) -> Result<OperationHash> {
+        let slot_index_str = slot_index.to_string();
+        let args = vec![
+            "publish",
+            "dal",
+            "commitment",
+            commitment,
+            "from",
+            src,
+            "for",
+            "slot",
+            &slot_index_str,
+            "with",
+            "proof",
+            commitment_proof,
+        ];
+        let output = self.spawn_and_wait_command(args).await?;
+        parse_operation_hash(&output)
+    }
 }
 
 fn parse_regex(pattern_str: &str, output: &str) -> Result<String> {
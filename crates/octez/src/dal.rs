@@ -0,0 +1,49 @@
+use anyhow::{anyhow, Result};
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+
+/// Commitment of a slot published to a DAL node
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SlotCommitment {
+    pub commitment: String,
+    pub commitment_proof: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct OctezDalClient {
+    endpoint: String,
+    client: reqwest::Client,
+}
+
+impl OctezDalClient {
+    pub fn new(endpoint: String) -> Self {
+        Self {
+            endpoint,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Posts the content of a slot to the DAL node, returning its commitment.
+    /// The commitment must then be published on the L1 for the slot to be
+    /// attested.
+    pub async fn post_slot(
+        &self,
+        slot_index: u8,
+        content: &[u8],
+    ) -> Result<SlotCommitment> {
+        let res = self
+            .client
+            .post(format!("{}/slots", self.endpoint))
+            .query(&[("slot_index", slot_index)])
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .body(content.to_vec())
+            .send()
+            .await?;
+
+        if res.status() == 200 {
+            Ok(res.json().await?)
+        } else {
+            Err(anyhow!("Unhandled response status: {}", res.status()))
+        }
+    }
+}
@@ -4,11 +4,13 @@ use anyhow::{anyhow, Result};
 
 pub mod r#async;
 mod client;
+mod dal;
 mod node;
 mod rollup;
 mod thread;
 
 pub use client::*;
+pub use dal::*;
 pub use node::*;
 pub use rollup::*;
 pub use thread::*;
@@ -1,7 +1,10 @@
 use std::{
+    fmt,
     fs::File,
     path::PathBuf,
     process::{Child, Command, Stdio},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Result};
@@ -78,6 +81,17 @@ impl OctezRollupNode {
 pub struct OctezRollupClient {
     endpoint: String,
     client: reqwest::Client,
+    observer: Option<RpcObserver>,
+}
+
+// Callback notified of the name and duration of each RPC call
+#[derive(Clone)]
+struct RpcObserver(Arc<dyn Fn(&'static str, Duration) + Send + Sync>);
+
+impl fmt::Debug for RpcObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RpcObserver")
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -98,47 +112,104 @@ enum ValueResponse {
 #[derive(Debug, Deserialize)]
 struct SubkeysResponse(Vec<String>);
 
+/// Status of a message injected into the batcher of the rollup node
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BatcherMessageStatus {
+    /// Stage of the message, e.g. `pending_batch`, `injected` or `included`
+    pub status: String,
+    /// Level of the L1 block including the message, once included
+    #[serde(default)]
+    pub l1_level: Option<u32>,
+}
+
+/// Proof that an outbox message is part of a cemented commitment, as
+/// expected by `octez-client execute outbox message`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboxProof {
+    /// Hash of the commitment the proof refers to
+    pub commitment: String,
+    /// Hex encoded output proof
+    pub proof: String,
+}
+
 impl OctezRollupClient {
     pub fn new(endpoint: String) -> Self {
         Self {
             endpoint,
             client: reqwest::Client::new(),
+            observer: None,
+        }
+    }
+
+    /// Calls `observer` with the name of the client method and the duration of
+    /// each RPC call made to the rollup node, e.g. to record its latency.
+    pub fn with_rpc_observer(
+        mut self,
+        observer: impl Fn(&'static str, Duration) + Send + Sync + 'static,
+    ) -> Self {
+        self.observer = Some(RpcObserver(Arc::new(observer)));
+        self
+    }
+
+    async fn send(
+        &self,
+        rpc: &'static str,
+        request: reqwest::RequestBuilder,
+    ) -> reqwest::Result<reqwest::Response> {
+        let start = Instant::now();
+        let res = request.send().await;
+        if let Some(RpcObserver(observer)) = &self.observer {
+            observer(rpc, start.elapsed());
         }
+        res
     }
 
-    pub async fn batcher_injection<S, I>(&self, external_messages: I) -> Result<()>
+    /// Injects `external_messages` into the batcher of the rollup node. Returns
+    /// the ids of the injected messages, in order.
+    pub async fn batcher_injection<S, I>(
+        &self,
+        external_messages: I,
+    ) -> Result<Vec<String>>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<[u8]>,
     {
         let res = self
-            .client
-            .post(format!("{}/local/batcher/injection", self.endpoint))
-            .json(
-                &external_messages
-                    .into_iter()
-                    .map(hex::encode)
-                    .collect::<Vec<String>>(),
+            .send(
+                "batcher_injection",
+                self.client
+                    .post(format!("{}/local/batcher/injection", self.endpoint))
+                    .json(
+                        &external_messages
+                            .into_iter()
+                            .map(hex::encode)
+                            .collect::<Vec<String>>(),
+                    ),
             )
-            .send()
             .await?;
 
         if res.status() == 200 {
-            // TODO: Should we ignore the response?
-            Ok(())
+            Ok(res.json::<Vec<String>>().await?)
         } else {
             Err(anyhow!("Unhandled response status: {}", res.status()))
         }
     }
 
     pub async fn get_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.get_value_at(key, "head").await
+    }
+
+    /// Returns the value of `key` in the durable storage at `block`, which is
+    /// either `head`, a level or a block hash
+    pub async fn get_value_at(&self, key: &str, block: &str) -> Result<Option<Vec<u8>>> {
         let res = self
-            .client
-            .get(format!(
-                "{}/global/block/head/durable/wasm_2_0_0/value?key={}",
-                self.endpoint, key
-            ))
-            .send()
+            .send(
+                "get_value",
+                self.client.get(format!(
+                    "{}/global/block/{}/durable/wasm_2_0_0/value?key={}",
+                    self.endpoint, block, key
+                )),
+            )
             .await?;
 
         if res.status() == 200 || res.status() == 500 {
@@ -161,13 +232,24 @@ impl OctezRollupClient {
     }
 
     pub async fn get_subkeys(&self, key: &str) -> Result<Option<Vec<String>>> {
+        self.get_subkeys_at(key, "head").await
+    }
+
+    /// Returns the subkeys of `key` in the durable storage at `block`, which is
+    /// either `head`, a level or a block hash
+    pub async fn get_subkeys_at(
+        &self,
+        key: &str,
+        block: &str,
+    ) -> Result<Option<Vec<String>>> {
         let res = self
-            .client
-            .get(format!(
-                "{}/global/block/head/durable/wasm_2_0_0/subkeys?key={}",
-                self.endpoint, key
-            ))
-            .send()
+            .send(
+                "get_subkeys",
+                self.client.get(format!(
+                    "{}/global/block/{}/durable/wasm_2_0_0/subkeys?key={}",
+                    self.endpoint, block, key
+                )),
+            )
             .await?;
 
         if res.status() == 200 || res.status() == 500 {
@@ -187,11 +269,115 @@ impl OctezRollupClient {
         }
     }
 
+    /// Returns the status of the message `message_id` injected into the batcher,
+    /// or `None` if the batcher does not know the message
+    pub async fn get_batcher_message_status(
+        &self,
+        message_id: &str,
+    ) -> Result<Option<BatcherMessageStatus>> {
+        let res = self
+            .send(
+                "get_batcher_message_status",
+                self.client.get(format!(
+                    "{}/local/batcher/queue/{}",
+                    self.endpoint, message_id
+                )),
+            )
+            .await?;
+
+        match res.status().as_u16() {
+            200 => Ok(res.json::<Option<BatcherMessageStatus>>().await?),
+            404 => Ok(None),
+            status => Err(anyhow!("Unhandled response status: {}", status)),
+        }
+    }
+
+    /// Returns the level of the head of the rollup node
+    pub async fn get_head_level(&self) -> Result<u32> {
+        let res = self
+            .send(
+                "get_head_level",
+                self.client
+                    .get(format!("{}/global/block/head/level", self.endpoint)),
+            )
+            .await?;
+
+        match res.status().as_u16() {
+            200 => Ok(res.json::<u32>().await?),
+            status => Err(anyhow!("Unhandled response status: {}", status)),
+        }
+    }
+
+    /// Returns the level of the last block of the rollup node that is final,
+    /// i.e. that can no longer be reorganised
+    pub async fn get_finalized_level(&self) -> Result<u32> {
+        let res = self
+            .send(
+                "get_finalized_level",
+                self.client
+                    .get(format!("{}/global/block/finalized/level", self.endpoint)),
+            )
+            .await?;
+
+        match res.status().as_u16() {
+            200 => Ok(res.json::<u32>().await?),
+            status => Err(anyhow!("Unhandled response status: {}", status)),
+        }
+    }
+
+    /// Returns the level of the last block whose commitment is cemented, if any
+    pub async fn get_cemented_level(&self) -> Result<Option<u32>> {
+        let res = self
+            .send(
+                "get_cemented_level",
+                self.client
+                    .get(format!("{}/global/block/cemented/level", self.endpoint)),
+            )
+            .await?;
+
+        match res.status().as_u16() {
+            200 => Ok(Some(res.json::<u32>().await?)),
+            404 => Ok(None),
+            status => Err(anyhow!("Unhandled response status: {}", status)),
+        }
+    }
+
+    /// Returns the proof of the outbox message `index` of `outbox_level`
+    /// against the last cemented commitment
+    pub async fn get_outbox_proof(
+        &self,
+        outbox_level: u32,
+        index: u32,
+    ) -> Result<OutboxProof> {
+        let res = self
+            .send(
+                "get_outbox_proof",
+                self.client.get(format!(
+                    "{}/global/block/cemented/helpers/proofs/outbox/{}/messages?index={}",
+                    self.endpoint, outbox_level, index
+                )),
+            )
+            .await?;
+
+        if res.status() == 200 {
+            Ok(res.json::<OutboxProof>().await?)
+        } else {
+            Err(anyhow!(
+                "Failed to get proof of outbox message {} of level {}: {}",
+                index,
+                outbox_level,
+                res.status()
+            ))
+        }
+    }
+
     pub async fn get_rollup_address(&self) -> Result<SmartRollupAddress> {
         let res = self
-            .client
-            .get(format!("{}/global/smart_rollup_address", self.endpoint))
-            .send()
+            .send(
+                "get_rollup_address",
+                self.client
+                    .get(format!("{}/global/smart_rollup_address", self.endpoint)),
+            )
             .await?;
 
         if res.status() == 200 {
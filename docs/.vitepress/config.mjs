@@ -48,6 +48,10 @@ export default defineConfig({
           { text: "URLPattern", link: "/api/url_pattern" },
           { text: "TextEncoder", link: "/api/text_encoder" },
           { text: "TextDecoder", link: "/api/text_decoder" },
+          { text: "Timers", link: "/api/timers" },
+          { text: "structuredClone", link: "/api/structured_clone" },
+          { text: "Event", link: "/api/event" },
+          { text: "AbortController", link: "/api/abort" },
         ],
       },
     ],
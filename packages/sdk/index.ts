@@ -46,6 +46,7 @@ namespace ffi {
         RunFunction: {
           body: Body;
           status_code: number;
+          status_text?: string;
           headers: Headers;
         };
       }
@@ -81,6 +82,7 @@ export type JstzRequest = {
 };
 export type JstzResponse = {
   statusCode: number;
+  statusText: string;
   headers: JstzHeaders;
   body: JstzBody;
 };
@@ -295,6 +297,7 @@ export class Jstz {
 
     return {
       statusCode: receiptContent.RunFunction.status_code,
+      statusText: receiptContent.RunFunction.status_text ?? "",
       headers: receiptContent.RunFunction.headers,
       body: receiptContent.RunFunction.body,
     };
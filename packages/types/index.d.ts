@@ -130,17 +130,133 @@ declare var Headers: {
   new (init?: HeadersInit): Headers;
 };
 
+declare interface EventInit {
+  bubbles?: boolean;
+  cancelable?: boolean;
+  composed?: boolean;
+}
+
+declare interface Event {
+  readonly bubbles: boolean;
+  readonly cancelable: boolean;
+  readonly composed: boolean;
+  readonly currentTarget: EventTarget | null;
+  readonly defaultPrevented: boolean;
+  readonly eventPhase: number;
+  readonly isTrusted: boolean;
+  readonly target: EventTarget | null;
+  readonly timeStamp: number;
+  readonly type: string;
+  composedPath(): EventTarget[];
+  preventDefault(): void;
+  stopImmediatePropagation(): void;
+  stopPropagation(): void;
+  readonly NONE: 0;
+  readonly CAPTURING_PHASE: 1;
+  readonly AT_TARGET: 2;
+  readonly BUBBLING_PHASE: 3;
+}
+
+declare var Event: {
+  readonly prototype: Event;
+  new (type: string, eventInitDict?: EventInit): Event;
+  readonly NONE: 0;
+  readonly CAPTURING_PHASE: 1;
+  readonly AT_TARGET: 2;
+  readonly BUBBLING_PHASE: 3;
+};
+
+declare interface CustomEventInit<T = any> extends EventInit {
+  detail?: T;
+}
+
+declare interface CustomEvent<T = any> extends Event {
+  readonly detail: T;
+}
+
+declare var CustomEvent: {
+  readonly prototype: CustomEvent;
+  new <T>(type: string, eventInitDict?: CustomEventInit<T>): CustomEvent<T>;
+};
+
+declare interface EventListener {
+  (evt: Event): void;
+}
+
+declare interface EventListenerObject {
+  handleEvent(object: Event): void;
+}
+
+declare type EventListenerOrEventListenerObject =
+  | EventListener
+  | EventListenerObject;
+
+declare interface EventListenerOptions {
+  capture?: boolean;
+}
+
+declare interface AddEventListenerOptions extends EventListenerOptions {
+  once?: boolean;
+  passive?: boolean;
+  signal?: AbortSignal;
+}
+
+declare interface EventTarget {
+  addEventListener(
+    type: string,
+    callback: EventListenerOrEventListenerObject | null,
+    options?: AddEventListenerOptions | boolean,
+  ): void;
+  dispatchEvent(event: Event): boolean;
+  removeEventListener(
+    type: string,
+    callback: EventListenerOrEventListenerObject | null,
+    options?: EventListenerOptions | boolean,
+  ): void;
+}
+
+declare var EventTarget: {
+  readonly prototype: EventTarget;
+  new (): EventTarget;
+};
+
+declare interface AbortSignal extends EventTarget {
+  readonly aborted: boolean;
+  onabort: ((this: AbortSignal, ev: Event) => any) | null;
+  readonly reason: any;
+  throwIfAborted(): void;
+}
+
+declare var AbortSignal: {
+  readonly prototype: AbortSignal;
+  abort(reason?: any): AbortSignal;
+  any(signals: AbortSignal[]): AbortSignal;
+  timeout(milliseconds: number): AbortSignal;
+};
+
+declare interface AbortController {
+  readonly signal: AbortSignal;
+  abort(reason?: any): void;
+}
+
+declare var AbortController: {
+  readonly prototype: AbortController;
+  new (): AbortController;
+};
+
 declare type RequestInfo = Request | string;
 
 declare interface RequestInit {
   body?: BodyInit | null;
   headers?: HeadersInit;
   method?: string;
+  signal?: AbortSignal | null;
 }
 
 declare interface Request extends Body {
   readonly headers: Headers;
   readonly method: string;
+  readonly signal: AbortSignal;
   readonly url: string;
 }
 
@@ -152,21 +268,34 @@ declare var Request: {
 declare interface ResponseInit {
   headers?: HeadersInit;
   status?: number;
+  statusText?: string;
 }
 
+declare type ResponseType =
+  | "basic"
+  | "cors"
+  | "default"
+  | "error"
+  | "opaque"
+  | "opaqueredirect";
+
 declare interface Response extends Body {
   readonly headers: Headers;
   readonly ok: boolean;
+  readonly redirected: boolean;
   readonly status: number;
   readonly statusText: string;
+  readonly type: ResponseType;
   readonly url: string;
+  clone(): Response;
 }
 
 declare var Response: {
   readonly prototype: Response;
   new (body?: BodyInit | null, init?: ResponseInit): Response;
-  json(data: unknown): Response;
+  json(data: unknown, init?: ResponseInit): Response;
   error(): Response;
+  redirect(url: string, status?: number): Response;
 };
 
 declare interface Console {
@@ -197,10 +326,20 @@ declare var Kv: Kv;
 
 declare type Mutez = number;
 
+declare type TicketHash = string;
+
+declare interface TicketBalance {
+  ticketHash: TicketHash;
+  amount: number;
+}
+
 declare interface Ledger {
   readonly selfAddress: Address;
   balance(address: Address): Mutez;
   transfer(address: Address, amount: Mutez): void;
+  ticketBalance(address: Address, ticketHash: TicketHash): number;
+  transferTicket(address: Address, ticketHash: TicketHash, amount: number): void;
+  tickets(address: Address): TicketBalance[];
 }
 
 declare var Ledger: Ledger;
@@ -214,6 +353,29 @@ declare var SmartFunction: SmartFunction;
 
 declare function fetch(request: Request): Promise<Response>;
 
+declare function setTimeout(
+  callback: (...args: any[]) => void,
+  delay?: number,
+  ...args: any[]
+): number;
+declare function setInterval(
+  callback: (...args: any[]) => void,
+  delay?: number,
+  ...args: any[]
+): number;
+declare function clearTimeout(id?: number): void;
+declare function clearInterval(id?: number): void;
+declare function queueMicrotask(callback: () => void): void;
+
+declare interface StructuredSerializeOptions {
+  transfer?: any[];
+}
+
+declare function structuredClone<T = any>(
+  value: T,
+  options?: StructuredSerializeOptions,
+): T;
+
 declare function atob(s: string): string;
 declare function btoa(s: string): string;
 